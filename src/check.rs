@@ -0,0 +1,199 @@
+//! `--check`: compare the target with what the requested operations would leave behind.
+//!
+//! Restow is checked like stow, since both end with the package's current contents linked.
+
+use crate::config::{Config, PackageOperation, StowMode};
+use crate::error::RustowError;
+use crate::fs_utils;
+use crate::stow::{self, ActionType, TargetAction};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// One way the target differs from what the requested operations produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Drift {
+    /// Nothing exists where a link belongs.
+    MissingLink { path: PathBuf, expected: PathBuf },
+    /// Nothing exists where an unfolded directory belongs.
+    MissingDirectory { path: PathBuf },
+    /// A link exists but points somewhere else, or should be a directory when `expected` is None.
+    WrongLink {
+        path: PathBuf,
+        actual: PathBuf,
+        expected: Option<PathBuf>,
+    },
+    /// Something that is not a link occupies a path the packages need.
+    Conflict { path: PathBuf, details: String },
+    /// A link into the packages that the operations would not leave in place.
+    ExtraLink { path: PathBuf, actual: PathBuf },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::MissingLink { path, expected } => {
+                write!(
+                    f,
+                    "missing link: {} -> {}",
+                    path.display(),
+                    expected.display()
+                )
+            },
+            Drift::MissingDirectory { path } => {
+                write!(f, "missing directory: {}", path.display())
+            },
+            Drift::WrongLink {
+                path,
+                actual,
+                expected: Some(expected),
+            } => write!(
+                f,
+                "wrong link: {} -> {} (expected {})",
+                path.display(),
+                actual.display(),
+                expected.display()
+            ),
+            Drift::WrongLink {
+                path,
+                actual,
+                expected: None,
+            } => write!(
+                f,
+                "wrong link: {} -> {} (expected a directory)",
+                path.display(),
+                actual.display()
+            ),
+            Drift::Conflict { path, details } => {
+                write!(f, "conflict: {}: {}", path.display(), details)
+            },
+            Drift::ExtraLink { path, actual } => {
+                write!(f, "extra link: {} -> {}", path.display(), actual.display())
+            },
+        }
+    }
+}
+
+/// Every difference between the target and what `operations` would produce, sorted by path.
+pub(crate) fn find_drift(
+    config: &Config,
+    operations: &[PackageOperation],
+) -> Result<Vec<Drift>, RustowError> {
+    let operations: Vec<PackageOperation> = operations
+        .iter()
+        .map(|operation| PackageOperation {
+            mode: match operation.mode {
+                StowMode::Restow => StowMode::Stow,
+                ref mode => mode.clone(),
+            },
+            packages: operation.packages.clone(),
+        })
+        .collect();
+    let plan = stow::plan_package_operations(config, &operations)?;
+    let actions: Vec<&TargetAction> = plan.actions().collect();
+
+    let mut drift = BTreeMap::new();
+    for (index, action) in actions.iter().enumerate() {
+        let replaced_later = actions[index + 1..]
+            .iter()
+            .any(|later| later.target_path == action.target_path);
+        if let Some(found) = drift_for_action(action, replaced_later) {
+            drift.entry(action.target_path.clone()).or_insert(found);
+        }
+    }
+
+    let stowed_packages: Vec<String> = operations
+        .iter()
+        .filter(|operation| operation.mode != StowMode::Delete)
+        .flat_map(|operation| operation.packages.iter().cloned())
+        .collect();
+    if !stowed_packages.is_empty() {
+        let mut stow_config = config.clone();
+        stow_config.packages = stowed_packages;
+        let planned: HashSet<&Path> = actions
+            .iter()
+            .map(|action| action.target_path.as_path())
+            .collect();
+        for path in stow::package_symlinks_in_target(&stow_config)? {
+            if planned.contains(path.as_path()) {
+                continue;
+            }
+            if let Ok(actual) = fs_utils::read_link(&path) {
+                drift
+                    .entry(path.clone())
+                    .or_insert(Drift::ExtraLink { path, actual });
+            }
+        }
+    }
+
+    Ok(drift.into_values().collect())
+}
+
+fn drift_for_action(action: &TargetAction, replaced_later: bool) -> Option<Drift> {
+    let path = action.target_path.clone();
+    let current_link = fs_utils::read_link(&path).ok();
+    let expected = action.link_target_path.clone().unwrap_or_default();
+
+    match (&action.action_type, current_link) {
+        (ActionType::Skip | ActionType::DeleteDirectory | ActionType::MoveItem, _) => None,
+        // Directories the package shares with the target are planned even when they exist.
+        (ActionType::CreateDirectory, None) if fs_utils::is_directory(&path) => None,
+        // Unfolding removes a link only to put a directory in its place; that is reported there.
+        (ActionType::DeleteSymlink, _) if replaced_later => None,
+        (ActionType::DeleteSymlink, Some(actual)) => Some(Drift::ExtraLink { path, actual }),
+        (ActionType::DeleteSymlink, None) => None,
+        (ActionType::CreateSymlink | ActionType::Conflict, Some(actual)) => {
+            Some(Drift::WrongLink {
+                path,
+                actual,
+                expected: Some(expected),
+            })
+        },
+        (ActionType::CreateSymlink, None) => Some(Drift::MissingLink { path, expected }),
+        (ActionType::CreateDirectory, Some(actual)) => Some(Drift::WrongLink {
+            path,
+            actual,
+            expected: None,
+        }),
+        (ActionType::CreateDirectory, None) => Some(Drift::MissingDirectory { path }),
+        (ActionType::Conflict, None) => Some(Drift::Conflict {
+            path,
+            details: action
+                .conflict_details
+                .clone()
+                .unwrap_or_else(|| "conflicts with the package".to_string()),
+        }),
+        (ActionType::AdoptFile | ActionType::AdoptDirectory, _) => Some(Drift::Conflict {
+            path,
+            details: "would be adopted into the package".to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drift_display_names_each_kind() {
+        let wrong = Drift::WrongLink {
+            path: PathBuf::from("/t/.vimrc"),
+            actual: PathBuf::from("elsewhere/.vimrc"),
+            expected: Some(PathBuf::from("stow/vim/.vimrc")),
+        };
+        let unfolded = Drift::WrongLink {
+            path: PathBuf::from("/t/.config"),
+            actual: PathBuf::from("stow/a/.config"),
+            expected: None,
+        };
+
+        assert_eq!(
+            wrong.to_string(),
+            "wrong link: /t/.vimrc -> elsewhere/.vimrc (expected stow/vim/.vimrc)"
+        );
+        assert_eq!(
+            unfolded.to_string(),
+            "wrong link: /t/.config -> stow/a/.config (expected a directory)"
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use clap::{Parser, builder::TypedValueParser};
+use crate::completions::{CompletionOption, CompletionShell, OptionRole, PackageCompletion};
+use crate::logging::log_event;
+use crate::selection::{ALL_MARKER, packages_from_marker};
+use crate::sensitive::InsecurePermissions;
+use clap::{ArgGroup, Parser, builder::TypedValueParser};
 use std::ffi::OsString;
 #[cfg(unix)]
 use std::ffi::{CStr, CString};
@@ -81,6 +85,7 @@ impl std::fmt::Debug for RuntimeParsedArgs {
 /// Rustow: A Rust implementation of GNU Stow
 #[derive(Parser, Debug, Clone)]
 #[clap(author, version, about, long_about = None, args_override_self = true)]
+#[clap(group(ArgGroup::new("moves_into_stow_dir").args(["adopt", "add"]).multiple(true)))]
 pub struct Args {
     // Ensure this is pub
     /// Target directory for symlinks
@@ -101,6 +106,15 @@ pub struct Args {
     )]
     pub dir: Option<PathBuf>,
 
+    /// Further stow directories, separated by ':', searched for packages after the one above
+    #[clap(
+        long,
+        value_name = "DIRS",
+        value_parser = clap::builder::OsStringValueParser::new().map(PathBuf::from),
+        allow_hyphen_values = true
+    )]
+    pub stow_path: Vec<PathBuf>,
+
     /// Stow the specified packages (default action)
     #[clap(short = 'S', long)]
     pub stow: bool,
@@ -117,10 +131,40 @@ pub struct Args {
     #[clap(long)]
     pub adopt: bool,
 
+    /// Commit files adopted into the stow directory when it is a git work tree
+    #[clap(long, requires = "moves_into_stow_dir")]
+    pub git_commit: bool,
+
     /// Disable folding of directories
     #[clap(long)]
     pub no_folding: bool,
 
+    /// Never fold directories whose path relative to the target matches the regex
+    #[clap(
+        long = "no-fold",
+        value_name = "REGEX",
+        value_parser,
+        allow_hyphen_values = true
+    )]
+    pub no_fold: Vec<String>,
+
+    /// Mode for new target directories, octal or 'package' for the package directory's; only for paths matching REGEX if given
+    #[clap(
+        long = "dir-mode",
+        value_name = "[REGEX=]MODE",
+        value_parser,
+        allow_hyphen_values = true
+    )]
+    pub dir_mode: Vec<String>,
+
+    /// Also treat target paths matching the regex as sensitive, on top of .ssh/, .gnupg/, .netrc and the like
+    #[clap(long, value_name = "REGEX", value_parser, allow_hyphen_values = true)]
+    pub sensitive: Vec<String>,
+
+    /// What to do when a sensitive item or a stow directory above it is accessible to others
+    #[clap(long, value_name = "POLICY", value_enum)]
+    pub insecure_permissions: Option<InsecurePermissions>,
+
     /// Enable special handling for dotfiles (prefix files with 'dot-')
     #[clap(long)]
     pub dotfiles: bool,
@@ -149,8 +193,177 @@ pub struct Args {
     #[clap(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
 
-    /// Packages to process
-    #[clap(value_parser, required = true, num_args = 1..)]
+    /// Log filter: a level, or CATEGORY=LEVEL pairs separated by commas (e.g. planner=trace,fs=debug)
+    #[clap(long, value_name = "FILTER", allow_hyphen_values = true)]
+    pub log: Option<String>,
+
+    /// Append log lines to PATH instead of standard error
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = clap::builder::OsStringValueParser::new().map(PathBuf::from),
+        allow_hyphen_values = true
+    )]
+    pub log_file: Option<PathBuf>,
+
+    /// Wait up to SECONDS for another run to release the target and stow directories (default 10)
+    #[clap(long, value_name = "SECONDS", value_parser)]
+    pub lock_timeout: Option<u64>,
+
+    /// Save the computed plan to PATH for a later --apply instead of executing it
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = clap::builder::OsStringValueParser::new().map(PathBuf::from),
+        allow_hyphen_values = true,
+        conflicts_with = "apply"
+    )]
+    pub plan_out: Option<PathBuf>,
+
+    /// Apply a plan saved with --plan-out, refusing if the filesystem changed since
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = clap::builder::OsStringValueParser::new().map(PathBuf::from),
+        allow_hyphen_values = true,
+        conflicts_with = "packages"
+    )]
+    pub apply: Option<PathBuf>,
+
+    /// Keep running and restow packages whenever their contents change
+    #[clap(long, conflicts_with_all = ["simulate", "plan_out", "apply"])]
+    pub watch: bool,
+
+    /// Exit with a distinct status, listing every difference, if the target is out of date
+    #[clap(long, conflicts_with_all = ["plan_out", "apply", "watch"])]
+    pub check: bool,
+
+    /// Skip restowing packages that have not changed since they were last stowed
+    #[clap(long, conflicts_with_all = ["plan_out", "apply", "watch", "check"])]
+    pub incremental: bool,
+
+    /// Restow every package given, including those --incremental would skip
+    #[clap(long, requires = "incremental")]
+    pub force: bool,
+
+    /// List files written into packages through folded directory links
+    #[clap(long, conflicts_with_all = ["plan_out", "apply", "watch", "check"])]
+    pub report_unmanaged: bool,
+
+    /// Unfold directories holding unmanaged files and move those files into the target
+    #[clap(long, conflicts_with_all = ["plan_out", "apply", "watch", "check"])]
+    pub unfold_unmanaged: bool,
+
+    /// Replace the folded directory link at PATH with a directory of per-entry links
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = clap::builder::OsStringValueParser::new().map(PathBuf::from),
+        allow_hyphen_values = true,
+        conflicts_with_all = [
+            "packages", "refold", "plan_out", "apply", "watch", "check",
+            "report_unmanaged", "unfold_unmanaged", "all", "packages_from", "exclude_package"
+        ]
+    )]
+    pub unfold: Option<PathBuf>,
+
+    /// Replace the directory at PATH with one link when all its entries link into one package directory
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = clap::builder::OsStringValueParser::new().map(PathBuf::from),
+        allow_hyphen_values = true,
+        conflicts_with_all = [
+            "packages", "plan_out", "apply", "watch", "check", "report_unmanaged",
+            "unfold_unmanaged", "all", "packages_from", "exclude_package"
+        ]
+    )]
+    pub refold: Option<PathBuf>,
+
+    /// Move the PATH arguments from the target into PACKAGE, creating it if needed, and link them
+    #[clap(
+        long,
+        value_name = "PACKAGE",
+        allow_hyphen_values = true,
+        conflicts_with_all = [
+            "delete", "restow", "plan_out", "apply", "watch", "check", "report_unmanaged",
+            "unfold_unmanaged", "unfold", "refold"
+        ]
+    )]
+    pub add: Option<String>,
+
+    /// Move the PACKAGE or PACKAGE/PATH arguments into PACKAGE, rewriting the links to them
+    #[clap(
+        long,
+        value_name = "PACKAGE",
+        allow_hyphen_values = true,
+        conflicts_with_all = [
+            "delete", "restow", "plan_out", "apply", "watch", "check", "report_unmanaged",
+            "unfold_unmanaged", "unfold", "refold", "add"
+        ]
+    )]
+    pub move_to: Option<String>,
+
+    /// Rewrite links into OLD_STOW_DIR, where the stow directory used to be, to lead into it
+    #[clap(
+        long,
+        value_name = "OLD_STOW_DIR",
+        value_parser = clap::builder::OsStringValueParser::new().map(PathBuf::from),
+        allow_hyphen_values = true,
+        conflicts_with_all = [
+            "delete", "restow", "plan_out", "apply", "watch", "check", "report_unmanaged",
+            "unfold_unmanaged", "unfold", "refold", "add", "move_to"
+        ]
+    )]
+    pub relink_from: Option<PathBuf>,
+
+    /// Select every package in the stow directory that is neither hidden nor ignored
+    #[clap(long, conflicts_with_all = ["apply", "add", "move_to"])]
+    pub all: bool,
+
+    /// Read package names or globs from PATH, one per line, or from standard input for -
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = clap::builder::OsStringValueParser::new().map(PathBuf::from),
+        allow_hyphen_values = true,
+        conflicts_with_all = ["apply", "add", "move_to"]
+    )]
+    pub packages_from: Vec<PathBuf>,
+
+    /// Leave out packages matching the shell-style PATTERN
+    #[clap(
+        long,
+        value_name = "PATTERN",
+        value_parser,
+        allow_hyphen_values = true,
+        conflicts_with_all = ["apply", "add", "move_to"]
+    )]
+    pub exclude_package: Vec<String>,
+
+    /// Print a completion script for SHELL to standard output
+    #[clap(long, value_name = "SHELL", value_enum, conflicts_with = "packages")]
+    pub completions: Option<CompletionShell>,
+
+    /// List the packages completion scripts offer: all of them, or those stowed in the target
+    #[clap(
+        long,
+        value_name = "SET",
+        value_enum,
+        hide = true,
+        conflicts_with = "completions"
+    )]
+    pub complete_packages: Option<PackageCompletion>,
+
+    /// Packages to process; the target paths to move with --add, or the items to move with --move-to
+    #[clap(
+        value_parser,
+        required_unless_present_any = [
+            "apply", "unfold", "refold", "relink_from", "completions", "complete_packages", "all",
+            "packages_from"
+        ],
+        num_args = 1..
+    )]
     pub packages: Vec<String>,
 }
 
@@ -704,10 +917,18 @@ fn read_stowrc_file(
     };
 
     if !metadata.is_file() {
+        log_event!(
+            Cli,
+            Debug,
+            "skipping resource file '{}': not a regular file",
+            display_path
+        );
         return Ok(());
     }
 
-    stowrc_tokens_from_reader(display_path, file, normalizer)
+    stowrc_tokens_from_reader(display_path, file, normalizer)?;
+    log_event!(Cli, Info, "loaded resource file '{}'", display_path);
+    Ok(())
 }
 
 #[cfg(unix)]
@@ -718,8 +939,19 @@ fn open_stowrc_file(path: &Path, display_path: &str) -> Result<Option<fs::File>,
         .open(path)
     {
         Ok(file) => Ok(Some(file)),
-        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
-        Err(err) if err.kind() == ErrorKind::PermissionDenied => Ok(None),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            log_event!(Cli, Trace, "no resource file '{}'", display_path);
+            Ok(None)
+        },
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+            log_event!(
+                Cli,
+                Warn,
+                "skipping unreadable resource file '{}'",
+                display_path
+            );
+            Ok(None)
+        },
         Err(err) => Err(clap::Error::raw(
             clap::error::ErrorKind::Io,
             format!("failed to open resource file '{}': {}", display_path, err),
@@ -731,8 +963,19 @@ fn open_stowrc_file(path: &Path, display_path: &str) -> Result<Option<fs::File>,
 fn open_stowrc_file(path: &Path, display_path: &str) -> Result<Option<fs::File>, clap::Error> {
     match fs::File::open(path) {
         Ok(file) => Ok(Some(file)),
-        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
-        Err(err) if err.kind() == ErrorKind::PermissionDenied => Ok(None),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            log_event!(Cli, Trace, "no resource file '{}'", display_path);
+            Ok(None)
+        },
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+            log_event!(
+                Cli,
+                Warn,
+                "skipping unreadable resource file '{}'",
+                display_path
+            );
+            Ok(None)
+        },
         Err(err) => Err(clap::Error::raw(
             clap::error::ErrorKind::Io,
             format!("failed to open resource file '{}': {}", display_path, err),
@@ -955,10 +1198,29 @@ fn stowrc_tokens_from_reader(
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ResourceValueOption {
     Dir,
+    StowPath,
     Target,
     Ignore,
     Defer,
     Override,
+    NoFold,
+    DirMode,
+    Sensitive,
+    InsecurePermissions,
+    Log,
+    LogFile,
+    LockTimeout,
+    PlanOut,
+    Apply,
+    Unfold,
+    Refold,
+    Add,
+    MoveTo,
+    RelinkFrom,
+    PackagesFrom,
+    ExcludePackage,
+    Completions,
+    CompletePackages,
 }
 
 impl ResourceValueOption {
@@ -969,10 +1231,29 @@ impl ResourceValueOption {
     fn option_name(self) -> &'static str {
         match self {
             Self::Dir => "--dir",
+            Self::StowPath => "--stow-path",
             Self::Target => "--target",
             Self::Ignore => "--ignore",
             Self::Defer => "--defer",
             Self::Override => "--override",
+            Self::NoFold => "--no-fold",
+            Self::DirMode => "--dir-mode",
+            Self::Sensitive => "--sensitive",
+            Self::InsecurePermissions => "--insecure-permissions",
+            Self::Log => "--log",
+            Self::LogFile => "--log-file",
+            Self::LockTimeout => "--lock-timeout",
+            Self::PlanOut => "--plan-out",
+            Self::Apply => "--apply",
+            Self::Unfold => "--unfold",
+            Self::Refold => "--refold",
+            Self::Add => "--add",
+            Self::MoveTo => "--move-to",
+            Self::RelinkFrom => "--relink-from",
+            Self::PackagesFrom => "--packages-from",
+            Self::ExcludePackage => "--exclude-package",
+            Self::Completions => "--completions",
+            Self::CompletePackages => "--complete-packages",
         }
     }
 }
@@ -1005,6 +1286,11 @@ const LONG_OPTION_SPECS: &[LongOptionSpec] = &[
         canonical: "dir",
         kind: LongOptionKind::Value(ResourceValueOption::Dir),
     },
+    LongOptionSpec {
+        name: "stow-path",
+        canonical: "stow-path",
+        kind: LongOptionKind::Value(ResourceValueOption::StowPath),
+    },
     LongOptionSpec {
         name: "stow",
         canonical: "stow",
@@ -1025,11 +1311,36 @@ const LONG_OPTION_SPECS: &[LongOptionSpec] = &[
         canonical: "adopt",
         kind: LongOptionKind::Bool,
     },
+    LongOptionSpec {
+        name: "git-commit",
+        canonical: "git-commit",
+        kind: LongOptionKind::Bool,
+    },
     LongOptionSpec {
         name: "no-folding",
         canonical: "no-folding",
         kind: LongOptionKind::Bool,
     },
+    LongOptionSpec {
+        name: "no-fold",
+        canonical: "no-fold",
+        kind: LongOptionKind::Value(ResourceValueOption::NoFold),
+    },
+    LongOptionSpec {
+        name: "dir-mode",
+        canonical: "dir-mode",
+        kind: LongOptionKind::Value(ResourceValueOption::DirMode),
+    },
+    LongOptionSpec {
+        name: "sensitive",
+        canonical: "sensitive",
+        kind: LongOptionKind::Value(ResourceValueOption::Sensitive),
+    },
+    LongOptionSpec {
+        name: "insecure-permissions",
+        canonical: "insecure-permissions",
+        kind: LongOptionKind::Value(ResourceValueOption::InsecurePermissions),
+    },
     LongOptionSpec {
         name: "dotfiles",
         canonical: "dotfiles",
@@ -1070,6 +1381,111 @@ const LONG_OPTION_SPECS: &[LongOptionSpec] = &[
         canonical: "verbose",
         kind: LongOptionKind::Verbose,
     },
+    LongOptionSpec {
+        name: "log",
+        canonical: "log",
+        kind: LongOptionKind::Value(ResourceValueOption::Log),
+    },
+    LongOptionSpec {
+        name: "log-file",
+        canonical: "log-file",
+        kind: LongOptionKind::Value(ResourceValueOption::LogFile),
+    },
+    LongOptionSpec {
+        name: "lock-timeout",
+        canonical: "lock-timeout",
+        kind: LongOptionKind::Value(ResourceValueOption::LockTimeout),
+    },
+    LongOptionSpec {
+        name: "plan-out",
+        canonical: "plan-out",
+        kind: LongOptionKind::Value(ResourceValueOption::PlanOut),
+    },
+    LongOptionSpec {
+        name: "apply",
+        canonical: "apply",
+        kind: LongOptionKind::Value(ResourceValueOption::Apply),
+    },
+    LongOptionSpec {
+        name: "watch",
+        canonical: "watch",
+        kind: LongOptionKind::Bool,
+    },
+    LongOptionSpec {
+        name: "check",
+        canonical: "check",
+        kind: LongOptionKind::Bool,
+    },
+    LongOptionSpec {
+        name: "incremental",
+        canonical: "incremental",
+        kind: LongOptionKind::Bool,
+    },
+    LongOptionSpec {
+        name: "force",
+        canonical: "force",
+        kind: LongOptionKind::Bool,
+    },
+    LongOptionSpec {
+        name: "report-unmanaged",
+        canonical: "report-unmanaged",
+        kind: LongOptionKind::Bool,
+    },
+    LongOptionSpec {
+        name: "unfold-unmanaged",
+        canonical: "unfold-unmanaged",
+        kind: LongOptionKind::Bool,
+    },
+    LongOptionSpec {
+        name: "unfold",
+        canonical: "unfold",
+        kind: LongOptionKind::Value(ResourceValueOption::Unfold),
+    },
+    LongOptionSpec {
+        name: "refold",
+        canonical: "refold",
+        kind: LongOptionKind::Value(ResourceValueOption::Refold),
+    },
+    LongOptionSpec {
+        name: "add",
+        canonical: "add",
+        kind: LongOptionKind::Value(ResourceValueOption::Add),
+    },
+    LongOptionSpec {
+        name: "move-to",
+        canonical: "move-to",
+        kind: LongOptionKind::Value(ResourceValueOption::MoveTo),
+    },
+    LongOptionSpec {
+        name: "relink-from",
+        canonical: "relink-from",
+        kind: LongOptionKind::Value(ResourceValueOption::RelinkFrom),
+    },
+    LongOptionSpec {
+        name: "all",
+        canonical: "all",
+        kind: LongOptionKind::Bool,
+    },
+    LongOptionSpec {
+        name: "packages-from",
+        canonical: "packages-from",
+        kind: LongOptionKind::Value(ResourceValueOption::PackagesFrom),
+    },
+    LongOptionSpec {
+        name: "exclude-package",
+        canonical: "exclude-package",
+        kind: LongOptionKind::Value(ResourceValueOption::ExcludePackage),
+    },
+    LongOptionSpec {
+        name: "completions",
+        canonical: "completions",
+        kind: LongOptionKind::Value(ResourceValueOption::Completions),
+    },
+    LongOptionSpec {
+        name: "complete-packages",
+        canonical: "complete-packages",
+        kind: LongOptionKind::Value(ResourceValueOption::CompletePackages),
+    },
     LongOptionSpec {
         name: "help",
         canonical: "help",
@@ -1090,6 +1506,25 @@ enum LongOptionResolveError {
     Ambiguous,
 }
 
+/// Rustow-only options that must be spelled out, so GNU Stow abbreviations such as `--no-f`
+/// for `--no-folding`, `--ad` for `--adopt`, `--di` for `--dir`, `--i` for `--ignore` or `--comp`
+/// for `--compat` stay unambiguous.
+const UNABBREVIATED_LONG_OPTIONS: &[&str] = &[
+    "stow-path",
+    "no-fold",
+    "add",
+    "all",
+    "dir-mode",
+    "insecure-permissions",
+    "incremental",
+    "completions",
+    "complete-packages",
+];
+
+fn long_option_matches_prefix(spec: &LongOptionSpec, option: &str) -> bool {
+    spec.name.starts_with(option) && !UNABBREVIATED_LONG_OPTIONS.contains(&spec.name)
+}
+
 fn resolve_long_option(option: &str) -> Result<LongOptionSpec, LongOptionResolveError> {
     if option.is_empty() {
         return Err(LongOptionResolveError::Unknown);
@@ -1102,7 +1537,7 @@ fn resolve_long_option(option: &str) -> Result<LongOptionSpec, LongOptionResolve
     let mut matched = None;
     for spec in LONG_OPTION_SPECS
         .iter()
-        .filter(|spec| spec.name.starts_with(option))
+        .filter(|spec| long_option_matches_prefix(spec, option))
     {
         if let Some(previous) = matched {
             let previous: LongOptionSpec = previous;
@@ -1117,11 +1552,89 @@ fn resolve_long_option(option: &str) -> Result<LongOptionSpec, LongOptionResolve
     matched.ok_or(LongOptionResolveError::Unknown)
 }
 
+/// Long options for completion scripts, with every abbreviation [`resolve_long_option`] accepts.
+///
+/// The hidden `--complete-packages` option is left out; only the scripts themselves use it.
+pub(crate) fn completion_options() -> Vec<CompletionOption> {
+    let command = <Args as clap::CommandFactory>::command();
+    LONG_OPTION_SPECS
+        .iter()
+        .filter(|spec| spec.canonical != "complete-packages")
+        .map(|spec| {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(spec.canonical));
+            let (short, help) = match spec.kind {
+                LongOptionKind::Help => (Some('h'), "Print help".to_string()),
+                LongOptionKind::Version => (Some('V'), "Print version".to_string()),
+                _ => (
+                    arg.and_then(|arg| arg.get_short()),
+                    arg.and_then(|arg| arg.get_help())
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                ),
+            };
+            let spellings = (1..=spec.name.len())
+                .rev()
+                .map(|len| &spec.name[..len])
+                .filter(|prefix| resolve_long_option(prefix) == Ok(*spec))
+                .map(str::to_string)
+                .collect();
+            CompletionOption {
+                name: spec.name,
+                // An alias such as --no shares the short option of the option it stands for.
+                short: short.filter(|_| spec.name == spec.canonical),
+                help,
+                role: completion_role(spec.kind),
+                spellings,
+            }
+        })
+        .collect()
+}
+
+fn completion_role(kind: LongOptionKind) -> OptionRole {
+    match kind {
+        LongOptionKind::Mode(OperationMode::Stow) => OptionRole::Stow,
+        LongOptionKind::Mode(OperationMode::Delete) => OptionRole::Delete,
+        LongOptionKind::Mode(OperationMode::Restow) => OptionRole::Restow,
+        LongOptionKind::Value(option) => match option {
+            ResourceValueOption::Target => OptionRole::Target,
+            ResourceValueOption::Dir => OptionRole::Dir,
+            ResourceValueOption::Unfold
+            | ResourceValueOption::Refold
+            | ResourceValueOption::RelinkFrom
+            | ResourceValueOption::StowPath => OptionRole::Directory,
+            ResourceValueOption::PackagesFrom => OptionRole::File,
+            ResourceValueOption::ExcludePackage => OptionRole::Package,
+            ResourceValueOption::LogFile
+            | ResourceValueOption::PlanOut
+            | ResourceValueOption::Apply => OptionRole::File,
+            ResourceValueOption::Add => OptionRole::Add,
+            ResourceValueOption::MoveTo => OptionRole::Package,
+            ResourceValueOption::Completions => OptionRole::Shell,
+            ResourceValueOption::Ignore
+            | ResourceValueOption::Defer
+            | ResourceValueOption::Override
+            | ResourceValueOption::NoFold
+            | ResourceValueOption::DirMode
+            | ResourceValueOption::Sensitive
+            | ResourceValueOption::InsecurePermissions
+            | ResourceValueOption::Log
+            | ResourceValueOption::LockTimeout
+            | ResourceValueOption::CompletePackages => OptionRole::Text,
+        },
+        LongOptionKind::Bool
+        | LongOptionKind::Verbose
+        | LongOptionKind::Help
+        | LongOptionKind::Version => OptionRole::Flag,
+    }
+}
+
 fn long_option_abbreviation_candidates(option: &str) -> Vec<&'static str> {
     let mut candidates = Vec::new();
     for spec in LONG_OPTION_SPECS
         .iter()
-        .filter(|spec| spec.name.starts_with(option))
+        .filter(|spec| long_option_matches_prefix(spec, option))
     {
         if !candidates.contains(&spec.name) {
             candidates.push(spec.name);
@@ -1283,10 +1796,10 @@ impl StowrcNormalizer {
             return Ok(());
         }
 
-        if token_text.len() > 1 {
-            if let Some(parsed) = parse_short_stowrc_option(&token_value, &origin)? {
-                self.apply_parsed_option(parsed, origin);
-            }
+        if token_text.len() > 1
+            && let Some(parsed) = parse_short_stowrc_option(&token_value, &origin)?
+        {
+            self.apply_parsed_option(parsed, origin);
         }
 
         Ok(())
@@ -1901,25 +2414,25 @@ fn expand_environment_value(
                 continue;
             }
 
-            if let Some(next) = chars.peek().copied() {
-                if is_valid_var_start(next) {
-                    let mut variable = String::new();
-                    variable.push(chars.next().expect("peeked variable start"));
-                    while let Some(next_char) = chars.peek() {
-                        if is_valid_var_char(*next_char) {
-                            variable.push(*next_char);
-                            chars.next();
-                        } else {
-                            break;
-                        }
+            if let Some(next) = chars.peek().copied()
+                && is_valid_var_start(next)
+            {
+                let mut variable = String::new();
+                variable.push(chars.next().expect("peeked variable start"));
+                while let Some(next_char) = chars.peek() {
+                    if is_valid_var_char(*next_char) {
+                        variable.push(*next_char);
+                        chars.next();
+                    } else {
+                        break;
                     }
-
-                    let value = env_resource_value(&variable, option_name)?;
-                    output.push(value);
-                    display.push_str(&format!("${}", variable));
-                    changed_display = true;
-                    continue;
                 }
+
+                let value = env_resource_value(&variable, option_name)?;
+                output.push(value);
+                display.push_str(&format!("${}", variable));
+                changed_display = true;
+                continue;
             }
 
             output.push("$");
@@ -2010,24 +2523,23 @@ fn expand_environment_value_os(
                 continue;
             }
 
-            if let Some(next) = bytes.get(index + 1) {
-                if is_valid_var_start_byte(*next) {
-                    let variable_start = index + 1;
-                    let mut variable_end = variable_start + 1;
-                    while variable_end < bytes.len() && is_valid_var_char_byte(bytes[variable_end])
-                    {
-                        variable_end += 1;
-                    }
-                    let variable = std::str::from_utf8(&bytes[variable_start..variable_end])
-                        .expect("validated environment name is ASCII");
-                    let value = env_resource_value(variable, option_name)?;
-                    output.extend_from_slice(value.as_os_str().as_bytes());
-                    display.push(b'$');
-                    display.extend_from_slice(variable.as_bytes());
-                    changed_display = true;
-                    index = variable_end;
-                    continue;
+            if let Some(next) = bytes.get(index + 1)
+                && is_valid_var_start_byte(*next)
+            {
+                let variable_start = index + 1;
+                let mut variable_end = variable_start + 1;
+                while variable_end < bytes.len() && is_valid_var_char_byte(bytes[variable_end]) {
+                    variable_end += 1;
                 }
+                let variable = std::str::from_utf8(&bytes[variable_start..variable_end])
+                    .expect("validated environment name is ASCII");
+                let value = env_resource_value(variable, option_name)?;
+                output.extend_from_slice(value.as_os_str().as_bytes());
+                display.push(b'$');
+                display.extend_from_slice(variable.as_bytes());
+                changed_display = true;
+                index = variable_end;
+                continue;
             }
 
             output.push(b'$');
@@ -2731,13 +3243,13 @@ fn parse_verbose_level(argv: &[OsString]) -> Result<u8, clap::Error> {
         }
 
         if arg.starts_with('-') && arg.len() > 1 {
-            if parse_short_verbose_cluster(&arg, &mut verbosity)? {
-                if let Some(next) = args.peek() {
-                    let next = next.to_string_lossy();
-                    if is_verbose_numeric_token(&next) {
-                        verbosity = parse_verbose_numeric_value(&next)?;
-                        args.next();
-                    }
+            if parse_short_verbose_cluster(&arg, &mut verbosity)?
+                && let Some(next) = args.peek()
+            {
+                let next = next.to_string_lossy();
+                if is_verbose_numeric_token(&next) {
+                    verbosity = parse_verbose_numeric_value(&next)?;
+                    args.next();
                 }
             }
             if short_option_cluster_consumes_value(&arg, &mut OperationMode::Stow) {
@@ -2917,6 +3429,7 @@ fn parse_operation_groups(argv: &[OsString]) -> Vec<OperationGroup> {
     let mut expecting_option_value = false;
     let mut expecting_verbose_value = false;
     let mut after_double_dash = false;
+    let mut packages_from_count = 0;
 
     for arg in argv.iter().skip(1) {
         let arg = arg.to_string_lossy();
@@ -2943,6 +3456,26 @@ fn parse_operation_groups(argv: &[OsString]) -> Vec<OperationGroup> {
             continue;
         }
 
+        // Packages selected by these options belong to the operation they appear in.
+        match arg
+            .strip_prefix("--")
+            .and_then(|token| resolve_long_option(token.split('=').next().unwrap_or(token)).ok())
+            .map(|spec| spec.canonical)
+        {
+            Some("all") => {
+                push_package_operation(&mut groups, current_mode, ALL_MARKER.to_string());
+            },
+            Some("packages-from") => {
+                push_package_operation(
+                    &mut groups,
+                    current_mode,
+                    packages_from_marker(packages_from_count),
+                );
+                packages_from_count += 1;
+            },
+            _ => {},
+        }
+
         if is_option_requiring_separate_value(&arg) {
             expecting_option_value = true;
             continue;
@@ -2984,11 +3517,11 @@ fn parse_operation_groups(argv: &[OsString]) -> Vec<OperationGroup> {
 }
 
 fn push_package_operation(groups: &mut Vec<OperationGroup>, mode: OperationMode, package: String) {
-    if let Some(last_group) = groups.last_mut() {
-        if last_group.mode == mode {
-            last_group.packages.push(package);
-            return;
-        }
+    if let Some(last_group) = groups.last_mut()
+        && last_group.mode == mode
+    {
+        last_group.packages.push(package);
+        return;
     }
 
     groups.push(OperationGroup {
@@ -3307,6 +3840,11 @@ mod tests {
                 "dir",
                 LongOptionKind::Value(ResourceValueOption::Dir),
             ),
+            (
+                "stow-path",
+                "stow-path",
+                LongOptionKind::Value(ResourceValueOption::StowPath),
+            ),
             ("stow", "stow", LongOptionKind::Mode(OperationMode::Stow)),
             (
                 "delete",
@@ -3319,7 +3857,28 @@ mod tests {
                 LongOptionKind::Mode(OperationMode::Restow),
             ),
             ("adopt", "adopt", LongOptionKind::Bool),
+            ("git-commit", "git-commit", LongOptionKind::Bool),
             ("no-folding", "no-folding", LongOptionKind::Bool),
+            (
+                "no-fold",
+                "no-fold",
+                LongOptionKind::Value(ResourceValueOption::NoFold),
+            ),
+            (
+                "dir-mode",
+                "dir-mode",
+                LongOptionKind::Value(ResourceValueOption::DirMode),
+            ),
+            (
+                "sensitive",
+                "sensitive",
+                LongOptionKind::Value(ResourceValueOption::Sensitive),
+            ),
+            (
+                "insecure-permissions",
+                "insecure-permissions",
+                LongOptionKind::Value(ResourceValueOption::InsecurePermissions),
+            ),
             ("dotfiles", "dotfiles", LongOptionKind::Bool),
             ("compat", "compat", LongOptionKind::Bool),
             (
@@ -3340,6 +3899,73 @@ mod tests {
             ("simulate", "simulate", LongOptionKind::Bool),
             ("no", "simulate", LongOptionKind::Bool),
             ("verbose", "verbose", LongOptionKind::Verbose),
+            (
+                "log",
+                "log",
+                LongOptionKind::Value(ResourceValueOption::Log),
+            ),
+            (
+                "log-file",
+                "log-file",
+                LongOptionKind::Value(ResourceValueOption::LogFile),
+            ),
+            (
+                "lock-timeout",
+                "lock-timeout",
+                LongOptionKind::Value(ResourceValueOption::LockTimeout),
+            ),
+            (
+                "plan-out",
+                "plan-out",
+                LongOptionKind::Value(ResourceValueOption::PlanOut),
+            ),
+            (
+                "apply",
+                "apply",
+                LongOptionKind::Value(ResourceValueOption::Apply),
+            ),
+            ("watch", "watch", LongOptionKind::Bool),
+            ("check", "check", LongOptionKind::Bool),
+            ("incremental", "incremental", LongOptionKind::Bool),
+            ("force", "force", LongOptionKind::Bool),
+            ("report-unmanaged", "report-unmanaged", LongOptionKind::Bool),
+            ("unfold-unmanaged", "unfold-unmanaged", LongOptionKind::Bool),
+            (
+                "unfold",
+                "unfold",
+                LongOptionKind::Value(ResourceValueOption::Unfold),
+            ),
+            (
+                "refold",
+                "refold",
+                LongOptionKind::Value(ResourceValueOption::Refold),
+            ),
+            (
+                "add",
+                "add",
+                LongOptionKind::Value(ResourceValueOption::Add),
+            ),
+            ("all", "all", LongOptionKind::Bool),
+            (
+                "packages-from",
+                "packages-from",
+                LongOptionKind::Value(ResourceValueOption::PackagesFrom),
+            ),
+            (
+                "exclude-package",
+                "exclude-package",
+                LongOptionKind::Value(ResourceValueOption::ExcludePackage),
+            ),
+            (
+                "completions",
+                "completions",
+                LongOptionKind::Value(ResourceValueOption::Completions),
+            ),
+            (
+                "complete-packages",
+                "complete-packages",
+                LongOptionKind::Value(ResourceValueOption::CompletePackages),
+            ),
             ("help", "help", LongOptionKind::Help),
             ("version", "version", LongOptionKind::Version),
         ];
@@ -3535,6 +4161,14 @@ mod tests {
         assert!(args.no_folding);
         assert_eq!(args.packages, vec!["mypackage"]);
 
+        let args = Args::parse_from(["rustow", "--no-fold=^\\.config$", "mypackage"]);
+        assert_eq!(args.no_fold, vec!["^\\.config$"]);
+        assert!(!args.no_folding);
+
+        let args = Args::parse_from(["rustow", "--ad", "mypackage"]);
+        assert!(args.adopt);
+        assert_eq!(args.add, None);
+
         let error = Args::try_parse_from(["rustow", "--ver", "mypackage"]).unwrap_err();
         assert!(
             error
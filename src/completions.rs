@@ -0,0 +1,623 @@
+//! Shell completion scripts and the package lists they complete from.
+//!
+//! The scripts are generated from the option table the parser uses, so they know every option,
+//! its abbreviations and whether it takes a value. Package names are not baked in: the scripts
+//! run `rustow --complete-packages=SET` with the `-d` and `-t` values typed so far, which
+//! resolves the stow directory like any other invocation, `STOW_DIR` and `.stowrc` included.
+
+use crate::config::Config;
+use crate::error::RustowError;
+use crate::fs_utils;
+use crate::selection;
+use crate::stow;
+use std::collections::BTreeSet;
+
+/// Shells `--completions` can generate a script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Packages `--complete-packages` lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PackageCompletion {
+    /// Every package in the stow directory.
+    All,
+    /// Packages with at least one link in the target directory.
+    Stowed,
+}
+
+/// What a completion script completes after an option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OptionRole {
+    Flag,
+    Stow,
+    Delete,
+    Restow,
+    /// The target directory, passed on to `--complete-packages`.
+    Target,
+    /// The stow directory, passed on to `--complete-packages`.
+    Dir,
+    Directory,
+    File,
+    /// The package `--add` moves paths into; the positional arguments become paths.
+    Add,
+    Package,
+    Shell,
+    Text,
+}
+
+impl OptionRole {
+    /// The word the scripts use for the role.
+    fn word(self) -> &'static str {
+        match self {
+            Self::Flag => "flag",
+            Self::Stow => "stow",
+            Self::Delete => "delete",
+            Self::Restow => "restow",
+            Self::Target => "target",
+            Self::Dir => "dir",
+            Self::Directory => "directory",
+            Self::File => "file",
+            Self::Add => "add",
+            Self::Package => "package",
+            Self::Shell => "shell",
+            Self::Text => "value",
+        }
+    }
+}
+
+/// A long option as the completion scripts offer and recognize it.
+#[derive(Debug, Clone)]
+pub(crate) struct CompletionOption {
+    pub(crate) name: &'static str,
+    pub(crate) short: Option<char>,
+    pub(crate) help: String,
+    pub(crate) role: OptionRole,
+    /// Every spelling the parser resolves to this option, the full name first.
+    pub(crate) spellings: Vec<String>,
+}
+
+/// Completion script for `shell`.
+pub(crate) fn script(shell: CompletionShell) -> String {
+    let options = crate::cli::completion_options();
+    match shell {
+        CompletionShell::Bash => bash_script(&options),
+        CompletionShell::Zsh => zsh_script(&options),
+        CompletionShell::Fish => fish_script(&options),
+    }
+}
+
+/// Names of the packages in `set`, sorted.
+///
+/// Hidden directories such as `.git` are left out, as nobody stows them.
+pub(crate) fn package_names(
+    config: &Config,
+    set: PackageCompletion,
+) -> Result<Vec<String>, RustowError> {
+    let packages = selection::package_names(config)?;
+    if set == PackageCompletion::All {
+        return Ok(packages);
+    }
+
+    let mut config = config.clone();
+    config.packages = packages;
+    let mut stowed = BTreeSet::new();
+    for link in stow::package_symlinks_in_target(&config)? {
+        if let Some((package, _)) = fs_utils::is_stow_symlink(&link, &config.stow_dirs())? {
+            stowed.insert(package);
+        }
+    }
+    Ok(stowed.into_iter().collect())
+}
+
+/// Case arms mapping every long option spelling that takes a value or sets the mode to its role.
+fn shell_kind_cases(options: &[CompletionOption], indent: &str) -> String {
+    let mut cases = String::new();
+    for option in options
+        .iter()
+        .filter(|option| option.role != OptionRole::Flag)
+    {
+        let patterns = option
+            .spellings
+            .iter()
+            .map(|spelling| format!("--{}", spelling))
+            .collect::<Vec<_>>();
+        cases.push_str(&format!(
+            "{}{}) _rustow_kind={} ;;\n",
+            indent,
+            patterns.join("|"),
+            option.role.word()
+        ));
+    }
+    cases
+}
+
+fn option_words(options: &[CompletionOption]) -> String {
+    let mut words = options
+        .iter()
+        .map(|option| format!("--{}", option.name))
+        .collect::<Vec<_>>();
+    words.extend(
+        options
+            .iter()
+            .filter_map(|option| option.short.map(|short| format!("-{}", short))),
+    );
+    words.join(" ")
+}
+
+/// Quotes `value` for a POSIX or fish shell single-quoted string.
+fn single_quote(value: &str, fish: bool) -> String {
+    if fish {
+        format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+fn bash_script(options: &[CompletionOption]) -> String {
+    format!(
+        "# bash completion for rustow\n\n{}{}",
+        shared_posix_functions(options),
+        BASH_MAIN.replace("@OPTIONS@", &option_words(options))
+    )
+}
+
+fn zsh_script(options: &[CompletionOption]) -> String {
+    let mut described = String::new();
+    for option in options {
+        let help = option.help.replace(':', "\\:");
+        described.push_str(&format!(
+            "            {}\n",
+            single_quote(&format!("--{}:{}", option.name, help), false)
+        ));
+        if let Some(short) = option.short {
+            described.push_str(&format!(
+                "            {}\n",
+                single_quote(&format!("-{}:{}", short, help), false)
+            ));
+        }
+    }
+    format!(
+        "#compdef rustow\n\n{}{}",
+        shared_posix_functions(options),
+        ZSH_MAIN.replace("@OPTIONS@\n", &described)
+    )
+}
+
+fn fish_script(options: &[CompletionOption]) -> String {
+    let mut kind_cases = String::new();
+    for option in options
+        .iter()
+        .filter(|option| option.role != OptionRole::Flag)
+    {
+        let patterns = option
+            .spellings
+            .iter()
+            .map(|spelling| format!("--{}", spelling))
+            .collect::<Vec<_>>();
+        kind_cases.push_str(&format!(
+            "        case {}\n            echo {}\n",
+            patterns.join(" "),
+            option.role.word()
+        ));
+    }
+
+    let mut completions = String::new();
+    for option in options {
+        let mut line = String::from("complete -c rustow");
+        if let Some(short) = option.short {
+            line.push_str(&format!(" -s {}", short));
+        }
+        line.push_str(&format!(
+            " -l {} -d {}",
+            option.name,
+            single_quote(&option.help, true)
+        ));
+        line.push_str(match option.role {
+            OptionRole::Target | OptionRole::Dir | OptionRole::Directory => {
+                " -x -a '(__fish_complete_directories (commandline -ct))'"
+            },
+            OptionRole::File => " -r -F",
+            OptionRole::Add | OptionRole::Package => " -x -a '(__rustow_packages all)'",
+            OptionRole::Shell => " -x -a 'bash zsh fish'",
+            OptionRole::Text => " -x",
+            OptionRole::Flag | OptionRole::Stow | OptionRole::Delete | OptionRole::Restow => "",
+        });
+        completions.push_str(&line);
+        completions.push('\n');
+    }
+
+    format!(
+        "# fish completion for rustow\n\n{}",
+        FISH_SCRIPT
+            .replace("@KIND_CASES@\n", &kind_cases)
+            .replace("@OPTIONS@\n", &completions)
+    )
+}
+
+/// Functions the bash and zsh scripts share; both shells run them unchanged.
+fn shared_posix_functions(options: &[CompletionOption]) -> String {
+    SHARED_POSIX_FUNCTIONS.replace("@KIND_CASES@\n", &shell_kind_cases(options, "        "))
+}
+
+const SHARED_POSIX_FUNCTIONS: &str = r#"# Sets _rustow_kind to what the long option $1, possibly abbreviated, expects.
+_rustow_option_kind() {
+    case "$1" in
+@KIND_CASES@
+        *) _rustow_kind=flag ;;
+    esac
+}
+
+_rustow_take_value() {
+    case "$1" in
+        target) _rustow_target=$2 ;;
+        dir) _rustow_dir=$2 ;;
+        add) _rustow_adding=1 ;;
+    esac
+}
+
+# Reads the words before the one being completed: the operation mode in effect, the stow and
+# target directories, and whether the next word is an option value.
+_rustow_scan() {
+    _rustow_mode=stow
+    _rustow_dir=
+    _rustow_target=
+    _rustow_adding=
+    _rustow_pending=
+    _rustow_positional_only=
+    local word rest
+    for word in "$@"; do
+        if [[ -n $_rustow_positional_only ]]; then
+            continue
+        elif [[ -n $_rustow_pending ]]; then
+            _rustow_take_value "$_rustow_pending" "$word"
+            _rustow_pending=
+            continue
+        fi
+        case "$word" in
+            --)
+                _rustow_positional_only=1 ;;
+            --*=*)
+                _rustow_option_kind "${word%%=*}"
+                _rustow_take_value "$_rustow_kind" "${word#*=}" ;;
+            --*)
+                _rustow_option_kind "$word"
+                case $_rustow_kind in
+                    stow|delete|restow) _rustow_mode=$_rustow_kind ;;
+                    flag) ;;
+                    *) _rustow_pending=$_rustow_kind ;;
+                esac ;;
+            -?*)
+                rest=${word#-}
+                while [[ -n $rest ]]; do
+                    case ${rest:0:1} in
+                        S) _rustow_mode=stow ;;
+                        D) _rustow_mode=delete ;;
+                        R) _rustow_mode=restow ;;
+                        t|d)
+                            [[ ${rest:0:1} == t ]] && _rustow_kind=target || _rustow_kind=dir
+                            if [[ -n ${rest:1} ]]; then
+                                _rustow_take_value "$_rustow_kind" "${rest:1}"
+                            else
+                                _rustow_pending=$_rustow_kind
+                            fi
+                            break ;;
+                    esac
+                    rest=${rest:1}
+                done ;;
+        esac
+    done
+}
+
+# Lists the packages in set $1, all or stowed, for the stow and target directories typed so far.
+_rustow_packages() {
+    local args=()
+    [[ -n $_rustow_dir ]] && args+=("--dir=${_rustow_dir/#\~/$HOME}")
+    [[ -n $_rustow_target ]] && args+=("--target=${_rustow_target/#\~/$HOME}")
+    "$_rustow_program" --complete-packages="$1" "${args[@]}" 2>/dev/null
+}
+"#;
+
+const BASH_MAIN: &str = r#"
+_rustow_complete_value() {
+    case "$1" in
+        target|dir|directory)
+            compopt -o filenames 2>/dev/null
+            COMPREPLY=($(compgen -d -- "$2")) ;;
+        file)
+            compopt -o filenames 2>/dev/null
+            COMPREPLY=($(compgen -f -- "$2")) ;;
+        add|package)
+            COMPREPLY=($(compgen -W "$(_rustow_packages all)" -- "$2")) ;;
+        shell)
+            COMPREPLY=($(compgen -W "bash zsh fish" -- "$2")) ;;
+        *)
+            COMPREPLY=() ;;
+    esac
+}
+
+_rustow() {
+    local words=() word cur i last
+    # Bash splits "--option=value" at the "="; put it back together.
+    for ((i = 1; i <= COMP_CWORD; i++)); do
+        word=${COMP_WORDS[i]}
+        last=$((${#words[@]} - 1))
+        if [[ $last -ge 0 && ${words[last]} == --* && ${words[last]} != *=* && $word == = ]] ||
+            [[ $last -ge 0 && ${words[last]} == --*= ]]; then
+            words[last]+=$word
+        else
+            words+=("$word")
+        fi
+    done
+    cur=${words[${#words[@]} - 1]}
+    _rustow_program=${COMP_WORDS[0]/#\~/$HOME}
+    _rustow_scan "${words[@]:0:${#words[@]}-1}"
+
+    if [[ -n $_rustow_pending ]]; then
+        _rustow_complete_value "$_rustow_pending" "$cur"
+    elif [[ -z $_rustow_positional_only && $cur == --*=* ]]; then
+        _rustow_option_kind "${cur%%=*}"
+        _rustow_complete_value "$_rustow_kind" "${cur#*=}"
+        if [[ $COMP_WORDBREAKS != *=* ]]; then
+            COMPREPLY=("${COMPREPLY[@]/#/${cur%%=*}=}")
+        fi
+    elif [[ -z $_rustow_positional_only && $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "@OPTIONS@" -- "$cur"))
+    elif [[ -n $_rustow_adding ]]; then
+        compopt -o filenames 2>/dev/null
+        COMPREPLY=($(compgen -f -- "$cur"))
+    elif [[ $_rustow_mode == delete ]]; then
+        COMPREPLY=($(compgen -W "$(_rustow_packages stowed)" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -W "$(_rustow_packages all)" -- "$cur"))
+    fi
+}
+
+complete -F _rustow rustow
+"#;
+
+const ZSH_MAIN: &str = r#"
+_rustow_complete_packages() {
+    local -a packages
+    packages=(${(f)"$(_rustow_packages "$1")"})
+    compadd -- $packages
+}
+
+_rustow_complete_value() {
+    case "$1" in
+        target|dir|directory) _files -/ ;;
+        file) _files ;;
+        add|package) _rustow_complete_packages all ;;
+        shell) compadd -- bash zsh fish ;;
+        *) _message value ;;
+    esac
+}
+
+_rustow() {
+    local cur=${words[CURRENT]}
+    local -a options
+    _rustow_program=${words[1]/#\~/$HOME}
+    _rustow_scan "${(@)words[2,CURRENT-1]}"
+
+    if [[ -n $_rustow_pending ]]; then
+        _rustow_complete_value "$_rustow_pending"
+    elif [[ -z $_rustow_positional_only && $cur == --*=* ]]; then
+        _rustow_option_kind "${cur%%=*}"
+        compset -P '*='
+        _rustow_complete_value "$_rustow_kind"
+    elif [[ -z $_rustow_positional_only && $cur == -* ]]; then
+        options=(
+@OPTIONS@
+        )
+        _describe -t options option options
+    elif [[ -n $_rustow_adding ]]; then
+        _files
+    elif [[ $_rustow_mode == delete ]]; then
+        _rustow_complete_packages stowed
+    else
+        _rustow_complete_packages all
+    fi
+}
+
+if [ "$funcstack[1]" = "_rustow" ]; then
+    _rustow "$@"
+else
+    compdef _rustow rustow
+fi
+"#;
+
+const FISH_SCRIPT: &str = r#"# Prints what the long option $argv[1], possibly abbreviated, expects.
+function __rustow_option_kind
+    switch $argv[1]
+@KIND_CASES@
+        case '*'
+            echo flag
+    end
+end
+
+function __rustow_take_value
+    switch $argv[1]
+        case target
+            set -g __rustow_target $argv[2]
+        case dir
+            set -g __rustow_dir $argv[2]
+        case add
+            set -g __rustow_adding 1
+    end
+end
+
+# Reads the tokens before the one being completed: the operation mode in effect, the stow and
+# target directories, and whether the next token is an option value.
+function __rustow_scan
+    set -g __rustow_mode stow
+    set -g __rustow_dir
+    set -g __rustow_target
+    set -g __rustow_adding
+    set -g __rustow_pending
+    set -g __rustow_positional_only
+    set -l tokens (commandline -opc)
+    set -e tokens[1]
+    for token in $tokens
+        if test -n "$__rustow_positional_only"
+            continue
+        else if test -n "$__rustow_pending"
+            __rustow_take_value $__rustow_pending $token
+            set -g __rustow_pending
+            continue
+        end
+        switch $token
+            case --
+                set -g __rustow_positional_only 1
+            case '--*=*'
+                set -l parts (string split -m 1 = -- $token)
+                __rustow_take_value (__rustow_option_kind $parts[1]) $parts[2]
+            case '--*'
+                set -l kind (__rustow_option_kind $token)
+                switch $kind
+                    case stow delete restow
+                        set -g __rustow_mode $kind
+                    case flag
+                    case '*'
+                        set -g __rustow_pending $kind
+                end
+            case '-?*'
+                set -l rest (string sub -s 2 -- $token)
+                while test -n "$rest"
+                    set -l char (string sub -l 1 -- $rest)
+                    set rest (string sub -s 2 -- $rest)
+                    switch $char
+                        case S
+                            set -g __rustow_mode stow
+                        case D
+                            set -g __rustow_mode delete
+                        case R
+                            set -g __rustow_mode restow
+                        case t d
+                            set -l kind target
+                            test $char = d; and set kind dir
+                            if test -n "$rest"
+                                __rustow_take_value $kind $rest
+                            else
+                                set -g __rustow_pending $kind
+                            end
+                            break
+                    end
+                end
+        end
+    end
+end
+
+# Succeeds when the token being completed is a $argv[1]: option, package, path, directory,
+# package-value or shell.
+function __rustow_needs
+    __rustow_scan
+    set -l token (commandline -ct)
+    set -l needed
+    switch "$__rustow_pending"
+        case target dir directory
+            set needed directory
+        case file
+            set needed path
+        case add package
+            set needed package-value
+        case shell
+            set needed shell
+        case value
+            set needed value
+        case ''
+            if test -z "$__rustow_positional_only"; and string match -q -- '-*' "$token"
+                set needed option
+            else if test -n "$__rustow_adding"
+                set needed path
+            else
+                set needed package
+            end
+    end
+    test "$needed" = $argv[1]
+end
+
+# Lists the packages in set $argv[1], or those the operation mode in effect applies to, for the
+# stow and target directories typed so far.
+function __rustow_packages
+    __rustow_scan
+    set -l kind all
+    if set -q argv[1]
+        set kind $argv[1]
+    else if test "$__rustow_mode" = delete
+        set kind stowed
+    end
+    set -l args
+    test -n "$__rustow_dir"; and set -a args --dir=(string replace -r -- '^~' $HOME $__rustow_dir)
+    test -n "$__rustow_target"; and set -a args --target=(string replace -r -- '^~' $HOME $__rustow_target)
+    set -l program (string replace -r -- '^~' $HOME (commandline -opc)[1])
+    command $program --complete-packages=$kind $args 2>/dev/null
+end
+
+complete -c rustow -f
+@OPTIONS@
+complete -c rustow -n '__rustow_needs package' -a '(__rustow_packages)'
+complete -c rustow -n '__rustow_needs path' -F
+complete -c rustow -n '__rustow_needs directory' -a '(__fish_complete_directories (commandline -ct))'
+complete -c rustow -n '__rustow_needs package-value' -a '(__rustow_packages all)'
+complete -c rustow -n '__rustow_needs shell' -a 'bash zsh fish'
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(name: &str) -> CompletionOption {
+        crate::cli::completion_options()
+            .into_iter()
+            .find(|option| option.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_completion_options_carry_the_abbreviations_the_parser_accepts() {
+        let delete = option("delete");
+        assert_eq!(delete.short, Some('D'));
+        assert_eq!(delete.role, OptionRole::Delete);
+        assert_eq!(delete.spellings, ["delete", "delet", "dele", "del"]);
+        assert_eq!(option("add").spellings, ["add"]);
+        assert_eq!(
+            option("compat").spellings,
+            ["compat", "compa", "comp", "com", "co"]
+        );
+        assert_eq!(option("no").short, None);
+        assert!(
+            crate::cli::completion_options()
+                .iter()
+                .all(|option| option.name != "complete-packages")
+        );
+    }
+
+    #[test]
+    fn test_scripts_offer_every_option_and_complete_packages_dynamically() {
+        for shell in [
+            CompletionShell::Bash,
+            CompletionShell::Zsh,
+            CompletionShell::Fish,
+        ] {
+            let script = script(shell);
+            for option in crate::cli::completion_options() {
+                assert!(
+                    script.contains(&format!("--{}", option.name))
+                        || script.contains(&format!("-l {}", option.name)),
+                    "{:?} script lacks --{}",
+                    shell,
+                    option.name
+                );
+            }
+            assert!(script.contains("--complete-packages="), "{:?}", shell);
+            assert!(
+                !script.contains("@OPTIONS@") && !script.contains("@KIND_CASES@"),
+                "{:?} script has a placeholder left",
+                shell
+            );
+        }
+    }
+}
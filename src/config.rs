@@ -1,43 +1,198 @@
 use crate::cli::{Args, PathDisplayOverride};
 use crate::error::{ConfigError, FsError, Result as RustowResult, RustowError};
+use crate::filesystem;
 use crate::fs_utils; // Import fs_utils
+use crate::lock::DEFAULT_LOCK_TIMEOUT;
+use crate::logging::log_event;
+use crate::sensitive::{DEFAULT_SENSITIVE_PATTERNS, InsecurePermissions};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::env;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StowMode {
     Stow,
     Delete,
     Restow,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageOperation {
     pub mode: StowMode,
     pub packages: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub target_dir: PathBuf,
     pub stow_dir: PathBuf,
+    /// Further stow directories (`--stow-path`), searched for packages after `stow_dir` in this
+    /// order.
+    #[serde(default)]
+    pub stow_path: Vec<PathBuf>,
     pub packages: Vec<String>,
     pub mode: StowMode,
     pub stow: bool,
     pub compat: bool,
     pub adopt: bool,
+    /// Commit files moved into the stow directory when it is a git work tree.
+    #[serde(default)]
+    pub git_commit: bool,
     pub no_folding: bool,
+    /// Directories, relative to the target directory, that are never folded.
+    #[serde(default, with = "regex_patterns")]
+    pub no_fold: Vec<Regex>,
     pub dotfiles: bool,
+    /// `--dir-mode` rules for directories created in the target, in command-line order.
+    #[serde(default)]
+    pub dir_modes: Vec<DirModeRule>,
+    /// Target paths, relative to the target directory, whose package items must be private.
+    #[serde(default = "default_sensitive_patterns", with = "regex_patterns")]
+    pub sensitive: Vec<Regex>,
+    #[serde(default)]
+    pub insecure_permissions: InsecurePermissions,
+    #[serde(with = "regex_patterns")]
     pub overrides: Vec<Regex>,
+    #[serde(with = "regex_patterns")]
     pub defers: Vec<Regex>,
+    #[serde(with = "regex_patterns")]
     pub ignore_patterns: Vec<Regex>,
     pub simulate: bool,
+    /// Skip restowing packages whose `--incremental` fingerprint is unchanged.
+    #[serde(default)]
+    pub incremental: bool,
+    /// Restow every package even with `incremental`.
+    #[serde(default)]
+    pub force: bool,
+    /// How long to wait for another run to release the target and stow directories.
+    #[serde(default = "default_lock_timeout")]
+    pub lock_timeout: Duration,
     pub verbosity: u8,
     pub home_dir: PathBuf,
 }
 
+/// Permission bits for a directory rustow creates in the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirMode {
+    /// The mode of the package directory the target directory stands for.
+    Package,
+    Bits(u32),
+}
+
+impl std::str::FromStr for DirMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "package" {
+            return Ok(Self::Package);
+        }
+        match u32::from_str_radix(value, 8) {
+            Ok(bits) if (3..=4).contains(&value.len()) && bits <= 0o7777 => Ok(Self::Bits(bits)),
+            _ => Err(format!(
+                "'{}' is neither 'package' nor an octal mode such as 0700",
+                value
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for DirMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Package => f.write_str("package"),
+            Self::Bits(bits) => write!(f, "{:04o}", bits),
+        }
+    }
+}
+
+/// A `--dir-mode` rule, `[REGEX=]MODE`.
+///
+/// Without `REGEX` it applies to every directory created in the target; with it, only to those
+/// whose path relative to the target matches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DirModeRule {
+    pub pattern: Option<Regex>,
+    pub mode: DirMode,
+}
+
+impl std::str::FromStr for DirModeRule {
+    type Err = ConfigError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| {
+            ConfigError::InvalidDirMode(format!("Invalid --dir-mode '{}': {}", rule, reason))
+        };
+        let Some((pattern, mode)) = rule.rsplit_once('=') else {
+            return Ok(Self {
+                pattern: None,
+                mode: rule.parse().map_err(invalid)?,
+            });
+        };
+        Ok(Self {
+            pattern: Some(Regex::new(pattern).map_err(|e| invalid(e.to_string()))?),
+            mode: mode.parse().map_err(invalid)?,
+        })
+    }
+}
+
+impl TryFrom<String> for DirModeRule {
+    type Error = ConfigError;
+
+    fn try_from(rule: String) -> Result<Self, Self::Error> {
+        rule.parse()
+    }
+}
+
+impl From<DirModeRule> for String {
+    fn from(rule: DirModeRule) -> Self {
+        match rule.pattern {
+            Some(pattern) => format!("{}={}", pattern.as_str(), rule.mode),
+            None => rule.mode.to_string(),
+        }
+    }
+}
+
 impl Config {
+    /// `stow_dir` followed by `stow_path`, in precedence order.
+    pub fn stow_dirs(&self) -> Vec<&Path> {
+        std::iter::once(self.stow_dir.as_path())
+            .chain(self.stow_path.iter().map(PathBuf::as_path))
+            .collect()
+    }
+
+    /// The stow directory `package` is taken from: the first of [`Self::stow_dirs`] that holds
+    /// it, else `stow_dir`.
+    pub fn package_stow_dir(&self, package: &str) -> &Path {
+        self.stow_dirs()
+            .into_iter()
+            .find(|stow_dir| filesystem::current().lstat(&stow_dir.join(package)).is_ok())
+            .unwrap_or(&self.stow_dir)
+    }
+
+    /// The directory of `package` in the stow directory it is taken from.
+    pub fn package_dir(&self, package: &str) -> PathBuf {
+        self.package_stow_dir(package).join(package)
+    }
+
+    /// Mode for a directory created at `target_relative_path`: the last `--dir-mode` rule whose
+    /// pattern matches, else the last rule without a pattern.
+    pub(crate) fn dir_mode_for(&self, target_relative_path: &Path) -> Option<DirMode> {
+        let path = target_relative_path.to_string_lossy();
+        let rules = self.dir_modes.iter().rev();
+        rules
+            .clone()
+            .find(|rule| {
+                rule.pattern
+                    .as_ref()
+                    .is_some_and(|pattern| pattern.is_match(&path))
+            })
+            .or_else(|| rules.clone().find(|rule| rule.pattern.is_none()))
+            .map(|rule| rule.mode)
+    }
+
     pub fn from_args(args: Args) -> RustowResult<Self> {
         let mut path_displays = Vec::new();
         Self::from_args_with_path_displays(args, &mut path_displays)
@@ -52,11 +207,26 @@ impl Config {
         let stow_dir_path_unresolved = unresolved_stow_dir(&args)?;
         let stow_dir_display = crate::cli::path_display(&stow_dir_path_unresolved, path_displays);
         let stow_dir = canonicalize_stow_dir(&stow_dir_path_unresolved, &stow_dir_display)?;
+        log_event!(Config, Debug, "stow directory: {}", stow_dir_display);
         path_displays.push(PathDisplayOverride::new(
             stow_dir.clone(),
             stow_dir_display.clone(),
         ));
 
+        let mut stow_path = Vec::new();
+        for path in args.stow_path.iter().flat_map(env::split_paths) {
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            let display = crate::cli::path_display(&path, path_displays);
+            let dir = canonicalize_stow_dir(&path, &display)?;
+            if dir != stow_dir && !stow_path.contains(&dir) {
+                log_event!(Config, Debug, "further stow directory: {}", display);
+                path_displays.push(PathDisplayOverride::new(dir.clone(), display));
+                stow_path.push(dir);
+            }
+        }
+
         let (target_dir_path_unresolved, target_dir_display) = unresolved_target_dir(
             &args,
             &stow_dir,
@@ -65,6 +235,7 @@ impl Config {
             path_displays,
         )?;
         let target_dir = canonicalize_target_dir(&target_dir_path_unresolved, &target_dir_display)?;
+        log_event!(Config, Debug, "target directory: {}", target_dir_display);
         path_displays.push(PathDisplayOverride::new(
             target_dir.clone(),
             target_dir_display,
@@ -79,21 +250,40 @@ impl Config {
         let overrides = compile_regex_patterns(&args.override_conflicts, "--override")?;
         let defers = compile_regex_patterns(&args.defer_conflicts, "--defer")?;
         let ignore_patterns = compile_regex_patterns(&args.ignore_patterns, "--ignore")?;
+        let no_fold = compile_regex_patterns(&args.no_fold, "--no-fold")?;
+        let mut sensitive = default_sensitive_patterns();
+        sensitive.extend(compile_regex_patterns(&args.sensitive, "--sensitive")?);
+        let dir_modes = args
+            .dir_mode
+            .iter()
+            .map(|rule| rule.parse::<DirModeRule>())
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(Self {
             target_dir,
             stow_dir,
+            stow_path,
             packages: args.packages.clone(),
             mode,
             stow: args.stow,
             compat: args.compat,
             adopt: args.adopt,
+            git_commit: args.git_commit,
             no_folding: args.no_folding,
+            no_fold,
             dotfiles: args.dotfiles,
+            dir_modes,
+            sensitive,
+            insecure_permissions: args.insecure_permissions.unwrap_or_default(),
             overrides,
             defers,
             ignore_patterns,
             simulate: args.simulate,
+            incremental: args.incremental,
+            force: args.force,
+            lock_timeout: args
+                .lock_timeout
+                .map_or(DEFAULT_LOCK_TIMEOUT, Duration::from_secs),
             verbosity: args.verbose,
             home_dir,
         })
@@ -114,7 +304,10 @@ fn unresolved_stow_dir(args: &Args) -> RustowResult<PathBuf> {
     match &args.dir {
         Some(path) => Ok(path.clone()),
         None => match env::var("STOW_DIR") {
-            Ok(val) => Ok(PathBuf::from(val)),
+            Ok(val) => {
+                log_event!(Config, Debug, "stow directory taken from STOW_DIR");
+                Ok(PathBuf::from(val))
+            },
             Err(_) => env::current_dir().map_err(|e| {
                 RustowError::Config(ConfigError::InvalidStowDir(format!(
                     "Failed to get current directory for stow_dir: {}",
@@ -157,6 +350,11 @@ fn unresolved_target_dir(
             Ok((path.clone(), display))
         },
         None => {
+            log_event!(
+                Config,
+                Debug,
+                "no target given; using the parent of the stow directory"
+            );
             let path = stow_dir
                 .parent()
                 .ok_or_else(|| {
@@ -214,6 +412,16 @@ fn canonicalize_target_dir(path: &Path, display: &str) -> RustowResult<PathBuf>
 }
 
 fn compile_regex_patterns(patterns: &[String], option_label: &str) -> RustowResult<Vec<Regex>> {
+    if !patterns.is_empty() {
+        log_event!(
+            Config,
+            Trace,
+            "compiling {} {} pattern(s): {:?}",
+            patterns.len(),
+            option_label,
+            patterns
+        );
+    }
     patterns
         .iter()
         .map(|pattern| {
@@ -227,6 +435,39 @@ fn compile_regex_patterns(patterns: &[String], option_label: &str) -> RustowResu
         .collect()
 }
 
+fn default_lock_timeout() -> Duration {
+    DEFAULT_LOCK_TIMEOUT
+}
+
+fn default_sensitive_patterns() -> Vec<Regex> {
+    DEFAULT_SENSITIVE_PATTERNS
+        .iter()
+        .map(|pattern| Regex::new(pattern).expect("default sensitive patterns are valid"))
+        .collect()
+}
+
+/// Serializes compiled patterns as their source strings so saved plans can rebuild them.
+mod regex_patterns {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        patterns: &[Regex],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(patterns.iter().map(Regex::as_str))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Regex>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
 fn display_parent(display: &str) -> Option<String> {
     let parent = Path::new(display).parent()?;
     let parent_display = parent.display().to_string();
@@ -804,4 +1045,46 @@ mod tests {
         assert!(config.adopt);
         assert_eq!(config.verbosity, 1);
     }
+
+    #[test]
+    fn test_dir_mode_rules_prefer_the_last_matching_pattern() {
+        let rule = |rule: &str| rule.parse::<DirModeRule>().unwrap();
+        let config = Config {
+            dir_modes: vec![
+                rule("0750"),
+                rule(r"^\.ssh(/|$)=package"),
+                rule(r"^\.ssh/sockets$=0711"),
+                rule("0755"),
+            ],
+            ..Config::from_args(Args::parse_from(["rustow", "--dir=/", "--target=/", "pkg"]))
+                .unwrap()
+        };
+
+        assert_eq!(
+            config.dir_mode_for(Path::new(".config")),
+            Some(DirMode::Bits(0o755))
+        );
+        assert_eq!(
+            config.dir_mode_for(Path::new(".ssh")),
+            Some(DirMode::Package)
+        );
+        assert_eq!(
+            config.dir_mode_for(Path::new(".ssh/sockets")),
+            Some(DirMode::Bits(0o711))
+        );
+        assert_eq!(
+            String::from(rule(r"^\.ssh/sockets$=711")),
+            r"^\.ssh/sockets$=0711"
+        );
+        for invalid in ["", "755x", "0999", "07777777", "(=0700", "^x$=rwx"] {
+            assert!(
+                matches!(
+                    invalid.parse::<DirModeRule>(),
+                    Err(ConfigError::InvalidDirMode(_))
+                ),
+                "{:?} should be rejected",
+                invalid
+            );
+        }
+    }
 }
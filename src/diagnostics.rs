@@ -1,7 +1,14 @@
+use crate::check::Drift;
 use crate::cli::PathDisplayOverride;
 use crate::config::Config;
 use crate::error::{ConfigError, FsError, IgnoreError, RustowError, StowError};
-use crate::stow::{TargetActionReport, TargetActionReportStatus};
+use crate::lock::StaleLock;
+use crate::plan_file::PathFingerprint;
+use crate::sensitive::InsecurePath;
+use crate::stow::{
+    ActionType, LooserDirectory, TargetActionReport, TargetActionReportStatus, UnmappedLink,
+};
+use crate::unmanaged::FoldedDirectory;
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 
@@ -10,6 +17,12 @@ pub(crate) struct RedactionTable {
 }
 
 impl RedactionTable {
+    pub(crate) const fn empty() -> Self {
+        Self {
+            replacements: Vec::new(),
+        }
+    }
+
     pub(crate) fn new(path_displays: &[PathDisplayOverride]) -> Self {
         let mut replacements: Vec<(String, String)> = Vec::new();
         for override_path in path_displays {
@@ -30,7 +43,7 @@ impl RedactionTable {
                 add_redaction_replacement(&mut replacements, debug_path, debug_display);
             }
         }
-        replacements.sort_by(|(left, _), (right, _)| right.len().cmp(&left.len()));
+        replacements.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
 
         Self { replacements }
     }
@@ -75,17 +88,17 @@ pub(crate) fn process_reports(
     for report in reports {
         match &report.status {
             TargetActionReportStatus::Success => {
-                if config.verbosity > 1 || config.simulate {
-                    if let Some(message) = &report.message {
-                        eprintln!("{}", redactions.redact(message));
-                    }
+                if (config.verbosity > 1 || config.simulate)
+                    && let Some(message) = &report.message
+                {
+                    eprintln!("{}", redactions.redact(message));
                 }
             },
             TargetActionReportStatus::Skipped => {
-                if config.verbosity > 0 || config.simulate {
-                    if let Some(message) = &report.message {
-                        eprintln!("{}", redactions.redact(message));
-                    }
+                if (config.verbosity > 0 || config.simulate)
+                    && let Some(message) = &report.message
+                {
+                    eprintln!("{}", redactions.redact(message));
                 }
             },
             TargetActionReportStatus::ConflictPrevented => {
@@ -127,6 +140,184 @@ pub(crate) fn process_reports(
     }
 }
 
+pub(crate) fn process_final_state(
+    final_state: &[PathFingerprint],
+    path_displays: &[PathDisplayOverride],
+) {
+    if final_state.is_empty() {
+        return;
+    }
+
+    let redactions = RedactionTable::new(path_displays);
+    eprintln!("\nSIMULATE: Final state of touched paths:");
+    for fingerprint in final_state {
+        eprintln!(
+            "  {}: {}",
+            redactions.redact(&fingerprint.path.display().to_string()),
+            redactions.redact(&fingerprint.state.to_string())
+        );
+    }
+}
+
+/// Explains why `--plan-out` left `plan_path` unwritten.
+pub(crate) fn process_unwritten_plan(plan_path: &Path, path_displays: &[PathDisplayOverride]) {
+    let redactions = RedactionTable::new(path_displays);
+    eprintln!(
+        "ERROR: plan not written to {}: resolve the conflicts above first",
+        redactions.redact(&plan_path.display().to_string())
+    );
+}
+
+pub(crate) fn process_drift(
+    drift: &[Drift],
+    config: &Config,
+    path_displays: &[PathDisplayOverride],
+) {
+    let redactions = RedactionTable::new(path_displays);
+    for difference in drift {
+        eprintln!("CHECK: {}", redactions.redact(&difference.to_string()));
+    }
+
+    if !drift.is_empty() {
+        eprintln!("CHECK: {} difference(s) found", drift.len());
+    } else if config.verbosity > 0 {
+        eprintln!("CHECK: target is up to date");
+    }
+}
+
+/// Lists the links and directories an `--unfold` or `--refold` changed.
+pub(crate) fn process_fold_changes(
+    reports: &[TargetActionReport],
+    path_displays: &[PathDisplayOverride],
+) {
+    let redactions = RedactionTable::new(path_displays);
+    for report in reports {
+        if !matches!(report.status, TargetActionReportStatus::Success) {
+            continue;
+        }
+        let action = &report.original_action;
+        let path = redactions
+            .redact(&action.target_path.display().to_string())
+            .into_owned();
+        match (&action.action_type, &action.link_target_path) {
+            (ActionType::CreateSymlink, Some(link_target)) => eprintln!(
+                "LINK: {} -> {}",
+                path,
+                redactions.redact(&link_target.display().to_string())
+            ),
+            (ActionType::DeleteSymlink, _) => eprintln!("UNLINK: {}", path),
+            (ActionType::CreateDirectory, _) => eprintln!("MKDIR: {}", path),
+            (ActionType::DeleteDirectory, _) => eprintln!("RMDIR: {}", path),
+            _ => {},
+        }
+    }
+}
+
+pub(crate) fn process_unmapped_links(
+    unmapped: &[UnmappedLink],
+    path_displays: &[PathDisplayOverride],
+) {
+    let redactions = RedactionTable::new(path_displays);
+    for link in unmapped {
+        eprintln!(
+            "RELINK: cannot relink {} -> {}: {}",
+            redactions.redact(&link.link.display().to_string()),
+            redactions.redact(&link.destination.display().to_string()),
+            redactions.redact(&link.reason)
+        );
+    }
+}
+
+pub(crate) fn process_looser_directories(
+    directories: &[LooserDirectory],
+    path_displays: &[PathDisplayOverride],
+) {
+    let redactions = RedactionTable::new(path_displays);
+    for directory in directories {
+        eprintln!(
+            "PERMISSIONS: {} is {:04o}, looser than {:04o} on {}",
+            redactions.redact(&directory.path.display().to_string()),
+            directory.mode,
+            directory.package_mode,
+            redactions.redact(&directory.package_dir.display().to_string())
+        );
+    }
+}
+
+pub(crate) fn process_insecure_paths(
+    insecure: &[InsecurePath],
+    fix: bool,
+    path_displays: &[PathDisplayOverride],
+) {
+    let redactions = RedactionTable::new(path_displays);
+    for path in insecure {
+        let consequence = if fix {
+            format!("changing it to {:04o}", path.secure_mode())
+        } else {
+            "linked from a sensitive path".to_string()
+        };
+        eprintln!(
+            "INSECURE: {} is {:04o}, {} ({})",
+            redactions.redact(&path.path.display().to_string()),
+            path.mode,
+            consequence,
+            redactions.redact(&path.link.display().to_string())
+        );
+    }
+}
+
+pub(crate) fn process_stale_locks(stale: &[StaleLock], path_displays: &[PathDisplayOverride]) {
+    let redactions = RedactionTable::new(path_displays);
+    for lock in stale {
+        eprintln!(
+            "STALE LOCK: {} was left by PID {}, which did not finish; its changes may be incomplete",
+            redactions.redact(&lock.path.display().to_string()),
+            lock.pid
+        );
+    }
+}
+
+pub(crate) fn process_unmanaged(
+    folded: &[FoldedDirectory],
+    unfolding: bool,
+    config: &Config,
+    path_displays: &[PathDisplayOverride],
+) {
+    let redactions = RedactionTable::new(path_displays);
+    for directory in folded {
+        for relative in &directory.unmanaged {
+            eprintln!(
+                "UNMANAGED: {} (in package '{}')",
+                redactions.redact(&directory.link.join(relative).display().to_string()),
+                directory.package
+            );
+        }
+    }
+
+    let file_count: usize = folded
+        .iter()
+        .map(|directory| directory.unmanaged.len())
+        .sum();
+    if folded.is_empty() {
+        if config.verbosity > 0 {
+            eprintln!("UNMANAGED: no unmanaged files in folded directories");
+        }
+    } else if unfolding {
+        eprintln!(
+            "UNMANAGED: unfolding {} directory(ies) to move {} file(s) into the target",
+            folded.len(),
+            file_count
+        );
+    } else {
+        eprintln!(
+            "UNMANAGED: {} file(s) in {} folded directory(ies); \
+             use --unfold-unmanaged to move them into the target",
+            file_count,
+            folded.len()
+        );
+    }
+}
+
 fn add_redaction_replacement(
     replacements: &mut Vec<(String, String)>,
     path: String,
@@ -275,6 +466,12 @@ fn redact_config_error(error: ConfigError, redactions: &RedactionTable) -> Confi
         ConfigError::InvalidOperation(message) => {
             ConfigError::InvalidOperation(redact_owned_string(message, redactions))
         },
+        ConfigError::InvalidLogFilter(message) => {
+            ConfigError::InvalidLogFilter(redact_owned_string(message, redactions))
+        },
+        ConfigError::InvalidDirMode(message) => {
+            ConfigError::InvalidDirMode(redact_owned_string(message, redactions))
+        },
         ConfigError::InvalidVerbosityLevel(level) => ConfigError::InvalidVerbosityLevel(level),
     }
 }
@@ -293,6 +490,24 @@ fn redact_stow_error(error: StowError, redactions: &RedactionTable) -> StowError
         StowError::OperationFailed(message) => {
             StowError::OperationFailed(redact_owned_string(message, redactions))
         },
+        StowError::InvalidPlanFile(message) => {
+            StowError::InvalidPlanFile(redact_owned_string(message, redactions))
+        },
+        StowError::StalePlan(message) => {
+            StowError::StalePlan(redact_owned_string(message, redactions))
+        },
+        StowError::Git(message) => StowError::Git(redact_owned_string(message, redactions)),
+        StowError::NotManagedDirectory(message) => {
+            StowError::NotManagedDirectory(redact_owned_string(message, redactions))
+        },
+        StowError::CannotAdd(message) => {
+            StowError::CannotAdd(redact_owned_string(message, redactions))
+        },
+        StowError::CannotMove(message) => {
+            StowError::CannotMove(redact_owned_string(message, redactions))
+        },
+        StowError::Locked(message) => StowError::Locked(redact_owned_string(message, redactions)),
+        error @ (StowError::ExecutionStopped { .. } | StowError::UnmappedLinks(_)) => error,
     }
 }
 
@@ -24,6 +24,35 @@ pub fn process_item_name(item_name: &str, is_dotfiles_enabled: bool) -> String {
     processed.to_string_lossy().into_owned()
 }
 
+/// The inverse of [`process_item_name`]: the package path that is stowed as `target_name`.
+///
+/// Returns `None` when no package path maps to `target_name`, such as `dot-foo` with dotfiles
+/// enabled, which a package can only provide as `.foo`.
+pub fn package_item_name(target_name: &str, is_dotfiles_enabled: bool) -> Option<String> {
+    if !is_dotfiles_enabled {
+        return Some(target_name.to_string());
+    }
+
+    let mut package_path = std::path::PathBuf::new();
+    for component in std::path::Path::new(target_name).components() {
+        match component {
+            std::path::Component::Normal(name) => {
+                let name_str = name.to_string_lossy();
+                if name_str.starts_with("dot-") {
+                    return None;
+                }
+                match name_str.strip_prefix('.') {
+                    Some(stripped) => package_path.push(format!("dot-{}", stripped)),
+                    None => package_path.push(name_str.as_ref()),
+                }
+            },
+            other => package_path.push(other.as_os_str()),
+        }
+    }
+
+    Some(package_path.to_string_lossy().into_owned())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,4 +92,21 @@ mod tests {
             ".config/sub/.another"
         );
     }
+
+    #[test]
+    fn test_package_item_name_inverts_process_item_name() {
+        assert_eq!(
+            package_item_name(".config/tmux/tmux.conf", true).as_deref(),
+            Some("dot-config/tmux/tmux.conf")
+        );
+        assert_eq!(
+            package_item_name(".tmux.conf", false).as_deref(),
+            Some(".tmux.conf")
+        );
+        assert_eq!(package_item_name("dot-vimrc", true), None);
+        assert_eq!(
+            process_item_name(&package_item_name("a/.b/c", true).unwrap(), true),
+            "a/.b/c"
+        );
+    }
 }
@@ -38,6 +38,10 @@ pub enum ConfigError {
     InvalidVerbosityLevel(u8),
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
+    #[error("Invalid log filter: {0}")]
+    InvalidLogFilter(String),
+    #[error("Invalid directory mode: {0}")]
+    InvalidDirMode(String),
 }
 
 #[allow(dead_code)]
@@ -51,6 +55,26 @@ pub enum StowError {
     InvalidPackageStructure(String),
     #[error("Operation failed: {0}")]
     OperationFailed(String),
+    #[error(
+        "Operation failed: Execution stopped with {conflicts} conflicts and {failures} failures"
+    )]
+    ExecutionStopped { conflicts: usize, failures: usize },
+    #[error("Invalid plan file: {0}")]
+    InvalidPlanFile(String),
+    #[error("Stale plan: {0}")]
+    StalePlan(String),
+    #[error("Git error: {0}")]
+    Git(String),
+    #[error("Not a managed directory: {0}")]
+    NotManagedDirectory(String),
+    #[error("Cannot add path: {0}")]
+    CannotAdd(String),
+    #[error("Cannot move package item: {0}")]
+    CannotMove(String),
+    #[error("{0} link(s) into the old stow directory could not be relinked")]
+    UnmappedLinks(usize),
+    #[error("Directory locked: {0}")]
+    Locked(String),
 }
 
 #[allow(dead_code)]
@@ -135,6 +159,67 @@ pub enum IgnoreError {
 
 pub type Result<T, E = RustowError> = std::result::Result<T, E>;
 
+/// Process exit statuses of the `rustow` binary.
+///
+/// | Code | Meaning |
+/// |------|---------|
+/// | 0 | Success; with `--simulate`, nothing would change |
+/// | 1 | Runtime error, e.g. an unreadable directory or a stale saved plan |
+/// | 2 | Usage or configuration error, including an invalid package name |
+/// | 3 | A requested package does not exist |
+/// | 4 | Conflicts prevented any change |
+/// | 5 | Some actions failed during execution, or `--relink-from` found links it could not map |
+/// | 6 | `--simulate` found pending changes |
+/// | 7 | `--check` found drift between the target and the requested packages |
+pub mod exit_code {
+    pub const SUCCESS: i32 = 0;
+    pub const RUNTIME_ERROR: i32 = 1;
+    pub const USAGE: i32 = 2;
+    pub const PACKAGE_NOT_FOUND: i32 = 3;
+    pub const CONFLICTS: i32 = 4;
+    pub const PARTIAL_FAILURE: i32 = 5;
+    pub const PENDING_CHANGES: i32 = 6;
+    pub const DRIFT: i32 = 7;
+}
+
+impl RustowError {
+    /// The [`exit_code`] the binary exits with for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RustowError::Config(_)
+            | RustowError::Ignore(_)
+            | RustowError::Cli(_)
+            | RustowError::Regex(_)
+            | RustowError::InvalidPattern(_) => exit_code::USAGE,
+            RustowError::Stow(error) => error.exit_code(),
+            RustowError::Fs(_) | RustowError::Io(_) => exit_code::RUNTIME_ERROR,
+        }
+    }
+}
+
+impl StowError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StowError::PackageNotFound(_) => exit_code::PACKAGE_NOT_FOUND,
+            StowError::ExecutionStopped { failures: 0, .. } | StowError::Conflict(_) => {
+                exit_code::CONFLICTS
+            },
+            StowError::ExecutionStopped { .. } | StowError::UnmappedLinks(_) => {
+                exit_code::PARTIAL_FAILURE
+            },
+            StowError::InvalidPlanFile(_)
+            | StowError::NotManagedDirectory(_)
+            | StowError::CannotAdd(_)
+            | StowError::CannotMove(_) => exit_code::USAGE,
+            StowError::InvalidPackageStructure(_)
+            | StowError::OperationFailed(_)
+            | StowError::StalePlan(_)
+            | StowError::Git(_)
+            | StowError::Locked(_) => exit_code::RUNTIME_ERROR,
+        }
+    }
+}
+
 // PartialEq for FsError variants containing std::io::Error for testing purposes.
 // This compares based on the error kind.
 impl PartialEq for FsError {
@@ -298,6 +383,50 @@ mod tests {
         assert_eq!(err.to_string(), "Invalid ignore pattern: *[invalid");
     }
 
+    #[test]
+    fn test_exit_codes_distinguish_outcome_categories() {
+        assert_eq!(
+            RustowError::from(ConfigError::InvalidTargetDir("t".to_string())).exit_code(),
+            exit_code::USAGE
+        );
+        assert_eq!(
+            RustowError::from(StowError::PackageNotFound("p".to_string())).exit_code(),
+            exit_code::PACKAGE_NOT_FOUND
+        );
+        assert_eq!(
+            RustowError::from(StowError::ExecutionStopped {
+                conflicts: 2,
+                failures: 0
+            })
+            .exit_code(),
+            exit_code::CONFLICTS
+        );
+        assert_eq!(
+            RustowError::from(StowError::ExecutionStopped {
+                conflicts: 0,
+                failures: 1
+            })
+            .exit_code(),
+            exit_code::PARTIAL_FAILURE
+        );
+        assert_eq!(
+            RustowError::from(FsError::NotFound(PathBuf::from("/x"))).exit_code(),
+            exit_code::RUNTIME_ERROR
+        );
+    }
+
+    #[test]
+    fn test_execution_stopped_display_includes_counts() {
+        let err = StowError::ExecutionStopped {
+            conflicts: 1,
+            failures: 2,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Operation failed: Execution stopped with 1 conflicts and 2 failures"
+        );
+    }
+
     #[test]
     fn test_error_conversion() {
         let config_err = ConfigError::InvalidTargetDir("bad path".to_string());
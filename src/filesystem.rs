@@ -0,0 +1,869 @@
+//! Filesystem access used by planning and execution.
+//!
+//! Every filesystem operation rustow performs on the target and stow directories goes through
+//! the [`Filesystem`] trait. [`RealFilesystem`] forwards to the operating system, while
+//! [`MemoryFilesystem`] keeps changes in memory, optionally layered over the real tree. The
+//! filesystem in effect for the current thread is chosen with [`with_filesystem`].
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+/// Maximum number of symlinks followed while resolving a single path.
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    pub kind: FileKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// Permission bits, when the filesystem keeps them.
+    pub mode: Option<u32>,
+}
+
+impl FileStat {
+    fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Directory
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            FileKind::Other
+        };
+
+        Self {
+            kind,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            mode: permission_bits(metadata),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Directory
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.kind == FileKind::Symlink
+    }
+}
+
+#[cfg(unix)]
+fn permission_bits(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn permission_bits(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    path: PathBuf,
+    kind: FileKind,
+}
+
+impl DirEntry {
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    pub fn file_name(&self) -> std::ffi::OsString {
+        self.path.file_name().unwrap_or_default().to_os_string()
+    }
+
+    pub fn kind(&self) -> FileKind {
+        self.kind
+    }
+}
+
+/// Primitive filesystem operations rustow needs to plan and execute actions.
+pub trait Filesystem {
+    /// Metadata for `path` without following a final symlink.
+    fn lstat(&self, path: &Path) -> io::Result<FileStat>;
+
+    /// Metadata for `path`, following symlinks.
+    fn stat(&self, path: &Path) -> io::Result<FileStat> {
+        self.lstat(&self.canonicalize(path)?)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Creates a symlink at `link` pointing to `target`.
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()>;
+
+    fn mkdir(&self, path: &Path) -> io::Result<()>;
+
+    /// Sets the permission bits of `path`, following symlinks.
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()>;
+
+    /// Removes an empty directory.
+    fn rmdir(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes a file or symlink.
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Atomically swaps the entries at `first` and `second`.
+    ///
+    /// Returns [`io::ErrorKind::Unsupported`] when the filesystem cannot do this in one step.
+    fn exchange(&self, _first: &Path, _second: &Path) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    /// Entries of the directory at `path`, sorted by file name.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+/// The operating system's filesystem.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealFilesystem;
+
+impl Filesystem for RealFilesystem {
+    fn lstat(&self, path: &Path) -> io::Result<FileStat> {
+        std::fs::symlink_metadata(path).map(|metadata| FileStat::from_metadata(&metadata))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<FileStat> {
+        std::fs::metadata(path).map(|metadata| FileStat::from_metadata(&metadata))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        std::fs::read_link(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(target, link)
+        }
+        #[cfg(windows)]
+        {
+            if target.is_dir() {
+                std::os::windows::fs::symlink_dir(target, link)
+            } else {
+                std::os::windows::fs::symlink_file(target, link)
+            }
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = (target, link);
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Symlink creation not supported on this platform",
+            ))
+        }
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        std::fs::create_dir(path)
+    }
+
+    #[cfg(unix)]
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn set_permissions(&self, _path: &Path, _mode: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn rmdir(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_dir(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn exchange(&self, first: &Path, second: &Path) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            crate::fs_utils::rename_exchange(first, second)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (first, second);
+            Err(io::Error::from(io::ErrorKind::Unsupported))
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = std::fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let kind = if file_type.is_symlink() {
+                    FileKind::Symlink
+                } else if file_type.is_dir() {
+                    FileKind::Directory
+                } else if file_type.is_file() {
+                    FileKind::File
+                } else {
+                    FileKind::Other
+                };
+                Ok(DirEntry {
+                    path: entry.path(),
+                    kind,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        std::fs::canonicalize(path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    /// A directory. `opaque` directories hide whatever the backing tree has at the same path.
+    Directory {
+        opaque: bool,
+    },
+    File {
+        len: u64,
+        modified: Option<SystemTime>,
+    },
+    Symlink(PathBuf),
+    Other,
+}
+
+impl Node {
+    fn stat(&self) -> FileStat {
+        let (kind, len, modified) = match self {
+            Node::Directory { .. } => (FileKind::Directory, 0, None),
+            Node::File { len, modified } => (FileKind::File, *len, *modified),
+            Node::Symlink(target) => (FileKind::Symlink, target.as_os_str().len() as u64, None),
+            Node::Other => (FileKind::Other, 0, None),
+        };
+        FileStat {
+            kind,
+            len,
+            modified,
+            mode: None,
+        }
+    }
+}
+
+/// An in-memory filesystem, optionally layered over the real one.
+///
+/// As an overlay it reads through to the real tree for every path it has not changed, and
+/// records creations, removals and renames in memory only. Running actions against an overlay
+/// therefore shows exactly what they would do without touching the disk.
+#[derive(Debug, Default)]
+pub struct MemoryFilesystem {
+    /// Changed paths, keyed by their resolved absolute path. `None` marks a removed path.
+    entries: RefCell<BTreeMap<PathBuf, Option<Node>>>,
+    backed_by_real_filesystem: bool,
+}
+
+impl MemoryFilesystem {
+    /// An empty filesystem containing only the root directory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A filesystem that starts out identical to the real one.
+    pub fn overlay() -> Self {
+        Self {
+            entries: RefCell::new(BTreeMap::new()),
+            backed_by_real_filesystem: true,
+        }
+    }
+
+    /// Creates a regular file of `len` bytes. Only metadata is tracked, not contents.
+    pub fn create_file(&self, path: &Path, len: u64) -> io::Result<()> {
+        self.create_node(
+            path,
+            Node::File {
+                len,
+                modified: Some(SystemTime::now()),
+            },
+        )
+    }
+
+    fn create_node(&self, path: &Path, node: Node) -> io::Result<()> {
+        let path = self.resolve_parent(path)?;
+        if self.node(&path)?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        self.entries.borrow_mut().insert(path, Some(node));
+        Ok(())
+    }
+
+    /// Looks up `path`, whose ancestors must already be resolved, without following it.
+    fn node(&self, path: &Path) -> io::Result<Option<Node>> {
+        if path.parent().is_none() {
+            return Ok(Some(Node::Directory { opaque: false }));
+        }
+
+        let entries = self.entries.borrow();
+        if let Some(entry) = entries.get(path) {
+            return Ok(entry.clone());
+        }
+
+        for ancestor in path.ancestors().skip(1) {
+            match entries.get(ancestor) {
+                Some(None) | Some(Some(Node::Directory { opaque: true })) => return Ok(None),
+                Some(Some(Node::Directory { opaque: false })) => break,
+                Some(Some(_)) => return Ok(None),
+                None => {},
+            }
+        }
+        drop(entries);
+
+        if !self.backed_by_real_filesystem {
+            return Ok(None);
+        }
+
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) => {
+                let file_type = metadata.file_type();
+                let node = if file_type.is_symlink() {
+                    Node::Symlink(std::fs::read_link(path)?)
+                } else if file_type.is_dir() {
+                    Node::Directory { opaque: false }
+                } else if file_type.is_file() {
+                    Node::File {
+                        len: metadata.len(),
+                        modified: metadata.modified().ok(),
+                    }
+                } else {
+                    Node::Other
+                };
+                Ok(Some(node))
+            },
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+                ) =>
+            {
+                Ok(None)
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    fn existing_node(&self, path: &Path) -> io::Result<Node> {
+        self.node(path)?.ok_or_else(|| not_found(path))
+    }
+
+    /// Resolves every component of `path`, following symlinks in all of them when
+    /// `follow_last` is set and in all but the last one otherwise.
+    fn resolve(&self, path: &Path, follow_last: bool) -> io::Result<PathBuf> {
+        // Components are processed from a stack so symlink targets can be spliced in front
+        // of the components that remain.
+        let mut remaining = Vec::new();
+        push_components(&absolute_path(path)?, &mut remaining);
+        let mut resolved = PathBuf::from("/");
+        let mut follows = 0;
+
+        while let Some(component) = remaining.pop() {
+            if component == ".." {
+                resolved.pop();
+                continue;
+            }
+            if component == "." || component.is_empty() {
+                continue;
+            }
+
+            let candidate = resolved.join(&component);
+            let is_last = remaining.is_empty();
+            if !is_last || follow_last {
+                if let Some(Node::Symlink(target)) = self.node(&candidate)? {
+                    follows += 1;
+                    if follows > MAX_SYMLINK_FOLLOWS {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("too many levels of symbolic links: {}", path.display()),
+                        ));
+                    }
+                    if target.is_absolute() {
+                        resolved = PathBuf::from("/");
+                    }
+                    push_components(&target, &mut remaining);
+                    continue;
+                }
+
+                if !is_last && !matches!(self.node(&candidate)?, Some(Node::Directory { .. })) {
+                    return Err(not_found(path));
+                }
+            }
+            resolved = candidate;
+        }
+
+        Ok(resolved)
+    }
+
+    /// Resolves the parent of `path` and joins the final component back on.
+    fn resolve_parent(&self, path: &Path) -> io::Result<PathBuf> {
+        let resolved = self.resolve(path, false)?;
+        let parent = resolved.parent().ok_or_else(|| invalid_path(path))?;
+        match self.node(parent)? {
+            Some(Node::Directory { .. }) => Ok(resolved),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{} is not a directory", parent.display()),
+            )),
+            None => Err(not_found(parent)),
+        }
+    }
+
+    fn child_names(&self, directory: &Path) -> io::Result<BTreeSet<std::ffi::OsString>> {
+        let mut names = BTreeSet::new();
+        let opaque = matches!(
+            self.entries.borrow().get(directory),
+            Some(Some(Node::Directory { opaque: true }))
+        );
+
+        if self.backed_by_real_filesystem && !opaque {
+            match std::fs::read_dir(directory) {
+                Ok(entries) => {
+                    for entry in entries {
+                        names.insert(entry?.file_name());
+                    }
+                },
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {},
+                Err(error) => return Err(error),
+            }
+        }
+
+        for path in self.entries.borrow().keys() {
+            if path.parent() == Some(directory)
+                && let Some(name) = path.file_name()
+            {
+                names.insert(name.to_os_string());
+            }
+        }
+
+        let mut present = BTreeSet::new();
+        for name in names {
+            if self.node(&directory.join(&name))?.is_some() {
+                present.insert(name);
+            }
+        }
+        Ok(present)
+    }
+
+    /// Every node at or below `path`, relative to `path`, parents before children.
+    fn subtree(&self, path: &Path) -> io::Result<Vec<(PathBuf, Node)>> {
+        let mut nodes = Vec::new();
+        let mut pending = vec![PathBuf::new()];
+
+        while let Some(relative_path) = pending.pop() {
+            let absolute_path = join_relative(path, &relative_path);
+            let node = match self.existing_node(&absolute_path)? {
+                Node::Directory { .. } => {
+                    for name in self.child_names(&absolute_path)?.into_iter().rev() {
+                        pending.push(relative_path.join(name));
+                    }
+                    Node::Directory { opaque: true }
+                },
+                other => other,
+            };
+            nodes.push((relative_path, node));
+        }
+
+        Ok(nodes)
+    }
+
+    fn remove_subtree(&self, path: &Path) {
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|entry_path, _| entry_path == path || !entry_path.starts_with(path));
+        entries.insert(path.to_path_buf(), None);
+    }
+
+    fn insert_subtree(&self, path: &Path, nodes: Vec<(PathBuf, Node)>) {
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|entry_path, _| !entry_path.starts_with(path));
+        for (relative_path, node) in nodes {
+            entries.insert(join_relative(path, &relative_path), Some(node));
+        }
+    }
+}
+
+impl Filesystem for MemoryFilesystem {
+    fn lstat(&self, path: &Path) -> io::Result<FileStat> {
+        let path = self.resolve(path, false)?;
+        Ok(self.existing_node(&path)?.stat())
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = self.resolve(path, false)?;
+        match self.existing_node(&path)? {
+            Node::Symlink(target) => Ok(target),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a symlink", path.display()),
+            )),
+        }
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        self.create_node(link, Node::Symlink(target.to_path_buf()))
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        self.create_node(path, Node::Directory { opaque: true })
+    }
+
+    /// Permission bits are not tracked in memory; this only checks that `path` exists.
+    fn set_permissions(&self, path: &Path, _mode: u32) -> io::Result<()> {
+        self.stat(path).map(|_| ())
+    }
+
+    fn rmdir(&self, path: &Path) -> io::Result<()> {
+        let path = self.resolve_parent(path)?;
+        if !matches!(self.existing_node(&path)?, Node::Directory { .. }) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{} is not a directory", path.display()),
+            ));
+        }
+        if !self.child_names(&path)?.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                format!("{} is not empty", path.display()),
+            ));
+        }
+
+        self.remove_subtree(&path);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = self.resolve_parent(path)?;
+        if matches!(self.existing_node(&path)?, Node::Directory { .. }) {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{} is a directory", path.display()),
+            ));
+        }
+
+        self.remove_subtree(&path);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let from = self.resolve_parent(from)?;
+        let to = self.resolve_parent(to)?;
+        let from_node = self.existing_node(&from)?;
+        if from == to {
+            return Ok(());
+        }
+        if to.starts_with(&from) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot move {} into itself", from.display()),
+            ));
+        }
+
+        match (&from_node, self.node(&to)?) {
+            (_, None) => {},
+            (Node::Directory { .. }, Some(Node::Directory { .. })) => {
+                if !self.child_names(&to)?.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::DirectoryNotEmpty,
+                        format!("{} is not empty", to.display()),
+                    ));
+                }
+            },
+            (Node::Directory { .. }, Some(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("{} is not a directory", to.display()),
+                ));
+            },
+            (_, Some(Node::Directory { .. })) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::IsADirectory,
+                    format!("{} is a directory", to.display()),
+                ));
+            },
+            (_, Some(_)) => {},
+        }
+
+        let nodes = self.subtree(&from)?;
+        self.remove_subtree(&from);
+        self.insert_subtree(&to, nodes);
+        Ok(())
+    }
+
+    fn exchange(&self, first: &Path, second: &Path) -> io::Result<()> {
+        let first = self.resolve_parent(first)?;
+        let second = self.resolve_parent(second)?;
+        if first.starts_with(&second) || second.starts_with(&first) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot exchange {} with {}",
+                    first.display(),
+                    second.display()
+                ),
+            ));
+        }
+
+        let first_nodes = self.subtree(&first)?;
+        let second_nodes = self.subtree(&second)?;
+        self.insert_subtree(&first, second_nodes);
+        self.insert_subtree(&second, first_nodes);
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let directory = self.resolve(path, true)?;
+        if !matches!(self.existing_node(&directory)?, Node::Directory { .. }) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{} is not a directory", path.display()),
+            ));
+        }
+
+        self.child_names(&directory)?
+            .into_iter()
+            .map(|name| {
+                let kind = self.existing_node(&directory.join(&name))?.stat().kind;
+                Ok(DirEntry {
+                    path: path.join(name),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let resolved = self.resolve(path, true)?;
+        self.existing_node(&resolved)?;
+        Ok(resolved)
+    }
+}
+
+fn push_components(path: &Path, remaining: &mut Vec<std::ffi::OsString>) {
+    for component in path.components().rev() {
+        match component {
+            Component::Normal(name) => remaining.push(name.to_os_string()),
+            Component::ParentDir => remaining.push("..".into()),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {},
+        }
+    }
+}
+
+/// Joins without appending a trailing separator when `relative_path` is empty.
+fn join_relative(path: &Path, relative_path: &Path) -> PathBuf {
+    if relative_path.as_os_str().is_empty() {
+        path.to_path_buf()
+    } else {
+        path.join(relative_path)
+    }
+}
+
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} does not exist", path.display()),
+    )
+}
+
+fn invalid_path(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} has no parent directory", path.display()),
+    )
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<dyn Filesystem>>> = const { RefCell::new(None) };
+}
+
+/// The filesystem in effect for the current thread; the real one unless overridden.
+pub fn current() -> Rc<dyn Filesystem> {
+    CURRENT
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(|| Rc::new(RealFilesystem))
+}
+
+/// Runs `f` with `filesystem` in effect for the current thread.
+pub fn with_filesystem<R>(filesystem: Rc<dyn Filesystem>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Rc<dyn Filesystem>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(filesystem));
+    let _restore = Restore(previous);
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn names(entries: Vec<DirEntry>) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_memory_filesystem_tracks_directories_and_symlinks() {
+        let fs = MemoryFilesystem::new();
+        fs.mkdir(Path::new("/stow")).unwrap();
+        fs.mkdir(Path::new("/stow/pkg")).unwrap();
+        fs.create_file(Path::new("/stow/pkg/b"), 3).unwrap();
+        fs.create_file(Path::new("/stow/pkg/a"), 5).unwrap();
+        fs.mkdir(Path::new("/target")).unwrap();
+        fs.symlink(Path::new("../stow/pkg"), Path::new("/target/pkg"))
+            .unwrap();
+
+        assert!(fs.lstat(Path::new("/target/pkg")).unwrap().is_symlink());
+        assert!(fs.stat(Path::new("/target/pkg")).unwrap().is_dir());
+        assert_eq!(
+            fs.canonicalize(Path::new("/target/pkg/a")).unwrap(),
+            PathBuf::from("/stow/pkg/a")
+        );
+        assert_eq!(fs.lstat(Path::new("/target/pkg/a")).unwrap().len, 5);
+        assert_eq!(
+            names(fs.read_dir(Path::new("/target/pkg")).unwrap()),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            fs.read_dir(Path::new("/target/pkg")).unwrap()[0].path(),
+            PathBuf::from("/target/pkg/a")
+        );
+        assert_eq!(
+            fs.mkdir(Path::new("/target/pkg")).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            fs.mkdir(Path::new("/missing/dir")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_memory_filesystem_rmdir_requires_empty_directory() {
+        let fs = MemoryFilesystem::new();
+        fs.mkdir(Path::new("/dir")).unwrap();
+        fs.create_file(Path::new("/dir/file"), 0).unwrap();
+
+        assert_eq!(
+            fs.rmdir(Path::new("/dir")).unwrap_err().kind(),
+            io::ErrorKind::DirectoryNotEmpty
+        );
+        fs.remove_file(Path::new("/dir/file")).unwrap();
+        fs.rmdir(Path::new("/dir")).unwrap();
+        assert_eq!(
+            fs.lstat(Path::new("/dir")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_memory_filesystem_rename_and_exchange_move_subtrees() {
+        let fs = MemoryFilesystem::new();
+        fs.mkdir(Path::new("/dir")).unwrap();
+        fs.mkdir(Path::new("/dir/nested")).unwrap();
+        fs.create_file(Path::new("/dir/nested/file"), 1).unwrap();
+        fs.symlink(Path::new("target"), Path::new("/link")).unwrap();
+
+        fs.rename(Path::new("/dir"), Path::new("/moved")).unwrap();
+        assert!(fs.lstat(Path::new("/dir")).is_err());
+        assert_eq!(fs.lstat(Path::new("/moved/nested/file")).unwrap().len, 1);
+
+        fs.exchange(Path::new("/moved"), Path::new("/link"))
+            .unwrap();
+        assert!(fs.lstat(Path::new("/moved")).unwrap().is_symlink());
+        assert_eq!(
+            fs.read_link(Path::new("/moved")).unwrap(),
+            PathBuf::from("target")
+        );
+        assert!(fs.lstat(Path::new("/link/nested/file")).is_ok());
+    }
+
+    #[test]
+    fn test_overlay_reads_real_tree_without_writing_to_it() {
+        let dir = tempdir().unwrap();
+        let real_dir = dir.path().join("real");
+        fs::create_dir_all(real_dir.join("nested")).unwrap();
+        fs::write(real_dir.join("nested/file"), "content").unwrap();
+        fs::write(real_dir.join("other"), "").unwrap();
+        let overlay = MemoryFilesystem::overlay();
+
+        assert_eq!(overlay.lstat(&real_dir.join("nested/file")).unwrap().len, 7);
+        overlay
+            .rename(&real_dir.join("nested"), &dir.path().join("moved"))
+            .unwrap();
+        overlay.remove_file(&real_dir.join("other")).unwrap();
+        overlay
+            .symlink(Path::new("moved"), &real_dir.join("link"))
+            .unwrap();
+
+        assert_eq!(names(overlay.read_dir(&real_dir).unwrap()), vec!["link"]);
+        assert_eq!(
+            overlay.lstat(&dir.path().join("moved/file")).unwrap().len,
+            7
+        );
+        assert!(real_dir.join("nested/file").exists());
+        assert!(real_dir.join("other").exists());
+        assert!(!real_dir.join("link").exists());
+        assert!(!dir.path().join("moved").exists());
+    }
+
+    #[test]
+    fn test_with_filesystem_scopes_current_filesystem() {
+        let memory: Rc<dyn Filesystem> = Rc::new(MemoryFilesystem::new());
+        memory.mkdir(Path::new("/only-in-memory")).unwrap();
+
+        with_filesystem(memory, || {
+            assert!(current().lstat(Path::new("/only-in-memory")).is_ok());
+        });
+        assert!(current().lstat(Path::new("/only-in-memory")).is_err());
+    }
+}
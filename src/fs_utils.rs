@@ -1,24 +1,37 @@
 use crate::error::{FsError, Result, RustowError};
+use crate::filesystem::{self, DirEntry, FileKind, Filesystem};
+use crate::logging::log_event;
 use crate::path_utils::normalize_path_components;
-use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub fn is_directory(path: &Path) -> bool {
-    path.is_dir()
+    filesystem::current()
+        .stat(path)
+        .is_ok_and(|stat| stat.is_dir())
 }
 
 pub fn is_symlink(path: &Path) -> bool {
-    path.is_symlink()
+    filesystem::current()
+        .lstat(path)
+        .is_ok_and(|stat| stat.is_symlink())
 }
 
 pub fn path_exists(path: &Path) -> bool {
-    path.exists()
+    filesystem::current().stat(path).is_ok()
 }
 
 pub fn create_symlink(link_path: &Path, target_path: &Path) -> Result<()> {
-    #[cfg(unix)]
-    {
-        std::os::unix::fs::symlink(target_path, link_path).map_err(|e| {
+    log_event!(
+        Fs,
+        Trace,
+        "symlink {} -> {}",
+        link_path.display(),
+        target_path.display()
+    );
+    filesystem::current()
+        .symlink(target_path, link_path)
+        .map_err(|e| {
             FsError::CreateSymlink {
                 link_path: link_path.to_path_buf(),
                 target_path: target_path.to_path_buf(),
@@ -26,40 +39,6 @@ pub fn create_symlink(link_path: &Path, target_path: &Path) -> Result<()> {
             }
             .into()
         })
-    }
-    #[cfg(windows)]
-    {
-        if target_path.is_dir() {
-            std::os::windows::fs::symlink_dir(target_path, link_path).map_err(|e| {
-                FsError::CreateSymlink {
-                    link_path: link_path.to_path_buf(),
-                    target_path: target_path.to_path_buf(),
-                    source: e,
-                }
-                .into()
-            })
-        } else {
-            std::os::windows::fs::symlink_file(target_path, link_path).map_err(|e| {
-                FsError::CreateSymlink {
-                    link_path: link_path.to_path_buf(),
-                    target_path: target_path.to_path_buf(),
-                    source: e,
-                }
-                .into()
-            })
-        }
-    }
-    #[cfg(not(any(unix, windows)))]
-    {
-        Err(FsError::Io {
-            path: link_path.to_path_buf(),
-            source: std::io::Error::new(
-                std::io::ErrorKind::Unsupported,
-                "Symlink creation not supported on this platform",
-            ),
-        }
-        .into())
-    }
 }
 
 pub fn read_link(path: &Path) -> Result<PathBuf> {
@@ -69,7 +48,7 @@ pub fn read_link(path: &Path) -> Result<PathBuf> {
         // So, this check correctly leads to NotASymlink for both cases.
         return Err(FsError::NotASymlink(path.to_path_buf()).into());
     }
-    std::fs::read_link(path).map_err(|e| {
+    filesystem::current().read_link(path).map_err(|e| {
         FsError::ReadSymlink {
             path: path.to_path_buf(),
             source: e,
@@ -89,8 +68,9 @@ pub fn delete_symlink(path: &Path) -> Result<()> {
     }
 
     // If is_symlink is true, the path refers to a symlink.
-    // It could be a broken symlink, but std::fs::remove_file should handle it.
-    std::fs::remove_file(path).map_err(|e| {
+    // It could be a broken symlink, but remove_file should handle it.
+    log_event!(Fs, Trace, "remove symlink {}", path.display());
+    filesystem::current().remove_file(path).map_err(|e| {
         FsError::DeleteSymlink {
             path: path.to_path_buf(),
             source: e,
@@ -100,7 +80,8 @@ pub fn delete_symlink(path: &Path) -> Result<()> {
 }
 
 pub fn create_dir_all(path: &Path) -> Result<()> {
-    std::fs::create_dir_all(path).map_err(|e| {
+    log_event!(Fs, Trace, "create directory {}", path.display());
+    create_dir_all_with(&*filesystem::current(), path).map_err(|e| {
         FsError::CreateDirectory {
             path: path.to_path_buf(),
             source: e,
@@ -109,6 +90,44 @@ pub fn create_dir_all(path: &Path) -> Result<()> {
     })
 }
 
+/// Permission bits of `path`, following symlinks, when the filesystem keeps them.
+pub(crate) fn permission_mode(path: &Path) -> Option<u32> {
+    filesystem::current().stat(path).ok()?.mode
+}
+
+pub(crate) fn set_permission_mode(path: &Path, mode: u32) -> Result<()> {
+    log_event!(Fs, Trace, "set mode {:04o} on {}", mode, path.display());
+    filesystem::current()
+        .set_permissions(path, mode)
+        .map_err(|e| {
+            FsError::Io {
+                path: path.to_path_buf(),
+                source: e,
+            }
+            .into()
+        })
+}
+
+pub(crate) fn create_dir_all_with(fs: &dyn Filesystem, path: &Path) -> std::io::Result<()> {
+    if path.as_os_str().is_empty() || fs.stat(path).is_ok_and(|stat| stat.is_dir()) {
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        create_dir_all_with(fs, parent)?;
+    }
+
+    match fs.mkdir(path) {
+        Err(error)
+            if error.kind() == std::io::ErrorKind::AlreadyExists
+                && fs.stat(path).is_ok_and(|stat| stat.is_dir()) =>
+        {
+            Ok(())
+        },
+        result => result,
+    }
+}
+
 pub fn delete_empty_dir(path: &Path) -> Result<()> {
     if is_symlink(path) {
         return Err(FsError::NotADirectory(path.to_path_buf()).into());
@@ -121,30 +140,22 @@ pub fn delete_empty_dir(path: &Path) -> Result<()> {
     }
 
     // Check if the directory is empty
-    match std::fs::read_dir(path) {
-        Ok(mut entries) => {
-            if entries.next().is_some() {
+    match read_dir(path) {
+        Ok(entries) => {
+            if !entries.is_empty() {
                 // Directory is not empty
                 return Err(FsError::DeleteDirectory {
                     path: path.to_path_buf(),
-                    source: std::io::Error::new(
-                        std::io::ErrorKind::Other, // Using Other as std::io::ErrorKind::DirectoryNotEmpty is unstable
-                        "Directory not empty",
-                    ),
+                    source: std::io::Error::other("Directory not empty"),
                 }
                 .into());
             }
         },
-        Err(e) => {
-            return Err(FsError::Io {
-                path: path.to_path_buf(),
-                source: e,
-            }
-            .into());
-        },
+        Err(e) => return Err(e),
     }
 
-    std::fs::remove_dir(path).map_err(|e| {
+    log_event!(Fs, Trace, "remove directory {}", path.display());
+    filesystem::current().rmdir(path).map_err(|e| {
         FsError::DeleteDirectory {
             path: path.to_path_buf(),
             source: e,
@@ -153,8 +164,33 @@ pub fn delete_empty_dir(path: &Path) -> Result<()> {
     })
 }
 
+/// Removes directories between `path` and `root` left empty by moving `path` away.
+pub(crate) fn remove_emptied_parents(path: &Path, root: &Path) -> Result<()> {
+    for parent in path.ancestors().skip(1) {
+        if parent == root || !parent.starts_with(root) {
+            break;
+        }
+        if !read_dir(parent)?.is_empty() {
+            break;
+        }
+        delete_empty_dir(parent)?;
+    }
+    Ok(())
+}
+
+/// Entries of the directory at `path`, sorted by file name.
+pub fn read_dir(path: &Path) -> Result<Vec<DirEntry>> {
+    filesystem::current().read_dir(path).map_err(|e| {
+        FsError::Io {
+            path: path.to_path_buf(),
+            source: e,
+        }
+        .into()
+    })
+}
+
 pub fn canonicalize_path(path: &Path) -> Result<PathBuf> {
-    std::fs::canonicalize(path).map_err(|e| {
+    filesystem::current().canonicalize(path).map_err(|e| {
         FsError::Canonicalize {
             path: path.to_path_buf(),
             source: e,
@@ -163,6 +199,382 @@ pub fn canonicalize_path(path: &Path) -> Result<PathBuf> {
     })
 }
 
+/// Renames `from` to `to`, falling back to a copy when they live on different filesystems.
+///
+/// The fallback copies into a temporary sibling of `to`, syncs it, renames it into place and
+/// only then removes `from`, so an interruption leaves at least one complete copy behind.
+pub fn rename_or_copy(from: &Path, to: &Path) -> std::io::Result<()> {
+    log_event!(Fs, Trace, "move {} to {}", from.display(), to.display());
+    match filesystem::current().rename(from, to) {
+        Err(error) if is_cross_device_error(&error) => copy_then_remove(from, to),
+        result => result,
+    }
+}
+
+fn is_cross_device_error(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::CrossesDevices
+}
+
+/// Returns an unused hidden path next to `path` for staging replacements.
+pub(crate) fn temporary_sibling_path(path: &Path, purpose: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    loop {
+        let candidate = parent.join(format!(
+            ".{}.rustow-{}-{}-{}",
+            file_name,
+            purpose,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if filesystem::current().lstat(&candidate).is_err() {
+            return candidate;
+        }
+    }
+}
+
+pub(crate) fn copy_then_remove(from: &Path, to: &Path) -> std::io::Result<()> {
+    let staging_path = temporary_sibling_path(to, "copy");
+
+    if let Err(error) = copy_item_preserving_metadata(from, &staging_path) {
+        let _ = remove_item(&staging_path);
+        return Err(error);
+    }
+
+    if let Err(error) = std::fs::rename(&staging_path, to) {
+        let _ = remove_item(&staging_path);
+        return Err(error);
+    }
+    sync_parent_directory(to)?;
+
+    remove_item(from)?;
+    sync_parent_directory(from)
+}
+
+/// Points `link_path` at `target_path`, atomically replacing any symlink already there.
+///
+/// The new link is created under a temporary sibling name and renamed over `link_path`, so a
+/// concurrent reader sees either the old link or the new one but never a missing path.
+pub fn replace_symlink(link_path: &Path, target_path: &Path) -> Result<()> {
+    let staging_path = temporary_sibling_path(link_path, "link");
+    let create_symlink_error = |source| -> RustowError {
+        FsError::CreateSymlink {
+            link_path: link_path.to_path_buf(),
+            target_path: target_path.to_path_buf(),
+            source,
+        }
+        .into()
+    };
+
+    create_symlink(&staging_path, target_path).map_err(|error| match error {
+        RustowError::Fs(FsError::CreateSymlink { source, .. }) => create_symlink_error(source),
+        other => other,
+    })?;
+
+    let fs = filesystem::current();
+    fs.rename(&staging_path, link_path).map_err(|e| {
+        let _ = fs.remove_file(&staging_path);
+        create_symlink_error(e)
+    })
+}
+
+/// Swaps the directory entries at `first` and `second`.
+///
+/// On Linux this is a single atomic `renameat2(RENAME_EXCHANGE)`. Elsewhere, or on filesystems
+/// without exchange support, it falls back to three renames through a temporary name, and
+/// `second` is then briefly absent between the first two; a failed second rename puts it back.
+pub(crate) fn exchange_paths(first: &Path, second: &Path) -> std::io::Result<()> {
+    log_event!(
+        Fs,
+        Trace,
+        "exchange {} and {}",
+        first.display(),
+        second.display()
+    );
+    let fs = filesystem::current();
+    match fs.exchange(first, second) {
+        Err(error) if is_exchange_unsupported_error(&error) => {
+            log_event!(
+                Fs,
+                Debug,
+                "cannot exchange {} atomically ({}); falling back to renames",
+                second.display(),
+                error
+            );
+        },
+        result => return result,
+    }
+
+    let aside_path = temporary_sibling_path(second, "swap");
+    fs.rename(second, &aside_path)?;
+    if let Err(error) = fs.rename(first, second) {
+        let _ = fs.rename(&aside_path, second);
+        return Err(error);
+    }
+    fs.rename(&aside_path, first)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn rename_exchange(first: &Path, second: &Path) -> std::io::Result<()> {
+    let first = path_to_cstring(first)?;
+    let second = path_to_cstring(second)?;
+    // SAFETY: both paths are NUL-terminated strings that outlive the syscall.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            first.as_ptr(),
+            libc::AT_FDCWD,
+            second.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn is_exchange_unsupported_error(error: &std::io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    if matches!(
+        error.raw_os_error(),
+        Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::ENOTSUP)
+    ) {
+        return true;
+    }
+
+    error.kind() == std::io::ErrorKind::Unsupported
+}
+
+fn copy_item_preserving_metadata(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(from)?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        let link_target = std::fs::read_link(from)?;
+        create_symlink(to, &link_target).map_err(|error| match error {
+            RustowError::Fs(FsError::CreateSymlink { source, .. }) => source,
+            other => std::io::Error::other(other.to_string()),
+        })?;
+    } else if file_type.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_item_preserving_metadata(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        std::fs::set_permissions(to, metadata.permissions())?;
+        sync_path(to)?;
+    } else if file_type.is_file() {
+        std::fs::copy(from, to)?;
+        sync_path(to)?;
+    } else {
+        // FIFOs, sockets and device nodes: copying would block or read the device's contents.
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!(
+                "{} is not a regular file, directory or symlink and cannot be copied",
+                from.display()
+            ),
+        ));
+    }
+
+    copy_extended_attributes(from, to)?;
+    copy_timestamps(&metadata, to)
+}
+
+/// Removes a partially built staging tree, ignoring a path that is already gone.
+pub(crate) fn remove_staged_tree(path: &Path) -> std::io::Result<()> {
+    let fs = filesystem::current();
+    match fs.lstat(path) {
+        Ok(stat) if stat.is_dir() => {
+            for entry in fs.read_dir(path)? {
+                remove_staged_tree(&entry.path())?;
+            }
+            fs.rmdir(path)
+        },
+        Ok(_) => fs.remove_file(path),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+fn remove_item(path: &Path) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+fn sync_path(path: &Path) -> std::io::Result<()> {
+    std::fs::File::open(path)?.sync_all()
+}
+
+#[cfg(unix)]
+pub(crate) fn sync_parent_directory(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_path(parent),
+        _ => sync_path(Path::new(".")),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_parent_directory(_path: &Path) -> std::io::Result<()> {
+    // Directory handles cannot be opened for syncing on this platform.
+    Ok(())
+}
+
+#[cfg(unix)]
+fn path_to_cstring(path: &Path) -> std::io::Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "path contains an interior NUL byte",
+        )
+    })
+}
+
+#[cfg(unix)]
+fn copy_timestamps(metadata: &std::fs::Metadata, to: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = path_to_cstring(to)?;
+    let times = [
+        libc::timespec {
+            tv_sec: metadata.atime() as libc::time_t,
+            tv_nsec: metadata.atime_nsec() as _,
+        },
+        libc::timespec {
+            tv_sec: metadata.mtime() as libc::time_t,
+            tv_nsec: metadata.mtime_nsec() as _,
+        },
+    ];
+
+    // SAFETY: `path` is a valid NUL-terminated string and `times` holds two timespecs.
+    let result = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_timestamps(metadata: &std::fs::Metadata, to: &Path) -> std::io::Result<()> {
+    if !metadata.is_file() {
+        return Ok(());
+    }
+
+    let times = std::fs::FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(to)?
+        .set_times(times)
+}
+
+#[cfg(target_os = "linux")]
+fn copy_extended_attributes(from: &Path, to: &Path) -> std::io::Result<()> {
+    let from_path = path_to_cstring(from)?;
+    let to_path = path_to_cstring(to)?;
+
+    let names = match read_xattr_buffer(|buffer, size| {
+        // SAFETY: `from_path` is NUL-terminated and `buffer` has room for `size` bytes.
+        unsafe { libc::llistxattr(from_path.as_ptr(), buffer.cast(), size) }
+    }) {
+        Ok(names) => names,
+        Err(error) if is_unsupported_xattr_error(&error) => return Ok(()),
+        Err(error) => return Err(error),
+    };
+
+    for name in names
+        .split(|byte| *byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let name = std::ffi::CString::new(name).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid xattr name")
+        })?;
+        let value = read_xattr_buffer(|buffer, size| {
+            // SAFETY: both strings are NUL-terminated and `buffer` has room for `size` bytes.
+            unsafe { libc::lgetxattr(from_path.as_ptr(), name.as_ptr(), buffer.cast(), size) }
+        })?;
+
+        // SAFETY: both strings are NUL-terminated and `value` is a live buffer of its length.
+        let result = unsafe {
+            libc::lsetxattr(
+                to_path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result != 0 {
+            let error = std::io::Error::last_os_error();
+            if !is_unsupported_xattr_error(&error) {
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn read_xattr_buffer<F>(mut read: F) -> std::io::Result<Vec<u8>>
+where
+    F: FnMut(*mut u8, usize) -> libc::ssize_t,
+{
+    loop {
+        let size = read(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut buffer = vec![0u8; size as usize];
+        let read_size = read(buffer.as_mut_ptr(), buffer.len());
+        if read_size >= 0 {
+            buffer.truncate(read_size as usize);
+            return Ok(buffer);
+        }
+
+        let error = std::io::Error::last_os_error();
+        // The attribute grew between the two calls; ask for the size again.
+        if error.raw_os_error() != Some(libc::ERANGE) {
+            return Err(error);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_unsupported_xattr_error(error: &std::io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::ENOTSUP) | Some(libc::EPERM) | Some(libc::ENODATA)
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_extended_attributes(_from: &Path, _to: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum RawStowItemType {
     File,
@@ -205,16 +617,24 @@ pub fn walk_package_dir(package_path: &Path) -> Result<Vec<RawStowItem>> {
     }
 
     let mut items: Vec<RawStowItem> = Vec::new();
+    walk_directory_into(package_path, package_path, &mut items)?;
+    Ok(items)
+}
 
-    for entry_result in WalkDir::new(package_path).min_depth(1) {
-        let entry: walkdir::DirEntry = entry_result.map_err(|e| FsError::WalkDir {
-            path: e.path().unwrap_or(package_path).to_path_buf(),
-            source: e
-                .into_io_error()
-                .unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "walkdir error")),
+fn walk_directory_into(
+    package_path: &Path,
+    dir_path: &Path,
+    items: &mut Vec<RawStowItem>,
+) -> Result<()> {
+    let entries = filesystem::current()
+        .read_dir(dir_path)
+        .map_err(|e| FsError::WalkDir {
+            path: dir_path.to_path_buf(),
+            source: e,
         })?;
 
-        let absolute_path: PathBuf = entry.path().to_path_buf();
+    for entry in entries {
+        let absolute_path: PathBuf = entry.path();
         let package_relative_path: PathBuf = absolute_path
             .strip_prefix(package_path)
             .map_err(|_| {
@@ -225,55 +645,62 @@ pub fn walk_package_dir(package_path: &Path) -> Result<Vec<RawStowItem>> {
             })?
             .to_path_buf();
 
-        let file_type: std::fs::FileType = entry.file_type();
-        let item_type: RawStowItemType = if file_type.is_symlink() {
-            RawStowItemType::Symlink
-        } else if file_type.is_dir() {
-            RawStowItemType::Directory
-        } else if file_type.is_file() {
-            RawStowItemType::File
-        } else {
+        let item_type: RawStowItemType = match entry.kind() {
+            FileKind::Symlink => RawStowItemType::Symlink,
+            FileKind::Directory => RawStowItemType::Directory,
+            FileKind::File => RawStowItemType::File,
             // Should not happen for normal files/dirs/symlinks
-            continue;
+            FileKind::Other => continue,
         };
 
         items.push(RawStowItem {
-            absolute_path,
+            absolute_path: absolute_path.clone(),
             package_relative_path,
-            item_type,
+            item_type: item_type.clone(),
         });
+
+        if item_type == RawStowItemType::Directory {
+            walk_directory_into(package_path, &absolute_path, items)?;
+        }
     }
-    Ok(items)
+
+    Ok(())
 }
 
+/// The package `link_path` links into and the item within it, if it links into one of
+/// `stow_dirs`; see [`owning_package`] for which package that is.
 pub fn is_stow_symlink(
     link_path: &Path,
-    stow_dir: &Path,
+    stow_dirs: &[&Path],
 ) -> Result<Option<(String, PathBuf)>, RustowError> {
     // 1. Check if link_path is a symlink
     if !is_symlink(link_path) {
         return Ok(None);
     }
 
-    // 2. Canonicalize stow_dir for reliable comparison and to surface missing/invalid paths
-    let canonical_stow_dir: PathBuf = match canonicalize_path(stow_dir) {
-        Ok(p) => p,
-        Err(RustowError::Fs(FsError::Canonicalize { path, source })) => {
-            // Propagate canonicalization error for stow_dir
-            return Err(RustowError::Fs(FsError::Canonicalize { path, source }));
-        },
-        Err(RustowError::Fs(FsError::NotFound(_)))
-        | Err(RustowError::Fs(FsError::NotADirectory(_))) => {
-            return Err(RustowError::Fs(FsError::Canonicalize {
-                path: stow_dir.to_path_buf(),
-                source: std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "Stow directory cannot be canonicalized or is not a directory",
-                ),
-            }));
-        },
-        Err(e) => return Err(e), // Other errors
-    };
+    // 2. Canonicalize the stow directories for reliable comparison and to surface missing or
+    //    invalid paths
+    let mut canonical_stow_dirs = Vec::with_capacity(stow_dirs.len());
+    for stow_dir in stow_dirs {
+        match canonicalize_path(stow_dir) {
+            Ok(p) => canonical_stow_dirs.push(normalize_path_components(&p)),
+            Err(RustowError::Fs(FsError::Canonicalize { path, source })) => {
+                // Propagate canonicalization error for stow_dir
+                return Err(RustowError::Fs(FsError::Canonicalize { path, source }));
+            },
+            Err(RustowError::Fs(FsError::NotFound(_)))
+            | Err(RustowError::Fs(FsError::NotADirectory(_))) => {
+                return Err(RustowError::Fs(FsError::Canonicalize {
+                    path: stow_dir.to_path_buf(),
+                    source: std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "Stow directory cannot be canonicalized or is not a directory",
+                    ),
+                }));
+            },
+            Err(e) => return Err(e), // Other errors
+        }
+    }
 
     // 3. Read the link's destination
     // read_link itself returns Err if not a symlink, but we've already checked.
@@ -294,47 +721,118 @@ pub fn is_stow_symlink(
 
     let resolved_target_path =
         normalize_path_components(&potentially_non_canonical_target_abs_path);
-    if !resolved_target_path.exists() {
+    if !path_exists(&resolved_target_path) {
         return Ok(None);
     }
 
-    let normalized_stow_dir = normalize_path_components(&canonical_stow_dir);
+    // 5. Extract package name and item path within package, if the destination lies in one of
+    //    the stow directories
+    let canonical_stow_dirs: Vec<&Path> =
+        canonical_stow_dirs.iter().map(PathBuf::as_path).collect();
+    Ok(owning_package(&canonical_stow_dirs, &resolved_target_path))
+}
 
-    // 5. Check if the canonical target path is within the canonical_stow_dir
-    if !resolved_target_path.starts_with(&normalized_stow_dir) {
-        return Ok(None);
+/// The package `path` lies in and the path within it, for a normalized `path` below one of
+/// `stow_dirs`.
+///
+/// `stow_dirs` are in precedence order: a package of the same name in an earlier stow directory
+/// shadows the later one, which then owns nothing.
+pub fn owning_package(stow_dirs: &[&Path], path: &Path) -> Option<(String, PathBuf)> {
+    // The innermost stow directory, should one hold another.
+    let (index, relative) = stow_dirs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, stow_dir)| Some((index, path.strip_prefix(stow_dir).ok()?)))
+        .min_by_key(|(_, relative)| relative.components().count())?;
+    let (package, item_path) = split_package_path(stow_dirs[index], relative)?;
+    if stow_dirs[..index].iter().any(|stow_dir| {
+        filesystem::current()
+            .lstat(&stow_dir.join(&package))
+            .is_ok()
+    }) {
+        return None;
+    }
+    Some((package, item_path))
+}
+
+/// Marks a directory in the stow directory as a namespace: the directories in it are packages,
+/// named with the namespace (`desktop/i3`), rather than it being a package itself.
+pub const NAMESPACE_MARKER: &str = ".rustow-namespace";
+
+/// Whether `dir` holds a [`NAMESPACE_MARKER`].
+pub fn is_namespace_dir(dir: &Path) -> bool {
+    filesystem::current()
+        .lstat(&dir.join(NAMESPACE_MARKER))
+        .is_ok()
+}
+
+/// Splits `relative`, a path relative to `stow_dir`, into the package it belongs to and the
+/// path within that package.
+///
+/// The package is the first component or, below namespace directories, the first component
+/// that is not a namespace: with `desktop` a namespace, `desktop/i3/.config` is `.config` in
+/// package `desktop/i3`. The stow directory itself and namespaces belong to no package.
+pub fn split_package_path(stow_dir: &Path, relative: &Path) -> Option<(String, PathBuf)> {
+    let mut components = relative
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir));
+    let mut package = PathBuf::new();
+    loop {
+        match components.next()? {
+            Component::Normal(name) => package.push(name),
+            _ => return None,
+        }
+        if !is_namespace_dir(&stow_dir.join(&package)) {
+            break;
+        }
     }
+    Some((package.to_string_lossy().into_owned(), components.collect()))
+}
 
-    // 6. Extract the path relative to the stow_dir (e.g., "package_name/item/path")
-    let path_relative_to_stow_dir = match resolved_target_path.strip_prefix(&normalized_stow_dir) {
-        Ok(p) => p.to_path_buf(),
-        Err(_) => {
-            return Err(crate::error::StowError::InvalidPackageStructure(format!(
-                "Internal error: Failed to strip prefix for {:?} from {:?} after starts_with check",
-                resolved_target_path, canonical_stow_dir
-            ))
-            .into());
-        },
-    };
+/// Marks a directory in the target as another stow directory, as in GNU Stow.
+pub const STOW_DIR_MARKER: &str = ".stow";
 
-    // 7. Extract package name and item path within package
-    let mut components = path_relative_to_stow_dir.components();
+/// Marks a directory in the target as protected from stowing, as in GNU Stow.
+pub const NONSTOW_MARKER: &str = ".nonstow";
 
-    match components.next() {
-        Some(std::path::Component::Normal(package_name_osstr)) => {
-            let package_name = package_name_osstr.to_string_lossy().into_owned();
-            // The rest of the components form the item's path relative to the package dir.
-            let item_path_in_package = components.as_path().to_path_buf();
-            Ok(Some((package_name, item_path_in_package)))
-        },
-        _ => {
-            // Path relative to stow_dir is empty (target is stow_dir itself)
-            // or starts with `.` or `..` (shouldn't happen with canonical paths)
-            // or is a root dir (also shouldn't happen).
-            // This means it's not pointing to an item *within a package* inside stow_dir.
-            Ok(None)
-        },
+/// The [`STOW_DIR_MARKER`] or [`NONSTOW_MARKER`] `dir` holds, if any.
+pub fn protection_marker(dir: &Path) -> Option<&'static str> {
+    [STOW_DIR_MARKER, NONSTOW_MARKER]
+        .into_iter()
+        .find(|marker| filesystem::current().lstat(&dir.join(marker)).is_ok())
+}
+
+/// The outermost directory from below `root` down to `path` itself that holds a protection
+/// marker, with that marker. `root` is never protected: it was chosen as the target.
+pub fn protected_ancestor(path: &Path, root: &Path) -> Option<(PathBuf, &'static str)> {
+    let relative_path = path.strip_prefix(root).ok()?;
+    let mut current = root.to_path_buf();
+    for component in relative_path.components() {
+        current.push(component.as_os_str());
+        if !is_directory(&current) || is_symlink(&current) {
+            return None;
+        }
+        if let Some(marker) = protection_marker(&current) {
+            return Some((current, marker));
+        }
+    }
+    None
+}
+
+/// The first directory at or below `dir`, not following symlinks, that holds a protection
+/// marker, with that marker.
+pub fn find_protected_dir(dir: &Path) -> Result<Option<(PathBuf, &'static str)>> {
+    if let Some(marker) = protection_marker(dir) {
+        return Ok(Some((dir.to_path_buf(), marker)));
     }
+    for entry in read_dir(dir)? {
+        if entry.kind() == FileKind::Directory
+            && let Some(found) = find_protected_dir(&entry.path())?
+        {
+            return Ok(Some(found));
+        }
+    }
+    Ok(None)
 }
 
 #[cfg(test)]
@@ -346,6 +844,19 @@ mod tests {
 
     // ... existing test_path_exists functions ...
 
+    #[test]
+    fn test_remove_emptied_parents_stops_at_root_and_non_empty_dirs() {
+        let temp = tempdir().unwrap();
+        let package_dir = temp.path().join("pkg");
+        fs::create_dir_all(package_dir.join("keep/cache/deep")).unwrap();
+        fs::write(package_dir.join("keep/config"), "tracked").unwrap();
+
+        remove_emptied_parents(&package_dir.join("keep/cache/deep/file"), &package_dir).unwrap();
+
+        assert!(!package_dir.join("keep/cache").exists());
+        assert!(package_dir.join("keep/config").exists());
+    }
+
     #[test]
     fn test_is_directory_for_directory() {
         let dir = tempdir().unwrap();
@@ -1298,7 +1809,10 @@ mod tests {
         let (stow_dir, _, _) = setup_stow_env_for_is_stow_symlink(temp.path());
         let not_a_link = temp.path().join("not_a_link.txt");
         File::create(&not_a_link).unwrap();
-        assert_eq!(is_stow_symlink(&not_a_link, &stow_dir).unwrap(), None);
+        assert_eq!(
+            is_stow_symlink(&not_a_link, &[stow_dir.as_path()]).unwrap(),
+            None
+        );
     }
 
     #[test]
@@ -1308,7 +1822,10 @@ mod tests {
         let link_path = temp.path().join("broken_link");
         let non_existent_target = temp.path().join("non_existent_target");
         create_symlink(&link_path, &non_existent_target).unwrap();
-        assert_eq!(is_stow_symlink(&link_path, &stow_dir).unwrap(), None);
+        assert_eq!(
+            is_stow_symlink(&link_path, &[stow_dir.as_path()]).unwrap(),
+            None
+        );
     }
 
     #[test]
@@ -1320,7 +1837,7 @@ mod tests {
         create_symlink(&link_path, &link_target_dummy).unwrap();
 
         let non_existent_stow_dir = temp.path().join("non_existent_stow");
-        let result = is_stow_symlink(&link_path, &non_existent_stow_dir);
+        let result = is_stow_symlink(&link_path, &[non_existent_stow_dir.as_path()]);
         assert!(result.is_err());
         match result.err().unwrap() {
             RustowError::Fs(FsError::Canonicalize { path, .. }) => {
@@ -1341,7 +1858,10 @@ mod tests {
         let link_path = temp.path().join("link_to_outside"); // Place link outside stow_dir for clarity
         create_symlink(&link_path, &outside_target).unwrap();
 
-        assert_eq!(is_stow_symlink(&link_path, &stow_dir).unwrap(), None);
+        assert_eq!(
+            is_stow_symlink(&link_path, &[stow_dir.as_path()]).unwrap(),
+            None
+        );
     }
 
     #[test]
@@ -1350,7 +1870,10 @@ mod tests {
         let (stow_dir, _, _) = setup_stow_env_for_is_stow_symlink(temp.path());
         let link_path = temp.path().join("link_to_stow_dir");
         create_symlink(&link_path, &stow_dir).unwrap();
-        assert_eq!(is_stow_symlink(&link_path, &stow_dir).unwrap(), None);
+        assert_eq!(
+            is_stow_symlink(&link_path, &[stow_dir.as_path()]).unwrap(),
+            None
+        );
     }
 
     #[test]
@@ -1363,7 +1886,7 @@ mod tests {
         let expected_package_name = "mypkg".to_string();
         let expected_item_path = PathBuf::new();
         assert_eq!(
-            is_stow_symlink(&link_path, &stow_dir).unwrap(),
+            is_stow_symlink(&link_path, &[stow_dir.as_path()]).unwrap(),
             Some((expected_package_name, expected_item_path))
         );
     }
@@ -1378,11 +1901,87 @@ mod tests {
         let expected_package_name = "mypkg".to_string();
         let expected_item_path = PathBuf::from("item.txt");
         assert_eq!(
-            is_stow_symlink(&link_path, &stow_dir).unwrap(),
+            is_stow_symlink(&link_path, &[stow_dir.as_path()]).unwrap(),
             Some((expected_package_name, expected_item_path))
         );
     }
 
+    #[test]
+    fn test_is_stow_symlink_target_is_in_namespaced_package() {
+        let temp = tempdir().unwrap();
+        let (stow_dir, _, _) = setup_stow_env_for_is_stow_symlink(temp.path());
+        let package_dir = stow_dir.join("desktop/wm/i3");
+        fs::create_dir_all(package_dir.join(".config")).unwrap();
+        File::create(stow_dir.join("desktop").join(NAMESPACE_MARKER)).unwrap();
+        File::create(stow_dir.join("desktop/wm").join(NAMESPACE_MARKER)).unwrap();
+
+        let link_path = temp.path().join("link_to_config");
+        create_symlink(&link_path, &package_dir.join(".config")).unwrap();
+        assert_eq!(
+            is_stow_symlink(&link_path, &[stow_dir.as_path()]).unwrap(),
+            Some(("desktop/wm/i3".to_string(), PathBuf::from(".config")))
+        );
+
+        let namespace_link = temp.path().join("link_to_namespace");
+        create_symlink(&namespace_link, &stow_dir.join("desktop/wm")).unwrap();
+        assert_eq!(
+            is_stow_symlink(&namespace_link, &[stow_dir.as_path()]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_stow_symlink_skips_shadowed_packages() {
+        let temp = tempdir().unwrap();
+        let (stow_dir, _, _) = setup_stow_env_for_is_stow_symlink(temp.path());
+        let later_stow_dir = temp.path().join("later_stow_dir");
+        for package in ["mypkg", "otherpkg"] {
+            fs::create_dir_all(later_stow_dir.join(package)).unwrap();
+            File::create(later_stow_dir.join(package).join("rc")).unwrap();
+        }
+        let stow_dirs = [stow_dir.as_path(), later_stow_dir.as_path()];
+
+        let link_path = temp.path().join("link_to_other");
+        create_symlink(&link_path, &later_stow_dir.join("otherpkg/rc")).unwrap();
+        assert_eq!(
+            is_stow_symlink(&link_path, &stow_dirs).unwrap(),
+            Some(("otherpkg".to_string(), PathBuf::from("rc")))
+        );
+
+        // `mypkg` is taken from the first stow directory, so the later one owns nothing.
+        let shadowed_link = temp.path().join("link_to_shadowed");
+        create_symlink(&shadowed_link, &later_stow_dir.join("mypkg/rc")).unwrap();
+        assert_eq!(is_stow_symlink(&shadowed_link, &stow_dirs).unwrap(), None);
+    }
+
+    #[test]
+    fn test_protected_ancestor_finds_outermost_marked_directory() {
+        let temp = tempdir().unwrap();
+        let target = temp.path();
+        fs::create_dir_all(target.join("opt/stow/pkg")).unwrap();
+        fs::create_dir_all(target.join("vault/inner")).unwrap();
+        File::create(target.join(STOW_DIR_MARKER)).unwrap();
+        File::create(target.join("opt/stow").join(STOW_DIR_MARKER)).unwrap();
+        File::create(target.join("vault").join(NONSTOW_MARKER)).unwrap();
+        File::create(target.join("vault/inner").join(STOW_DIR_MARKER)).unwrap();
+
+        // The target itself is never protected.
+        assert_eq!(protected_ancestor(target, target), None);
+        assert_eq!(protected_ancestor(&target.join("opt/file"), target), None);
+        assert_eq!(
+            protected_ancestor(&target.join("opt/stow/pkg/file"), target),
+            Some((target.join("opt/stow"), STOW_DIR_MARKER))
+        );
+        assert_eq!(
+            protected_ancestor(&target.join("vault/inner/file"), target),
+            Some((target.join("vault"), NONSTOW_MARKER))
+        );
+        assert_eq!(
+            find_protected_dir(&target.join("opt")).unwrap(),
+            Some((target.join("opt/stow"), STOW_DIR_MARKER))
+        );
+    }
+
     #[test]
     fn test_is_stow_symlink_target_is_nested_item() {
         let temp = tempdir().unwrap();
@@ -1400,7 +1999,7 @@ mod tests {
         let expected_package_name = "mypkg".to_string();
         let expected_item_path = PathBuf::from("sub").join(nested_item_name);
         assert_eq!(
-            is_stow_symlink(&link_path, &stow_dir).unwrap(),
+            is_stow_symlink(&link_path, &[stow_dir.as_path()]).unwrap(),
             Some((expected_package_name, expected_item_path))
         );
     }
@@ -1422,7 +2021,7 @@ mod tests {
         let expected_package_name = "mypkg".to_string();
         let expected_item_path = PathBuf::from("link_to_external.txt");
         assert_eq!(
-            is_stow_symlink(&link_path, &stow_dir).unwrap(),
+            is_stow_symlink(&link_path, &[stow_dir.as_path()]).unwrap(),
             Some((expected_package_name, expected_item_path))
         );
     }
@@ -1480,11 +2079,253 @@ mod tests {
 
         let expected_package_name = "mypkg".to_string();
         let expected_item_path_in_package = PathBuf::from(item_name);
-        let result = is_stow_symlink(&link_path, &stow_dir_abs);
+        let result = is_stow_symlink(&link_path, &[stow_dir_abs.as_path()]);
         assert!(result.is_ok(), "is_stow_symlink failed: {:?}", result.err());
         assert_eq!(
             result.unwrap(),
             Some((expected_package_name, expected_item_path_in_package))
         );
     }
+
+    #[test]
+    fn test_is_cross_device_error_matches_exdev() {
+        #[cfg(unix)]
+        assert!(is_cross_device_error(&std::io::Error::from_raw_os_error(
+            libc::EXDEV
+        )));
+        assert!(!is_cross_device_error(&std::io::Error::from(
+            std::io::ErrorKind::NotFound
+        )));
+    }
+
+    #[test]
+    fn test_rename_or_copy_moves_file_on_same_filesystem() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source.txt");
+        let destination = dir.path().join("destination.txt");
+        fs::write(&source, "content").unwrap();
+
+        rename_or_copy(&source, &destination).unwrap();
+
+        assert!(!source.exists());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "content");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_then_remove_preserves_tree_metadata_and_symlinks() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let destination = dir.path().join("destination");
+        fs::create_dir_all(source.join("nested")).unwrap();
+        fs::write(source.join("nested/secret"), "key").unwrap();
+        fs::set_permissions(
+            source.join("nested/secret"),
+            fs::Permissions::from_mode(0o600),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("nested/secret", source.join("link")).unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o700)).unwrap();
+        let old_time =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(source.join("nested/secret"))
+            .unwrap()
+            .set_modified(old_time)
+            .unwrap();
+        let source_file_mtime = fs::metadata(source.join("nested/secret")).unwrap().mtime();
+        let source_dir_mtime = fs::metadata(source.join("nested")).unwrap().mtime();
+
+        copy_then_remove(&source, &destination).unwrap();
+
+        assert!(!source.exists());
+        let copied_file = fs::metadata(destination.join("nested/secret")).unwrap();
+        assert_eq!(copied_file.permissions().mode() & 0o777, 0o600);
+        assert_eq!(copied_file.mtime(), source_file_mtime);
+        assert_eq!(
+            fs::metadata(destination.join("nested")).unwrap().mtime(),
+            source_dir_mtime
+        );
+        assert_eq!(
+            fs::metadata(&destination).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+        assert_eq!(
+            fs::read_link(destination.join("link")).unwrap(),
+            PathBuf::from("nested/secret")
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("nested/secret")).unwrap(),
+            "key"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_then_remove_replaces_existing_destination_file() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let destination = dir.path().join("destination");
+        fs::write(&source, "new").unwrap();
+        fs::write(&destination, "old").unwrap();
+
+        copy_then_remove(&source, &destination).unwrap();
+
+        assert!(!source.exists());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+        let leftovers: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(leftovers, vec![std::ffi::OsString::from("destination")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_then_remove_refuses_special_files() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("fifo");
+        let destination = dir.path().join("destination");
+        let c_source = path_to_cstring(&source).unwrap();
+        // SAFETY: the path is a NUL-terminated string that outlives the call.
+        assert_eq!(unsafe { libc::mkfifo(c_source.as_ptr(), 0o600) }, 0);
+
+        let error = copy_then_remove(&source, &destination).unwrap_err();
+
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+        assert!(fs::symlink_metadata(&source).is_ok());
+        let leftovers: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(leftovers, vec![std::ffi::OsString::from("fifo")]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_copy_then_remove_preserves_user_xattrs_when_supported() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let destination = dir.path().join("destination");
+        fs::write(&source, "content").unwrap();
+
+        let path = path_to_cstring(&source).unwrap();
+        let name = std::ffi::CString::new("user.rustow.test").unwrap();
+        let value = b"kept";
+        // SAFETY: all pointers reference live, NUL-terminated or correctly sized buffers.
+        let result = unsafe {
+            libc::lsetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result != 0 {
+            // The temp filesystem does not support user xattrs; nothing to verify.
+            return;
+        }
+
+        copy_then_remove(&source, &destination).unwrap();
+
+        let destination_path = path_to_cstring(&destination).unwrap();
+        let copied = read_xattr_buffer(|buffer, size| unsafe {
+            libc::lgetxattr(
+                destination_path.as_ptr(),
+                name.as_ptr(),
+                buffer.cast(),
+                size,
+            )
+        })
+        .unwrap();
+        assert_eq!(copied, value);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_symlink_swaps_link_target_without_leftovers() {
+        let dir = tempdir().unwrap();
+        let link_path = dir.path().join("link");
+        create_symlink(&link_path, Path::new("old")).unwrap();
+
+        replace_symlink(&link_path, Path::new("new")).unwrap();
+
+        assert_eq!(fs::read_link(&link_path).unwrap(), PathBuf::from("new"));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_symlink_reports_error_for_original_link_path() {
+        let dir = tempdir().unwrap();
+        let link_path = dir.path().join("directory");
+        fs::create_dir(&link_path).unwrap();
+        File::create(link_path.join("keep")).unwrap();
+
+        let result = replace_symlink(&link_path, Path::new("new"));
+
+        match result {
+            Err(RustowError::Fs(FsError::CreateSymlink {
+                link_path: reported_path,
+                ..
+            })) => assert_eq!(reported_path, link_path),
+            other => panic!("Expected CreateSymlink error, got {:?}", other),
+        }
+        assert!(link_path.join("keep").exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exchange_paths_swaps_directory_and_symlink() {
+        let dir = tempdir().unwrap();
+        let directory = dir.path().join("directory");
+        let link = dir.path().join("link");
+        fs::create_dir(&directory).unwrap();
+        File::create(directory.join("file")).unwrap();
+        create_symlink(&link, Path::new("elsewhere")).unwrap();
+
+        exchange_paths(&link, &directory).unwrap();
+
+        assert_eq!(
+            fs::read_link(&directory).unwrap(),
+            PathBuf::from("elsewhere")
+        );
+        assert!(link.join("file").exists());
+        assert!(!is_symlink(&link));
+    }
+
+    #[test]
+    fn test_owning_package_splits_package_and_relative_path() {
+        let stow_dir = Path::new("/stow");
+        assert_eq!(
+            owning_package(&[stow_dir], Path::new("/stow/vim/dot-vim/colors")),
+            Some(("vim".to_string(), PathBuf::from("dot-vim/colors")))
+        );
+        assert_eq!(
+            owning_package(&[stow_dir], Path::new("/stow/vim")),
+            Some(("vim".to_string(), PathBuf::new()))
+        );
+        assert_eq!(owning_package(&[stow_dir], Path::new("/stow")), None);
+        assert_eq!(
+            owning_package(&[stow_dir], Path::new("/elsewhere/vim")),
+            None
+        );
+
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(temp.path().join("desktop/i3")).unwrap();
+        std::fs::write(temp.path().join("desktop").join(NAMESPACE_MARKER), "").unwrap();
+        assert_eq!(
+            owning_package(&[temp.path()], &temp.path().join("desktop/i3/.config")),
+            Some(("desktop/i3".to_string(), PathBuf::from(".config")))
+        );
+        assert_eq!(
+            owning_package(&[temp.path()], &temp.path().join("desktop")),
+            None
+        );
+    }
 }
@@ -0,0 +1,310 @@
+//! Git integration for stow directories kept under version control.
+//!
+//! `--git-commit` records files moved into the stow directory as a commit. The stow directory
+//! has to be inside a git work tree with nothing staged, so the commit holds exactly the files
+//! rustow moved and nothing the user prepared separately.
+
+use crate::error::{RustowError, StowError};
+use crate::filesystem::{self, FileKind};
+use crate::fs_utils;
+use crate::logging::log_event;
+use crate::stow::{ActionType, TargetActionReport, TargetActionReportStatus};
+use crate::stow_types::Plan;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Staged paths listed in the error when the repository is not clean.
+const MAX_LISTED_STAGED_PATHS: usize = 5;
+
+/// The git work tree containing a stow directory.
+pub(crate) struct StowRepository {
+    stow_dir: PathBuf,
+}
+
+impl StowRepository {
+    /// Opens the work tree containing `stow_dir`, refusing if it has staged changes.
+    pub(crate) fn open_clean(stow_dir: &Path) -> Result<Self, RustowError> {
+        let repository = Self {
+            stow_dir: stow_dir.to_path_buf(),
+        };
+
+        let inside = repository
+            .git(["rev-parse", "--is-inside-work-tree"])
+            .map_err(|error| {
+                git_error(format!(
+                    "--git-commit needs the stow directory {:?} to be in a git work tree: {}",
+                    stow_dir, error
+                ))
+            })?;
+        if inside.trim() != "true" {
+            return Err(git_error(format!(
+                "--git-commit needs the stow directory {:?} to be in a git work tree",
+                stow_dir
+            )));
+        }
+
+        let staged = repository.git(["diff", "--cached", "--name-only", "-z"])?;
+        let staged: Vec<&str> = staged.split('\0').filter(|path| !path.is_empty()).collect();
+        if !staged.is_empty() {
+            let mut listed = staged[..staged.len().min(MAX_LISTED_STAGED_PATHS)].join(", ");
+            if staged.len() > MAX_LISTED_STAGED_PATHS {
+                listed.push_str(", ...");
+            }
+            return Err(git_error(format!(
+                "refusing to commit: the repository already has {} staged change(s) ({})",
+                staged.len(),
+                listed
+            )));
+        }
+
+        Ok(repository)
+    }
+
+    /// Stages `files` and commits them with `message`.
+    ///
+    /// Files the repository ignores are left out, as `git add` would skip them inside a
+    /// directory; nothing is committed when no file is left.
+    pub(crate) fn commit(&self, files: &[PathBuf], message: &str) -> Result<(), RustowError> {
+        let mut list_ignored = vec![
+            OsStr::new("ls-files"),
+            OsStr::new("--others"),
+            OsStr::new("--ignored"),
+            OsStr::new("--exclude-standard"),
+            OsStr::new("-z"),
+            OsStr::new("--"),
+        ];
+        list_ignored.extend(files.iter().map(|path| path.as_os_str()));
+        let ignored = self.git(list_ignored)?;
+        let ignored: BTreeSet<PathBuf> = ignored
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(|path| self.stow_dir.join(path))
+            .collect();
+        let files: Vec<&PathBuf> = files
+            .iter()
+            .filter(|path| !ignored.contains(*path))
+            .collect();
+        if files.is_empty() {
+            log_event!(
+                Exec,
+                Info,
+                "nothing to commit: every moved file is ignored by git"
+            );
+            return Ok(());
+        }
+
+        let mut add = vec![OsStr::new("add"), OsStr::new("--all"), OsStr::new("--")];
+        add.extend(files.iter().map(|path| path.as_os_str()));
+        self.git(add)?;
+        self.git(["commit", "--quiet", "--message", message])?;
+        log_event!(Exec, Info, "committed {} file(s) to git", files.len());
+        Ok(())
+    }
+
+    fn git<I, S>(&self, args: I) -> Result<String, RustowError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        run_git(&self.stow_dir, args)
+    }
+}
+
+/// Files under `dir` that git does not track, ignored ones included, relative to `dir`.
+///
+/// Returns `None` when `dir` is not inside a git work tree.
+pub(crate) fn untracked_files(dir: &Path) -> Result<Option<Vec<PathBuf>>, RustowError> {
+    match run_git(dir, ["rev-parse", "--is-inside-work-tree"]) {
+        Ok(inside) if inside.trim() == "true" => {},
+        _ => return Ok(None),
+    }
+
+    let output = run_git(dir, ["ls-files", "--others", "-z", "--", "."])?;
+    Ok(Some(
+        output
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+    ))
+}
+
+fn run_git<I, S>(dir: &Path, args: I) -> Result<String, RustowError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<S> = args.into_iter().collect();
+    let rendered: Vec<String> = args
+        .iter()
+        .map(|arg| arg.as_ref().to_string_lossy().into_owned())
+        .collect();
+    log_event!(Exec, Debug, "running git {}", rendered.join(" "));
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(&args)
+        .output()
+        .map_err(|error| git_error(format!("failed to run git: {}", error)))?;
+    if !output.status.success() {
+        return Err(git_error(format!(
+            "git {} failed: {}",
+            rendered.first().map_or("", String::as_str),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn git_error(message: String) -> RustowError {
+    RustowError::Stow(StowError::Git(message))
+}
+
+/// Package paths that adopt actions moved out of the target, in report order.
+///
+/// A failed adopt still counts when the file already left the target, since the failure
+/// happened while linking it back.
+pub(crate) fn adopted_paths(reports: &[TargetActionReport]) -> Vec<PathBuf> {
+    let mut seen = BTreeSet::new();
+    reports
+        .iter()
+        .filter(|report| {
+            matches!(
+                report.original_action.action_type,
+                ActionType::AdoptFile | ActionType::AdoptDirectory
+            )
+        })
+        .filter(|report| match report.status {
+            TargetActionReportStatus::Success => true,
+            TargetActionReportStatus::Failure(_) => {
+                let target = &report.original_action.target_path;
+                !fs_utils::path_exists(target) && !fs_utils::is_symlink(target)
+            },
+            TargetActionReportStatus::Skipped | TargetActionReportStatus::ConflictPrevented => {
+                false
+            },
+        })
+        .filter_map(|report| report.original_action.source_item.as_ref())
+        .map(|item| item.source_path.clone())
+        .filter(|path| fs_utils::path_exists(path) && seen.insert(path.clone()))
+        .collect()
+}
+
+/// The files adopt actions move into the stow directory, listed before they run.
+///
+/// An adopted directory may be merged into one the package already has, so only the files
+/// that came from the target are committed, never what was already in the package.
+pub(crate) struct AdoptedFiles {
+    by_item: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl AdoptedFiles {
+    /// Lists the files below each target path `plan` adopts, at their place in the package.
+    pub(crate) fn capture(plan: &Plan) -> Result<Self, RustowError> {
+        let mut by_item = BTreeMap::new();
+        let adopts = plan.actions().filter(|action| {
+            matches!(
+                action.action_type,
+                ActionType::AdoptFile | ActionType::AdoptDirectory
+            )
+        });
+        for action in adopts {
+            let Some(item) = &action.source_item else {
+                continue;
+            };
+            let Ok(stat) = filesystem::current().lstat(&action.target_path) else {
+                continue;
+            };
+            let mut files = Vec::new();
+            if stat.is_dir() {
+                collect_files(&action.target_path, &item.source_path, &mut files)?;
+            } else {
+                files.push(item.source_path.clone());
+            }
+            by_item.insert(item.source_path.clone(), files);
+        }
+        Ok(Self { by_item })
+    }
+
+    /// The files moved in for `items`, package paths as [`adopted_paths`] returns them, that
+    /// are still in place.
+    pub(crate) fn files_of(&self, items: &[PathBuf]) -> Vec<PathBuf> {
+        items
+            .iter()
+            .filter_map(|item| self.by_item.get(item))
+            .flatten()
+            .filter(|file| filesystem::current().lstat(file).is_ok())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Every non-directory below `from`, as the path it has below `to`.
+fn collect_files(from: &Path, to: &Path, files: &mut Vec<PathBuf>) -> Result<(), RustowError> {
+    for entry in fs_utils::read_dir(from)? {
+        let path = entry.path();
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        match entry.kind() {
+            FileKind::Directory => collect_files(&path, &to.join(name), files)?,
+            FileKind::File | FileKind::Symlink | FileKind::Other => files.push(to.join(name)),
+        }
+    }
+    Ok(())
+}
+
+/// A commit message for `operation` (such as "adopt" or "add") naming each path by package.
+pub(crate) fn commit_message(operation: &str, stow_dir: &Path, paths: &[PathBuf]) -> String {
+    let entries: Vec<(String, String)> = paths
+        .iter()
+        .map(|path| {
+            let relative = path.strip_prefix(stow_dir).unwrap_or(path);
+            let (package, item_path) = fs_utils::split_package_path(stow_dir, relative)
+                .unwrap_or_else(|| (String::new(), relative.to_path_buf()));
+            (package, item_path.display().to_string())
+        })
+        .collect();
+    let packages: BTreeSet<&str> = entries
+        .iter()
+        .map(|(package, _)| package.as_str())
+        .collect();
+
+    let mut message = format!(
+        "rustow: {} {} path(s) into {}\n\n",
+        operation,
+        entries.len(),
+        packages.into_iter().collect::<Vec<_>>().join(", ")
+    );
+    for (package, path) in &entries {
+        message.push_str(&format!("{}: {}\n", package, path));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_message_lists_paths_by_package() {
+        let stow_dir = Path::new("/stow");
+        let message = commit_message(
+            "adopt",
+            stow_dir,
+            &[
+                PathBuf::from("/stow/zsh/dot-zshrc"),
+                PathBuf::from("/stow/nvim/dot-config/nvim"),
+            ],
+        );
+
+        assert_eq!(
+            message,
+            "rustow: adopt 2 path(s) into nvim, zsh\n\nzsh: dot-zshrc\nnvim: dot-config/nvim\n"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 // src/ignore.rs
 
+use crate::logging::log_event;
 use regex;
 use regex::Regex;
 use std::fs::File;
@@ -36,11 +37,13 @@ pub fn is_ignored(
         let pattern_str: &str = regex_pattern.as_str();
         if pattern_str.contains('/') {
             if regex_pattern.is_match(relative_path_str) {
+                log_ignored(item_package_relative_path, regex_pattern);
                 return true;
             }
         } else {
             // Check current item's basename directly
             if regex_pattern.is_match(item_basename) {
+                log_ignored(item_package_relative_path, regex_pattern);
                 return true;
             }
             // Check if any parent directory component in the path matches the basename pattern
@@ -81,6 +84,7 @@ pub fn is_ignored(
                                 .is_ok_and(|p| p == Path::new(name_str));
 
                             if !(name_str == item_basename && is_top_level_item_match) {
+                                log_ignored(item_package_relative_path, regex_pattern);
                                 return true;
                             }
                         }
@@ -93,6 +97,16 @@ pub fn is_ignored(
     false
 }
 
+fn log_ignored(item_package_relative_path: &Path, regex_pattern: &Regex) {
+    log_event!(
+        Ignore,
+        Trace,
+        "{} ignored by pattern '{}'",
+        item_package_relative_path.display(),
+        regex_pattern.as_str()
+    );
+}
+
 // Helper function to read patterns from a file, skipping comments and empty lines
 fn read_patterns_from_file(file_path: &Path) -> Result<Vec<Regex>, IgnoreError> {
     let file: File = File::open(file_path).map_err(|e| IgnoreError::FileIoError {
@@ -136,6 +150,7 @@ const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
     r"\.#.+", // From spec table for Emacs lock files etc. Example: .#file.txt
     r".+,v",  // Corrected: From spec table for RCS/CVS version files. Example: file.c,v
     r"\.stow-local-ignore",
+    r"\.rustow-no-fold",
     r"\.gitignore",
     r"\.cvsignore",
     // Full path patterns (must start with ^/ as per spec examples)
@@ -188,9 +203,16 @@ impl IgnorePatterns {
             let local_ignore_path: PathBuf = stow_dir.join(name).join(".stow-local-ignore");
             if local_ignore_path.is_file() {
                 // Check if it's a file
-                return Ok(IgnorePatterns {
-                    patterns: read_patterns_from_file(&local_ignore_path)?,
-                });
+                let patterns = read_patterns_from_file(&local_ignore_path)?;
+                log_event!(
+                    Ignore,
+                    Debug,
+                    "package '{}': {} pattern(s) from {}",
+                    name,
+                    patterns.len(),
+                    local_ignore_path.display()
+                );
+                return Ok(IgnorePatterns { patterns });
             }
         }
 
@@ -198,16 +220,41 @@ impl IgnorePatterns {
         let global_ignore_path: PathBuf = home_dir.join(".stow-global-ignore");
         if global_ignore_path.is_file() {
             // Check if it's a file
-            return Ok(IgnorePatterns {
-                patterns: read_patterns_from_file(&global_ignore_path)?,
-            });
+            let patterns = read_patterns_from_file(&global_ignore_path)?;
+            log_event!(
+                Ignore,
+                Debug,
+                "{} pattern(s) from {}",
+                patterns.len(),
+                global_ignore_path.display()
+            );
+            return Ok(IgnorePatterns { patterns });
         }
 
         // 3. Use built-in default ignore list
+        log_event!(Ignore, Debug, "using built-in ignore patterns");
         Ok(IgnorePatterns {
             patterns: get_default_ignore_patterns()?,
         })
     }
+
+    /// Patterns for the entries of the stow directory itself: its own `.stow-local-ignore`,
+    /// else the global or built-in list.
+    pub fn load_for_stow_dir(stow_dir: &Path, home_dir: &Path) -> Result<Self, IgnoreError> {
+        let local_ignore_path = stow_dir.join(".stow-local-ignore");
+        if local_ignore_path.is_file() {
+            let patterns = read_patterns_from_file(&local_ignore_path)?;
+            log_event!(
+                Ignore,
+                Debug,
+                "stow directory: {} pattern(s) from {}",
+                patterns.len(),
+                local_ignore_path.display()
+            );
+            return Ok(IgnorePatterns { patterns });
+        }
+        Self::load(stow_dir, None, home_dir)
+    }
 }
 
 // For filter_items test purposes, a simplified item structure.
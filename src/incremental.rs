@@ -0,0 +1,241 @@
+//! `--incremental`: skip restowing packages nothing has changed for.
+//!
+//! After a successful run, every stowed or restowed package gets a fingerprint covering the
+//! options the run used, every entry of the package (path, type, size, mtime, link destination)
+//! and what the target holds where those entries are linked. A later `-R` of a package whose
+//! fingerprint is unchanged would only recreate the same links, so it is left out of planning.
+//!
+//! The fingerprints are kept per stow and target directory in the user's cache directory. They
+//! are only a cache: losing them, or a new rustow fingerprinting differently, costs one full
+//! restow. Both they and the state file names use a hash that is the same in every build, so a
+//! new Rust toolchain does not invalidate them.
+
+use crate::config::{Config, PackageOperation, StowMode};
+use crate::dotfiles;
+use crate::error::{FsError, RustowError};
+use crate::fs_utils::{self, RawStowItemType};
+use crate::logging::log_event;
+use crate::plan_file::PathState;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Config fields that do not change which links a restow creates.
+const UNFINGERPRINTED_FIELDS: &[&str] = &[
+    "packages",
+    "mode",
+    "stow",
+    "simulate",
+    "lock_timeout",
+    "verbosity",
+    "incremental",
+    "force",
+];
+
+/// Package fingerprints recorded for one stow and target directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct RestowState {
+    #[serde(skip)]
+    path: PathBuf,
+    stow_dir: PathBuf,
+    target_dir: PathBuf,
+    packages: BTreeMap<String, String>,
+}
+
+impl RestowState {
+    /// Reads the fingerprints recorded for `config`; a missing or unreadable file records none.
+    pub(crate) fn load(config: &Config) -> Self {
+        let path = state_path(config);
+        let state = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Self>(&contents).ok())
+            .filter(|state| {
+                state.stow_dir == config.stow_dir && state.target_dir == config.target_dir
+            });
+        let mut state = state.unwrap_or_else(|| Self {
+            stow_dir: config.stow_dir.clone(),
+            target_dir: config.target_dir.clone(),
+            ..Self::default()
+        });
+        state.path = path;
+        state
+    }
+
+    /// Removes restowed packages whose fingerprint is unchanged from `operations`, dropping
+    /// operations left without packages, and returns the packages removed.
+    pub(crate) fn skip_unchanged(
+        &self,
+        config: &Config,
+        operations: &mut Vec<PackageOperation>,
+    ) -> Result<Vec<String>, RustowError> {
+        let mut skipped = Vec::new();
+        for index in 0..operations.len() {
+            if operations[index].mode != StowMode::Restow {
+                continue;
+            }
+            let mut kept = Vec::new();
+            for package in std::mem::take(&mut operations[index].packages) {
+                // A package also named by another operation is planned with it.
+                let elsewhere = operations.iter().enumerate().any(|(other, operation)| {
+                    other != index && operation.packages.contains(&package)
+                });
+                if !elsewhere
+                    && self.packages.get(&package) == Some(&fingerprint(config, &package)?)
+                {
+                    log_event!(
+                        Planner,
+                        Info,
+                        "package '{}' is unchanged since it was last stowed; skipping it",
+                        package
+                    );
+                    skipped.push(package);
+                } else {
+                    kept.push(package);
+                }
+            }
+            operations[index].packages = kept;
+        }
+        operations.retain(|operation| !operation.packages.is_empty());
+        Ok(skipped)
+    }
+
+    /// Records the fingerprints left by successfully applying `operations` and saves them.
+    pub(crate) fn record(
+        mut self,
+        config: &Config,
+        operations: &[PackageOperation],
+    ) -> Result<(), RustowError> {
+        for operation in operations {
+            for package in &operation.packages {
+                match operation.mode {
+                    StowMode::Delete => {
+                        self.packages.remove(package);
+                    },
+                    StowMode::Stow | StowMode::Restow => {
+                        self.packages
+                            .insert(package.clone(), fingerprint(config, package)?);
+                    },
+                }
+            }
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), RustowError> {
+        let io_error = |source| FsError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        let mut contents = serde_json::to_string_pretty(self).map_err(std::io::Error::from)?;
+        contents.push('\n');
+        // Written aside and renamed, so a run that is interrupted leaves the old state.
+        let partial = self.path.with_extension("json.partial");
+        std::fs::write(&partial, contents).map_err(io_error)?;
+        std::fs::rename(&partial, &self.path).map_err(io_error)?;
+        Ok(())
+    }
+}
+
+fn state_path(config: &Config) -> PathBuf {
+    let hash = stable_hash([
+        config.stow_dir.as_os_str().as_encoded_bytes(),
+        config.target_dir.as_os_str().as_encoded_bytes(),
+    ]);
+    dirs::cache_dir()
+        .unwrap_or_else(|| config.home_dir.join(".cache"))
+        .join("rustow")
+        .join(format!("restow-{:016x}.json", hash))
+}
+
+/// Fingerprint of the options in `config`, the tree of `package` and the target paths it
+/// links to.
+fn fingerprint(config: &Config, package: &str) -> Result<String, RustowError> {
+    let mut options = serde_json::to_value(config).map_err(std::io::Error::from)?;
+    if let Some(fields) = options.as_object_mut() {
+        for field in UNFINGERPRINTED_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    let global_ignore = config.home_dir.join(".stow-global-ignore");
+
+    let package_dir = config.package_dir(package);
+    let mut items = fs_utils::walk_package_dir(&package_dir)?;
+    items.sort_by(|a, b| a.package_relative_path.cmp(&b.package_relative_path));
+
+    let mut entries = vec![(PathBuf::new(), capture(&package_dir)?)];
+    let mut targets = Vec::new();
+    // Package directories whose target is not a real directory, so nothing below is linked.
+    let mut covered: Vec<&Path> = Vec::new();
+    for item in &items {
+        let relative = item.package_relative_path.as_path();
+        entries.push((relative.to_path_buf(), capture(&item.absolute_path)?));
+        if covered
+            .iter()
+            .any(|directory| relative.starts_with(directory))
+        {
+            continue;
+        }
+        let target = config.target_dir.join(dotfiles::process_item_name(
+            &relative.to_string_lossy(),
+            config.dotfiles,
+        ));
+        let state = capture(&target)?;
+        if item.item_type == RawStowItemType::Directory && state != PathState::Directory {
+            covered.push(relative);
+        }
+        targets.push((target, state));
+    }
+
+    let tree = serde_json::to_string(&(capture(&global_ignore)?, entries, targets))
+        .map_err(std::io::Error::from)?;
+    let hash = stable_hash([options.to_string().as_bytes(), tree.as_bytes()]);
+    Ok(format!("{:016x}", hash))
+}
+
+/// 64-bit FNV-1a over `parts`, each preceded by its length so their boundaries count.
+///
+/// Unlike `DefaultHasher`, whose output may change with any Rust release, this is fixed, so
+/// recorded fingerprints and state file names stay valid across toolchains.
+fn stable_hash<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = OFFSET_BASIS;
+    for part in parts {
+        let length = (part.len() as u64).to_le_bytes();
+        for byte in length.iter().chain(part) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+fn capture(path: &Path) -> Result<PathState, RustowError> {
+    PathState::capture(path).map_err(|source| {
+        FsError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stable_hash_is_fixed_and_respects_part_boundaries() {
+        // Recorded state depends on these values; changing them orphans every state file.
+        assert_eq!(stable_hash([]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash([&b"a"[..]]), 0x529a_4ddc_8ff5_6bbf);
+        assert_ne!(
+            stable_hash([&b"ab"[..], &b"c"[..]]),
+            stable_hash([&b"a"[..], &b"bc"[..]])
+        );
+    }
+}
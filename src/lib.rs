@@ -1,26 +1,91 @@
+mod check;
 pub mod cli;
+pub mod completions;
 pub mod config;
 mod diagnostics;
 pub mod dotfiles;
 pub mod error;
+pub mod filesystem;
 pub mod fs_utils;
+mod git;
 pub mod ignore;
+mod incremental;
+mod lock;
+pub mod logging;
 mod path_utils;
+pub mod plan_file;
+mod selection;
+pub mod sensitive;
 pub mod stow;
-mod stow_types;
+pub mod stow_types;
 #[cfg(test)]
 mod test_sync;
+mod unmanaged;
+mod watch;
 
 use crate::cli::{
     Args, OperationGroup, OperationMode, ParsedArgs, PathDisplayOverride, RuntimeParsedArgs,
 };
 use crate::config::{Config, PackageOperation, StowMode};
 use crate::error::{ConfigError, RustowError, StowError};
-use crate::stow::{
-    delete_packages, mixed_packages, restow_packages, stow_packages,
-    validate_package_for_operation_with_display,
-};
-use std::path::{Component, Path};
+use crate::incremental::RestowState;
+use crate::lock::RunLock;
+use crate::logging::{Category, Level, LogFilter, LogSettings};
+use crate::plan_file::SavedPlan;
+use crate::selection::PackageSelection;
+use crate::sensitive::InsecurePermissions;
+use crate::stow::{ActionType, TargetActionReport, validate_package_for_operation_with_display};
+pub use crate::stow_types::{Plan, SimulatedApply};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// Plans `operations` against `config` without modifying the target directory.
+///
+/// Package names are validated the same way as on the command line. Several operations are
+/// planned together like a mixed `-D`/`-S`/`-R` invocation.
+pub fn plan(config: &Config, operations: &[PackageOperation]) -> Result<Plan, RustowError> {
+    preflight_package_operations(config, operations, &[])?;
+    stow::plan_package_operations(config, operations)
+}
+
+/// Applies a plan produced by [`plan`], returning one report per executed action.
+///
+/// Plans containing conflicts are not executed; every action is reported as prevented or
+/// skipped instead. With `config.simulate` set, actions run against an in-memory copy of the
+/// target and are only reported; see [`simulate`].
+pub fn apply(plan: &Plan) -> Result<Vec<TargetActionReport>, RustowError> {
+    stow::apply_plan(plan)
+}
+
+/// Runs a plan against an in-memory overlay of the real filesystem.
+///
+/// Nothing on disk changes. Actions that would fail are reported as failures, and the result
+/// includes the state each touched path would be left in.
+pub fn simulate(plan: &Plan) -> Result<SimulatedApply, RustowError> {
+    stow::simulate_plan(plan)
+}
+
+/// How a run that did not fail ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// Everything requested was done, or a simulation found nothing to change.
+    Completed,
+    /// A simulation found actions that would change the target.
+    PendingChanges,
+    /// `--check` found the target differs from what the requested operations produce.
+    Drift,
+}
+
+impl RunOutcome {
+    /// The [`error::exit_code`] the binary exits with for this outcome.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunOutcome::Completed => error::exit_code::SUCCESS,
+            RunOutcome::PendingChanges => error::exit_code::PENDING_CHANGES,
+            RunOutcome::Drift => error::exit_code::DRIFT,
+        }
+    }
+}
 
 /// Runs the rustow application logic.
 pub fn run(args: Args) -> Result<(), RustowError> {
@@ -35,6 +100,7 @@ pub fn run_parsed(parsed_args: ParsedArgs) -> Result<(), RustowError> {
         Vec::new(),
         false,
     )
+    .map(|_| ())
 }
 
 /// Runs runtime-parsed arguments and returns errors redacted for diagnostics.
@@ -42,7 +108,7 @@ pub fn run_parsed(parsed_args: ParsedArgs) -> Result<(), RustowError> {
 /// Resource-file path values expanded from environment variables or tildes may
 /// be represented with their original display strings in returned errors.
 #[doc(hidden)]
-pub fn run_runtime_parsed(parsed_args: RuntimeParsedArgs) -> Result<(), RustowError> {
+pub fn run_runtime_parsed(parsed_args: RuntimeParsedArgs) -> Result<RunOutcome, RustowError> {
     let (parsed_args, path_displays) = parsed_args.into_parts();
     run_with_operation_groups_and_path_displays(
         parsed_args.args,
@@ -58,66 +124,189 @@ pub fn run_with_operation_groups(
     operation_groups: Vec<OperationGroup>,
 ) -> Result<(), RustowError> {
     run_with_operation_groups_and_path_displays(args, operation_groups, Vec::new(), false)
+        .map(|_| ())
 }
 
 fn run_with_operation_groups_and_path_displays(
-    args: Args,
+    mut args: Args,
     operation_groups: Vec<OperationGroup>,
     mut path_displays: Vec<PathDisplayOverride>,
     redact_diagnostics: bool,
-) -> Result<(), RustowError> {
+) -> Result<RunOutcome, RustowError> {
     let result = (|| {
         if operation_groups.is_empty() {
             reject_ambiguous_mixed_args(&args)?;
         }
 
+        let mut log_filter = LogFilter::for_verbosity(args.verbose);
+        if args.watch {
+            // Watching is only useful if the changes it makes are visible.
+            log_filter = log_filter.at_least(Category::Watch, Level::Info);
+        }
+        let log_settings =
+            LogSettings::new(log_filter, args.log.as_deref(), args.log_file.as_deref())?;
+        logging::configure(
+            &log_settings,
+            if redact_diagnostics {
+                &path_displays
+            } else {
+                &[]
+            },
+        )?;
+
+        if let Some(shell) = args.completions {
+            print!("{}", completions::script(shell));
+            return Ok(RunOutcome::Completed);
+        }
+        if let Some(plan_path) = &args.apply {
+            return apply_saved_plan(plan_path, args.simulate, args.verbose, args.lock_timeout);
+        }
+        let complete_packages = args.complete_packages;
+        let plan_out = args.plan_out.clone();
+        let watch = args.watch;
+        let check = args.check;
+        let unfold_unmanaged = args.unfold_unmanaged;
+        let report_unmanaged = args.report_unmanaged || unfold_unmanaged;
+        let fold_request = match (args.unfold.clone(), args.refold.clone()) {
+            (Some(path), _) => Some((FoldRequest::Unfold, path)),
+            (None, Some(path)) => Some((FoldRequest::Refold, path)),
+            (None, None) => None,
+        };
+        // With --add the positional arguments are target paths, and the package is the only one.
+        let add = args.add.clone().map(|package| {
+            let paths = std::mem::replace(&mut args.packages, vec![package.clone()]);
+            (
+                package,
+                paths.into_iter().map(PathBuf::from).collect::<Vec<_>>(),
+            )
+        });
+        let move_to = args
+            .move_to
+            .clone()
+            .map(|package| (package, std::mem::take(&mut args.packages)));
+        let relink_from = args.relink_from.clone();
+        let package_selection = PackageSelection {
+            all: args.all,
+            packages_from: args.packages_from.clone(),
+            exclude: args.exclude_package.clone(),
+        };
+
         match Config::from_args_with_path_displays(args, &mut path_displays) {
             Ok(config) => {
                 path_displays.push(PathDisplayOverride::new(
                     config.home_dir.join(".stow-global-ignore"),
                     "~/.stow-global-ignore".to_string(),
                 ));
-
-                let package_operations = package_operations_for_config(&config, operation_groups);
+                if redact_diagnostics {
+                    logging::set_path_displays(&path_displays);
+                }
                 let diagnostic_path_displays = if redact_diagnostics {
                     path_displays.as_slice()
                 } else {
                     &[]
                 };
+                if let Some(set) = complete_packages {
+                    for package in completions::package_names(&config, set)? {
+                        println!("{}", package);
+                    }
+                    return Ok(RunOutcome::Completed);
+                }
+                // Held from planning through execution, so no other run changes what we planned.
+                let lock = RunLock::acquire(&config)?;
+                diagnostics::process_stale_locks(lock.stale(), diagnostic_path_displays);
+                if let Some((package, paths)) = &add {
+                    return add_to_package(&config, package, paths, diagnostic_path_displays);
+                }
+                if let Some((package, items)) = &move_to {
+                    return move_to_package(&config, package, items, diagnostic_path_displays);
+                }
+                if let Some(old_stow_dir) = &relink_from {
+                    // The packages limiting the rewrite are selected like any others.
+                    let mut package_operations =
+                        package_operations_for_config(&config, operation_groups);
+                    package_selection.expand(&config, &mut package_operations)?;
+                    let mut relink_config = config.clone();
+                    relink_config.packages = package_operations
+                        .into_iter()
+                        .flat_map(|operation| operation.packages)
+                        .collect();
+                    return relink_from_old_stow_dir(
+                        &relink_config,
+                        old_stow_dir,
+                        diagnostic_path_displays,
+                    );
+                }
+                if let Some((request, path)) = &fold_request {
+                    return fold_target_directory(
+                        &config,
+                        *request,
+                        path,
+                        diagnostic_path_displays,
+                    );
+                }
+
+                let mut package_operations =
+                    package_operations_for_config(&config, operation_groups);
+                package_selection.expand(&config, &mut package_operations)?;
                 preflight_package_operations(
                     &config,
                     &package_operations,
                     diagnostic_path_displays,
                 )?;
-                let reports = execute_config_operations(&config, &package_operations)?;
-
-                // Process reports for logging/output
-                diagnostics::process_reports(&reports, &config, diagnostic_path_displays);
-
-                let conflict_count = reports
-                    .iter()
-                    .filter(|r| {
-                        matches!(
-                            r.status,
-                            crate::stow::TargetActionReportStatus::ConflictPrevented
-                        )
-                    })
-                    .count();
-                let failure_count = reports
-                    .iter()
-                    .filter(|r| {
-                        matches!(r.status, crate::stow::TargetActionReportStatus::Failure(_))
-                    })
-                    .count();
-
-                if conflict_count > 0 || failure_count > 0 {
-                    return Err(RustowError::Stow(StowError::OperationFailed(format!(
-                        "Execution stopped with {} conflicts and {} failures",
-                        conflict_count, failure_count
-                    ))));
+                if report_unmanaged {
+                    return report_unmanaged_files(
+                        &config,
+                        &package_operations,
+                        unfold_unmanaged,
+                        diagnostic_path_displays,
+                    );
+                }
+                if check {
+                    return check_package_operations(
+                        &config,
+                        &package_operations,
+                        diagnostic_path_displays,
+                    );
+                }
+                let restow_state = config.incremental.then(|| RestowState::load(&config));
+                if let Some(state) = &restow_state
+                    && !config.force
+                {
+                    state.skip_unchanged(&config, &mut package_operations)?;
+                    if package_operations.is_empty() {
+                        return Ok(RunOutcome::Completed);
+                    }
+                }
+                let plan = stow::plan_package_operations(&config, &package_operations)?;
+                if let Some(plan_out) = &plan_out {
+                    if !plan.has_conflicts() {
+                        SavedPlan::capture(plan)?.write_to(plan_out)?;
+                        return Ok(RunOutcome::Completed);
+                    }
+                    // A plan with conflicts could never be applied, so only they are reported.
+                    let outcome = execute_plan(&plan, diagnostic_path_displays);
+                    diagnostics::process_unwritten_plan(plan_out, diagnostic_path_displays);
+                    return outcome;
+                }
+
+                if watch {
+                    return watch_after_initial_run(
+                        &config,
+                        &plan,
+                        &package_operations,
+                        lock,
+                        diagnostic_path_displays,
+                    );
                 }
 
-                Ok(())
+                // A conflicting plan is never executed, so this only reports the conflicts.
+                let outcome = execute_plan(&plan, diagnostic_path_displays)?;
+                if let Some(state) = restow_state
+                    && !config.simulate
+                {
+                    state.record(&config, &package_operations)?;
+                }
+                Ok(outcome)
             },
             Err(e) => Err(e),
         }
@@ -130,6 +319,296 @@ fn run_with_operation_groups_and_path_displays(
     }
 }
 
+fn apply_saved_plan(
+    plan_path: &Path,
+    simulate: bool,
+    verbosity: u8,
+    lock_timeout: Option<u64>,
+) -> Result<RunOutcome, RustowError> {
+    let mut saved = SavedPlan::read_from(plan_path)?;
+    saved.plan.config.simulate = simulate;
+    saved.plan.config.verbosity = verbosity;
+    if let Some(seconds) = lock_timeout {
+        saved.plan.config.lock_timeout = Duration::from_secs(seconds);
+    }
+    // The plan is only checked against the filesystem once no other run can change it.
+    let lock = RunLock::acquire(&saved.plan.config)?;
+    diagnostics::process_stale_locks(lock.stale(), &[]);
+    let plan = saved.into_current_plan()?;
+
+    execute_plan(&plan, &[])
+}
+
+fn check_package_operations(
+    config: &Config,
+    operations: &[PackageOperation],
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    let drift = check::find_drift(config, operations)?;
+    diagnostics::process_drift(&drift, config, path_displays);
+    Ok(if drift.is_empty() {
+        RunOutcome::Completed
+    } else {
+        RunOutcome::Drift
+    })
+}
+
+/// Lists unmanaged files in folded directories and, unless simulating, unfolds them if asked.
+fn report_unmanaged_files(
+    config: &Config,
+    operations: &[PackageOperation],
+    unfold: bool,
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    let mut package_config = config.clone();
+    package_config.packages = operations
+        .iter()
+        .flat_map(|operation| operation.packages.iter().cloned())
+        .collect();
+    let folded = unmanaged::find_unmanaged(&package_config)?;
+    let unfold = unfold && !config.simulate;
+    diagnostics::process_unmanaged(&folded, unfold, config, path_displays);
+
+    if unfold && !folded.is_empty() {
+        let reports = unmanaged::unfold(config, &folded)?;
+        finish_reports(&reports, config, path_displays)?;
+    }
+    Ok(RunOutcome::Completed)
+}
+
+/// Moves target paths into a package, creating it if needed, and links them back.
+fn add_to_package(
+    config: &Config,
+    package: &str,
+    paths: &[PathBuf],
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    validate_package_name(package)?;
+    let plan = stow::plan_add_paths(config, package, paths)?;
+    execute_plan_as(&plan, "add", path_displays)
+}
+
+/// Moves package items, or whole packages, into another package and rewrites their links.
+fn move_to_package(
+    config: &Config,
+    package: &str,
+    items: &[String],
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    validate_package_name(package)?;
+    let plan = stow::plan_move_items(config, package, items)?;
+    execute_plan(&plan, path_displays)
+}
+
+/// Rewrites links into a previous location of the stow directory, reporting those it cannot.
+fn relink_from_old_stow_dir(
+    config: &Config,
+    old_stow_dir: &Path,
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    for package in &config.packages {
+        validate_package_name(package)?;
+    }
+    let (plan, unmapped) = stow::plan_relink_from(config, old_stow_dir)?;
+    diagnostics::process_unmapped_links(&unmapped, path_displays);
+    let outcome = execute_plan(&plan, path_displays)?;
+    if !unmapped.is_empty() {
+        return Err(StowError::UnmappedLinks(unmapped.len()).into());
+    }
+    Ok(outcome)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FoldRequest {
+    Unfold,
+    Refold,
+}
+
+/// Runs `--unfold` or `--refold` on one directory in the target, listing every link changed.
+fn fold_target_directory(
+    config: &Config,
+    request: FoldRequest,
+    path: &Path,
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    let plan = match request {
+        FoldRequest::Unfold => stow::plan_unfold_directory(config, path)?,
+        FoldRequest::Refold => stow::plan_refold_directory(config, path)?,
+    };
+    if config.simulate {
+        return execute_plan(&plan, path_displays);
+    }
+
+    let reports = match request {
+        FoldRequest::Unfold => stow::apply_plan(&plan)?,
+        FoldRequest::Refold => stow::apply_refold_plan(&plan)?,
+    };
+    diagnostics::process_fold_changes(&reports, path_displays);
+    finish_reports(&reports, config, path_displays)?;
+    Ok(RunOutcome::Completed)
+}
+
+/// Applies the initial plan, then keeps its packages stowed until the watch fails.
+///
+/// Conflicts in the initial plan are reported like conflicts found while watching: the
+/// affected package is left as it is and the watcher still starts.
+fn watch_after_initial_run(
+    config: &Config,
+    plan: &Plan,
+    operations: &[PackageOperation],
+    lock: RunLock,
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    let packages = watch::watched_packages(operations);
+    match execute_plan(plan, path_displays) {
+        Err(RustowError::Stow(error @ StowError::ExecutionStopped { .. })) => {
+            logging::log_event!(Watch, Warn, "{}", error);
+        },
+        result => {
+            result?;
+        },
+    }
+    // Each restow locks again, so other runs are only kept waiting while one is in progress.
+    drop(lock);
+
+    watch::watch_packages(config, &packages, path_displays)?;
+    Ok(RunOutcome::Completed)
+}
+
+fn execute_plan(
+    plan: &Plan,
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    execute_plan_as(plan, "adopt", path_displays)
+}
+
+/// Executes `plan`, naming the files it moves into the stow directory after `operation` when
+/// `--git-commit` commits them.
+fn execute_plan_as(
+    plan: &Plan,
+    operation: &str,
+    path_displays: &[PathDisplayOverride],
+) -> Result<RunOutcome, RustowError> {
+    diagnostics::process_looser_directories(&stow::looser_target_directories(plan), path_displays);
+    // Modes are fixed together with the stow phase, so a plan with conflicts changes none.
+    let fix = plan.config.insecure_permissions == InsecurePermissions::Fix && !plan.has_conflicts();
+    diagnostics::process_insecure_paths(&sensitive::find_insecure(plan), fix, path_displays);
+    if !plan.config.simulate {
+        let mut repositories = Vec::new();
+        if plan.config.git_commit {
+            for stow_dir in adopting_stow_dirs(plan) {
+                repositories.push((stow_dir, git::StowRepository::open_clean(stow_dir)?));
+            }
+        }
+        let adopted_files = if repositories.is_empty() {
+            None
+        } else {
+            Some(git::AdoptedFiles::capture(plan)?)
+        };
+        let reports = stow::apply_plan(plan)?;
+        let committed = match &adopted_files {
+            Some(adopted_files) => {
+                commit_adopted_paths(&repositories, &reports, adopted_files, operation)
+            },
+            None => Ok(()),
+        };
+        finish_reports(&reports, &plan.config, path_displays)?;
+        committed?;
+        return Ok(RunOutcome::Completed);
+    }
+
+    let simulation = stow::simulate_plan(plan)?;
+    let result = finish_reports(&simulation.reports, &plan.config, path_displays);
+    if !plan.has_conflicts() {
+        diagnostics::process_final_state(&simulation.final_state, path_displays);
+    }
+    result?;
+
+    let has_pending_changes = simulation
+        .reports
+        .iter()
+        .any(|report| report.original_action.action_type != ActionType::Skip);
+    Ok(if has_pending_changes {
+        RunOutcome::PendingChanges
+    } else {
+        RunOutcome::Completed
+    })
+}
+
+/// The stow directories `plan` adopts files into.
+fn adopting_stow_dirs(plan: &Plan) -> Vec<&Path> {
+    let adopted: Vec<&Path> = plan
+        .actions()
+        .filter(|action| {
+            matches!(
+                action.action_type,
+                ActionType::AdoptFile | ActionType::AdoptDirectory
+            )
+        })
+        .filter_map(|action| Some(action.source_item.as_ref()?.source_path.as_path()))
+        .collect();
+    plan.config
+        .stow_dirs()
+        .into_iter()
+        .filter(|stow_dir| adopted.iter().any(|path| path.starts_with(stow_dir)))
+        .collect()
+}
+
+fn commit_adopted_paths(
+    repositories: &[(&Path, git::StowRepository)],
+    reports: &[TargetActionReport],
+    adopted_files: &git::AdoptedFiles,
+    operation: &str,
+) -> Result<(), RustowError> {
+    let paths = git::adopted_paths(reports);
+    for (stow_dir, repository) in repositories {
+        let paths: Vec<PathBuf> = paths
+            .iter()
+            .filter(|path| path.starts_with(stow_dir))
+            .cloned()
+            .collect();
+        if !paths.is_empty() {
+            repository.commit(
+                &adopted_files.files_of(&paths),
+                &git::commit_message(operation, stow_dir, &paths),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn finish_reports(
+    reports: &[TargetActionReport],
+    config: &Config,
+    path_displays: &[PathDisplayOverride],
+) -> Result<(), RustowError> {
+    // Process reports for logging/output
+    diagnostics::process_reports(reports, config, path_displays);
+
+    let conflict_count = reports
+        .iter()
+        .filter(|r| {
+            matches!(
+                r.status,
+                crate::stow::TargetActionReportStatus::ConflictPrevented
+            )
+        })
+        .count();
+    let failure_count = reports
+        .iter()
+        .filter(|r| matches!(r.status, crate::stow::TargetActionReportStatus::Failure(_)))
+        .count();
+
+    if conflict_count > 0 || failure_count > 0 {
+        return Err(RustowError::Stow(StowError::ExecutionStopped {
+            conflicts: conflict_count,
+            failures: failure_count,
+        }));
+    }
+
+    Ok(())
+}
+
 fn reject_ambiguous_mixed_args(args: &Args) -> Result<(), RustowError> {
     let operation_flag_count = [args.stow, args.delete, args.restow]
         .into_iter()
@@ -182,13 +661,25 @@ fn preflight_package_operations(
     for operation in operations {
         for package_name in &operation.packages {
             validate_package_name(package_name)?;
-            let package_path = config.stow_dir.join(package_name);
+            let stow_dir = config.package_stow_dir(package_name);
+            for shadowed in config.stow_dirs().into_iter().filter(|other| {
+                *other != stow_dir && fs_utils::path_exists(&other.join(package_name))
+            }) {
+                logging::log_event!(
+                    Planner,
+                    Info,
+                    "package '{}' is taken from {}, not {}",
+                    package_name,
+                    crate::cli::path_display_with_prefix(stow_dir, path_displays),
+                    crate::cli::path_display_with_prefix(shadowed, path_displays)
+                );
+            }
+            let package_path = stow_dir.join(package_name);
             let package_path_display =
                 crate::cli::path_display_with_prefix(&package_path, path_displays);
-            let stow_dir_display =
-                crate::cli::path_display_with_prefix(&config.stow_dir, path_displays);
+            let stow_dir_display = crate::cli::path_display_with_prefix(stow_dir, path_displays);
             validate_package_for_operation_with_display(
-                &config.stow_dir,
+                stow_dir,
                 package_name,
                 Some(&package_path_display),
                 Some(&stow_dir_display),
@@ -217,64 +708,3 @@ fn validate_package_name(package_name: &str) -> Result<(), RustowError> {
 
     Ok(())
 }
-
-fn execute_config_operations(
-    config: &Config,
-    operation_groups: &[PackageOperation],
-) -> Result<Vec<crate::stow::TargetActionReport>, RustowError> {
-    if operation_groups.len() > 1 {
-        return execute_mixed_operation_groups(config, operation_groups);
-    }
-
-    let mut reports = Vec::new();
-
-    for operation in operation_groups {
-        let mut operation_reports = execute_operation_group(config, operation)?;
-        let should_stop = !config.simulate && reports_have_blocking_status(&operation_reports);
-        reports.append(&mut operation_reports);
-
-        if should_stop {
-            break;
-        }
-    }
-
-    Ok(reports)
-}
-
-fn execute_mixed_operation_groups(
-    config: &Config,
-    operation_groups: &[PackageOperation],
-) -> Result<Vec<crate::stow::TargetActionReport>, RustowError> {
-    let mut delete_packages = Vec::new();
-    let mut stow_packages = Vec::new();
-    let mut restow_packages = Vec::new();
-
-    for operation in operation_groups {
-        match operation.mode {
-            StowMode::Stow => stow_packages.extend(operation.packages.clone()),
-            StowMode::Delete => delete_packages.extend(operation.packages.clone()),
-            StowMode::Restow => restow_packages.extend(operation.packages.clone()),
-        }
-    }
-
-    mixed_packages(config, &delete_packages, &stow_packages, &restow_packages)
-}
-
-fn execute_operation_group(
-    config: &Config,
-    operation: &PackageOperation,
-) -> Result<Vec<crate::stow::TargetActionReport>, RustowError> {
-    let mut operation_config = config.clone();
-    operation_config.mode = operation.mode.clone();
-    operation_config.packages = operation.packages.clone();
-
-    match &operation.mode {
-        StowMode::Stow => stow_packages(&operation_config),
-        StowMode::Delete => delete_packages(&operation_config),
-        StowMode::Restow => restow_packages(&operation_config),
-    }
-}
-
-fn reports_have_blocking_status(reports: &[crate::stow::TargetActionReport]) -> bool {
-    reports.iter().any(|report| report.status.is_blocking())
-}
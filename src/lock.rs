@@ -0,0 +1,261 @@
+//! Advisory locking of the target and stow directories.
+//!
+//! Two runs against the same directories, say a login hook and an interactive restow, would
+//! otherwise interleave their deletes and creates. Each run takes an exclusive `flock` on a
+//! [`LOCK_FILE_NAME`] file in both directories before planning and keeps it until it is done.
+//! The holder writes its PID into the file and removes the file when it finishes, so a lock file
+//! found with a PID in it that nobody holds was left by a run that did not finish.
+//!
+//! Without `flock`, on non-unix targets, the lock is the lock file itself, created exclusively.
+//! A run that did not finish then leaves a file that blocks later runs until it is removed.
+
+use crate::config::Config;
+use crate::error::{FsError, RustowError, StowError};
+use crate::logging::log_event;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Name of the lock file created in the target and stow directories.
+pub const LOCK_FILE_NAME: &str = ".rustow.lock";
+
+/// How long `--lock-timeout` waits unless told otherwise.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Locks held for one run; dropping it releases them.
+#[derive(Debug)]
+pub(crate) struct RunLock {
+    files: Vec<(PathBuf, File)>,
+    stale: Vec<StaleLock>,
+}
+
+/// A lock file left behind by a run that did not finish.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StaleLock {
+    pub(crate) path: PathBuf,
+    pub(crate) pid: u32,
+}
+
+impl RunLock {
+    /// Locks the target and stow directories of `config`, waiting up to `config.lock_timeout`
+    /// for another run to finish. Simulations change nothing and do not lock.
+    pub(crate) fn acquire(config: &Config) -> Result<Self, RustowError> {
+        let mut lock = Self {
+            files: Vec::new(),
+            stale: Vec::new(),
+        };
+        if config.simulate {
+            return Ok(lock);
+        }
+
+        // Always lock in the same order, so two runs never wait for each other's second lock.
+        let mut directories = config.stow_dirs();
+        directories.push(config.target_dir.as_path());
+        directories.sort();
+        directories.dedup();
+        for directory in directories {
+            if let Some((path, file, stale_pid)) = lock_directory(directory, config.lock_timeout)? {
+                if let Some(pid) = stale_pid {
+                    lock.stale.push(StaleLock {
+                        path: path.clone(),
+                        pid,
+                    });
+                }
+                lock.files.push((path, file));
+            }
+        }
+        Ok(lock)
+    }
+
+    /// Lock files taken over from runs that did not finish.
+    pub(crate) fn stale(&self) -> &[StaleLock] {
+        &self.stale
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // Removing the file while still holding it; closing it then releases the lock.
+        for (path, _) in &self.files {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// One attempt at taking the lock file.
+enum Attempt {
+    /// Locked, with the PID a run that did not finish left in the file, if any.
+    Acquired(File, Option<u32>),
+    /// Held by another run, which wrote this PID.
+    Busy(Option<u32>),
+    /// The file went away under us; try again at once.
+    Retry,
+}
+
+/// Locks `directory`, returning the lock file and the PID of a run that left it behind, if any.
+fn lock_directory(
+    directory: &Path,
+    timeout: Duration,
+) -> Result<Option<(PathBuf, File, Option<u32>)>, RustowError> {
+    let path = directory.join(LOCK_FILE_NAME);
+    let io_error = |source| FsError::Io {
+        path: path.clone(),
+        source,
+    };
+    let deadline = Instant::now() + timeout;
+    let mut waiting = false;
+    loop {
+        let holder = match try_lock(&path) {
+            Ok(Attempt::Acquired(mut file, stale_pid)) => {
+                record_pid(&mut file).map_err(io_error)?;
+                log_event!(Exec, Debug, "locked {:?}", path);
+                return Ok(Some((path, file, stale_pid)));
+            },
+            Ok(Attempt::Retry) => continue,
+            Ok(Attempt::Busy(holder)) => holder,
+            // Nothing in a directory we cannot write to is going to be changed by us either.
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+                ) =>
+            {
+                log_event!(Exec, Debug, "not locking {:?}: {}", directory, error);
+                return Ok(None);
+            },
+            Err(source) => return Err(io_error(source).into()),
+        };
+
+        if Instant::now() >= deadline {
+            return Err(locked_error(&path, holder, timeout).into());
+        }
+        if !waiting {
+            log_event!(
+                Exec,
+                Info,
+                "waiting up to {}s for {:?}{}",
+                timeout.as_secs(),
+                path,
+                holder.map_or(String::new(), |pid| format!(", held by PID {}", pid))
+            );
+            waiting = true;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+    }
+}
+
+#[cfg(unix)]
+fn try_lock(path: &Path) -> io::Result<Attempt> {
+    use std::os::fd::AsRawFd;
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    // SAFETY: the descriptor belongs to `file`, which outlives the call.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(error);
+        }
+        return Ok(Attempt::Busy(recorded_pid(&mut file)));
+    }
+
+    // The previous holder removes the file on its way out; a lock on that file guards nothing.
+    if !is_same_file(&file, path) {
+        return Ok(Attempt::Retry);
+    }
+    let stale_pid = recorded_pid(&mut file);
+    Ok(Attempt::Acquired(file, stale_pid))
+}
+
+#[cfg(not(unix))]
+fn try_lock(path: &Path) -> io::Result<Attempt> {
+    match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(path)
+    {
+        Ok(file) => Ok(Attempt::Acquired(file, None)),
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+            match File::open(path) {
+                Ok(mut file) => Ok(Attempt::Busy(recorded_pid(&mut file))),
+                // Removed by its holder in the meantime.
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Attempt::Retry),
+                Err(error) => Err(error),
+            }
+        },
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn locked_error(path: &Path, holder: Option<u32>, timeout: Duration) -> StowError {
+    let holder = match holder {
+        Some(pid) if process_exists(pid) => format!("PID {}", pid),
+        // The descriptor outlived the process that wrote its PID, e.g. in a child it started.
+        Some(pid) => format!("a process started by PID {}, which has exited", pid),
+        None => "another process".to_string(),
+    };
+    StowError::Locked(format!(
+        "{:?} is held by {}; gave up after {}s (see --lock-timeout)",
+        path,
+        holder,
+        timeout.as_secs()
+    ))
+}
+
+#[cfg(not(unix))]
+fn locked_error(path: &Path, holder: Option<u32>, timeout: Duration) -> StowError {
+    // Without `flock` there is no telling a live holder from a run that did not finish.
+    StowError::Locked(format!(
+        "{:?} is held by {}; gave up after {}s (see --lock-timeout). If no rustow run is \
+         active, it was left by one that did not finish: remove it",
+        path,
+        holder.map_or("another process".to_string(), |pid| format!("PID {}", pid)),
+        timeout.as_secs()
+    ))
+}
+
+fn recorded_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+fn record_pid(file: &mut File) -> io::Result<()> {
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", std::process::id())?;
+    file.sync_data()
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks whether the process exists.
+    unsafe {
+        libc::kill(pid, 0) == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
@@ -0,0 +1,492 @@
+//! Levelled diagnostic logging grouped by category.
+//!
+//! Events are written as `TIMESTAMP LEVEL category: message` lines to standard error, or
+//! appended to a log file. Which events are written is decided by a [`LogFilter`], built from
+//! the `-v` count and refined by `--log` directives such as `planner=trace,fs=debug`.
+//!
+//! Events recorded before the logger is configured (for example while resource files are
+//! read) are kept and written, subject to the filter, once [`configure`] runs.
+
+use crate::cli::PathDisplayOverride;
+use crate::diagnostics::RedactionTable;
+use crate::error::{ConfigError, FsError, RustowError};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of events kept while the logger is not configured yet.
+const MAX_PENDING_EVENTS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    /// The most detailed level written for a `-v` count when no `--log` directive applies.
+    pub fn for_verbosity(verbosity: u8) -> Level {
+        match verbosity {
+            0 => Level::Error,
+            1 => Level::Info,
+            2 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&self.name().to_ascii_uppercase())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// Command-line and resource file parsing.
+    Cli,
+    /// Resolution of directories and patterns into a configuration.
+    Config,
+    /// Loading and matching of ignore patterns.
+    Ignore,
+    /// Planning of actions, folding and conflict detection.
+    Planner,
+    /// Execution of planned actions.
+    Exec,
+    /// Individual filesystem changes.
+    Fs,
+    /// Changes noticed and applied by `--watch`.
+    Watch,
+}
+
+impl Category {
+    const ALL: [Category; 7] = [
+        Category::Cli,
+        Category::Config,
+        Category::Ignore,
+        Category::Planner,
+        Category::Exec,
+        Category::Fs,
+        Category::Watch,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Cli => "cli",
+            Category::Config => "config",
+            Category::Ignore => "ignore",
+            Category::Planner => "planner",
+            Category::Exec => "exec",
+            Category::Fs => "fs",
+            Category::Watch => "watch",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Most detailed level written per category; `None` turns a category off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogFilter {
+    levels: [Option<Level>; Category::ALL.len()],
+}
+
+impl LogFilter {
+    /// A filter writing `level` and more severe events for every category.
+    pub fn uniform(level: Option<Level>) -> Self {
+        Self {
+            levels: [level; Category::ALL.len()],
+        }
+    }
+
+    /// The filter implied by a `-v` count alone.
+    pub fn for_verbosity(verbosity: u8) -> Self {
+        Self::uniform(Some(Level::for_verbosity(verbosity)))
+    }
+
+    /// Writes at least `level` events for `category`, keeping a more detailed setting.
+    pub fn at_least(mut self, category: Category, level: Level) -> Self {
+        let current = &mut self.levels[category.index()];
+        *current = Some(current.map_or(level, |current| current.max(level)));
+        self
+    }
+
+    /// Applies comma separated `--log` directives on top of this filter.
+    ///
+    /// A directive is either `LEVEL`, which sets every category, or `CATEGORY=LEVEL`. Levels
+    /// are `off`, `error`, `warn`, `info`, `debug` and `trace`. Later directives win.
+    pub fn with_directives(mut self, spec: &str) -> Result<Self, ConfigError> {
+        for directive in spec.split(',').map(str::trim) {
+            if directive.is_empty() {
+                continue;
+            }
+
+            match directive.split_once('=') {
+                Some((category, level)) => {
+                    let category = parse_category(category.trim(), spec)?;
+                    self.levels[category.index()] = parse_level(level.trim(), spec)?;
+                },
+                None => {
+                    self.levels = [parse_level(directive, spec)?; Category::ALL.len()];
+                },
+            }
+        }
+
+        Ok(self)
+    }
+
+    pub fn enabled(&self, category: Category, level: Level) -> bool {
+        self.levels[category.index()].is_some_and(|max| level <= max)
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = ConfigError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        Self::uniform(Some(Level::Error)).with_directives(spec)
+    }
+}
+
+fn parse_level(value: &str, spec: &str) -> Result<Option<Level>, ConfigError> {
+    if value.eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+
+    Level::ALL
+        .into_iter()
+        .find(|level| value.eq_ignore_ascii_case(level.name()))
+        .map(Some)
+        .ok_or_else(|| {
+            ConfigError::InvalidLogFilter(format!(
+                "unknown level '{}' in '{}' (expected off, error, warn, info, debug or trace)",
+                value, spec
+            ))
+        })
+}
+
+fn parse_category(value: &str, spec: &str) -> Result<Category, ConfigError> {
+    Category::ALL
+        .into_iter()
+        .find(|category| value.eq_ignore_ascii_case(category.name()))
+        .ok_or_else(|| {
+            let names: Vec<&str> = Category::ALL
+                .iter()
+                .map(|category| category.name())
+                .collect();
+            ConfigError::InvalidLogFilter(format!(
+                "unknown category '{}' in '{}' (expected one of {})",
+                value,
+                spec,
+                names.join(", ")
+            ))
+        })
+}
+
+/// Where log lines go and which of them are written.
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    pub filter: LogFilter,
+    pub file: Option<PathBuf>,
+}
+
+impl LogSettings {
+    /// Refines `filter`, usually [`LogFilter::for_verbosity`], with optional `--log`
+    /// directives and sets the `--log-file`.
+    pub fn new(
+        mut filter: LogFilter,
+        directives: Option<&str>,
+        file: Option<&Path>,
+    ) -> Result<Self, RustowError> {
+        if let Some(directives) = directives {
+            filter = filter.with_directives(directives)?;
+        }
+
+        Ok(Self {
+            filter,
+            file: file.map(Path::to_path_buf),
+        })
+    }
+}
+
+struct Event {
+    timestamp: SystemTime,
+    category: Category,
+    level: Level,
+    message: String,
+}
+
+enum Sink {
+    Stderr,
+    File(fs::File),
+}
+
+struct Logger {
+    filter: Option<LogFilter>,
+    sink: Sink,
+    redactions: RedactionTable,
+    pending: Vec<Event>,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    filter: None,
+    sink: Sink::Stderr,
+    redactions: RedactionTable::empty(),
+    pending: Vec::new(),
+});
+
+fn with_logger<R>(f: impl FnOnce(&mut Logger) -> R) -> R {
+    let mut logger = LOGGER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&mut logger)
+}
+
+/// Installs `settings` and writes the events recorded so far that pass the new filter.
+///
+/// Paths in messages are shown with their `path_displays` form, like other diagnostics.
+pub(crate) fn configure(
+    settings: &LogSettings,
+    path_displays: &[PathDisplayOverride],
+) -> Result<(), RustowError> {
+    let sink = match &settings.file {
+        Some(path) => Sink::File(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|source| FsError::Io {
+                    path: path.clone(),
+                    source,
+                })?,
+        ),
+        None => Sink::Stderr,
+    };
+
+    with_logger(|logger| {
+        logger.filter = Some(settings.filter);
+        logger.sink = sink;
+        logger.redactions = RedactionTable::new(path_displays);
+        for event in std::mem::take(&mut logger.pending) {
+            if settings.filter.enabled(event.category, event.level) {
+                logger.write(&event);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Replaces the path displays used to redact later messages.
+pub(crate) fn set_path_displays(path_displays: &[PathDisplayOverride]) {
+    with_logger(|logger| logger.redactions = RedactionTable::new(path_displays));
+}
+
+/// Whether an event would currently be written or kept.
+pub fn enabled(category: Category, level: Level) -> bool {
+    with_logger(|logger| match &logger.filter {
+        Some(filter) => filter.enabled(category, level),
+        None => logger.pending.len() < MAX_PENDING_EVENTS,
+    })
+}
+
+/// Records one event; prefer the [`log_event!`] macro, which skips formatting when disabled.
+pub fn log(category: Category, level: Level, message: fmt::Arguments<'_>) {
+    let event = Event {
+        timestamp: SystemTime::now(),
+        category,
+        level,
+        message: message.to_string(),
+    };
+
+    with_logger(|logger| match &logger.filter {
+        Some(filter) => {
+            if filter.enabled(category, level) {
+                logger.write(&event);
+            }
+        },
+        None => {
+            if logger.pending.len() < MAX_PENDING_EVENTS {
+                logger.pending.push(event);
+            }
+        },
+    });
+}
+
+impl Logger {
+    fn write(&mut self, event: &Event) {
+        let line = format!(
+            "{} {:<5} {}: {}\n",
+            format_timestamp(event.timestamp),
+            event.level,
+            event.category,
+            self.redactions.redact(&event.message)
+        );
+
+        // Logging must never turn a successful run into a failed one.
+        let _ = match &mut self.sink {
+            Sink::Stderr => io::stderr().write_all(line.as_bytes()),
+            Sink::File(file) => file.write_all(line.as_bytes()),
+        };
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp with millisecond precision.
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let seconds_of_day = seconds % 86_400;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Converts days since 1970-01-01 to a proleptic Gregorian (year, month, day).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Records an event in a category at a level, formatting the message only when it is enabled.
+///
+/// `log_event!(Planner, Debug, "folding {}", path.display())`
+macro_rules! log_event {
+    ($category:ident, $level:ident, $($arg:tt)+) => {
+        if $crate::logging::enabled(
+            $crate::logging::Category::$category,
+            $crate::logging::Level::$level,
+        ) {
+            $crate::logging::log(
+                $crate::logging::Category::$category,
+                $crate::logging::Level::$level,
+                format_args!($($arg)+),
+            );
+        }
+    };
+}
+
+pub(crate) use log_event;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_verbosity_maps_onto_levels() {
+        let quiet = LogFilter::for_verbosity(0);
+        assert!(quiet.enabled(Category::Planner, Level::Error));
+        assert!(!quiet.enabled(Category::Planner, Level::Warn));
+
+        let verbose = LogFilter::for_verbosity(1);
+        assert!(verbose.enabled(Category::Fs, Level::Info));
+        assert!(!verbose.enabled(Category::Fs, Level::Debug));
+
+        assert!(LogFilter::for_verbosity(2).enabled(Category::Exec, Level::Debug));
+        assert!(LogFilter::for_verbosity(5).enabled(Category::Exec, Level::Trace));
+    }
+
+    #[test]
+    fn test_directives_override_categories_in_order() {
+        let filter = LogFilter::for_verbosity(1)
+            .with_directives("planner=trace, fs=debug,ignore=off")
+            .unwrap();
+        assert!(filter.enabled(Category::Planner, Level::Trace));
+        assert!(filter.enabled(Category::Fs, Level::Debug));
+        assert!(!filter.enabled(Category::Fs, Level::Trace));
+        assert!(!filter.enabled(Category::Ignore, Level::Error));
+        assert!(filter.enabled(Category::Config, Level::Info));
+        assert!(!filter.enabled(Category::Config, Level::Debug));
+
+        let filter = LogFilter::for_verbosity(0)
+            .at_least(Category::Watch, Level::Info)
+            .at_least(Category::Exec, Level::Error);
+        assert!(filter.enabled(Category::Watch, Level::Info));
+        assert!(!filter.enabled(Category::Exec, Level::Warn));
+
+        let filter: LogFilter = "planner=trace,warn".parse().unwrap();
+        assert!(filter.enabled(Category::Planner, Level::Warn));
+        assert!(!filter.enabled(Category::Planner, Level::Info));
+    }
+
+    #[test]
+    fn test_invalid_directives_are_rejected() {
+        for spec in ["loud", "planner=loud", "network=debug"] {
+            let error = spec.parse::<LogFilter>().unwrap_err();
+            assert!(
+                matches!(&error, ConfigError::InvalidLogFilter(message) if message.contains(spec)),
+                "unexpected error for '{}': {:?}",
+                spec,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_timestamp_is_rfc3339_utc() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(951_782_400_250)),
+            "2000-02-29T00:00:00.250Z"
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(1_792_325_045)),
+            "2026-10-18T12:04:05.000Z"
+        );
+    }
+}
@@ -3,8 +3,11 @@ use rustow::cli::Args;
 fn main() {
     let parsed_args = Args::parse_runtime_with_operation_groups();
 
-    if let Err(e) = rustow::run_runtime_parsed(parsed_args) {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    match rustow::run_runtime_parsed(parsed_args) {
+        Ok(outcome) => std::process::exit(outcome.exit_code()),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(e.exit_code());
+        },
     }
 }
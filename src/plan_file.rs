@@ -0,0 +1,354 @@
+use crate::error::{FsError, RustowError, StowError};
+use crate::filesystem::{self, FileKind};
+use crate::stow_types::Plan;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Version of the saved plan layout; bumped whenever it changes incompatibly.
+pub const PLAN_FORMAT_VERSION: u32 = 1;
+
+/// Observed state of a path that a saved plan depends on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PathState {
+    Missing,
+    File {
+        size: u64,
+        modified: Option<SystemTime>,
+    },
+    Directory,
+    Symlink {
+        destination: PathBuf,
+    },
+    Other,
+}
+
+impl PathState {
+    /// Reads the current state of `path` without following a final symlink.
+    pub fn capture(path: &Path) -> std::io::Result<Self> {
+        let fs = filesystem::current();
+        let stat = match fs.lstat(path) {
+            Ok(stat) => stat,
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory
+                ) =>
+            {
+                return Ok(Self::Missing);
+            },
+            Err(error) => return Err(error),
+        };
+
+        let state = match stat.kind {
+            FileKind::Symlink => Self::Symlink {
+                destination: fs.read_link(path)?,
+            },
+            FileKind::Directory => Self::Directory,
+            FileKind::File => Self::File {
+                size: stat.len,
+                modified: stat.modified,
+            },
+            FileKind::Other => Self::Other,
+        };
+
+        Ok(state)
+    }
+}
+
+impl std::fmt::Display for PathState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "missing"),
+            Self::File { size, .. } => write!(f, "file ({} bytes)", size),
+            Self::Directory => write!(f, "directory"),
+            Self::Symlink { destination } => write!(f, "symlink -> {}", destination.display()),
+            Self::Other => write!(f, "special file"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathFingerprint {
+    pub path: PathBuf,
+    pub state: PathState,
+}
+
+impl PathFingerprint {
+    pub fn capture(path: PathBuf) -> Result<Self, RustowError> {
+        let state = PathState::capture(&path).map_err(|source| FsError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Self { path, state })
+    }
+}
+
+/// A plan written by `--plan-out` for a later `--apply`.
+///
+/// Besides the plan itself it records the state of every target path, target ancestor and
+/// package item the plan touches, so applying can refuse when any of them changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPlan {
+    pub format_version: u32,
+    pub plan: Plan,
+    pub fingerprints: Vec<PathFingerprint>,
+}
+
+impl SavedPlan {
+    /// Pairs `plan` with fingerprints of the paths it depends on, as they are right now.
+    pub fn capture(plan: Plan) -> Result<Self, RustowError> {
+        let fingerprints = relevant_paths(&plan)
+            .into_iter()
+            .map(PathFingerprint::capture)
+            .collect::<Result<Vec<_>, RustowError>>()?;
+
+        Ok(Self {
+            format_version: PLAN_FORMAT_VERSION,
+            plan,
+            fingerprints,
+        })
+    }
+
+    /// Fingerprints whose path no longer matches the state recorded at planning time.
+    pub fn stale_fingerprints(&self) -> Vec<&PathFingerprint> {
+        self.fingerprints
+            .iter()
+            .filter(|fingerprint| {
+                PathState::capture(&fingerprint.path)
+                    .map_or(true, |state| state != fingerprint.state)
+            })
+            .collect()
+    }
+
+    /// Returns the saved plan if the filesystem still matches it.
+    pub fn into_current_plan(self) -> Result<Plan, RustowError> {
+        let stale_paths: Vec<String> = self
+            .stale_fingerprints()
+            .iter()
+            .map(|fingerprint| fingerprint.path.display().to_string())
+            .collect();
+        if !stale_paths.is_empty() {
+            return Err(RustowError::Stow(StowError::StalePlan(format!(
+                "{} path(s) changed since the plan was made: {}; re-run with --plan-out",
+                stale_paths.len(),
+                stale_paths.join(", ")
+            ))));
+        }
+
+        Ok(self.plan)
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<(), RustowError> {
+        let mut contents = serde_json::to_string_pretty(self)
+            .map_err(|error| invalid_plan_file_error(path, &error))?;
+        contents.push('\n');
+        std::fs::write(path, contents).map_err(|source| {
+            FsError::Io {
+                path: path.to_path_buf(),
+                source,
+            }
+            .into()
+        })
+    }
+
+    pub fn read_from(path: &Path) -> Result<Self, RustowError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| FsError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let saved_plan: Self = serde_json::from_str(&contents)
+            .map_err(|error| invalid_plan_file_error(path, &error))?;
+
+        if saved_plan.format_version != PLAN_FORMAT_VERSION {
+            return Err(RustowError::Stow(StowError::InvalidPlanFile(format!(
+                "{} uses plan format version {}, expected {}",
+                path.display(),
+                saved_plan.format_version,
+                PLAN_FORMAT_VERSION
+            ))));
+        }
+
+        Ok(saved_plan)
+    }
+}
+
+fn relevant_paths(plan: &Plan) -> BTreeSet<PathBuf> {
+    let mut paths = BTreeSet::new();
+
+    for action in plan.actions() {
+        for ancestor in action.target_path.ancestors() {
+            if !ancestor.starts_with(&plan.config.target_dir) {
+                break;
+            }
+            paths.insert(ancestor.to_path_buf());
+        }
+
+        if let Some(source_item) = &action.source_item {
+            paths.insert(source_item.source_path.clone());
+        }
+    }
+
+    paths
+}
+
+fn invalid_plan_file_error(path: &Path, error: &serde_json::Error) -> RustowError {
+    RustowError::Stow(StowError::InvalidPlanFile(format!(
+        "{}: {}",
+        path.display(),
+        error
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, PackageOperation, StowMode};
+    use crate::stow_types::{ActionType, StowItem, StowItemType, TargetAction};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn plan_linking(target_dir: &Path, source_path: &Path) -> Plan {
+        Plan {
+            config: Config {
+                target_dir: target_dir.to_path_buf(),
+                stow_dir: source_path
+                    .parent()
+                    .unwrap()
+                    .parent()
+                    .unwrap()
+                    .to_path_buf(),
+                stow_path: Vec::new(),
+                packages: vec!["pkg".to_string()],
+                mode: StowMode::Stow,
+                stow: false,
+                compat: false,
+                adopt: false,
+                git_commit: false,
+                no_folding: false,
+                no_fold: Vec::new(),
+                dotfiles: false,
+                dir_modes: Vec::new(),
+                sensitive: Vec::new(),
+                insecure_permissions: Default::default(),
+                overrides: Vec::new(),
+                defers: Vec::new(),
+                ignore_patterns: vec![regex::Regex::new(r"^\.git$").unwrap()],
+                simulate: false,
+                incremental: false,
+                force: false,
+                lock_timeout: std::time::Duration::from_secs(10),
+                verbosity: 0,
+                home_dir: target_dir.to_path_buf(),
+            },
+            operations: vec![PackageOperation {
+                mode: StowMode::Stow,
+                packages: vec!["pkg".to_string()],
+            }],
+            delete_actions: Vec::new(),
+            stow_actions: vec![TargetAction {
+                source_item: Some(StowItem {
+                    package_relative_path: PathBuf::from("file"),
+                    source_path: source_path.to_path_buf(),
+                    item_type: StowItemType::File,
+                    target_name_after_dotfiles_processing: PathBuf::from("file"),
+                }),
+                target_path: target_dir.join("nested/file"),
+                link_target_path: Some(PathBuf::from("../../stow/pkg/file")),
+                action_type: ActionType::CreateSymlink,
+                conflict_details: None,
+            }],
+            refold: false,
+        }
+    }
+
+    #[test]
+    fn test_saved_plan_round_trips_through_json() {
+        let dir = tempdir().unwrap();
+        let target_dir = dir.path().join("target");
+        let source_path = dir.path().join("stow/pkg/file");
+        fs::create_dir_all(target_dir.join("nested")).unwrap();
+        fs::create_dir_all(source_path.parent().unwrap()).unwrap();
+        fs::write(&source_path, "content").unwrap();
+        let plan_path = dir.path().join("plan.json");
+
+        SavedPlan::capture(plan_linking(&target_dir, &source_path))
+            .unwrap()
+            .write_to(&plan_path)
+            .unwrap();
+        let saved_plan = SavedPlan::read_from(&plan_path).unwrap();
+
+        assert_eq!(saved_plan.format_version, PLAN_FORMAT_VERSION);
+        assert_eq!(
+            saved_plan.plan.config.ignore_patterns[0].as_str(),
+            r"^\.git$"
+        );
+        assert_eq!(
+            saved_plan.plan.stow_actions[0].target_path,
+            target_dir.join("nested/file")
+        );
+        let fingerprinted: Vec<&Path> = saved_plan
+            .fingerprints
+            .iter()
+            .map(|fingerprint| fingerprint.path.as_path())
+            .collect();
+        assert!(fingerprinted.contains(&target_dir.as_path()));
+        assert!(fingerprinted.contains(&target_dir.join("nested").as_path()));
+        assert!(fingerprinted.contains(&target_dir.join("nested/file").as_path()));
+        assert!(fingerprinted.contains(&source_path.as_path()));
+        assert!(saved_plan.into_current_plan().is_ok());
+    }
+
+    #[test]
+    fn test_saved_plan_detects_changed_target_and_package_item() {
+        let dir = tempdir().unwrap();
+        let target_dir = dir.path().join("target");
+        let source_path = dir.path().join("stow/pkg/file");
+        fs::create_dir_all(target_dir.join("nested")).unwrap();
+        fs::create_dir_all(source_path.parent().unwrap()).unwrap();
+        fs::write(&source_path, "content").unwrap();
+        let saved_plan = SavedPlan::capture(plan_linking(&target_dir, &source_path)).unwrap();
+
+        fs::write(target_dir.join("nested/file"), "unmanaged").unwrap();
+        fs::write(&source_path, "longer content").unwrap();
+
+        let stale: Vec<&Path> = saved_plan
+            .stale_fingerprints()
+            .iter()
+            .map(|fingerprint| fingerprint.path.as_path())
+            .collect();
+        assert_eq!(
+            stale,
+            vec![source_path.clone(), target_dir.join("nested/file")]
+        );
+        assert!(matches!(
+            saved_plan.into_current_plan(),
+            Err(RustowError::Stow(StowError::StalePlan(_)))
+        ));
+    }
+
+    #[test]
+    fn test_read_from_rejects_unknown_format_version() {
+        let dir = tempdir().unwrap();
+        let target_dir = dir.path().join("target");
+        let source_path = dir.path().join("stow/pkg/file");
+        fs::create_dir_all(&target_dir).unwrap();
+        let plan_path = dir.path().join("plan.json");
+        let mut saved_plan = SavedPlan::capture(plan_linking(&target_dir, &source_path)).unwrap();
+        saved_plan.format_version = PLAN_FORMAT_VERSION + 1;
+        saved_plan.write_to(&plan_path).unwrap();
+
+        assert!(matches!(
+            SavedPlan::read_from(&plan_path),
+            Err(RustowError::Stow(StowError::InvalidPlanFile(_)))
+        ));
+
+        fs::write(&plan_path, "not json").unwrap();
+        assert!(matches!(
+            SavedPlan::read_from(&plan_path),
+            Err(RustowError::Stow(StowError::InvalidPlanFile(_)))
+        ));
+    }
+}
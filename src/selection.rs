@@ -0,0 +1,361 @@
+//! Package selection: `--all`, shell-style globs, `--packages-from` and `--exclude-package`.
+//!
+//! `--all` and `--packages-from` select packages for the `-S`, `-D` or `-R` operation they
+//! appear in. [`crate::cli`] records where they appear with markers in the operation's package
+//! list; markers start with a NUL byte, which no command-line argument can contain. The markers
+//! and globs are replaced by package names before the packages are validated.
+
+use crate::config::{Config, PackageOperation};
+use crate::error::{ConfigError, FsError, RustowError, StowError};
+use crate::fs_utils;
+use crate::ignore::{self, IgnorePatterns};
+use regex::Regex;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Marker for `--all` in an operation's package list.
+pub(crate) const ALL_MARKER: &str = "\0all";
+
+const PACKAGES_FROM_MARKER_PREFIX: &str = "\0packages-from=";
+
+/// Marker for the `index`th `--packages-from` in an operation's package list.
+pub(crate) fn packages_from_marker(index: usize) -> String {
+    format!("{}{}", PACKAGES_FROM_MARKER_PREFIX, index)
+}
+
+/// What `--all`, `--packages-from` and `--exclude-package` asked for.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PackageSelection {
+    pub(crate) all: bool,
+    /// Files listing packages, `-` for standard input, in command-line order.
+    pub(crate) packages_from: Vec<PathBuf>,
+    pub(crate) exclude: Vec<String>,
+}
+
+impl PackageSelection {
+    /// Replaces markers and globs in `operations` with the packages they select, then removes
+    /// excluded packages and operations left without any.
+    pub(crate) fn expand(
+        &self,
+        config: &Config,
+        operations: &mut Vec<PackageOperation>,
+    ) -> Result<(), RustowError> {
+        let mut markers = Vec::new();
+        if self.all {
+            markers.push(ALL_MARKER.to_string());
+        }
+        markers.extend((0..self.packages_from.len()).map(packages_from_marker));
+        // Without operation groups, e.g. through `run`, the markers have no place yet.
+        for marker in markers {
+            if !operations
+                .iter()
+                .any(|operation| operation.packages.contains(&marker))
+                && let Some(last) = operations.last_mut()
+            {
+                last.packages.push(marker);
+            }
+        }
+        if !operations
+            .iter()
+            .flat_map(|operation| &operation.packages)
+            .any(|package| is_selector(package))
+            && self.exclude.is_empty()
+        {
+            return Ok(());
+        }
+
+        let exclude = self
+            .exclude
+            .iter()
+            .map(|pattern| glob_regex(pattern))
+            .collect::<Vec<_>>();
+        let mut listed = vec![None; self.packages_from.len()];
+        for operation in operations.iter_mut() {
+            let mut packages = Vec::new();
+            for selector in std::mem::take(&mut operation.packages) {
+                let selected = if selector == ALL_MARKER {
+                    package_names(config)?
+                } else if let Some(index) = selector
+                    .strip_prefix(PACKAGES_FROM_MARKER_PREFIX)
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .filter(|index| *index < listed.len())
+                {
+                    if listed[index].is_none() {
+                        listed[index] = Some(read_package_list(&self.packages_from[index])?);
+                    }
+                    let mut selected = Vec::new();
+                    for entry in listed[index].iter().flatten() {
+                        selected.extend(expand_selector(config, entry)?);
+                    }
+                    selected
+                } else {
+                    expand_selector(config, &selector)?
+                };
+                for package in selected {
+                    if !packages.contains(&package)
+                        && !exclude.iter().any(|pattern| pattern.is_match(&package))
+                    {
+                        packages.push(package);
+                    }
+                }
+            }
+            operation.packages = packages;
+        }
+
+        operations.retain(|operation| !operation.packages.is_empty());
+        if operations.is_empty() {
+            return Err(ConfigError::InvalidOperation(
+                "no packages left to operate on after --exclude-package".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Every package in the stow directories: directories that are neither hidden, ignored nor the
+/// target directory, sorted by name. Namespaces are replaced by the packages in them.
+pub(crate) fn package_names(config: &Config) -> Result<Vec<String>, RustowError> {
+    let ignore_patterns = stow_dirs_ignore_patterns(config)?;
+    let mut packages = Vec::new();
+    let mut pending = vec![String::new()];
+    while let Some(namespace) = pending.pop() {
+        for package in directory_names(config, &namespace, &ignore_patterns)? {
+            if fs_utils::is_namespace_dir(&config.package_dir(&package)) {
+                pending.push(package);
+            } else {
+                packages.push(package);
+            }
+        }
+    }
+    packages.sort();
+    Ok(packages)
+}
+
+/// The directories in `namespace` in any stow directory, prefixed with it, that could be packages
+/// or namespaces. Each stow directory's entries are matched against its own ignore patterns.
+fn directory_names(
+    config: &Config,
+    namespace: &str,
+    ignore_patterns: &[(&Path, IgnorePatterns)],
+) -> Result<Vec<String>, RustowError> {
+    let mut names = Vec::new();
+    for (stow_dir, ignore_patterns) in ignore_patterns {
+        let dir = stow_dir.join(namespace);
+        if !namespace.is_empty() && !dir.is_dir() {
+            continue;
+        }
+        for entry in fs_utils::read_dir(&dir)? {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let package = if namespace.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", namespace, name)
+            };
+            if !name.starts_with('.')
+                && path.is_dir()
+                && path != config.target_dir
+                && !ignore::is_ignored(&Path::new("/").join(&package), name, ignore_patterns)
+                && !names.contains(&package)
+            {
+                names.push(package);
+            }
+        }
+    }
+    Ok(names)
+}
+
+fn is_selector(package: &str) -> bool {
+    package.starts_with('\0') || is_glob(package)
+}
+
+fn is_glob(package: &str) -> bool {
+    package.contains(['*', '?', '['])
+}
+
+/// The packages a command-line or listed selector names: itself, or what its glob matches.
+fn expand_selector(config: &Config, selector: &str) -> Result<Vec<String>, RustowError> {
+    if !is_glob(selector) {
+        return Ok(vec![selector.to_string()]);
+    }
+
+    // Matched a component at a time, so `desktop/*` lists the directories in `desktop`.
+    let mut matches = vec![String::new()];
+    for component in selector
+        .split('/')
+        .filter(|component| !component.is_empty())
+    {
+        let mut next = Vec::new();
+        for parent in &matches {
+            let join = |name: &str| {
+                if parent.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{}/{}", parent, name)
+                }
+            };
+            if !is_glob(component) {
+                if config.package_dir(&join(component)).is_dir() {
+                    next.push(join(component));
+                }
+                continue;
+            }
+            let names = if parent.is_empty() {
+                directory_names(config, parent, &stow_dirs_ignore_patterns(config)?)?
+            } else {
+                subdirectory_names(config, parent)?
+            };
+            let pattern = glob_regex(component);
+            next.extend(
+                names
+                    .iter()
+                    .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                    .filter(|name| pattern.is_match(name))
+                    .map(|name| join(name)),
+            );
+        }
+        matches = next;
+    }
+
+    // A namespace is no package; `desktop/*` names the packages in it.
+    matches.retain(|name| !fs_utils::is_namespace_dir(&config.package_dir(name)));
+    if matches.is_empty() {
+        return Err(StowError::PackageNotFound(selector.to_string()).into());
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+fn subdirectory_names(config: &Config, parent: &str) -> Result<Vec<String>, RustowError> {
+    let mut names = Vec::new();
+    for entry in stow_dirs_entries(config, parent)? {
+        let path = entry.path();
+        if let Some(name) = path.file_name().and_then(|name| name.to_str())
+            && path.is_dir()
+            && !names.iter().any(|known| known == name)
+        {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// The entries of `relative` in every stow directory that has it.
+fn stow_dirs_entries(
+    config: &Config,
+    relative: &str,
+) -> Result<Vec<crate::filesystem::DirEntry>, RustowError> {
+    let mut entries = Vec::new();
+    for stow_dir in config.stow_dirs() {
+        let dir = stow_dir.join(relative);
+        if relative.is_empty() || dir.is_dir() {
+            entries.extend(fs_utils::read_dir(&dir)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// The ignore patterns of every stow directory, in precedence order.
+fn stow_dirs_ignore_patterns(config: &Config) -> Result<Vec<(&Path, IgnorePatterns)>, RustowError> {
+    config
+        .stow_dirs()
+        .into_iter()
+        .map(|stow_dir| {
+            IgnorePatterns::load_for_stow_dir(stow_dir, &config.home_dir)
+                .map(|patterns| {
+                    (
+                        stow_dir,
+                        patterns.with_extra_patterns(&config.ignore_patterns),
+                    )
+                })
+                .map_err(|e| {
+                    RustowError::Ignore(crate::error::IgnoreError::LoadPatternsError(format!(
+                        "Failed to load ignore patterns for the stow directory {:?}: {:?}",
+                        stow_dir, e
+                    )))
+                })
+        })
+        .collect()
+}
+
+/// Package names and globs listed in `path`, or standard input for `-`: one per line, with
+/// blank lines and `#` comments skipped.
+fn read_package_list(path: &Path) -> Result<Vec<String>, RustowError> {
+    let mut contents = String::new();
+    let read = if path == Path::new("-") {
+        std::io::stdin().read_to_string(&mut contents).map(|_| ())
+    } else {
+        std::fs::read_to_string(path).map(|read| contents = read)
+    };
+    read.map_err(|source| FsError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Translates a shell-style glob into an anchored regex: `*` and `?` match within one path
+/// component, and `[...]` is a character class, negated with a leading `!` or `^`.
+fn glob_regex(glob: &str) -> Regex {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.clone().take_while(|&c| c != ']').collect();
+                if class.is_empty() || chars.clone().nth(class.chars().count()).is_none() {
+                    regex.push_str(r"\[");
+                    continue;
+                }
+                chars.nth(class.chars().count());
+                regex.push('[');
+                let class = match class.strip_prefix(['!', '^']) {
+                    Some(negated) => {
+                        regex.push('^');
+                        negated
+                    },
+                    None => class.as_str(),
+                };
+                for c in class.chars() {
+                    if matches!(c, '\\' | '[' | ']' | '^' | '&' | '~') {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).expect("escaped glob is a valid regex")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_regex() {
+        let matches = |glob: &str, name: &str| glob_regex(glob).is_match(name);
+        assert!(matches("x11-*", "x11-fonts"));
+        assert!(!matches("x11-*", "x11"));
+        assert!(matches("v?m", "vim"));
+        assert!(matches("[nv]vim", "nvim"));
+        assert!(!matches("[!nv]vim", "nvim"));
+        assert!(!matches("*", "group/pkg"));
+        assert!(matches("a.b", "a.b") && !matches("a.b", "axb"));
+        assert!(matches("[x", "[x"));
+    }
+}
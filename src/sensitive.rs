@@ -0,0 +1,233 @@
+//! Permission checks for package items linked to sensitive target paths.
+//!
+//! Git does not keep file modes beyond the executable bit, so a checked-out `~/.ssh/id_ed25519`
+//! or `~/.netrc` is often readable by everyone. Items whose target path matches one of
+//! [`Config::sensitive`] must not be accessible to group or others, and the stow directories
+//! above them must not be writable by group or others.
+
+use crate::config::Config;
+use crate::dotfiles;
+use crate::error::RustowError;
+use crate::fs_utils::{self, RawStowItemType};
+use crate::stow_types::{ActionType, Plan, TargetAction};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Target paths, relative to the target directory, checked unless the configuration says
+/// otherwise.
+pub const DEFAULT_SENSITIVE_PATTERNS: &[&str] = &[
+    r"^\.ssh(/|$)",
+    r"^\.gnupg(/|$)",
+    r"^\.netrc$",
+    r"^\.pgpass$",
+    r"^\.git-credentials$",
+    r"^\.aws/credentials$",
+    r"^\.docker/config\.json$",
+    r"^\.kube/config$",
+];
+
+/// What to do about sensitive items with insecure permissions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum InsecurePermissions {
+    /// Stow them and print a warning.
+    #[default]
+    Warn,
+    /// Turn their links into conflicts, so nothing is stowed.
+    Refuse,
+    /// Remove the offending permission bits in the stow directory, then stow them.
+    Fix,
+}
+
+/// A path in the stow directory whose permissions are too open for what links to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct InsecurePath {
+    pub(crate) path: PathBuf,
+    pub(crate) mode: u32,
+    /// Permission bits `path` must not have.
+    pub(crate) forbidden: u32,
+    /// The planned link that exposes `path`.
+    pub(crate) link: PathBuf,
+}
+
+impl InsecurePath {
+    pub(crate) fn secure_mode(&self) -> u32 {
+        self.mode & !self.forbidden
+    }
+}
+
+/// Items or sensitive directories: nothing for group and others.
+const PRIVATE: u32 = 0o077;
+/// Other directories above them: no writing for group and others.
+const NOT_SHARED_WRITABLE: u32 = 0o022;
+
+/// Insecure paths behind the links `plan` creates, each reported once, sorted by path.
+pub(crate) fn find_insecure(plan: &Plan) -> Vec<InsecurePath> {
+    find_insecure_in(&plan.stow_actions, &plan.config)
+}
+
+/// Insecure paths behind the links `stow_actions` create, each reported once, sorted by path.
+pub(crate) fn find_insecure_in(
+    stow_actions: &[TargetAction],
+    config: &Config,
+) -> Vec<InsecurePath> {
+    let mut found = BTreeMap::new();
+    for action in stow_actions {
+        for insecure in insecure_paths_for_action(action, config) {
+            found.entry(insecure.path.clone()).or_insert(insecure);
+        }
+    }
+    found.into_values().collect()
+}
+
+/// Turns every link exposing an insecure path into a conflict, as `--insecure-permissions=refuse`
+/// asks.
+pub(crate) fn refuse_insecure(plan: &mut Plan) {
+    let config = plan.config.clone();
+    for action in &mut plan.stow_actions {
+        let insecure = insecure_paths_for_action(action, &config);
+        if insecure.is_empty() {
+            continue;
+        }
+        let details = insecure
+            .iter()
+            .map(|insecure| format!("{:?} is {:04o}", insecure.path, insecure.mode))
+            .collect::<Vec<_>>()
+            .join(", ");
+        action.action_type = ActionType::Conflict;
+        action.conflict_details = Some(format!(
+            "Refusing to link a sensitive path with insecure permissions: {}",
+            details
+        ));
+    }
+}
+
+/// Removes the forbidden permission bits from every path in `insecure`.
+pub(crate) fn fix_insecure(insecure: &[InsecurePath]) -> Result<(), RustowError> {
+    for insecure in insecure {
+        fs_utils::set_permission_mode(&insecure.path, insecure.secure_mode())?;
+    }
+    Ok(())
+}
+
+fn is_sensitive(target_relative_path: &Path, config: &Config) -> bool {
+    let path = target_relative_path.to_string_lossy();
+    config
+        .sensitive
+        .iter()
+        .any(|pattern| pattern.is_match(&path))
+}
+
+fn insecure_paths_for_action(action: &TargetAction, config: &Config) -> Vec<InsecurePath> {
+    let mut insecure = Vec::new();
+    if action.action_type != ActionType::CreateSymlink || config.sensitive.is_empty() {
+        return insecure;
+    }
+    let Some(source_item) = &action.source_item else {
+        return insecure;
+    };
+    let Ok(target_relative_path) = action.target_path.strip_prefix(&config.target_dir) else {
+        return insecure;
+    };
+    let Some(package_root) = source_item
+        .source_path
+        .ancestors()
+        .nth(source_item.package_relative_path.components().count())
+    else {
+        return insecure;
+    };
+
+    // A folded directory link exposes everything below the package directory too.
+    let mut exposed = vec![(
+        source_item.source_path.clone(),
+        target_relative_path.to_path_buf(),
+    )];
+    if fs_utils::is_directory(&source_item.source_path)
+        && let Ok(items) = fs_utils::walk_package_dir(&source_item.source_path)
+    {
+        exposed.extend(
+            items
+                .into_iter()
+                .filter(|item| item.item_type != RawStowItemType::Symlink)
+                .map(|item| {
+                    let target = target_relative_path.join(dotfiles::process_item_name(
+                        item.package_relative_path.to_str().unwrap_or(""),
+                        config.dotfiles,
+                    ));
+                    (item.absolute_path, target)
+                }),
+        );
+    }
+
+    for (path, target) in exposed {
+        if !is_sensitive(&target, config) {
+            continue;
+        }
+        let mut check = |path: &Path, forbidden: u32| {
+            if let Some(mode) = fs_utils::permission_mode(path)
+                && mode & forbidden != 0
+                && !insecure
+                    .iter()
+                    .any(|found: &InsecurePath| found.path == path)
+            {
+                insecure.push(InsecurePath {
+                    path: path.to_path_buf(),
+                    mode,
+                    forbidden,
+                    link: action.target_path.clone(),
+                });
+            }
+        };
+        check(&path, PRIVATE);
+        for ancestor in path.ancestors().skip(1).take_while(|ancestor| {
+            config
+                .stow_dirs()
+                .iter()
+                .any(|stow_dir| ancestor.starts_with(stow_dir))
+        }) {
+            let forbidden = match ancestor.strip_prefix(package_root) {
+                Ok(relative) if !relative.as_os_str().is_empty() => {
+                    let target = dotfiles::process_item_name(
+                        relative.to_str().unwrap_or(""),
+                        config.dotfiles,
+                    );
+                    if is_sensitive(Path::new(&target), config) {
+                        PRIVATE
+                    } else {
+                        NOT_SHARED_WRITABLE
+                    }
+                },
+                _ => NOT_SHARED_WRITABLE,
+            };
+            check(ancestor, forbidden);
+        }
+    }
+    insecure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Args;
+
+    #[test]
+    fn test_default_sensitive_patterns() {
+        let args = Args::parse_from(["rustow", "--sensitive=^\\.config/rclone(/|$)", "pkg"]);
+        let config = Config::from_args(args).unwrap();
+        for path in [
+            ".ssh",
+            ".ssh/id_ed25519",
+            ".gnupg/private-keys-v1.d",
+            ".netrc",
+        ] {
+            assert!(is_sensitive(Path::new(path), &config), "{}", path);
+        }
+        assert!(is_sensitive(
+            Path::new(".config/rclone/rclone.conf"),
+            &config
+        ));
+        for path in [".sshrc", ".netrc.bak", ".config/git/config", "ssh"] {
+            assert!(!is_sensitive(Path::new(path), &config), "{}", path);
+        }
+    }
+}
@@ -1,11 +1,16 @@
-use crate::config::Config;
+use crate::config::{Config, DirMode, PackageOperation, StowMode};
 use crate::dotfiles;
 use crate::error::{FsError, RustowError, StowError};
+use crate::filesystem::{self, FileKind, MemoryFilesystem};
 use crate::fs_utils::{self};
 use crate::ignore::{self, IgnorePatterns};
+use crate::logging::log_event;
 use crate::path_utils::{normalize_path_components, resolve_symlink_target};
-use std::collections::{HashMap, HashSet};
+use crate::plan_file::PathFingerprint;
+use crate::sensitive::{self, InsecurePermissions};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 mod conflict_resolver {
     use crate::stow::{ActionType, TargetAction};
@@ -52,8 +57,14 @@ mod conflict_resolver {
                 if action_indices.len() > 1
                     && !Self::are_target_actions_compatible(actions, &action_indices)
                 {
+                    // Every source is named, as they may come from different stow directories.
+                    let sources = action_indices
+                        .iter()
+                        .filter_map(|index| actions[*index].source_item.as_ref())
+                        .map(|source_item| source_item.source_path.display().to_string())
+                        .collect::<Vec<_>>();
                     for index in action_indices {
-                        Self::mark_action_as_conflict(&mut actions[index]);
+                        Self::mark_action_as_conflict(&mut actions[index], &sources);
                     }
                 }
             }
@@ -97,16 +108,16 @@ mod conflict_resolver {
                 })
         }
 
-        fn mark_action_as_conflict(action: &mut TargetAction) {
+        fn mark_action_as_conflict(action: &mut TargetAction, sources: &[String]) {
             action.action_type = ActionType::Conflict;
             if action.conflict_details.is_none() {
-                let sources_involved = action
-                    .source_item
-                    .as_ref()
-                    .map(|si| si.source_path.display().to_string())
-                    .unwrap_or_else(|| "Unknown source".to_string());
+                let sources_involved = if sources.is_empty() {
+                    "Unknown source".to_string()
+                } else {
+                    sources.join(", ")
+                };
                 action.conflict_details = Some(format!(
-                    "Inter-package conflict: Multiple packages attempt to manage target path {:?}. Source: {}.",
+                    "Inter-package conflict: Multiple packages attempt to manage target path {:?}. Sources: {}.",
                     action.target_path, sources_involved
                 ));
             }
@@ -134,19 +145,19 @@ mod conflict_resolver {
                     continue;
                 }
 
-                if let Some(parent_target_path) = action.target_path.parent() {
-                    if parent_conflicts.contains(parent_target_path) {
-                        let conflict_message = format!(
-                            "Parent path {:?} is in conflict, so child item {:?} is also a conflict.",
-                            parent_target_path,
-                            action
-                                .source_item
-                                .as_ref()
-                                .map(|si| si.target_name_after_dotfiles_processing.clone())
-                                .unwrap_or_else(|| PathBuf::from("UnknownSource"))
-                        );
-                        child_conflict_updates.push((i, conflict_message));
-                    }
+                if let Some(parent_target_path) = action.target_path.parent()
+                    && parent_conflicts.contains(parent_target_path)
+                {
+                    let conflict_message = format!(
+                        "Parent path {:?} is in conflict, so child item {:?} is also a conflict.",
+                        parent_target_path,
+                        action
+                            .source_item
+                            .as_ref()
+                            .map(|si| si.target_name_after_dotfiles_processing.clone())
+                            .unwrap_or_else(|| PathBuf::from("UnknownSource"))
+                    );
+                    child_conflict_updates.push((i, conflict_message));
                 }
             }
 
@@ -239,7 +250,8 @@ mod pattern_matcher {
 }
 
 pub use crate::stow_types::{
-    ActionType, StowItem, StowItemType, TargetAction, TargetActionReport, TargetActionReportStatus,
+    ActionType, Plan, SimulatedApply, StowItem, StowItemType, TargetAction, TargetActionReport,
+    TargetActionReportStatus,
 };
 use conflict_resolver::ConflictResolver;
 use pattern_matcher::PatternMatcher;
@@ -249,9 +261,16 @@ fn plan_actions(
     config: &Config,
     current_ignore_patterns: &IgnorePatterns,
 ) -> Result<Vec<TargetAction>, RustowError> {
-    let package_path = validated_package_path(&config.stow_dir, package_name)?;
+    let package_path = validated_package_path(config.package_stow_dir(package_name), package_name)?;
 
     let raw_items = load_package_items(&package_path, package_name)?;
+    log_event!(
+        Planner,
+        Info,
+        "planning stow of package '{}' ({} item(s))",
+        package_name,
+        raw_items.len()
+    );
     let mut actions = Vec::new();
 
     // Process each item to create initial actions
@@ -294,6 +313,15 @@ fn process_item_for_stow(
     let target_path_abs = config.target_dir.join(&processed_target_relative_path);
     let stow_item = create_stow_item_from_raw(raw_item, processed_target_relative_path);
 
+    if let Some(conflict) = create_action_for_protected_target(
+        &stow_item,
+        &target_path_abs,
+        config,
+        ActionType::Conflict,
+    ) {
+        return Ok(vec![conflict]);
+    }
+
     if let Some(actions) =
         plan_split_open_actions_if_needed(&stow_item, &target_path_abs, config, package_name)?
     {
@@ -309,6 +337,13 @@ fn process_item_for_stow(
         config,
         package_name,
     )?;
+    log_event!(
+        Planner,
+        Trace,
+        "{:?} {}",
+        action.action_type,
+        action.target_path.display()
+    );
 
     Ok(vec![action])
 }
@@ -324,7 +359,12 @@ fn calculate_link_target(
 
     pathdiff::diff_paths(&stow_item.source_path, relative_to_target_parent).unwrap_or_else(|| {
         PathBuf::from("..")
-            .join(config.stow_dir.file_name().unwrap_or_default())
+            .join(
+                config
+                    .package_stow_dir(package_name)
+                    .file_name()
+                    .unwrap_or_default(),
+            )
             .join(package_name)
             .join(&stow_item.package_relative_path)
     })
@@ -388,8 +428,9 @@ fn source_item_for_action(
             ActionType::AdoptFile | ActionType::AdoptDirectory
         )
     {
-        source_item.source_path = canonical_package_path(&config.stow_dir, package_name)?
-            .join(&stow_item.package_relative_path);
+        source_item.source_path =
+            canonical_package_path(config.package_stow_dir(package_name), package_name)?
+                .join(&stow_item.package_relative_path);
     }
 
     Ok(source_item)
@@ -401,22 +442,9 @@ fn check_directory_for_non_stow_files(
     target_path_abs: &Path,
     config: &Config,
 ) -> Result<bool, RustowError> {
-    let entries = std::fs::read_dir(target_path_abs).map_err(|e| {
-        RustowError::Fs(FsError::Io {
-            path: target_path_abs.to_path_buf(),
-            source: e,
-        })
-    })?;
-
-    for entry in entries {
-        let entry = entry.map_err(|e| {
-            RustowError::Fs(FsError::Io {
-                path: target_path_abs.to_path_buf(),
-                source: e,
-            })
-        })?;
+    for entry in fs_utils::read_dir(target_path_abs)? {
         let entry_path = entry.path();
-        if is_non_stow_entry(&entry_path, &config.stow_dir) {
+        if is_non_stow_entry(&entry_path, &config.stow_dirs()) {
             return Ok(true);
         }
     }
@@ -425,14 +453,14 @@ fn check_directory_for_non_stow_files(
 }
 
 /// Check if a directory entry represents a non-stow managed file
-fn is_non_stow_entry(entry_path: &Path, stow_dir: &Path) -> bool {
+fn is_non_stow_entry(entry_path: &Path, stow_dirs: &[&Path]) -> bool {
     // If there's any file that's not a stow-managed symlink, it's a conflict
     if !fs_utils::is_symlink(entry_path) {
         return true;
     }
 
     // Check if it's a stow-managed symlink
-    match fs_utils::is_stow_symlink(entry_path, stow_dir) {
+    match fs_utils::is_stow_symlink(entry_path, stow_dirs) {
         Ok(Some(_)) => {
             // It's a stow-managed symlink, not a conflict
             false
@@ -450,6 +478,20 @@ fn handle_directory_conflict(
     config: &Config,
 ) -> Result<(ActionType, Option<String>, Option<PathBuf>), RustowError> {
     if config.adopt && check_directory_for_non_stow_files(target_path_abs, config)? {
+        // Adopting moves the whole directory, so never adopt one holding a marked directory;
+        // its entries are planned one by one instead.
+        if let Some((protected_dir, marker)) = fs_utils::find_protected_dir(target_path_abs)? {
+            log_event!(
+                Planner,
+                Info,
+                "not adopting {:?} as a whole: {:?} is marked with {}",
+                target_path_abs,
+                protected_dir,
+                marker
+            );
+            return Ok((ActionType::CreateDirectory, None, None));
+        }
+
         // Check for --adopt option when directory contains non-stow files
         return Ok((
             ActionType::AdoptDirectory,
@@ -467,9 +509,9 @@ fn handle_directory_conflict(
 /// Validate if symlink is stow-managed and extract package info
 fn validate_stow_symlink(
     target_path_abs: &Path,
-    stow_dir: &Path,
+    stow_dirs: &[&Path],
 ) -> Result<Option<(String, PathBuf)>, RustowError> {
-    fs_utils::is_stow_symlink(target_path_abs, stow_dir)
+    fs_utils::is_stow_symlink(target_path_abs, stow_dirs)
 }
 
 /// Check if symlink points to the same package and item
@@ -490,54 +532,12 @@ fn calculate_link_target_for_source(source_path: &Path, target_path_abs: &Path)
         .unwrap_or_else(|| source_path.to_path_buf())
 }
 
-fn read_sorted_directory_entries(path: &Path) -> Result<Vec<std::fs::DirEntry>, RustowError> {
-    let entries = std::fs::read_dir(path).map_err(|e| {
-        RustowError::Fs(FsError::Io {
-            path: path.to_path_buf(),
-            source: e,
-        })
-    })?;
-
-    let mut entries = entries.collect::<Result<Vec<_>, _>>().map_err(|e| {
-        RustowError::Fs(FsError::Io {
-            path: path.to_path_buf(),
-            source: e,
-        })
-    })?;
-    entries.sort_by_key(|entry| entry.file_name());
-    Ok(entries)
-}
-
-fn read_directory_entries(path: &Path) -> Result<std::fs::ReadDir, RustowError> {
-    std::fs::read_dir(path).map_err(|e| {
-        RustowError::Fs(FsError::Io {
-            path: path.to_path_buf(),
-            source: e,
-        })
-    })
-}
-
-fn read_directory_entry(
-    entry: std::io::Result<std::fs::DirEntry>,
-    path: &Path,
-) -> Result<std::fs::DirEntry, RustowError> {
-    entry.map_err(|e| {
-        RustowError::Fs(FsError::Io {
-            path: path.to_path_buf(),
-            source: e,
-        })
-    })
-}
-
-fn stow_item_type_from_file_type(file_type: std::fs::FileType) -> Option<StowItemType> {
-    if file_type.is_symlink() {
-        Some(StowItemType::Symlink)
-    } else if file_type.is_dir() {
-        Some(StowItemType::Directory)
-    } else if file_type.is_file() {
-        Some(StowItemType::File)
-    } else {
-        None
+fn stow_item_type_from_file_kind(kind: FileKind) -> Option<StowItemType> {
+    match kind {
+        FileKind::Symlink => Some(StowItemType::Symlink),
+        FileKind::Directory => Some(StowItemType::Directory),
+        FileKind::File => Some(StowItemType::File),
+        FileKind::Other => None,
     }
 }
 
@@ -561,7 +561,7 @@ fn directory_contains_ignored_descendants(
     config: &Config,
     ignore_patterns: &IgnorePatterns,
 ) -> Result<bool, RustowError> {
-    let source_dir = config.stow_dir.join(package_name).join(directory_item_path);
+    let source_dir = config.package_dir(package_name).join(directory_item_path);
 
     for raw_item in fs_utils::walk_package_dir(&source_dir)? {
         let package_relative_path = directory_item_path.join(raw_item.package_relative_path);
@@ -578,6 +578,52 @@ fn directory_contains_ignored_descendants(
     Ok(false)
 }
 
+/// Marker file that keeps the package directory holding it from being folded.
+pub const NO_FOLD_MARKER: &str = ".rustow-no-fold";
+
+/// Whether a `--no-fold` rule matches the directory at `target_relative_path`.
+fn target_directory_matches_no_fold(target_relative_path: &Path, config: &Config) -> bool {
+    let path = target_relative_path.to_string_lossy();
+    config.no_fold.iter().any(|pattern| pattern.is_match(&path))
+}
+
+/// Whether the package directory at `directory_item_path` or any directory below it must stay
+/// unfolded, because of a `--no-fold` rule or a [`NO_FOLD_MARKER`] file.
+fn directory_has_no_fold_rule(
+    package_name: &str,
+    directory_item_path: &Path,
+    config: &Config,
+) -> Result<bool, RustowError> {
+    let source_dir = config.package_dir(package_name).join(directory_item_path);
+    let processed_directory_path = PathBuf::from(dotfiles::process_item_name(
+        directory_item_path.to_str().unwrap_or(""),
+        config.dotfiles,
+    ));
+    if target_directory_matches_no_fold(&processed_directory_path, config) {
+        return Ok(true);
+    }
+
+    for raw_item in fs_utils::walk_package_dir(&source_dir)? {
+        match raw_item.item_type {
+            fs_utils::RawStowItemType::Directory => {
+                let package_relative_path =
+                    directory_item_path.join(&raw_item.package_relative_path);
+                let processed_target_relative_path = PathBuf::from(dotfiles::process_item_name(
+                    package_relative_path.to_str().unwrap_or(""),
+                    config.dotfiles,
+                ));
+                if target_directory_matches_no_fold(&processed_target_relative_path, config) {
+                    return Ok(true);
+                }
+            },
+            _ if raw_item.basename() == NO_FOLD_MARKER => return Ok(true),
+            _ => {},
+        }
+    }
+
+    Ok(false)
+}
+
 fn create_symlink_action_for_item(stow_item: StowItem, target_path: PathBuf) -> TargetAction {
     let link_target_path = calculate_link_target_for_source(&stow_item.source_path, &target_path);
 
@@ -606,19 +652,12 @@ fn create_relink_actions_for_directory_contents(
     config: &Config,
     ignore_patterns: &IgnorePatterns,
 ) -> Result<Vec<TargetAction>, RustowError> {
-    let source_dir = config.stow_dir.join(package_name).join(directory_item_path);
+    let source_dir = config.package_dir(package_name).join(directory_item_path);
     let mut actions = Vec::new();
 
-    for entry in read_sorted_directory_entries(&source_dir)? {
+    for entry in fs_utils::read_dir(&source_dir)? {
         let source_path = entry.path();
-        let file_type = entry.file_type().map_err(|e| {
-            RustowError::Fs(FsError::Io {
-                path: source_path.clone(),
-                source: e,
-            })
-        })?;
-
-        let Some(item_type) = stow_item_type_from_file_type(file_type) else {
+        let Some(item_type) = stow_item_type_from_file_kind(entry.kind()) else {
             continue;
         };
 
@@ -642,6 +681,7 @@ fn create_relink_actions_for_directory_contents(
 
         if item_type == StowItemType::Directory
             && (config.no_folding
+                || directory_has_no_fold_rule(package_name, &package_relative_path, config)?
                 || directory_contains_ignored_descendants(
                     package_name,
                     &package_relative_path,
@@ -675,12 +715,12 @@ fn plan_split_open_actions_if_needed(
     }
 
     let Some((existing_package_name, existing_item_path)) =
-        validate_stow_symlink(target_path_abs, &config.stow_dir)?
+        validate_stow_symlink(target_path_abs, &config.stow_dirs())?
     else {
         return Ok(None);
     };
     let (existing_package_name, existing_item_path) =
-        lexical_stow_symlink_package_and_item_path(target_path_abs, &config.stow_dir)?
+        lexical_stow_symlink_package_and_item_path(target_path_abs, &config.stow_dirs())?
             .unwrap_or((existing_package_name, existing_item_path));
     if !package_is_valid_refold_source(&existing_package_name, config)? {
         return Ok(None);
@@ -697,26 +737,52 @@ fn plan_split_open_actions_if_needed(
     }
 
     let existing_source_dir = config
-        .stow_dir
-        .join(&existing_package_name)
+        .package_dir(&existing_package_name)
         .join(&existing_item_path);
     if !fs_utils::is_directory(&existing_source_dir) {
         return Ok(None);
     }
 
-    let ignore_patterns = load_ignore_patterns_for_package(&existing_package_name, config)?;
+    split_open_actions(
+        target_path_abs,
+        &existing_package_name,
+        &existing_item_path,
+        config,
+    )
+    .map(Some)
+}
+
+/// Actions replacing the folded link at `target_path_abs` with a directory of links to the
+/// entries of the package directory it points to.
+fn split_open_actions(
+    target_path_abs: &Path,
+    package_name: &str,
+    item_path: &Path,
+    config: &Config,
+) -> Result<Vec<TargetAction>, RustowError> {
+    let ignore_patterns = load_ignore_patterns_for_package(package_name, config)?;
+    // The package directory the link led to is what the new directory stands for.
+    let directory_item = create_stow_item_from_existing_package_path(
+        config.package_dir(package_name).join(item_path),
+        item_path.to_path_buf(),
+        PathBuf::from(dotfiles::process_item_name(
+            item_path.to_str().unwrap_or(""),
+            config.dotfiles,
+        )),
+        StowItemType::Directory,
+    );
     let mut actions = vec![
         create_delete_symlink_action(target_path_abs.to_path_buf()),
-        create_create_directory_action(target_path_abs.to_path_buf()),
+        create_directory_action_for_item(directory_item, target_path_abs.to_path_buf()),
     ];
     actions.extend(create_relink_actions_for_directory_contents(
-        &existing_package_name,
-        &existing_item_path,
+        package_name,
+        item_path,
         config,
         &ignore_patterns,
     )?);
 
-    Ok(Some(actions))
+    Ok(actions)
 }
 
 /// Handle conflicts with existing symlinks
@@ -728,7 +794,7 @@ fn handle_existing_symlink_conflict(
     package_name: &str,
 ) -> Result<(ActionType, Option<String>, Option<PathBuf>), RustowError> {
     if let Some((existing_package_name, existing_item_path)) =
-        validate_stow_symlink(target_path_abs, &config.stow_dir)?
+        validate_stow_symlink(target_path_abs, &config.stow_dirs())?
     {
         // It's a stow-managed symlink
         if is_same_package_and_item(
@@ -942,15 +1008,7 @@ fn prune_actions_for_adopted_dirs(actions: &mut Vec<TargetAction>) {
 
 fn action_package_name(action: &TargetAction, config: &Config) -> Option<String> {
     let source_path = &action.source_item.as_ref()?.source_path;
-    let relative_to_stow = source_path.strip_prefix(&config.stow_dir).ok()?;
-    let mut components = relative_to_stow.components();
-
-    match components.next() {
-        Some(std::path::Component::Normal(package_name)) => {
-            Some(package_name.to_string_lossy().into_owned())
-        },
-        _ => None,
-    }
+    fs_utils::owning_package(&config.stow_dirs(), source_path).map(|(package, _)| package)
 }
 
 fn path_depth(path: &Path) -> usize {
@@ -1053,16 +1111,43 @@ fn directory_action_can_fold(
 
     if target_has_other_package_actions(index, actions, config, &action.target_path, &package_name)
     {
+        log_event!(
+            Planner,
+            Debug,
+            "not folding {}: other packages also populate it",
+            action.target_path.display()
+        );
+        return Ok(false);
+    }
+
+    if directory_has_no_fold_rule(&package_name, &stow_item.package_relative_path, config)? {
+        log_event!(
+            Planner,
+            Debug,
+            "not folding {}: a --no-fold rule or {} marker applies",
+            action.target_path.display(),
+            NO_FOLD_MARKER
+        );
         return Ok(false);
     }
 
     let ignore_patterns = load_ignore_patterns_for_package(&package_name, config)?;
-    Ok(!directory_contains_ignored_descendants(
+    if directory_contains_ignored_descendants(
         &package_name,
         &stow_item.package_relative_path,
         config,
         &ignore_patterns,
-    )?)
+    )? {
+        log_event!(
+            Planner,
+            Debug,
+            "not folding {}: it contains ignored entries",
+            action.target_path.display()
+        );
+        return Ok(false);
+    }
+
+    Ok(true)
 }
 
 fn fold_missing_directory_actions(
@@ -1112,6 +1197,13 @@ fn fold_missing_directory_actions(
             .clone();
         let link_target =
             calculate_link_target_for_source(&source_path, &actions[index].target_path);
+        log_event!(
+            Planner,
+            Debug,
+            "folding {} into a link to {}",
+            actions[index].target_path.display(),
+            link_target.display()
+        );
 
         actions[index].action_type = ActionType::CreateSymlink;
         actions[index].link_target_path = Some(link_target);
@@ -1137,7 +1229,9 @@ fn deduplicate_create_directory_actions(actions: &mut Vec<TargetAction>) {
 fn apply_tree_folding(actions: &mut Vec<TargetAction>, config: &Config) -> Result<(), RustowError> {
     prune_descendants_of_existing_folded_directory_actions(actions, config);
 
-    if !config.no_folding {
+    if config.no_folding {
+        log_event!(Planner, Debug, "tree folding disabled by --no-folding");
+    } else {
         fold_missing_directory_actions(actions, config)?;
     }
 
@@ -1278,23 +1372,256 @@ fn execute_actions(
         .iter()
         .any(|a| a.action_type == ActionType::Conflict)
     {
+        log_event!(
+            Exec,
+            Info,
+            "not executing {} action(s): the plan has conflicts",
+            actions.len()
+        );
         return Ok(build_conflict_reports(actions));
     }
 
+    log_event!(Exec, Debug, "executing {} action(s)", actions.len());
     let mut reports = Vec::new();
-
-    for action in actions {
-        let report = if config.simulate {
-            execute_simulate_action(action)
+    let mut staged_reports = HashMap::new();
+
+    for (index, action) in actions.iter().enumerate() {
+        let report = if let Some(report) = staged_reports.remove(&index) {
+            report
+        } else if let Some(group) = UnfoldGroup::find(index, actions) {
+            staged_reports.extend(execute_unfold_group(&group, actions, config));
+            staged_reports
+                .remove(&index)
+                .unwrap_or_else(|| execute_real_action(action, config))
         } else {
             execute_real_action(action, config)
         };
+        log_event!(
+            Exec,
+            Debug,
+            "{:?} {}: {:?}",
+            action.action_type,
+            action.target_path.display(),
+            report.status
+        );
         reports.push(report);
     }
 
     Ok(reports)
 }
 
+/// Actions that turn the folded symlink at `path` into a real directory.
+///
+/// They are executed together so the unfolded directory can be built aside and swapped in.
+struct UnfoldGroup {
+    path: PathBuf,
+    delete_symlink_index: usize,
+    create_directory_index: usize,
+    child_indices: Vec<usize>,
+}
+
+impl UnfoldGroup {
+    fn find(delete_symlink_index: usize, actions: &[TargetAction]) -> Option<Self> {
+        let delete_action = &actions[delete_symlink_index];
+        if delete_action.action_type != ActionType::DeleteSymlink
+            || !fs_utils::is_symlink(&delete_action.target_path)
+        {
+            return None;
+        }
+
+        let path = delete_action.target_path.clone();
+        let mut create_directory_index = None;
+        let mut child_indices = Vec::new();
+
+        for (index, action) in actions.iter().enumerate() {
+            if index == delete_symlink_index {
+                continue;
+            }
+
+            if action.target_path == path {
+                if action.action_type != ActionType::CreateDirectory
+                    || create_directory_index.is_some()
+                {
+                    return None;
+                }
+                create_directory_index = Some(index);
+            } else if action.target_path.starts_with(&path) {
+                if !matches!(
+                    action.action_type,
+                    ActionType::CreateDirectory | ActionType::CreateSymlink | ActionType::Skip
+                ) {
+                    return None;
+                }
+                child_indices.push(index);
+            }
+        }
+
+        let create_directory_index = create_directory_index?;
+        if create_directory_index < delete_symlink_index
+            || child_indices
+                .iter()
+                .any(|index| *index < delete_symlink_index)
+        {
+            return None;
+        }
+
+        Some(Self {
+            path,
+            delete_symlink_index,
+            create_directory_index,
+            child_indices,
+        })
+    }
+
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        [self.delete_symlink_index, self.create_directory_index]
+            .into_iter()
+            .chain(self.child_indices.iter().copied())
+    }
+}
+
+/// Unfold a directory symlink by building the new directory under a temporary name and
+/// swapping it into place, so the target path never disappears where the filesystem can
+/// exchange paths (see [`fs_utils::exchange_paths`]).
+fn execute_unfold_group(
+    group: &UnfoldGroup,
+    actions: &[TargetAction],
+    config: &Config,
+) -> Vec<(usize, TargetActionReport)> {
+    let delete_action = &actions[group.delete_symlink_index];
+    if let Some(error_report) =
+        ensure_target_path_ancestors_not_symlink(delete_action, config, false)
+    {
+        return vec![(group.delete_symlink_index, error_report)];
+    }
+
+    let staged_dir = fs_utils::temporary_sibling_path(&group.path, "unfold");
+    let abort = |failed_index: usize, error: String, message: String| {
+        let _ = fs_utils::remove_staged_tree(&staged_dir);
+        group
+            .indices()
+            .map(|index| {
+                let report = if index == failed_index {
+                    TargetActionReport {
+                        original_action: actions[index].clone(),
+                        status: TargetActionReportStatus::Failure(error.clone()),
+                        message: Some(message.clone()),
+                    }
+                } else {
+                    TargetActionReport {
+                        original_action: actions[index].clone(),
+                        status: TargetActionReportStatus::Skipped,
+                        message: Some(format!("Skipped because unfolding {:?} failed", group.path)),
+                    }
+                };
+                (index, report)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if let Err(e) = fs_utils::create_dir_all(&staged_dir).and_then(|_| {
+        apply_created_directory_mode(&actions[group.create_directory_index], &staged_dir, config)
+    }) {
+        return abort(
+            group.create_directory_index,
+            e.to_string(),
+            format!("Failed to create directory {:?}: {}", group.path, e),
+        );
+    }
+
+    for &index in &group.child_indices {
+        let action = &actions[index];
+        let Ok(relative_path) = action.target_path.strip_prefix(&group.path) else {
+            continue;
+        };
+        let staged_path = staged_dir.join(relative_path);
+
+        let result = match (&action.action_type, &action.link_target_path) {
+            (ActionType::CreateDirectory, _) => fs_utils::create_dir_all(&staged_path)
+                .and_then(|_| apply_created_directory_mode(action, &staged_path, config)),
+            (ActionType::CreateSymlink, Some(link_target)) => staged_path
+                .parent()
+                .map_or(Ok(()), fs_utils::create_dir_all)
+                .and_then(|_| fs_utils::create_symlink(&staged_path, link_target)),
+            (ActionType::CreateSymlink, None) => Err(RustowError::Stow(
+                StowError::InvalidPackageStructure(format!(
+                    "CreateSymlink action for {:?} is missing link_target_path",
+                    action.target_path
+                )),
+            )),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            return abort(
+                index,
+                e.to_string(),
+                format!(
+                    "Failed to stage {:?} while unfolding {:?}: {}",
+                    action.target_path, group.path, e
+                ),
+            );
+        }
+    }
+
+    if let Err(e) = fs_utils::exchange_paths(&staged_dir, &group.path) {
+        return abort(
+            group.delete_symlink_index,
+            e.to_string(),
+            format!(
+                "Failed to swap unfolded directory {:?} into place: {}",
+                group.path, e
+            ),
+        );
+    }
+
+    // `staged_dir` now names the folded symlink that was swapped out.
+    let delete_report = match fs_utils::delete_symlink(&staged_dir) {
+        Ok(_) => TargetActionReport {
+            original_action: delete_action.clone(),
+            status: TargetActionReportStatus::Success,
+            message: Some(format!(
+                "Successfully deleted symlink {:?}",
+                delete_action.target_path
+            )),
+        },
+        Err(e) => TargetActionReport {
+            original_action: delete_action.clone(),
+            status: TargetActionReportStatus::Failure(e.to_string()),
+            message: Some(format!(
+                "Unfolded {:?} but failed to remove the replaced symlink {:?}: {}",
+                group.path, staged_dir, e
+            )),
+        },
+    };
+
+    let mut reports = vec![(group.delete_symlink_index, delete_report)];
+    for index in group.indices().skip(1) {
+        let action = &actions[index];
+        let message = match (&action.action_type, &action.link_target_path) {
+            (ActionType::Skip, _) => {
+                reports.push((index, execute_skip_action(action)));
+                continue;
+            },
+            (ActionType::CreateSymlink, Some(link_target)) => format!(
+                "Successfully created symlink {:?} -> {:?}",
+                action.target_path, link_target
+            ),
+            _ => format!("Successfully created directory {:?}", action.target_path),
+        };
+        reports.push((
+            index,
+            TargetActionReport {
+                original_action: action.clone(),
+                status: TargetActionReportStatus::Success,
+                message: Some(message),
+            },
+        ));
+    }
+
+    reports
+}
+
 fn build_conflict_reports(actions: &[TargetAction]) -> Vec<TargetActionReport> {
     actions
         .iter()
@@ -1312,26 +1639,43 @@ fn build_conflict_reports(actions: &[TargetAction]) -> Vec<TargetActionReport> {
         .collect()
 }
 
-/// Execute an action in simulation mode
-fn execute_simulate_action(action: &TargetAction) -> TargetActionReport {
-    let message = format!(
-        "SIMULATE: Would perform {:?} on target {:?} (source: {:?}, link_target: {:?})",
-        action.action_type,
-        action.target_path,
-        action
-            .source_item
-            .as_ref()
-            .map_or_else(|| PathBuf::from("N/A"), |si| si.source_path.clone()),
-        action
-            .link_target_path
-            .as_ref()
-            .map_or_else(|| PathBuf::from("N/A"), |p| p.clone())
-    );
+/// Rewrite a report produced against the in-memory overlay into a simulate report.
+fn simulated_report(report: TargetActionReport) -> TargetActionReport {
+    let action = report.original_action;
+    match report.status {
+        TargetActionReportStatus::Success | TargetActionReportStatus::Skipped => {
+            let message = format!(
+                "SIMULATE: Would perform {:?} on target {:?} (source: {:?}, link_target: {:?})",
+                action.action_type,
+                action.target_path,
+                action
+                    .source_item
+                    .as_ref()
+                    .map_or_else(|| PathBuf::from("N/A"), |si| si.source_path.clone()),
+                action
+                    .link_target_path
+                    .as_ref()
+                    .map_or_else(|| PathBuf::from("N/A"), |p| p.clone())
+            );
 
-    TargetActionReport {
-        original_action: action.clone(),
-        status: TargetActionReportStatus::Skipped,
-        message: Some(message),
+            TargetActionReport {
+                original_action: action,
+                status: TargetActionReportStatus::Skipped,
+                message: Some(message),
+            }
+        },
+        TargetActionReportStatus::Failure(error) => TargetActionReport {
+            original_action: action,
+            status: TargetActionReportStatus::Failure(error),
+            message: report
+                .message
+                .map(|message| format!("SIMULATE: {}", message)),
+        },
+        TargetActionReportStatus::ConflictPrevented => TargetActionReport {
+            original_action: action,
+            status: TargetActionReportStatus::ConflictPrevented,
+            message: report.message,
+        },
     }
 }
 
@@ -1345,6 +1689,7 @@ fn execute_real_action(action: &TargetAction, config: &Config) -> TargetActionRe
         ActionType::DeleteDirectory => execute_delete_directory_action(action, config),
         ActionType::AdoptFile => execute_adopt_file_action(action, config),
         ActionType::AdoptDirectory => execute_adopt_directory_action(action, config),
+        ActionType::MoveItem => execute_move_item_action(action),
         ActionType::Skip => execute_skip_action(action),
     }
 }
@@ -1368,7 +1713,16 @@ fn execute_create_directory_action(action: &TargetAction, config: &Config) -> Ta
         return error_report;
     }
 
-    match fs_utils::create_dir_all(&action.target_path) {
+    // An existing directory keeps its mode; only new ones get the --dir-mode rules.
+    let existed = fs_utils::is_directory(&action.target_path);
+    let created = fs_utils::create_dir_all(&action.target_path).and_then(|_| {
+        if existed {
+            Ok(())
+        } else {
+            apply_created_directory_mode(action, &action.target_path, config)
+        }
+    });
+    match created {
         Ok(_) => TargetActionReport {
             original_action: action.clone(),
             status: TargetActionReportStatus::Success,
@@ -1388,24 +1742,79 @@ fn execute_create_directory_action(action: &TargetAction, config: &Config) -> Ta
     }
 }
 
+/// Mode the `--dir-mode` rules give the directory `action` creates, if any.
+fn created_directory_mode(action: &TargetAction, config: &Config) -> Option<u32> {
+    let target_relative_path = action.target_path.strip_prefix(&config.target_dir).ok()?;
+    match config.dir_mode_for(target_relative_path)? {
+        DirMode::Bits(bits) => Some(bits),
+        DirMode::Package => fs_utils::permission_mode(&action.source_item.as_ref()?.source_path),
+    }
+}
+
+/// Applies the `--dir-mode` rules for `action` to `path`, where its directory was just created.
+fn apply_created_directory_mode(
+    action: &TargetAction,
+    path: &Path,
+    config: &Config,
+) -> Result<(), RustowError> {
+    match created_directory_mode(action, config) {
+        Some(mode) => fs_utils::set_permission_mode(path, mode),
+        None => Ok(()),
+    }
+}
+
+/// An existing target directory that grants permissions its package directory does not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LooserDirectory {
+    pub(crate) path: PathBuf,
+    pub(crate) mode: u32,
+    pub(crate) package_dir: PathBuf,
+    pub(crate) package_mode: u32,
+}
+
+/// Existing directories `plan` stows into that are looser than their package directories.
+///
+/// `--dir-mode` only applies to directories rustow creates, so these keep their mode.
+pub(crate) fn looser_target_directories(plan: &Plan) -> Vec<LooserDirectory> {
+    plan.stow_actions
+        .iter()
+        .filter(|action| action.action_type == ActionType::CreateDirectory)
+        .filter_map(|action| {
+            let package_dir = &action.source_item.as_ref()?.source_path;
+            if fs_utils::is_symlink(&action.target_path)
+                || !fs_utils::is_directory(&action.target_path)
+            {
+                return None;
+            }
+            let mode = fs_utils::permission_mode(&action.target_path)?;
+            let package_mode = fs_utils::permission_mode(package_dir)?;
+            (mode & !package_mode != 0).then(|| LooserDirectory {
+                path: action.target_path.clone(),
+                mode,
+                package_dir: package_dir.clone(),
+                package_mode,
+            })
+        })
+        .collect()
+}
+
 /// Ensure parent directory exists for symlink creation
 fn ensure_parent_directory_exists(action: &TargetAction) -> Option<TargetActionReport> {
-    if let Some(parent_dir) = action.target_path.parent() {
-        if !fs_utils::path_exists(parent_dir) {
-            if let Err(e) = fs_utils::create_dir_all(parent_dir) {
-                return Some(TargetActionReport {
-                    original_action: action.clone(),
-                    status: TargetActionReportStatus::Failure(format!(
-                        "Failed to create parent directory {:?} for symlink: {}",
-                        parent_dir, e
-                    )),
-                    message: Some(format!(
-                        "Failed to create parent directory {:?} for symlink {:?}: {}",
-                        parent_dir, action.target_path, e
-                    )),
-                });
-            }
-        }
+    if let Some(parent_dir) = action.target_path.parent()
+        && !fs_utils::path_exists(parent_dir)
+        && let Err(e) = fs_utils::create_dir_all(parent_dir)
+    {
+        return Some(TargetActionReport {
+            original_action: action.clone(),
+            status: TargetActionReportStatus::Failure(format!(
+                "Failed to create parent directory {:?} for symlink: {}",
+                parent_dir, e
+            )),
+            message: Some(format!(
+                "Failed to create parent directory {:?} for symlink {:?}: {}",
+                parent_dir, action.target_path, e
+            )),
+        });
     }
     None
 }
@@ -1447,43 +1856,33 @@ fn target_symlink_ancestor_path(path: &Path, root: &Path, include_target: bool)
     None
 }
 
-/// Remove existing target if it exists (for override behavior)
-fn remove_existing_target(action: &TargetAction) -> Option<TargetActionReport> {
-    if fs_utils::path_exists(&action.target_path) {
-        if fs_utils::is_symlink(&action.target_path) {
-            if let Err(e) = fs_utils::delete_symlink(&action.target_path) {
-                return Some(TargetActionReport {
-                    original_action: action.clone(),
-                    status: TargetActionReportStatus::Failure(format!(
-                        "Failed to remove existing symlink before override: {}",
-                        e
-                    )),
-                    message: Some(format!(
-                        "Failed to remove existing symlink {:?} before creating new one: {}",
-                        action.target_path, e
-                    )),
-                });
-            }
-        } else {
-            // Target exists but is not a symlink - this should have been caught in planning
-            return Some(TargetActionReport {
-                original_action: action.clone(),
-                status: TargetActionReportStatus::Failure(
-                    "Target exists and is not a symlink - cannot override".to_string(),
-                ),
-                message: Some(format!(
-                    "Target {:?} exists and is not a symlink - cannot override",
-                    action.target_path
-                )),
-            });
-        }
+/// Ensure an existing target can be replaced in place (for override behavior)
+fn ensure_existing_target_is_replaceable(action: &TargetAction) -> Option<TargetActionReport> {
+    if fs_utils::path_exists(&action.target_path) && !fs_utils::is_symlink(&action.target_path) {
+        // Target exists but is not a symlink - this should have been caught in planning
+        return Some(TargetActionReport {
+            original_action: action.clone(),
+            status: TargetActionReportStatus::Failure(
+                "Target exists and is not a symlink - cannot override".to_string(),
+            ),
+            message: Some(format!(
+                "Target {:?} exists and is not a symlink - cannot override",
+                action.target_path
+            )),
+        });
     }
     None
 }
 
-/// Create the actual symlink
+/// Create the actual symlink, atomically replacing an existing one so the target never vanishes
 fn create_symlink_with_target(action: &TargetAction, link_target: &Path) -> TargetActionReport {
-    match fs_utils::create_symlink(&action.target_path, link_target) {
+    let result = if fs_utils::is_symlink(&action.target_path) {
+        fs_utils::replace_symlink(&action.target_path, link_target)
+    } else {
+        fs_utils::create_symlink(&action.target_path, link_target)
+    };
+
+    match result {
         Ok(_) => TargetActionReport {
             original_action: action.clone(),
             status: TargetActionReportStatus::Success,
@@ -1514,8 +1913,8 @@ fn prepare_symlink_creation(action: &TargetAction, config: &Config) -> Option<Ta
         return Some(error_report);
     }
 
-    // Remove existing target if needed
-    if let Some(error_report) = remove_existing_target(action) {
+    // Existing symlinks are replaced atomically; anything else blocks the override
+    if let Some(error_report) = ensure_existing_target_is_replaceable(action) {
         return Some(error_report);
     }
 
@@ -1666,7 +2065,7 @@ fn common_package_directory_for_symlinks(
     dir_path: &Path,
     config: &Config,
 ) -> Result<Option<PathBuf>, RustowError> {
-    let entries = read_sorted_directory_entries(dir_path)?;
+    let entries = fs_utils::read_dir(dir_path)?;
     if entries.is_empty() {
         return Ok(None);
     }
@@ -1681,7 +2080,7 @@ fn common_package_directory_for_symlinks(
         }
 
         let Some((package_name, item_path)) =
-            lexical_stow_symlink_package_and_item_path(&entry_path, &config.stow_dir)?
+            lexical_stow_symlink_package_and_item_path(&entry_path, &config.stow_dirs())?
         else {
             return Ok(None);
         };
@@ -1710,7 +2109,7 @@ fn common_package_directory_for_symlinks(
     }
 
     let item_parent = common_item_parent.unwrap_or_default();
-    let source_dir = config.stow_dir.join(package_name).join(item_parent);
+    let source_dir = config.package_dir(&package_name).join(item_parent);
 
     if fs_utils::is_directory(&source_dir)
         && source_directory_can_refold(dir_path, &source_dir, config)?
@@ -1735,6 +2134,23 @@ fn source_directory_can_refold(
         return Ok(false);
     }
 
+    let no_fold_target =
+        dir_path
+            .strip_prefix(&config.target_dir)
+            .is_ok_and(|target_relative_path| {
+                target_directory_matches_no_fold(target_relative_path, config)
+            });
+    if no_fold_target || directory_has_no_fold_rule(&package_name, &item_parent, config)? {
+        log_event!(
+            Exec,
+            Debug,
+            "not refolding {}: a --no-fold rule or {} marker applies",
+            dir_path.display(),
+            NO_FOLD_MARKER
+        );
+        return Ok(false);
+    }
+
     let ignore_patterns = load_ignore_patterns_for_package(&package_name, config)?;
     if directory_contains_ignored_descendants(
         &package_name,
@@ -1746,8 +2162,8 @@ fn source_directory_can_refold(
         return Ok(false);
     }
 
-    for entry in read_directory_entries(dir_path)? {
-        let path = read_directory_entry(entry, dir_path)?.path();
+    for entry in fs_utils::read_dir(dir_path)? {
+        let path = entry.path();
         let Ok(target_relative_path) = path.strip_prefix(&config.target_dir) else {
             return Ok(false);
         };
@@ -1763,7 +2179,7 @@ fn source_directory_can_refold(
         }
 
         let Some((target_package, target_item_path)) =
-            lexical_stow_symlink_package_and_item_path(&path, &config.stow_dir)?
+            lexical_stow_symlink_package_and_item_path(&path, &config.stow_dirs())?
         else {
             return Ok(false);
         };
@@ -1783,16 +2199,14 @@ fn source_directory_can_refold(
 
         if !fs_utils::path_exists(
             &config
-                .stow_dir
-                .join(&target_package)
+                .package_dir(&target_package)
                 .join(target_item_path.as_path()),
         ) {
             return Ok(false);
         }
     }
 
-    for entry in read_directory_entries(source_dir)? {
-        let entry = read_directory_entry(entry, source_dir)?;
+    for entry in fs_utils::read_dir(source_dir)? {
         let entry_file_name = entry.file_name();
         let package_relative_path = item_parent.join(entry_file_name);
         let processed_target_relative_path = PathBuf::from(dotfiles::process_item_name(
@@ -1812,7 +2226,7 @@ fn source_directory_can_refold(
         }
 
         let Some((target_package, target_item_path)) =
-            lexical_stow_symlink_package_and_item_path(&target_path, &config.stow_dir)?
+            lexical_stow_symlink_package_and_item_path(&target_path, &config.stow_dirs())?
         else {
             return Ok(false);
         };
@@ -1829,23 +2243,14 @@ fn package_and_item_path_for_source_dir(
     source_dir: &Path,
     config: &Config,
 ) -> Option<(String, PathBuf)> {
-    let relative_to_stow = source_dir.strip_prefix(&config.stow_dir).ok()?;
-    let mut components = relative_to_stow.components();
-    let package_name = match components.next() {
-        Some(std::path::Component::Normal(package_name)) => {
-            package_name.to_string_lossy().into_owned()
-        },
-        _ => return None,
-    };
-
-    Some((package_name, components.as_path().to_path_buf()))
+    fs_utils::owning_package(&config.stow_dirs(), source_dir)
 }
 
 fn package_is_valid_refold_source(
     package_name: &str,
     config: &Config,
 ) -> Result<bool, RustowError> {
-    match canonical_package_path(&config.stow_dir, package_name) {
+    match canonical_package_path(config.package_stow_dir(package_name), package_name) {
         Ok(_) => Ok(true),
         Err(RustowError::Stow(StowError::InvalidPackageStructure(_)))
         | Err(RustowError::Fs(FsError::NotFound(_)))
@@ -1864,7 +2269,7 @@ fn directory_contains_deferred_descendants(
     directory_item_path: &Path,
     config: &Config,
 ) -> Result<bool, RustowError> {
-    let source_dir = config.stow_dir.join(package_name).join(directory_item_path);
+    let source_dir = config.package_dir(package_name).join(directory_item_path);
 
     for raw_item in fs_utils::walk_package_dir(&source_dir)? {
         let package_relative_path = directory_item_path.join(raw_item.package_relative_path);
@@ -1891,7 +2296,7 @@ fn should_defer_item(processed_target_relative_path: &Path, config: &Config) ->
 
 fn lexical_stow_symlink_package_and_item_path(
     link_path: &Path,
-    stow_dir: &Path,
+    stow_dirs: &[&Path],
 ) -> Result<Option<(String, PathBuf)>, RustowError> {
     if !fs_utils::is_symlink(link_path) {
         return Ok(None);
@@ -1900,21 +2305,22 @@ fn lexical_stow_symlink_package_and_item_path(
     let link_target = fs_utils::read_link(link_path)?;
     let resolved_target =
         normalize_path_components(&resolve_symlink_target(link_path, &link_target));
-    let normalized_stow_dir = normalize_path_components(stow_dir);
-    let Ok(relative_to_stow) = resolved_target.strip_prefix(&normalized_stow_dir) else {
-        return Ok(None);
-    };
-
-    let mut components = relative_to_stow.components();
-    match components.next() {
-        Some(std::path::Component::Normal(package_name)) => Ok(Some((
-            package_name.to_string_lossy().into_owned(),
-            components.as_path().to_path_buf(),
-        ))),
-        _ => Ok(None),
-    }
+    let normalized_stow_dirs: Vec<PathBuf> = stow_dirs
+        .iter()
+        .map(|stow_dir| normalize_path_components(stow_dir))
+        .collect();
+    let normalized_stow_dirs: Vec<&Path> =
+        normalized_stow_dirs.iter().map(PathBuf::as_path).collect();
+    Ok(fs_utils::owning_package(
+        &normalized_stow_dirs,
+        &resolved_target,
+    ))
 }
 
+/// Replace `dir_path` with a folded symlink without ever leaving the path absent.
+///
+/// The folded link is built under a temporary name and swapped with the directory; the old
+/// directory and its child links are removed only once the link is in place.
 fn refold_directory(dir_path: &Path, source_dir: &Path) -> TargetActionReport {
     let link_target = calculate_link_target_for_source(source_dir, dir_path);
     let action = TargetAction {
@@ -1924,48 +2330,46 @@ fn refold_directory(dir_path: &Path, source_dir: &Path) -> TargetActionReport {
         action_type: ActionType::CreateSymlink,
         conflict_details: Some(format!("Refolding directory {:?}", dir_path)),
     };
+    let failure = |action: TargetAction, error: String, message: String| TargetActionReport {
+        original_action: action,
+        status: TargetActionReportStatus::Failure(error),
+        message: Some(message),
+    };
 
-    match read_sorted_directory_entries(dir_path) {
-        Ok(entries) => {
-            for entry in entries {
-                if let Err(e) = fs_utils::delete_symlink(&entry.path()) {
-                    return TargetActionReport {
-                        original_action: action,
-                        status: TargetActionReportStatus::Failure(e.to_string()),
-                        message: Some(format!(
-                            "Failed to remove symlink {:?} while refolding {:?}: {}",
-                            entry.path(),
-                            dir_path,
-                            e
-                        )),
-                    };
-                }
-            }
-        },
-        Err(e) => {
-            return TargetActionReport {
-                original_action: action,
-                status: TargetActionReportStatus::Failure(e.to_string()),
-                message: Some(format!(
-                    "Failed to read directory {:?} while refolding: {}",
-                    dir_path, e
-                )),
-            };
-        },
+    let staged_link = fs_utils::temporary_sibling_path(dir_path, "refold");
+    if let Err(e) = fs_utils::create_symlink(&staged_link, &link_target) {
+        return failure(
+            action,
+            e.to_string(),
+            format!(
+                "Failed to create refolded symlink {:?} -> {:?}: {}",
+                dir_path, link_target, e
+            ),
+        );
     }
 
-    if let Err(e) = fs_utils::delete_empty_dir(dir_path) {
-        return TargetActionReport {
-            original_action: action,
-            status: TargetActionReportStatus::Failure(e.to_string()),
-            message: Some(format!(
-                "Failed to remove directory {:?} while refolding: {}",
-                dir_path, e
-            )),
-        };
+    if let Err(e) = fs_utils::exchange_paths(&staged_link, dir_path) {
+        let _ = fs_utils::delete_symlink(&staged_link);
+        return failure(
+            action,
+            e.to_string(),
+            format!(
+                "Failed to swap refolded symlink {:?} -> {:?} into place: {}",
+                dir_path, link_target, e
+            ),
+        );
     }
 
-    match fs_utils::create_symlink(dir_path, &link_target) {
+    // `staged_link` now names the unfolded directory that was swapped out.
+    let old_dir = staged_link;
+    let cleanup_result = fs_utils::read_dir(&old_dir).and_then(|entries| {
+        for entry in entries {
+            fs_utils::delete_symlink(&entry.path())?;
+        }
+        fs_utils::delete_empty_dir(&old_dir)
+    });
+
+    match cleanup_result {
         Ok(_) => TargetActionReport {
             original_action: action,
             status: TargetActionReportStatus::Success,
@@ -1974,15 +2378,736 @@ fn refold_directory(dir_path: &Path, source_dir: &Path) -> TargetActionReport {
                 dir_path, link_target
             )),
         },
-        Err(e) => TargetActionReport {
-            original_action: action,
-            status: TargetActionReportStatus::Failure(e.to_string()),
-            message: Some(format!(
-                "Failed to create refolded symlink {:?} -> {:?}: {}",
-                dir_path, link_target, e
+        Err(e) => failure(
+            action,
+            e.to_string(),
+            format!(
+                "Refolded {:?} -> {:?} but failed to remove the replaced directory {:?}: {}",
+                dir_path, link_target, old_dir, e
+            ),
+        ),
+    }
+}
+
+/// Resolves a PATH argument to a path strictly inside the target directory that is not itself
+/// inside a folded directory, reporting problems with `error`.
+fn target_path_argument(
+    config: &Config,
+    path: &Path,
+    error: fn(String) -> StowError,
+) -> Result<PathBuf, RustowError> {
+    let absolute_path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map_err(|source| FsError::Io {
+                path: path.to_path_buf(),
+                source,
+            })?
+            .join(path)
+    };
+    let mut resolved = normalize_path_components(&absolute_path);
+    if !resolved.starts_with(&config.target_dir)
+        && let (Some(parent), Some(name)) = (resolved.parent(), resolved.file_name())
+        && let Ok(canonical_parent) = fs_utils::canonicalize_path(parent)
+    {
+        resolved = canonical_parent.join(name);
+    }
+
+    if resolved == config.target_dir || !resolved.starts_with(&config.target_dir) {
+        return Err(error(format!(
+            "{:?} is not inside the target directory {:?}",
+            path, config.target_dir
+        ))
+        .into());
+    }
+    if path_has_symlink_ancestor(&resolved, &config.target_dir) {
+        return Err(error(format!(
+            "{:?} is inside a folded directory; unfold its parent first",
+            path
+        ))
+        .into());
+    }
+
+    Ok(resolved)
+}
+
+/// Plan moving each of `paths` from the target into `package_name` at the package path that
+/// stows back to it, then linking it, like adopting a path the package did not have yet.
+///
+/// The package is created when it does not exist.
+pub(crate) fn plan_add_paths(
+    config: &Config,
+    package_name: &str,
+    paths: &[PathBuf],
+) -> Result<Plan, RustowError> {
+    let package_dir = config.package_dir(package_name);
+    if fs_utils::path_exists(&package_dir) && !fs_utils::is_directory(&package_dir) {
+        return Err(
+            StowError::CannotAdd(format!("package '{}' is not a directory", package_name)).into(),
+        );
+    }
+    let ignore_patterns = load_ignore_patterns_for_package(package_name, config)?;
+
+    let mut target_paths: Vec<(&PathBuf, PathBuf)> = Vec::new();
+    for path in paths {
+        let target_path = target_path_argument(config, path, StowError::CannotAdd)?;
+        if target_paths
+            .iter()
+            .any(|(_, added)| added.starts_with(&target_path) || target_path.starts_with(added))
+        {
+            return Err(StowError::CannotAdd(format!(
+                "{:?} overlaps another path being added",
+                path
+            ))
+            .into());
+        }
+        target_paths.push((path, target_path));
+    }
+
+    let mut stow_actions = Vec::new();
+    for (path, target_path) in target_paths {
+        let item_type = if matches!(
+            fs_utils::is_stow_symlink(&target_path, &config.stow_dirs()),
+            Ok(Some(_))
+        ) {
+            return Err(StowError::CannotAdd(format!(
+                "{:?} is already linked into the stow directory",
+                path
+            ))
+            .into());
+        } else if fs_utils::is_symlink(&target_path) {
+            return Err(StowError::CannotAdd(format!(
+                "{:?} is a symlink; only files and directories can be added",
+                path
+            ))
+            .into());
+        } else if fs_utils::is_directory(&target_path) {
+            StowItemType::Directory
+        } else if fs_utils::path_exists(&target_path) {
+            StowItemType::File
+        } else {
+            return Err(StowError::CannotAdd(format!("{:?} does not exist", path)).into());
+        };
+
+        let target_relative_path = target_path
+            .strip_prefix(&config.target_dir)
+            .unwrap_or(&target_path)
+            .to_path_buf();
+        // The package path is derived by name; a lossy one could name the package itself.
+        let Some(target_relative_name) = target_relative_path.to_str() else {
+            return Err(StowError::CannotAdd(format!(
+                "{:?} is not valid UTF-8 and cannot be named in the package",
+                path
+            ))
+            .into());
+        };
+        let Some(package_relative_path) =
+            dotfiles::package_item_name(target_relative_name, config.dotfiles)
+        else {
+            return Err(StowError::CannotAdd(format!(
+                "{:?} has a 'dot-' name, which --dotfiles would never stow back to it",
+                path
+            ))
+            .into());
+        };
+        if target_relative_path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| should_ignore_item(ancestor, &ignore_patterns))
+        {
+            return Err(StowError::CannotAdd(format!(
+                "{:?} matches an ignore pattern of package '{}' and would not be linked",
+                path, package_name
+            ))
+            .into());
+        }
+        let source_path = package_dir.join(&package_relative_path);
+        if fs_utils::path_exists(&source_path) || fs_utils::is_symlink(&source_path) {
+            return Err(StowError::CannotAdd(format!(
+                "{:?} already exists in package '{}'",
+                package_relative_path, package_name
+            ))
+            .into());
+        }
+
+        let link_target_path = calculate_link_target_for_source(&source_path, &target_path);
+        stow_actions.push(TargetAction {
+            source_item: Some(create_stow_item_from_existing_package_path(
+                source_path,
+                PathBuf::from(package_relative_path),
+                target_relative_path,
+                item_type.clone(),
             )),
-        },
+            conflict_details: Some(format!(
+                "Adding {:?} to package '{}'",
+                target_path, package_name
+            )),
+            target_path,
+            link_target_path: Some(link_target_path),
+            action_type: if item_type == StowItemType::Directory {
+                ActionType::AdoptDirectory
+            } else {
+                ActionType::AdoptFile
+            },
+        });
+    }
+
+    let mut plan_config = config.clone();
+    plan_config.packages = vec![package_name.to_string()];
+    Ok(single_operation_plan(
+        &plan_config,
+        StowMode::Stow,
+        Vec::new(),
+        stow_actions,
+        false,
+    ))
+}
+
+/// Plan moving package items into the package `destination` and rewriting every link in the
+/// target that leads to them, unfolding folded links that cover a moved item.
+///
+/// Each item is `PACKAGE` or `PACKAGE/PATH` relative to the stow directory; moving a whole
+/// package renames it. An item the destination package already has is planned as a conflict.
+pub(crate) fn plan_move_items(
+    config: &Config,
+    destination: &str,
+    items: &[String],
+) -> Result<Plan, RustowError> {
+    let destination_dir = config.package_dir(destination);
+    if fs_utils::path_exists(&destination_dir) && !fs_utils::is_directory(&destination_dir) {
+        return Err(
+            StowError::CannotMove(format!("package '{}' is not a directory", destination)).into(),
+        );
+    }
+
+    let mut moves: Vec<(String, PathBuf)> = Vec::new();
+    for item in items {
+        let Some((package, item_path)) = split_package_item(config, Path::new(item)) else {
+            return Err(StowError::CannotMove(format!("{:?} is not a package item", item)).into());
+        };
+        if item_path
+            .components()
+            .any(|component| !matches!(component, std::path::Component::Normal(_)))
+        {
+            return Err(StowError::CannotMove(format!("{:?} is not a package item", item)).into());
+        }
+        if !fs_utils::is_directory(&config.package_dir(&package)) {
+            return Err(StowError::PackageNotFound(package).into());
+        }
+        if package == destination {
+            return Err(StowError::CannotMove(format!(
+                "{:?} is already in package '{}'",
+                item, destination
+            ))
+            .into());
+        }
+        if filesystem::current()
+            .lstat(&config.package_dir(&package).join(&item_path))
+            .is_err()
+        {
+            return Err(StowError::CannotMove(format!("{:?} does not exist", item)).into());
+        }
+        if moves.iter().any(|(other_package, other_path)| {
+            *other_package == package
+                && (other_path.starts_with(&item_path) || item_path.starts_with(other_path))
+        }) {
+            return Err(StowError::CannotMove(format!(
+                "{:?} overlaps another item being moved",
+                item
+            ))
+            .into());
+        }
+        moves.push((package, item_path));
+    }
+
+    let mut delete_actions = Vec::new();
+    let mut stow_actions = Vec::new();
+    for (package, item_path) in &moves {
+        let source_path = join_relative(&config.package_dir(package), item_path);
+        let moved_to = join_relative(&destination_dir, item_path);
+        let item_type = if fs_utils::is_symlink(&source_path) {
+            StowItemType::Symlink
+        } else if fs_utils::is_directory(&source_path) {
+            StowItemType::Directory
+        } else {
+            StowItemType::File
+        };
+        // The target name is derived by name; a lossy one could name the package itself.
+        let Some(item_name) = item_path.to_str() else {
+            return Err(StowError::CannotMove(format!(
+                "{:?} is not valid UTF-8 and cannot be named in the target",
+                item_path
+            ))
+            .into());
+        };
+        let target_name = PathBuf::from(dotfiles::process_item_name(item_name, config.dotfiles));
+        let stow_item = create_stow_item_from_existing_package_path(
+            source_path,
+            item_path.clone(),
+            target_name,
+            item_type,
+        );
+
+        if filesystem::current().lstat(&moved_to).is_ok() {
+            stow_actions.push(TargetAction {
+                source_item: Some(stow_item),
+                target_path: moved_to.clone(),
+                link_target_path: None,
+                action_type: ActionType::Conflict,
+                conflict_details: Some(if item_path.as_os_str().is_empty() {
+                    format!("package '{}' already exists", destination)
+                } else {
+                    format!(
+                        "{:?} already exists in package '{}'",
+                        item_path, destination
+                    )
+                }),
+            });
+            continue;
+        }
+        stow_actions.push(TargetAction {
+            source_item: Some(stow_item),
+            target_path: moved_to.clone(),
+            link_target_path: None,
+            action_type: ActionType::MoveItem,
+            conflict_details: Some(format!(
+                "Moving {:?} from package '{}' to package '{}'",
+                item_path, package, destination
+            )),
+        });
+
+        let mut package_config = config.clone();
+        package_config.packages = vec![package.clone()];
+        for link in package_symlinks_in_target(&package_config)? {
+            let Some((link_package, link_item_path)) =
+                lexical_stow_symlink_package_and_item_path(&link, &config.stow_dirs())?
+            else {
+                continue;
+            };
+            if link_package != *package {
+                continue;
+            }
+
+            if let Ok(rest) = link_item_path.strip_prefix(item_path) {
+                let link_item = create_stow_item_from_existing_package_path(
+                    join_relative(&moved_to, rest),
+                    link_item_path.clone(),
+                    link.strip_prefix(&config.target_dir)
+                        .unwrap_or(&link)
+                        .to_path_buf(),
+                    if fs_utils::is_directory(&link) {
+                        StowItemType::Directory
+                    } else {
+                        StowItemType::File
+                    },
+                );
+                delete_actions.push(create_delete_symlink_action(link.clone()));
+                stow_actions.push(create_symlink_action_for_item(link_item, link));
+            } else if item_path.starts_with(&link_item_path) {
+                delete_actions.push(create_delete_symlink_action(link.clone()));
+                stow_actions.extend(unfold_around_moved_item(
+                    &link,
+                    package,
+                    &link_item_path,
+                    item_path,
+                    &moved_to,
+                    config,
+                )?);
+            }
+        }
+    }
+    sort_deletion_actions(&mut delete_actions);
+
+    let mut plan_config = config.clone();
+    plan_config.packages = Vec::new();
+    for (package, _) in &moves {
+        if !plan_config.packages.contains(package) {
+            plan_config.packages.push(package.clone());
+        }
     }
+    Ok(single_operation_plan(
+        &plan_config,
+        StowMode::Stow,
+        delete_actions,
+        stow_actions,
+        false,
+    ))
+}
+
+/// `base` joined with `relative`, without the trailing separator `Path::join` adds for an empty
+/// `relative`.
+fn join_relative(base: &Path, relative: &Path) -> PathBuf {
+    if relative.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(relative)
+    }
+}
+
+/// Actions turning the folded link `target_path`, which covers `moved_item_path`, into a
+/// directory whose links lead to the rest of the package and to the moved item's new location.
+fn unfold_around_moved_item(
+    target_path: &Path,
+    package_name: &str,
+    directory_item_path: &Path,
+    moved_item_path: &Path,
+    moved_to: &Path,
+    config: &Config,
+) -> Result<Vec<TargetAction>, RustowError> {
+    let ignore_patterns = load_ignore_patterns_for_package(package_name, config)?;
+    let mut actions = vec![create_create_directory_action(target_path.to_path_buf())];
+
+    for action in create_relink_actions_for_directory_contents(
+        package_name,
+        directory_item_path,
+        config,
+        &ignore_patterns,
+    )? {
+        let Some(mut stow_item) = action.source_item.clone() else {
+            actions.push(action);
+            continue;
+        };
+        let package_relative_path = stow_item.package_relative_path.clone();
+        if let Ok(rest) = package_relative_path.strip_prefix(moved_item_path) {
+            stow_item.source_path = join_relative(moved_to, rest);
+            actions.push(match action.action_type {
+                ActionType::CreateSymlink => {
+                    create_symlink_action_for_item(stow_item, action.target_path)
+                },
+                _ => create_directory_action_for_item(stow_item, action.target_path),
+            });
+        } else if action.action_type == ActionType::CreateSymlink
+            && moved_item_path.starts_with(&package_relative_path)
+        {
+            actions.extend(unfold_around_moved_item(
+                &action.target_path,
+                package_name,
+                &package_relative_path,
+                moved_item_path,
+                moved_to,
+                config,
+            )?);
+        } else {
+            actions.push(action);
+        }
+    }
+
+    Ok(actions)
+}
+
+/// A link into the old stow directory that `--relink-from` could not map onto the current one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct UnmappedLink {
+    pub(crate) link: PathBuf,
+    /// Where the link leads, resolved lexically.
+    pub(crate) destination: PathBuf,
+    pub(crate) reason: String,
+}
+
+/// Splits `item`, a package followed by a path within it, in the stow directory that package is
+/// taken from.
+fn split_package_item(config: &Config, item: &Path) -> Option<(String, PathBuf)> {
+    config
+        .stow_dirs()
+        .into_iter()
+        .find_map(|stow_dir| {
+            fs_utils::split_package_path(stow_dir, item)
+                .filter(|(package, _)| config.package_stow_dir(package) == stow_dir)
+        })
+        .or_else(|| fs_utils::split_package_path(&config.stow_dir, item))
+}
+
+/// Plan rewriting every link in the target whose lexical destination lies in `old_stow_dir` to
+/// lead to the same package item in the current stow directories.
+///
+/// Only links of `config.packages` are rewritten when it is not empty. Links whose item is
+/// missing from the current stow directories are returned instead of planned.
+pub(crate) fn plan_relink_from(
+    config: &Config,
+    old_stow_dir: &Path,
+) -> Result<(Plan, Vec<UnmappedLink>), RustowError> {
+    let old_stow_dir = if old_stow_dir.is_absolute() {
+        normalize_path_components(old_stow_dir)
+    } else {
+        normalize_path_components(
+            &std::env::current_dir()
+                .map_err(|source| FsError::Io {
+                    path: old_stow_dir.to_path_buf(),
+                    source,
+                })?
+                .join(old_stow_dir),
+        )
+    };
+    // The old directory is usually gone, but its parent resolves like the links' paths do.
+    let old_stow_dir = match (old_stow_dir.parent(), old_stow_dir.file_name()) {
+        (Some(parent), Some(name)) => fs_utils::canonicalize_path(parent)
+            .map(|parent| parent.join(name))
+            .unwrap_or(old_stow_dir),
+        _ => old_stow_dir,
+    };
+    let stow_dirs = config.stow_dirs();
+    if stow_dirs
+        .iter()
+        .any(|stow_dir| old_stow_dir == normalize_path_components(stow_dir))
+    {
+        return Err(crate::error::ConfigError::InvalidStowDir(format!(
+            "{:?} is a current stow directory; give the directory it was moved from",
+            old_stow_dir
+        ))
+        .into());
+    }
+
+    let mut links = Vec::new();
+    let mut skip = stow_dirs.clone();
+    skip.push(&old_stow_dir);
+    collect_symlinks_under(&config.target_dir, &skip, &mut links)?;
+
+    let mut delete_actions = Vec::new();
+    let mut stow_actions = Vec::new();
+    let mut unmapped = Vec::new();
+    let mut packages: Vec<String> = Vec::new();
+    for link in links {
+        let destination =
+            normalize_path_components(&resolve_symlink_target(&link, &fs_utils::read_link(&link)?));
+        let Ok(relative_to_stow) = destination.strip_prefix(&old_stow_dir) else {
+            continue;
+        };
+        // Namespaces are looked up in the stow directories now holding the packages; the old
+        // one may be gone.
+        let Some((package, package_relative_path)) = split_package_item(config, relative_to_stow)
+        else {
+            unmapped.push(UnmappedLink {
+                link,
+                destination,
+                reason: "it leads to the stow directory or a namespace, not a package".to_string(),
+            });
+            continue;
+        };
+        if !config.packages.is_empty() && !config.packages.contains(&package) {
+            continue;
+        }
+
+        let source_path = config.package_dir(&package).join(&package_relative_path);
+        let Ok(stat) = filesystem::current().lstat(&source_path) else {
+            unmapped.push(UnmappedLink {
+                link,
+                destination,
+                reason: format!("{:?} does not exist", source_path),
+            });
+            continue;
+        };
+        let item_type = if stat.is_symlink() {
+            StowItemType::Symlink
+        } else if stat.is_dir() {
+            StowItemType::Directory
+        } else {
+            StowItemType::File
+        };
+        let stow_item = create_stow_item_from_existing_package_path(
+            source_path,
+            package_relative_path,
+            link.strip_prefix(&config.target_dir)
+                .unwrap_or(&link)
+                .to_path_buf(),
+            item_type,
+        );
+        delete_actions.push(create_delete_symlink_action(link.clone()));
+        stow_actions.push(create_symlink_action_for_item(stow_item, link));
+        if !packages.contains(&package) {
+            packages.push(package);
+        }
+    }
+
+    let mut plan_config = config.clone();
+    plan_config.packages = packages;
+    Ok((
+        single_operation_plan(
+            &plan_config,
+            StowMode::Stow,
+            delete_actions,
+            stow_actions,
+            false,
+        ),
+        unmapped,
+    ))
+}
+
+/// Every symlink below `dir`, without descending into symlinked directories, `skip`, or
+/// directories marked with `.stow` or `.nonstow`.
+fn collect_symlinks_under(
+    dir: &Path,
+    skip: &[&Path],
+    links: &mut Vec<PathBuf>,
+) -> Result<(), RustowError> {
+    for entry in fs_utils::read_dir(dir)? {
+        let path = entry.path();
+        if skip.iter().any(|skipped| path.starts_with(skipped)) {
+            continue;
+        }
+        match entry.kind() {
+            FileKind::Symlink => links.push(path),
+            FileKind::Directory if fs_utils::protection_marker(&path).is_some() => {},
+            FileKind::Directory => collect_symlinks_under(&path, skip, links)?,
+            FileKind::File | FileKind::Other => {},
+        }
+    }
+    Ok(())
+}
+
+/// Plan replacing the folded directory link at `path` with a real directory holding one link
+/// per entry of the package directory, like stowing a second package into it would.
+pub(crate) fn plan_unfold_directory(config: &Config, path: &Path) -> Result<Plan, RustowError> {
+    let target_path = target_path_argument(config, path, StowError::NotManagedDirectory)?;
+    let not_folded = || -> RustowError {
+        StowError::NotManagedDirectory(format!(
+            "{:?} is not a folded directory link into a package",
+            path
+        ))
+        .into()
+    };
+
+    let Some((package_name, item_path)) =
+        lexical_stow_symlink_package_and_item_path(&target_path, &config.stow_dirs())?
+    else {
+        return Err(not_folded());
+    };
+    let expected_target_path = config.target_dir.join(dotfiles::process_item_name(
+        item_path.to_str().unwrap_or(""),
+        config.dotfiles,
+    ));
+    if item_path.as_os_str().is_empty()
+        || expected_target_path != target_path
+        || !package_is_valid_refold_source(&package_name, config)?
+        || !fs_utils::is_directory(&config.package_dir(&package_name).join(&item_path))
+    {
+        return Err(not_folded());
+    }
+
+    let mut plan_config = config.clone();
+    plan_config.packages = vec![package_name.clone()];
+    let stow_actions = split_open_actions(&target_path, &package_name, &item_path, config)?;
+    Ok(single_operation_plan(
+        &plan_config,
+        StowMode::Stow,
+        Vec::new(),
+        stow_actions,
+        false,
+    ))
+}
+
+/// Plan replacing the directory at `path`, whose entries all link into one package directory,
+/// with a single folded link to that package directory.
+pub(crate) fn plan_refold_directory(config: &Config, path: &Path) -> Result<Plan, RustowError> {
+    let target_path = target_path_argument(config, path, StowError::NotManagedDirectory)?;
+    if fs_utils::is_symlink(&target_path) || !fs_utils::is_directory(&target_path) {
+        return Err(StowError::NotManagedDirectory(format!(
+            "{:?} is not a directory; only unfolded directories can be refolded",
+            path
+        ))
+        .into());
+    }
+    let Some(source_dir) = common_package_directory_for_symlinks(&target_path, config)? else {
+        return Err(StowError::NotManagedDirectory(format!(
+            "{:?} cannot be refolded: its entries must all link to the entries of one package \
+             directory, with nothing ignored, deferred or kept unfolded by a folding rule",
+            path
+        ))
+        .into());
+    };
+    let Some((package_name, item_path)) = package_and_item_path_for_source_dir(&source_dir, config)
+    else {
+        return Err(StowError::NotManagedDirectory(format!(
+            "{:?} does not belong to a package",
+            source_dir
+        ))
+        .into());
+    };
+
+    let mut delete_actions: Vec<TargetAction> = fs_utils::read_dir(&target_path)?
+        .into_iter()
+        .map(|entry| create_delete_symlink_action(entry.path()))
+        .collect();
+    delete_actions.push(create_delete_directory_action(target_path.clone()));
+    sort_deletion_actions(&mut delete_actions);
+
+    let processed_target_relative_path = PathBuf::from(dotfiles::process_item_name(
+        item_path.to_str().unwrap_or(""),
+        config.dotfiles,
+    ));
+    let stow_item = create_stow_item_from_existing_package_path(
+        source_dir,
+        item_path,
+        processed_target_relative_path,
+        StowItemType::Directory,
+    );
+    let mut plan_config = config.clone();
+    plan_config.packages = vec![package_name];
+    Ok(single_operation_plan(
+        &plan_config,
+        StowMode::Stow,
+        delete_actions,
+        vec![create_symlink_action_for_item(stow_item, target_path)],
+        false,
+    ))
+}
+
+/// Execute a plan from [`plan_refold_directory`], swapping the folded link into place so the
+/// path never goes missing where the filesystem can exchange paths.
+///
+/// One report is returned per link removed and for the folded link created.
+pub(crate) fn apply_refold_plan(plan: &Plan) -> Result<Vec<TargetActionReport>, RustowError> {
+    let config = &plan.config;
+    let (Some(link_action), Some(source_item)) = (
+        plan.stow_actions.first(),
+        plan.stow_actions
+            .first()
+            .and_then(|action| action.source_item.as_ref()),
+    ) else {
+        return Err(
+            StowError::OperationFailed("refold plan has no link to create".to_string()).into(),
+        );
+    };
+    let dir_path = &link_action.target_path;
+
+    let source_dir = common_package_directory_for_symlinks(dir_path, config)?;
+    if source_dir.as_deref() != Some(source_item.source_path.as_path()) {
+        return Err(StowError::OperationFailed(format!(
+            "{:?} changed since it was planned for refolding",
+            dir_path
+        ))
+        .into());
+    }
+
+    log_event!(
+        Exec,
+        Debug,
+        "refolding {} into a link to {}",
+        dir_path.display(),
+        source_item.source_path.display()
+    );
+    let mut report = refold_directory(dir_path, &source_item.source_path);
+    if !matches!(report.status, TargetActionReportStatus::Success) {
+        return Ok(vec![report]);
+    }
+
+    let mut reports: Vec<TargetActionReport> = plan
+        .delete_actions
+        .iter()
+        .map(|action| TargetActionReport {
+            original_action: action.clone(),
+            status: TargetActionReportStatus::Success,
+            message: Some(match action.action_type {
+                ActionType::DeleteDirectory => {
+                    format!("Successfully deleted directory {:?}", action.target_path)
+                },
+                _ => format!("Successfully deleted symlink {:?}", action.target_path),
+            }),
+        })
+        .collect();
+    report.original_action = link_action.clone();
+    reports.push(report);
+    Ok(reports)
 }
 
 fn collect_refold_candidate_dirs<'a, I>(actions: I, config: &Config) -> Vec<PathBuf>
@@ -1998,8 +3123,10 @@ where
             }
 
             if !candidate.starts_with(&config.target_dir)
-                || candidate == config.stow_dir
-                || candidate.starts_with(&config.stow_dir)
+                || config
+                    .stow_dirs()
+                    .iter()
+                    .any(|stow_dir| candidate.starts_with(stow_dir))
             {
                 continue;
             }
@@ -2037,6 +3164,13 @@ where
         }
 
         if let Some(source_dir) = common_package_directory_for_symlinks(&dir, config)? {
+            log_event!(
+                Exec,
+                Debug,
+                "refolding {} into a link to {}",
+                dir.display(),
+                source_dir.display()
+            );
             reports.push(refold_directory(&dir, &source_dir));
         }
     }
@@ -2045,9 +3179,7 @@ where
 }
 
 fn reports_allow_refolding(reports: &[TargetActionReport], config: &Config) -> bool {
-    !config.simulate
-        && !config.no_folding
-        && reports.iter().all(|report| !report.status.is_blocking())
+    !config.no_folding && reports.iter().all(|report| !report.status.is_blocking())
 }
 
 /// Execute a skip action
@@ -2067,14 +3199,18 @@ fn load_ignore_patterns_for_package(
     package_name: &str,
     config: &Config,
 ) -> Result<IgnorePatterns, RustowError> {
-    IgnorePatterns::load(&config.stow_dir, Some(package_name), &config.home_dir)
-        .map(|patterns| patterns.with_extra_patterns(&config.ignore_patterns))
-        .map_err(|e| {
-            RustowError::Ignore(crate::error::IgnoreError::LoadPatternsError(format!(
-                "Failed to load ignore patterns for package '{}': {:?}",
-                package_name, e
-            )))
-        })
+    IgnorePatterns::load(
+        config.package_stow_dir(package_name),
+        Some(package_name),
+        &config.home_dir,
+    )
+    .map(|patterns| patterns.with_extra_patterns(&config.ignore_patterns))
+    .map_err(|e| {
+        RustowError::Ignore(crate::error::IgnoreError::LoadPatternsError(format!(
+            "Failed to load ignore patterns for package '{}': {:?}",
+            package_name, e
+        )))
+    })
 }
 
 /// Process all packages and collect their actions
@@ -2116,12 +3252,147 @@ fn plan_stow_package_actions(config: &Config) -> Result<Vec<TargetAction>, Rusto
 }
 
 pub fn stow_packages(config: &Config) -> Result<Vec<TargetActionReport>, RustowError> {
-    if config.packages.is_empty() {
-        return Ok(Vec::new());
+    apply_plan(&plan_stow_packages(config)?)
+}
+
+fn plan_stow_packages(config: &Config) -> Result<Plan, RustowError> {
+    let stow_actions = if config.packages.is_empty() {
+        Vec::new()
+    } else {
+        plan_stow_package_actions(config)?
+    };
+
+    Ok(single_operation_plan(
+        config,
+        StowMode::Stow,
+        Vec::new(),
+        stow_actions,
+        false,
+    ))
+}
+
+fn single_operation_plan(
+    config: &Config,
+    mode: StowMode,
+    delete_actions: Vec<TargetAction>,
+    stow_actions: Vec<TargetAction>,
+    refold: bool,
+) -> Plan {
+    Plan {
+        config: config.clone(),
+        operations: vec![PackageOperation {
+            mode,
+            packages: config.packages.clone(),
+        }],
+        delete_actions,
+        stow_actions,
+        refold,
+    }
+}
+
+/// Plan a sequence of package operations as a single [`Plan`].
+///
+/// One operation is planned on its own mode; several are planned together like a mixed
+/// `-D`/`-S`/`-R` command line, deleting before stowing.
+pub(crate) fn plan_package_operations(
+    config: &Config,
+    operations: &[PackageOperation],
+) -> Result<Plan, RustowError> {
+    let [operation] = operations else {
+        let mut delete_packages = Vec::new();
+        let mut stow_packages = Vec::new();
+        let mut restow_packages = Vec::new();
+
+        for operation in operations {
+            match operation.mode {
+                StowMode::Stow => stow_packages.extend(operation.packages.clone()),
+                StowMode::Delete => delete_packages.extend(operation.packages.clone()),
+                StowMode::Restow => restow_packages.extend(operation.packages.clone()),
+            }
+        }
+
+        let mut plan =
+            plan_mixed_packages(config, &delete_packages, &stow_packages, &restow_packages)?;
+        plan.operations = operations.to_vec();
+        return Ok(check_sensitive_items(plan));
+    };
+
+    let mut operation_config = config.clone();
+    operation_config.mode = operation.mode.clone();
+    operation_config.packages = operation.packages.clone();
+
+    let plan = match &operation.mode {
+        StowMode::Stow => plan_stow_packages(&operation_config),
+        StowMode::Delete => plan_delete_packages(&operation_config),
+        StowMode::Restow => plan_restow_packages(&operation_config),
+    }?;
+    Ok(check_sensitive_items(plan))
+}
+
+/// Under `--insecure-permissions=refuse`, turns links to sensitive items with insecure
+/// permissions into conflicts; otherwise they are reported when the plan runs.
+fn check_sensitive_items(mut plan: Plan) -> Plan {
+    if plan.config.insecure_permissions == InsecurePermissions::Refuse {
+        sensitive::refuse_insecure(&mut plan);
+    }
+    plan
+}
+
+/// Execute a plan, deleting before stowing and refolding afterwards when the plan asks for it.
+///
+/// Simulated plans run against an in-memory overlay, see [`simulate_plan`].
+pub(crate) fn apply_plan(plan: &Plan) -> Result<Vec<TargetActionReport>, RustowError> {
+    if plan.config.simulate {
+        return simulate_plan(plan).map(|simulation| simulation.reports);
+    }
+
+    let config = &plan.config;
+    let mut reports =
+        execute_delete_then_stow_actions(&plan.delete_actions, &plan.stow_actions, config)?;
+    if plan.refold && reports_allow_refolding(&reports, config) {
+        reports.extend(refold_foldable_trees(config, plan.actions())?);
     }
 
-    let all_planned_actions = plan_stow_package_actions(config)?;
-    execute_actions(&all_planned_actions, config)
+    Ok(reports)
+}
+
+/// Execute a plan against an in-memory overlay of the real filesystem.
+///
+/// Every action runs exactly as it would for real, so actions that would fail are reported as
+/// failures, and the overlay afterwards holds the state the target would be left in.
+pub(crate) fn simulate_plan(plan: &Plan) -> Result<SimulatedApply, RustowError> {
+    log_event!(Exec, Debug, "simulating plan against an in-memory overlay");
+    let mut overlay_plan = plan.clone();
+    overlay_plan.config.simulate = false;
+
+    filesystem::with_filesystem(Rc::new(MemoryFilesystem::overlay()), || {
+        let reports = apply_plan(&overlay_plan)?;
+        let final_state = touched_paths(&reports)
+            .into_iter()
+            .map(PathFingerprint::capture)
+            .collect::<Result<Vec<_>, RustowError>>()?;
+
+        Ok(SimulatedApply {
+            reports: reports.into_iter().map(simulated_report).collect(),
+            final_state,
+        })
+    })
+}
+
+fn touched_paths(reports: &[TargetActionReport]) -> BTreeSet<PathBuf> {
+    let mut paths = BTreeSet::new();
+    for report in reports {
+        let action = &report.original_action;
+        paths.insert(action.target_path.clone());
+        if matches!(
+            action.action_type,
+            ActionType::AdoptFile | ActionType::AdoptDirectory | ActionType::MoveItem
+        ) && let Some(source_item) = &action.source_item
+        {
+            paths.insert(source_item.source_path.clone());
+        }
+    }
+    paths
 }
 
 fn plan_delete_package_actions(config: &Config) -> Result<Vec<TargetAction>, RustowError> {
@@ -2133,17 +3404,23 @@ fn plan_delete_package_actions(config: &Config) -> Result<Vec<TargetAction>, Rus
 
 /// Delete (unstow) packages from the target directory
 pub fn delete_packages(config: &Config) -> Result<Vec<TargetActionReport>, RustowError> {
-    if config.packages.is_empty() {
-        return Ok(Vec::new());
-    }
+    apply_plan(&plan_delete_packages(config)?)
+}
 
-    let all_planned_actions = plan_delete_package_actions(config)?;
-    let mut reports = execute_actions(&all_planned_actions, config)?;
-    if reports_allow_refolding(&reports, config) {
-        reports.extend(refold_foldable_trees(config, all_planned_actions.iter())?);
-    }
+fn plan_delete_packages(config: &Config) -> Result<Plan, RustowError> {
+    let delete_actions = if config.packages.is_empty() {
+        Vec::new()
+    } else {
+        plan_delete_package_actions(config)?
+    };
 
-    Ok(reports)
+    Ok(single_operation_plan(
+        config,
+        StowMode::Delete,
+        delete_actions,
+        Vec::new(),
+        true,
+    ))
 }
 
 fn plan_restow_delete_package_actions(config: &Config) -> Result<Vec<TargetAction>, RustowError> {
@@ -2181,7 +3458,10 @@ fn collect_matching_stow_symlinks_for_current_package_images(
     let mut candidate_target_dirs = Vec::new();
 
     for package_matcher in package_matchers {
-        let package_path = validated_package_path(&config.stow_dir, &package_matcher.name)?;
+        let package_path = validated_package_path(
+            config.package_stow_dir(&package_matcher.name),
+            &package_matcher.name,
+        )?;
         let ignore_patterns = &package_matcher.ignore_patterns;
         let raw_items = load_package_items(&package_path, &package_matcher.name)?;
 
@@ -2245,7 +3525,11 @@ fn create_restow_symlink_package_matchers(
         .map(|package_name| {
             Ok(RestowSymlinkPackageMatcher {
                 name: package_name.clone(),
-                canonical_path: canonical_package_path(&config.stow_dir, package_name).ok(),
+                canonical_path: canonical_package_path(
+                    config.package_stow_dir(package_name),
+                    package_name,
+                )
+                .ok(),
                 ignore_patterns: load_ignore_patterns_for_package(package_name, config)?,
             })
         })
@@ -2281,14 +3565,20 @@ fn collect_matching_stow_symlinks_under_target_dir(
         return Ok(false);
     }
 
-    if !fs_utils::is_directory(target_path) {
+    if !fs_utils::is_directory(target_path)
+        || fs_utils::protected_ancestor(target_path, &config.target_dir).is_some()
+    {
         return Ok(false);
     }
 
     let mut contains_package_symlink = false;
-    for entry in read_directory_entries(target_path)? {
-        let path = read_directory_entry(entry, target_path)?.path();
-        if path == config.stow_dir || path.starts_with(&config.stow_dir) {
+    for entry in fs_utils::read_dir(target_path)? {
+        let path = entry.path();
+        if config
+            .stow_dirs()
+            .iter()
+            .any(|stow_dir| path.starts_with(stow_dir))
+        {
             continue;
         }
 
@@ -2326,7 +3616,7 @@ fn symlink_matches_any_package_target_path(
     existing_package_canonical_paths: &mut HashMap<String, Option<PathBuf>>,
 ) -> Result<bool, RustowError> {
     let Some((existing_package_name, item_path)) =
-        lexical_stow_symlink_package_and_item_path(target_path, &config.stow_dir)?
+        lexical_stow_symlink_package_and_item_path(target_path, &config.stow_dirs())?
     else {
         return Ok(false);
     };
@@ -2354,7 +3644,11 @@ fn symlink_matches_any_package_target_path(
         let existing_canonical_path = existing_package_canonical_paths
             .entry(existing_package_name.clone())
             .or_insert_with(|| {
-                canonical_package_path(&config.stow_dir, &existing_package_name).ok()
+                canonical_package_path(
+                    config.package_stow_dir(&existing_package_name),
+                    &existing_package_name,
+                )
+                .ok()
             });
         if matches!(
             (
@@ -2378,7 +3672,41 @@ fn target_relative_path_is_ignored(
 }
 
 pub fn restow_packages(config: &Config) -> Result<Vec<TargetActionReport>, RustowError> {
-    let delete_actions = plan_restow_delete_package_actions(config)?;
+    apply_plan(&plan_restow_packages(config)?)
+}
+
+fn plan_restow_packages(config: &Config) -> Result<Plan, RustowError> {
+    plan_restow_packages_pruning(config, &[])
+}
+
+/// Plan a restow of `config.packages` that also removes their links at or below
+/// `vanished_targets`.
+///
+/// Those are the target paths of entries removed from a package since it was stowed. The
+/// delete phase otherwise only finds links for entries that still exist in the package.
+pub(crate) fn plan_restow_packages_pruning(
+    config: &Config,
+    vanished_targets: &[PathBuf],
+) -> Result<Plan, RustowError> {
+    let mut delete_actions = plan_restow_delete_package_actions(config)?;
+    if !vanished_targets.is_empty() {
+        let package_matchers = create_restow_symlink_package_matchers(config)?;
+        let mut existing_package_canonical_paths = HashMap::new();
+        for target_path in vanished_targets {
+            let emptied = collect_matching_stow_symlinks_under_target_dir(
+                target_path,
+                config,
+                &package_matchers,
+                &mut existing_package_canonical_paths,
+                &mut delete_actions,
+            )?;
+            if emptied && !fs_utils::is_symlink(target_path) {
+                delete_actions.push(create_delete_directory_action(target_path.clone()));
+            }
+        }
+        sort_deletion_actions(&mut delete_actions);
+        deduplicate_delete_actions(&mut delete_actions);
+    }
     let mut stow_actions = plan_stow_package_actions(config)?;
 
     reconcile_stow_actions_with_delete_phase(
@@ -2391,23 +3719,55 @@ pub fn restow_packages(config: &Config) -> Result<Vec<TargetActionReport>, Rusto
     )?;
     apply_conflict_resolution(&mut stow_actions, config);
 
-    let mut reports = execute_delete_then_stow_actions(&delete_actions, &stow_actions, config)?;
-    if reports_allow_refolding(&reports, config) {
-        reports.extend(refold_foldable_trees(
-            config,
-            delete_actions.iter().chain(stow_actions.iter()),
-        )?);
-    }
+    Ok(single_operation_plan(
+        config,
+        StowMode::Restow,
+        delete_actions,
+        stow_actions,
+        true,
+    ))
+}
 
-    Ok(reports)
+/// Links anywhere under the target directory that point into one of `config.packages`.
+///
+/// Links to ignored package entries are left out, as restow leaves them alone too.
+pub(crate) fn package_symlinks_in_target(config: &Config) -> Result<Vec<PathBuf>, RustowError> {
+    let package_matchers = create_restow_symlink_package_matchers(config)?;
+    let mut existing_package_canonical_paths = HashMap::new();
+    let mut actions = Vec::new();
+    collect_matching_stow_symlinks_under_target_dir(
+        &config.target_dir,
+        config,
+        &package_matchers,
+        &mut existing_package_canonical_paths,
+        &mut actions,
+    )?;
+    Ok(actions
+        .into_iter()
+        .map(|action| action.target_path)
+        .collect())
+}
+
+pub fn mixed_packages(
+    config: &Config,
+    delete_packages: &[String],
+    stow_packages: &[String],
+    restow_packages: &[String],
+) -> Result<Vec<TargetActionReport>, RustowError> {
+    apply_plan(&plan_mixed_packages(
+        config,
+        delete_packages,
+        stow_packages,
+        restow_packages,
+    )?)
 }
 
-pub fn mixed_packages(
+fn plan_mixed_packages(
     config: &Config,
     delete_packages: &[String],
     stow_packages: &[String],
     restow_packages: &[String],
-) -> Result<Vec<TargetActionReport>, RustowError> {
+) -> Result<Plan, RustowError> {
     let (delete_packages, stow_packages, restow_packages) =
         normalize_mixed_package_sets(delete_packages, stow_packages, restow_packages);
     let mut delete_actions = Vec::new();
@@ -2435,15 +3795,23 @@ pub fn mixed_packages(
     )?;
     apply_conflict_resolution(&mut stow_actions, config);
 
-    let mut reports = execute_delete_then_stow_actions(&delete_actions, &stow_actions, config)?;
-    if reports_allow_refolding(&reports, config) {
-        reports.extend(refold_foldable_trees(
-            config,
-            delete_actions.iter().chain(stow_actions.iter()),
-        )?);
-    }
-
-    Ok(reports)
+    let operations = [
+        (StowMode::Delete, delete_packages),
+        (StowMode::Stow, stow_packages),
+        (StowMode::Restow, restow_packages),
+    ]
+    .into_iter()
+    .filter(|(_, packages)| !packages.is_empty())
+    .map(|(mode, packages)| PackageOperation { mode, packages })
+    .collect();
+
+    Ok(Plan {
+        config: config.clone(),
+        operations,
+        delete_actions,
+        stow_actions,
+        refold: true,
+    })
 }
 
 fn normalize_mixed_package_sets(
@@ -2505,15 +3873,77 @@ fn execute_delete_then_stow_actions(
         return execute_actions(&all_actions, config);
     }
 
-    let mut reports = execute_actions(delete_actions, config)?;
+    let replaced_in_place = symlinks_replaced_in_place(delete_actions, stow_actions);
+    let (deferred_actions, delete_actions): (Vec<TargetAction>, Vec<TargetAction>) =
+        delete_actions.iter().cloned().partition(|action| {
+            action.action_type == ActionType::DeleteSymlink
+                && replaced_in_place.contains(&action.target_path)
+        });
+
+    let mut reports = execute_actions(&delete_actions, config)?;
     if target_action_reports_have_blocking_status(&reports) {
         return Ok(reports);
     }
 
-    reports.extend(execute_actions(stow_actions, config)?);
+    // Only now is the stow phase certain to run, so a plan that stops early changes no modes.
+    if config.insecure_permissions == InsecurePermissions::Fix {
+        sensitive::fix_insecure(&sensitive::find_insecure_in(stow_actions, config))?;
+    }
+    let stow_reports = execute_actions(stow_actions, config)?;
+    // A kept symlink is only gone once the link replacing it is in place; otherwise the delete
+    // phase removes it after all.
+    reports.extend(deferred_actions.into_iter().map(|action| {
+        let replaced = stow_reports.iter().any(|report| {
+            report.original_action.target_path == action.target_path
+                && report.original_action.action_type == ActionType::CreateSymlink
+                && report.status == TargetActionReportStatus::Success
+        });
+        if replaced {
+            TargetActionReport {
+                message: Some(format!(
+                    "Replaced symlink {:?} in place with the new link",
+                    action.target_path
+                )),
+                original_action: action,
+                status: TargetActionReportStatus::Success,
+            }
+        } else {
+            execute_real_action(&action, config)
+        }
+    }));
+    reports.extend(stow_reports);
     Ok(reports)
 }
 
+/// Symlinks the delete phase would remove only for the stow phase to recreate at the same path.
+///
+/// Leaving them in place lets the stow phase swap in the new link atomically instead of
+/// exposing a missing path between the two phases.
+fn symlinks_replaced_in_place(
+    delete_actions: &[TargetAction],
+    stow_actions: &[TargetAction],
+) -> HashSet<PathBuf> {
+    let recreated_symlinks: HashSet<&Path> = stow_actions
+        .iter()
+        .filter(|action| action.action_type == ActionType::CreateSymlink)
+        .map(|action| action.target_path.as_path())
+        .collect();
+
+    delete_actions
+        .iter()
+        .filter(|action| {
+            action.action_type == ActionType::DeleteSymlink
+                && recreated_symlinks.contains(action.target_path.as_path())
+                && !action
+                    .target_path
+                    .ancestors()
+                    .skip(1)
+                    .any(|ancestor| recreated_symlinks.contains(ancestor))
+        })
+        .map(|action| action.target_path.clone())
+        .collect()
+}
+
 fn config_for_packages(config: &Config, packages: &[String]) -> Config {
     let mut operation_config = config.clone();
     operation_config.packages = packages.to_vec();
@@ -2565,10 +3995,9 @@ fn reconcile_stow_actions_with_delete_phase(
         if matches!(
             action.action_type,
             ActionType::CreateSymlink | ActionType::CreateDirectory
-        ) {
-            if let Some(package_name) = action_package_name(action, config) {
-                return !delete_only_packages.contains(package_name.as_str());
-            }
+        ) && let Some(package_name) = action_package_name(action, config)
+        {
+            return !delete_only_packages.contains(package_name.as_str());
         }
 
         true
@@ -2586,6 +4015,12 @@ fn reconcile_stow_actions_with_delete_phase(
             };
 
             if open_directory_targets.contains(&action.target_path) {
+                log_event!(
+                    Planner,
+                    Debug,
+                    "keeping {} open as a directory after the delete phase",
+                    action.target_path.display()
+                );
                 action.action_type = ActionType::CreateDirectory;
                 action.link_target_path = None;
                 action.conflict_details = None;
@@ -2600,12 +4035,18 @@ fn reconcile_stow_actions_with_delete_phase(
             }
 
             if action.link_target_path.is_some() {
+                log_event!(
+                    Planner,
+                    Debug,
+                    "relinking {} freed by the delete phase",
+                    action.target_path.display()
+                );
                 action.action_type = ActionType::CreateSymlink;
                 action.conflict_details = None;
-                if stow_item.item_type == StowItemType::Directory {
-                    if let Some(package_name) = action_package_name(action, config) {
-                        folded_targets.push((action.target_path.clone(), package_name));
-                    }
+                if stow_item.item_type == StowItemType::Directory
+                    && let Some(package_name) = action_package_name(action, config)
+                {
+                    folded_targets.push((action.target_path.clone(), package_name));
                 }
             }
         }
@@ -2736,8 +4177,8 @@ fn planned_directory_will_be_empty(
         return Ok(false);
     }
 
-    for entry in read_directory_entries(directory_target)? {
-        if !removed_targets.contains(&read_directory_entry(entry, directory_target)?.path()) {
+    for entry in fs_utils::read_dir(directory_target)? {
+        if !removed_targets.contains(&entry.path()) {
             return Ok(false);
         }
     }
@@ -2788,16 +4229,7 @@ fn path_has_symlink_ancestor(path: &Path, root: &Path) -> bool {
 
 /// Check if a directory is empty
 fn is_directory_empty(dir_path: &Path) -> Result<bool, RustowError> {
-    Ok(read_directory_entries(dir_path)?
-        .next()
-        .transpose()
-        .map_err(|e| {
-            RustowError::Fs(FsError::Io {
-                path: dir_path.to_path_buf(),
-                source: e,
-            })
-        })?
-        .is_none())
+    Ok(fs_utils::read_dir(dir_path)?.is_empty())
 }
 
 /// Create a delete symlink action
@@ -2845,6 +4277,13 @@ fn process_deletion_items(
         if let Some(action) =
             process_item_for_deletion(raw_item, config, current_ignore_patterns, package_name)?
         {
+            log_event!(
+                Planner,
+                Trace,
+                "{:?} {}",
+                action.action_type,
+                action.target_path.display()
+            );
             actions.push(action);
         }
     }
@@ -2861,9 +4300,16 @@ fn plan_delete_actions(
     config: &Config,
     current_ignore_patterns: &IgnorePatterns,
 ) -> Result<Vec<TargetAction>, RustowError> {
-    let package_path = validated_package_path(&config.stow_dir, package_name)?;
+    let package_path = validated_package_path(config.package_stow_dir(package_name), package_name)?;
 
     let raw_items = load_package_items(&package_path, package_name)?;
+    log_event!(
+        Planner,
+        Info,
+        "planning unstow of package '{}' ({} item(s))",
+        package_name,
+        raw_items.len()
+    );
     process_deletion_items(raw_items, config, current_ignore_patterns, package_name)
 }
 
@@ -2903,6 +4349,7 @@ fn validated_package_path_with_display(
 
     let package_path = stow_dir.join(package_name);
     validate_package_path(&package_path, package_name, package_path_display)?;
+    validate_package_root(stow_dir, package_name)?;
 
     let canonical_package_path = fs_utils::canonicalize_path(&package_path)?;
     let canonical_stow_dir = fs_utils::canonicalize_path(stow_dir)?;
@@ -2929,6 +4376,29 @@ fn validated_package_path_with_display(
     Ok(package_path)
 }
 
+/// Reject naming a namespace, or a directory inside a package, as a package.
+fn validate_package_root(stow_dir: &Path, package_name: &str) -> Result<(), RustowError> {
+    match fs_utils::split_package_path(stow_dir, Path::new(package_name)) {
+        Some((_, item_path)) if item_path.as_os_str().is_empty() => Ok(()),
+        Some((package, _)) => Err(StowError::InvalidPackageStructure(format!(
+            "'{}' is a directory in package '{}'; create '{}/{}' to make the directories in \
+             '{}' packages",
+            package_name,
+            package,
+            package,
+            fs_utils::NAMESPACE_MARKER,
+            package
+        ))
+        .into()),
+        None => Err(StowError::InvalidPackageStructure(format!(
+            "'{}' is a namespace ({} marks it), not a package; name the packages in it instead",
+            package_name,
+            fs_utils::NAMESPACE_MARKER
+        ))
+        .into()),
+    }
+}
+
 fn canonical_package_path(stow_dir: &Path, package_name: &str) -> Result<PathBuf, RustowError> {
     validate_relative_package_name(package_name)?;
 
@@ -3045,6 +4515,10 @@ fn process_item_for_deletion(
         create_conflict_for_symlinked_target_ancestor(&stow_item, &target_path_abs, config)
     {
         conflict
+    } else if let Some(skip) =
+        create_action_for_protected_target(&stow_item, &target_path_abs, config, ActionType::Skip)
+    {
+        skip
     } else if fs_utils::path_exists(&target_path_abs) {
         plan_deletion_for_existing_target(&stow_item, &target_path_abs, config, package_name)?
     } else {
@@ -3073,6 +4547,39 @@ fn create_conflict_for_symlinked_target_ancestor(
     })
 }
 
+/// Why `target_path_abs` lies in a directory marked with `.stow` or `.nonstow`, if it does.
+fn protected_target_reason(target_path_abs: &Path, config: &Config) -> Option<String> {
+    let (dir, marker) = fs_utils::protected_ancestor(target_path_abs, &config.target_dir)?;
+    Some(if marker == fs_utils::STOW_DIR_MARKER {
+        format!(
+            "{:?} is marked with {} as another stow directory",
+            dir, marker
+        )
+    } else {
+        format!("{:?} is protected with {}", dir, marker)
+    })
+}
+
+fn create_action_for_protected_target(
+    stow_item: &StowItem,
+    target_path_abs: &Path,
+    config: &Config,
+    action_type: ActionType,
+) -> Option<TargetAction> {
+    let reason = protected_target_reason(target_path_abs, config)?;
+
+    Some(TargetAction {
+        source_item: Some(stow_item.clone()),
+        target_path: target_path_abs.to_path_buf(),
+        link_target_path: None,
+        conflict_details: Some(match action_type {
+            ActionType::Conflict => format!("Refusing to stow into a marked directory: {}", reason),
+            _ => format!("Not unstowing from a marked directory: {}", reason),
+        }),
+        action_type,
+    })
+}
+
 /// Prepare paths for ignore pattern checking
 fn prepare_ignore_check_paths(processed_target_relative_path: &Path) -> (PathBuf, String) {
     let path_for_ignore_check_fullpath = PathBuf::from("/").join(processed_target_relative_path);
@@ -3203,7 +4710,7 @@ fn validate_target_for_deletion(
         ));
     }
 
-    match fs_utils::is_stow_symlink(target_path_abs, &config.stow_dir) {
+    match fs_utils::is_stow_symlink(target_path_abs, &config.stow_dirs()) {
         Ok(Some((existing_package_name, item_path_in_package))) => {
             if is_same_package_for_deletion(&existing_package_name, package_name, config)
                 && item_path_in_package == stow_item.package_relative_path
@@ -3250,13 +4757,16 @@ fn is_same_package_name(
         return true;
     }
 
-    let Ok(existing_package_path) = canonical_package_path(&config.stow_dir, existing_package_name)
-    else {
+    let Ok(existing_package_path) = canonical_package_path(
+        config.package_stow_dir(existing_package_name),
+        existing_package_name,
+    ) else {
         return false;
     };
-    let Ok(requested_package_path) =
-        canonical_package_path(&config.stow_dir, requested_package_name)
-    else {
+    let Ok(requested_package_path) = canonical_package_path(
+        config.package_stow_dir(requested_package_name),
+        requested_package_name,
+    ) else {
         return false;
     };
 
@@ -3327,17 +4837,17 @@ fn execute_adopt_file_action(action: &TargetAction, config: &Config) -> TargetAc
     }
 
     // Ensure the package directory exists
-    if let Some(package_dir) = source_item.source_path.parent() {
-        if let Err(e) = fs_utils::create_dir_all(package_dir) {
-            return TargetActionReport {
-                original_action: action.clone(),
-                status: TargetActionReportStatus::Failure(e.to_string()),
-                message: Some(format!(
-                    "Failed to create package directory {:?}: {}",
-                    package_dir, e
-                )),
-            };
-        }
+    if let Some(package_dir) = source_item.source_path.parent()
+        && let Err(e) = fs_utils::create_dir_all(package_dir)
+    {
+        return TargetActionReport {
+            original_action: action.clone(),
+            status: TargetActionReportStatus::Failure(e.to_string()),
+            message: Some(format!(
+                "Failed to create package directory {:?}: {}",
+                package_dir, e
+            )),
+        };
     }
 
     // Move the file from target to package directory
@@ -3446,17 +4956,17 @@ fn execute_adopt_directory_action(action: &TargetAction, config: &Config) -> Tar
     }
 
     // Ensure the parent package directory exists
-    if let Some(package_parent) = source_item.source_path.parent() {
-        if let Err(e) = fs_utils::create_dir_all(package_parent) {
-            return TargetActionReport {
-                original_action: action.clone(),
-                status: TargetActionReportStatus::Failure(e.to_string()),
-                message: Some(format!(
-                    "Failed to create package parent directory {:?}: {}",
-                    package_parent, e
-                )),
-            };
-        }
+    if let Some(package_parent) = source_item.source_path.parent()
+        && let Err(e) = fs_utils::create_dir_all(package_parent)
+    {
+        return TargetActionReport {
+            original_action: action.clone(),
+            status: TargetActionReportStatus::Failure(e.to_string()),
+            message: Some(format!(
+                "Failed to create package parent directory {:?}: {}",
+                package_parent, e
+            )),
+        };
     }
 
     // Move the directory from target to package directory
@@ -3511,6 +5021,71 @@ fn execute_adopt_directory_action(action: &TargetAction, config: &Config) -> Tar
     }
 }
 
+/// Execute a move action (move a package item to another package inside the stow dir)
+fn execute_move_item_action(action: &TargetAction) -> TargetActionReport {
+    let failure = |error: String, message: String| TargetActionReport {
+        original_action: action.clone(),
+        status: TargetActionReportStatus::Failure(error),
+        message: Some(message),
+    };
+    let Some(source_item) = &action.source_item else {
+        return failure(
+            "MoveItem action missing source_item".to_string(),
+            "MoveItem action requires source_item to determine what to move".to_string(),
+        );
+    };
+    let source_path = &source_item.source_path;
+    let package_dir = source_path
+        .ancestors()
+        .nth(source_item.package_relative_path.components().count())
+        .unwrap_or(source_path);
+
+    if filesystem::current().lstat(&action.target_path).is_ok() {
+        return failure(
+            "Destination already exists".to_string(),
+            format!(
+                "Refusing to move {:?} over existing {:?}",
+                source_path, action.target_path
+            ),
+        );
+    }
+    if let Some(parent) = action.target_path.parent()
+        && let Err(e) = fs_utils::create_dir_all(parent)
+    {
+        return failure(
+            e.to_string(),
+            format!("Failed to create package directory {:?}: {}", parent, e),
+        );
+    }
+    if let Err(e) = fs_utils::rename_or_copy(source_path, &action.target_path) {
+        return failure(
+            e.to_string(),
+            format!(
+                "Failed to move {:?} to {:?}: {}",
+                source_path, action.target_path, e
+            ),
+        );
+    }
+    if let Err(e) = fs_utils::remove_emptied_parents(source_path, package_dir) {
+        return failure(
+            e.to_string(),
+            format!(
+                "Moved {:?} but failed to remove emptied directories: {}",
+                source_path, e
+            ),
+        );
+    }
+
+    TargetActionReport {
+        original_action: action.clone(),
+        status: TargetActionReportStatus::Success,
+        message: Some(format!(
+            "Successfully moved {:?} to {:?}",
+            source_path, action.target_path
+        )),
+    }
+}
+
 fn ensure_adopt_destination_ancestors_not_symlink(
     action: &TargetAction,
     source_item: &StowItem,
@@ -3535,7 +5110,7 @@ fn ensure_adopt_destination_ancestors_not_symlink(
 fn move_file(from: &Path, to: &Path) -> Result<(), crate::error::FsError> {
     ensure_destination_ancestors_not_symlink(from, to)?;
 
-    std::fs::rename(from, to).map_err(|e| crate::error::FsError::MoveItem {
+    fs_utils::rename_or_copy(from, to).map_err(|e| crate::error::FsError::MoveItem {
         source_path: from.to_path_buf(),
         destination_path: to.to_path_buf(),
         source_io_error: e,
@@ -3543,12 +5118,11 @@ fn move_file(from: &Path, to: &Path) -> Result<(), crate::error::FsError> {
 }
 
 fn ensure_destination_is_not_symlink(from: &Path, to: &Path) -> Result<(), crate::error::FsError> {
-    match std::fs::symlink_metadata(to) {
-        Ok(metadata) if metadata.file_type().is_symlink() => Err(crate::error::FsError::MoveItem {
+    match filesystem::current().lstat(to) {
+        Ok(stat) if stat.is_symlink() => Err(crate::error::FsError::MoveItem {
             source_path: from.to_path_buf(),
             destination_path: to.to_path_buf(),
-            source_io_error: std::io::Error::new(
-                std::io::ErrorKind::Other,
+            source_io_error: std::io::Error::other(
                 "Refusing to merge directory into symlinked destination",
             ),
         }),
@@ -3578,8 +5152,7 @@ fn ensure_destination_ancestors_not_symlink(
             return Err(crate::error::FsError::MoveItem {
                 source_path: from.to_path_buf(),
                 destination_path: to.to_path_buf(),
-                source_io_error: std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                source_io_error: std::io::Error::other(
                     "Refusing to move into path containing symlinked ancestor",
                 ),
             });
@@ -3593,17 +5166,18 @@ fn ensure_destination_ancestors_not_symlink(
 fn move_directory(from: &Path, to: &Path) -> Result<(), crate::error::FsError> {
     ensure_destination_ancestors_not_symlink(from, to)?;
 
-    let from_file_type = std::fs::symlink_metadata(from)
-        .map_err(|e| crate::error::FsError::MoveItem {
-            source_path: from.to_path_buf(),
-            destination_path: to.to_path_buf(),
-            source_io_error: e,
-        })?
-        .file_type();
+    let from_stat =
+        filesystem::current()
+            .lstat(from)
+            .map_err(|e| crate::error::FsError::MoveItem {
+                source_path: from.to_path_buf(),
+                destination_path: to.to_path_buf(),
+                source_io_error: e,
+            })?;
 
-    if from_file_type.is_symlink() || !from_file_type.is_dir() {
+    if !from_stat.is_dir() {
         ensure_destination_is_not_symlink(from, to)?;
-        return std::fs::rename(from, to).map_err(|e| crate::error::FsError::MoveItem {
+        return fs_utils::rename_or_copy(from, to).map_err(|e| crate::error::FsError::MoveItem {
             source_path: from.to_path_buf(),
             destination_path: to.to_path_buf(),
             source_io_error: e,
@@ -3614,7 +5188,7 @@ fn move_directory(from: &Path, to: &Path) -> Result<(), crate::error::FsError> {
 
     // If destination doesn't exist, simple rename
     if !fs_utils::path_exists(to) {
-        return std::fs::rename(from, to).map_err(|e| crate::error::FsError::MoveItem {
+        return fs_utils::rename_or_copy(from, to).map_err(|e| crate::error::FsError::MoveItem {
             source_path: from.to_path_buf(),
             destination_path: to.to_path_buf(),
             source_io_error: e,
@@ -3625,11 +5199,13 @@ fn move_directory(from: &Path, to: &Path) -> Result<(), crate::error::FsError> {
     move_directory_contents_recursive(from, to)?;
 
     // Remove the now-empty source directory
-    std::fs::remove_dir(from).map_err(|e| crate::error::FsError::MoveItem {
-        source_path: from.to_path_buf(),
-        destination_path: to.to_path_buf(),
-        source_io_error: e,
-    })
+    filesystem::current()
+        .rmdir(from)
+        .map_err(|e| crate::error::FsError::MoveItem {
+            source_path: from.to_path_buf(),
+            destination_path: to.to_path_buf(),
+            source_io_error: e,
+        })
 }
 
 /// Recursively move contents from source directory to destination directory
@@ -3638,26 +5214,25 @@ fn move_directory_contents_recursive(from: &Path, to: &Path) -> Result<(), crate
     ensure_destination_is_not_symlink(from, to)?;
 
     // Ensure destination directory exists
-    std::fs::create_dir_all(to).map_err(|e| crate::error::FsError::MoveItem {
-        source_path: from.to_path_buf(),
-        destination_path: to.to_path_buf(),
-        source_io_error: e,
+    fs_utils::create_dir_all_with(&*filesystem::current(), to).map_err(|e| {
+        crate::error::FsError::MoveItem {
+            source_path: from.to_path_buf(),
+            destination_path: to.to_path_buf(),
+            source_io_error: e,
+        }
     })?;
 
     // Read all entries in the source directory
-    let entries = std::fs::read_dir(from).map_err(|e| crate::error::FsError::MoveItem {
-        source_path: from.to_path_buf(),
-        destination_path: to.to_path_buf(),
-        source_io_error: e,
-    })?;
+    let entries =
+        filesystem::current()
+            .read_dir(from)
+            .map_err(|e| crate::error::FsError::MoveItem {
+                source_path: from.to_path_buf(),
+                destination_path: to.to_path_buf(),
+                source_io_error: e,
+            })?;
 
     for entry in entries {
-        let entry = entry.map_err(|e| crate::error::FsError::MoveItem {
-            source_path: from.to_path_buf(),
-            destination_path: to.to_path_buf(),
-            source_io_error: e,
-        })?;
-
         let source_path = entry.path();
         let file_name = source_path
             .file_name()
@@ -3671,26 +5246,20 @@ fn move_directory_contents_recursive(from: &Path, to: &Path) -> Result<(), crate
             })?;
         let dest_path = to.join(file_name);
 
-        let file_type = entry
-            .file_type()
-            .map_err(|e| crate::error::FsError::MoveItem {
-                source_path: source_path.clone(),
-                destination_path: dest_path.clone(),
-                source_io_error: e,
-            })?;
-
-        if file_type.is_dir() {
+        if entry.kind() == FileKind::Directory {
             // Recursively move directory contents
             move_directory_contents_recursive(&source_path, &dest_path)?;
             // Remove the now-empty source directory
-            std::fs::remove_dir(&source_path).map_err(|e| crate::error::FsError::MoveItem {
-                source_path: source_path.clone(),
-                destination_path: dest_path.clone(),
-                source_io_error: e,
+            filesystem::current().rmdir(&source_path).map_err(|e| {
+                crate::error::FsError::MoveItem {
+                    source_path: source_path.clone(),
+                    destination_path: dest_path.clone(),
+                    source_io_error: e,
+                }
             })?;
         } else {
             // Move file
-            std::fs::rename(&source_path, &dest_path).map_err(|e| {
+            fs_utils::rename_or_copy(&source_path, &dest_path).map_err(|e| {
                 crate::error::FsError::MoveItem {
                     source_path: source_path.clone(),
                     destination_path: dest_path.clone(),
@@ -3715,17 +5284,26 @@ mod tests {
         Config {
             target_dir: target_dir.to_path_buf(),
             stow_dir: stow_dir.to_path_buf(),
+            stow_path: Vec::new(),
             packages: vec!["test_package".to_string()],
             mode: StowMode::Stow,
             stow: false,
             compat: false,
             adopt: false,
+            git_commit: false,
             no_folding: false,
+            no_fold: Vec::new(),
             dotfiles: false,
+            dir_modes: Vec::new(),
+            sensitive: Vec::new(),
+            insecure_permissions: Default::default(),
             overrides: vec![],
             defers: vec![],
             ignore_patterns: vec![],
             simulate: false,
+            incremental: false,
+            force: false,
+            lock_timeout: std::time::Duration::from_secs(10),
             verbosity: 0,
             home_dir: PathBuf::from("/tmp"),
         }
@@ -3769,6 +5347,121 @@ mod tests {
         assert!(!fs_utils::is_symlink(&stow_target));
     }
 
+    #[test]
+    fn test_execute_delete_then_stow_actions_deletes_kept_symlink_when_replacement_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let target_dir = temp_dir.path().join("target");
+        let stow_dir = temp_dir.path().join("stow");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::create_dir_all(stow_dir.join("pkg")).unwrap();
+        fs::write(stow_dir.join("pkg/rc"), "rc").unwrap();
+        let link = target_dir.join("rc");
+        fs_utils::create_symlink(&link, Path::new("../stow/pkg/rc")).unwrap();
+        let config = create_test_config(&target_dir, &stow_dir);
+
+        let delete_actions = vec![create_delete_symlink_action(link.clone())];
+        // Without a link target the replacing link cannot be created.
+        let stow_actions = vec![TargetAction {
+            source_item: None,
+            target_path: link.clone(),
+            link_target_path: None,
+            action_type: ActionType::CreateSymlink,
+            conflict_details: None,
+        }];
+
+        let reports =
+            execute_delete_then_stow_actions(&delete_actions, &stow_actions, &config).unwrap();
+
+        let delete_report = reports
+            .iter()
+            .find(|report| report.original_action.action_type == ActionType::DeleteSymlink)
+            .unwrap();
+        assert_eq!(delete_report.status, TargetActionReportStatus::Success);
+        assert!(
+            !delete_report
+                .message
+                .as_deref()
+                .unwrap()
+                .contains("in place")
+        );
+        assert!(matches!(
+            reports.last().unwrap().status,
+            TargetActionReportStatus::Failure(_)
+        ));
+        assert!(!fs_utils::is_symlink(&link));
+    }
+
+    #[test]
+    fn test_directory_has_no_fold_rule_finds_nested_marker_and_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        let target_dir = temp_dir.path().join("target");
+        let stow_dir = temp_dir.path().join("stow");
+        let package_dir = stow_dir.join("test_package");
+        fs::create_dir_all(package_dir.join("share/app/state")).unwrap();
+        fs::create_dir_all(package_dir.join("bin")).unwrap();
+        fs::write(package_dir.join("share/app/state").join(NO_FOLD_MARKER), "").unwrap();
+        let mut config = create_test_config(&target_dir, &stow_dir);
+
+        assert!(directory_has_no_fold_rule("test_package", Path::new("share"), &config).unwrap());
+        assert!(!directory_has_no_fold_rule("test_package", Path::new("bin"), &config).unwrap());
+
+        config.no_fold = vec![regex::Regex::new("^bin$").unwrap()];
+        assert!(directory_has_no_fold_rule("test_package", Path::new("bin"), &config).unwrap());
+    }
+
+    #[test]
+    fn test_plan_move_items_rejects_invalid_items() {
+        let temp_dir = TempDir::new().unwrap();
+        let target_dir = temp_dir.path().join("target");
+        let stow_dir = temp_dir.path().join("stow");
+        fs::create_dir_all(stow_dir.join("vim/colors")).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        let config = create_test_config(&target_dir, &stow_dir);
+        let plan_for = |items: &[&str]| {
+            let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+            plan_move_items(&config, "neovim", &items)
+        };
+
+        assert!(plan_for(&["vim/../vim/colors"]).is_err());
+        assert!(plan_for(&["vim/missing"]).is_err());
+        assert!(plan_for(&["vim", "vim/colors"]).is_err());
+        assert!(matches!(
+            plan_for(&["emacs/init.el"]),
+            Err(RustowError::Stow(StowError::PackageNotFound(_)))
+        ));
+
+        let plan = plan_for(&["vim/colors"]).unwrap();
+        assert_eq!(plan.stow_actions[0].action_type, ActionType::MoveItem);
+        assert_eq!(
+            plan.stow_actions[0].target_path,
+            stow_dir.join("neovim/colors")
+        );
+    }
+
+    #[test]
+    fn test_target_path_argument_rejects_target_root_and_outside_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let target_dir = temp_dir.path().join("target");
+        let stow_dir = temp_dir.path().join("stow");
+        fs::create_dir_all(target_dir.join("bin")).unwrap();
+        fs::create_dir_all(&stow_dir).unwrap();
+        let config = create_test_config(&target_dir, &stow_dir);
+
+        assert_eq!(
+            target_path_argument(
+                &config,
+                &target_dir.join("bin/../bin"),
+                StowError::NotManagedDirectory
+            )
+            .unwrap(),
+            target_dir.join("bin")
+        );
+        assert!(
+            target_path_argument(&config, &target_dir, StowError::NotManagedDirectory).is_err()
+        );
+        assert!(target_path_argument(&config, &stow_dir, StowError::NotManagedDirectory).is_err());
+    }
+
     #[test]
     fn test_check_directory_for_non_stow_files_empty_directory() {
         let temp_dir = TempDir::new().unwrap();
@@ -4014,7 +5707,7 @@ mod tests {
     }
 
     #[test]
-    fn test_remove_existing_target_symlink() {
+    fn test_ensure_existing_target_is_replaceable_keeps_symlink() {
         let temp_dir = TempDir::new().unwrap();
         let target_dir = temp_dir.path().join("target");
         let stow_dir = temp_dir.path().join("stow");
@@ -4038,16 +5731,28 @@ mod tests {
             conflict_details: None,
         };
 
-        let result = remove_existing_target(&action);
+        let result = ensure_existing_target_is_replaceable(&action);
+        assert!(result.is_none(), "Existing symlink should be replaceable");
         assert!(
-            result.is_none(),
-            "Should succeed in removing existing symlink"
+            fs_utils::is_symlink(&target_file),
+            "Existing symlink should stay in place until it is replaced"
+        );
+
+        let report = create_symlink_with_target(&action, action.link_target_path.as_ref().unwrap());
+        assert_eq!(report.status, TargetActionReportStatus::Success);
+        assert_eq!(
+            fs::read_link(&target_file).unwrap(),
+            PathBuf::from("../stow/test_package/test_file.txt")
         );
-        assert!(!target_file.exists(), "Existing symlink should be removed");
+        let entries: Vec<_> = fs::read_dir(&target_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec![std::ffi::OsString::from("test_file.txt")]);
     }
 
     #[test]
-    fn test_remove_existing_target_non_symlink() {
+    fn test_ensure_existing_target_is_replaceable_rejects_non_symlink() {
         let temp_dir = TempDir::new().unwrap();
         let target_dir = temp_dir.path().join("target");
         let target_file = target_dir.join("test_file.txt");
@@ -4063,7 +5768,7 @@ mod tests {
             conflict_details: None,
         };
 
-        let result = remove_existing_target(&action);
+        let result = ensure_existing_target_is_replaceable(&action);
         assert!(result.is_some(), "Should fail when target is not a symlink");
 
         let error_report = result.unwrap();
@@ -4247,7 +5952,7 @@ mod tests {
         let link_target = PathBuf::from("../stow/test_package/test_file.txt");
         fs_utils::create_symlink(&test_file, &link_target).unwrap();
 
-        let result = validate_stow_symlink(&test_file, &stow_dir).unwrap();
+        let result = validate_stow_symlink(&test_file, &[stow_dir.as_path()]).unwrap();
 
         assert!(result.is_some());
         let (package_name, item_path) = result.unwrap();
@@ -4269,7 +5974,7 @@ mod tests {
         let link_target = PathBuf::from("../other/file.txt");
         fs_utils::create_symlink(&test_file, &link_target).unwrap();
 
-        let result = validate_stow_symlink(&test_file, &stow_dir).unwrap();
+        let result = validate_stow_symlink(&test_file, &[stow_dir.as_path()]).unwrap();
 
         assert!(result.is_none());
     }
@@ -4725,7 +6430,7 @@ mod tests {
         fs::create_dir_all(&stow_dir).unwrap();
         fs::write(&regular_file, "content").unwrap();
 
-        let result = is_non_stow_entry(&regular_file, &stow_dir);
+        let result = is_non_stow_entry(&regular_file, &[stow_dir.as_path()]);
         assert!(result); // Regular file should be considered non-stow
     }
 
@@ -4743,7 +6448,7 @@ mod tests {
         // Create a symlink from target to source
         fs_utils::create_symlink(&target_file, &source_file).unwrap();
 
-        let result = is_non_stow_entry(&target_file, &stow_dir);
+        let result = is_non_stow_entry(&target_file, &[stow_dir.as_path()]);
         assert!(!result); // Stow-managed symlink should not be considered non-stow
     }
 
@@ -4760,7 +6465,7 @@ mod tests {
         // Create a symlink pointing outside stow directory
         fs_utils::create_symlink(&symlink_file, &external_file).unwrap();
 
-        let result = is_non_stow_entry(&symlink_file, &stow_dir);
+        let result = is_non_stow_entry(&symlink_file, &[stow_dir.as_path()]);
         assert!(result); // Non-stow symlink should be considered non-stow
     }
 
@@ -5275,7 +6980,7 @@ mod tests {
         };
         let config = create_test_config(&target_dir, &stow_dir);
 
-        // Should succeed - existing symlink will be removed
+        // Should succeed - existing symlink will be replaced in place
         let result = prepare_symlink_creation(&action, &config);
         assert!(
             result.is_none(),
@@ -5283,8 +6988,11 @@ mod tests {
             result
         );
 
-        // Verify old symlink was removed
-        assert!(!fs_utils::path_exists(&target_dir.join("file.txt")));
+        // Verify old symlink is kept until the new one is renamed over it
+        assert_eq!(
+            fs_utils::read_link(&target_dir.join("file.txt")).unwrap(),
+            existing_target
+        );
     }
 
     #[test]
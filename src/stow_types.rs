@@ -1,6 +1,9 @@
+use crate::config::{Config, PackageOperation};
+use crate::plan_file::PathFingerprint;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ActionType {
     CreateSymlink,
     DeleteSymlink,
@@ -8,11 +11,13 @@ pub enum ActionType {
     DeleteDirectory,
     AdoptFile,
     AdoptDirectory,
+    /// Moves the source item to `target_path`, another location inside the stow directory.
+    MoveItem,
     Skip,
     Conflict,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TargetAction {
     pub source_item: Option<StowItem>,
     pub target_path: PathBuf,
@@ -21,14 +26,14 @@ pub struct TargetAction {
     pub conflict_details: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum StowItemType {
     File,
     Directory,
     Symlink,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct StowItem {
     pub package_relative_path: PathBuf,
     pub source_path: PathBuf,
@@ -59,3 +64,50 @@ pub struct TargetActionReport {
     pub status: TargetActionReportStatus,
     pub message: Option<String>,
 }
+
+/// Planned changes for a set of package operations, computed without touching the target.
+///
+/// `delete_actions` run before `stow_actions`; applying stops after the delete phase if any of
+/// its actions fail. Embedders may inspect or filter the actions before passing the plan to
+/// [`crate::apply`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    /// Resolved configuration the plan was computed with and will be applied with.
+    pub config: Config,
+    /// Package operations the plan covers, in command-line order.
+    pub operations: Vec<PackageOperation>,
+    pub delete_actions: Vec<TargetAction>,
+    pub stow_actions: Vec<TargetAction>,
+    /// Whether directories touched by the plan are refolded after a successful apply.
+    pub refold: bool,
+}
+
+impl Plan {
+    /// All planned actions in execution order.
+    pub fn actions(&self) -> impl Iterator<Item = &TargetAction> {
+        self.delete_actions.iter().chain(self.stow_actions.iter())
+    }
+
+    /// Actions that could not be planned because they conflict with the target.
+    pub fn conflicts(&self) -> impl Iterator<Item = &TargetAction> {
+        self.actions()
+            .filter(|action| action.action_type == ActionType::Conflict)
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.conflicts().next().is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.delete_actions.is_empty() && self.stow_actions.is_empty()
+    }
+}
+
+/// Outcome of running a plan against an in-memory copy of the target and stow directories.
+#[derive(Debug, Clone)]
+pub struct SimulatedApply {
+    /// One report per action, as [`crate::apply`] would return them in simulate mode.
+    pub reports: Vec<TargetActionReport>,
+    /// State every touched path would be left in, sorted by path.
+    pub final_state: Vec<PathFingerprint>,
+}
@@ -0,0 +1,207 @@
+//! Files written into packages through folded directory links.
+//!
+//! Once `~/.config/foo` is a link to `stow/foo/dot-config/foo`, whatever the application writes
+//! there lands in the package. A file counts as unmanaged when the stow directory's git work
+//! tree does not track it or, outside git, when it is newer than the folded link.
+
+use crate::config::Config;
+use crate::dotfiles;
+use crate::error::{FsError, RustowError};
+use crate::filesystem;
+use crate::fs_utils::{self, RawStowItemType};
+use crate::git;
+use crate::logging::log_event;
+use crate::stow::{self, Plan, TargetActionReport, TargetActionReportStatus};
+use std::path::{Path, PathBuf};
+
+/// A folded directory link whose package directory holds unmanaged files.
+#[derive(Debug, Clone)]
+pub(crate) struct FoldedDirectory {
+    /// The link in the target directory.
+    pub(crate) link: PathBuf,
+    /// The package directory the link resolves to.
+    pub(crate) package_dir: PathBuf,
+    pub(crate) package: String,
+    /// Unmanaged files relative to `package_dir`, sorted.
+    pub(crate) unmanaged: Vec<PathBuf>,
+}
+
+/// Folded directory links of `config.packages` that lead to unmanaged files.
+pub(crate) fn find_unmanaged(config: &Config) -> Result<Vec<FoldedDirectory>, RustowError> {
+    let package_dirs = config
+        .packages
+        .iter()
+        .filter_map(|package| {
+            fs_utils::canonicalize_path(&config.package_dir(package))
+                .ok()
+                .map(|dir| (package.clone(), dir))
+        })
+        .collect::<Vec<_>>();
+
+    let mut folded = Vec::new();
+    for link in stow::package_symlinks_in_target(config)? {
+        if !fs_utils::is_directory(&link) {
+            continue;
+        }
+        let package_dir = fs_utils::canonicalize_path(&link)?;
+        let Some((package, _)) = package_dirs
+            .iter()
+            .find(|(_, dir)| package_dir.starts_with(dir))
+        else {
+            continue;
+        };
+
+        let unmanaged = unmanaged_files(&link, &package_dir)?;
+        log_event!(
+            Planner,
+            Debug,
+            "folded directory {} holds {} unmanaged file(s)",
+            link.display(),
+            unmanaged.len()
+        );
+        if !unmanaged.is_empty() {
+            folded.push(FoldedDirectory {
+                link,
+                package_dir,
+                package: package.clone(),
+                unmanaged,
+            });
+        }
+    }
+
+    Ok(folded)
+}
+
+fn unmanaged_files(link: &Path, package_dir: &Path) -> Result<Vec<PathBuf>, RustowError> {
+    let mut files = match git::untracked_files(package_dir)? {
+        Some(files) => files,
+        None => files_newer_than_link(link, package_dir)?,
+    };
+    files.sort();
+    Ok(files)
+}
+
+fn files_newer_than_link(link: &Path, package_dir: &Path) -> Result<Vec<PathBuf>, RustowError> {
+    let modified = |path: &Path| {
+        filesystem::current()
+            .lstat(path)
+            .ok()
+            .and_then(|stat| stat.modified)
+    };
+    let Some(stowed_at) = modified(link) else {
+        return Ok(Vec::new());
+    };
+
+    Ok(fs_utils::walk_package_dir(package_dir)?
+        .into_iter()
+        .filter(|item| item.item_type != RawStowItemType::Directory)
+        .filter(|item| modified(&item.absolute_path).is_some_and(|time| time > stowed_at))
+        .map(|item| item.package_relative_path)
+        .collect())
+}
+
+/// Replaces each folded link with a real directory of links to its package directory, then
+/// moves its unmanaged files out of the package into that directory.
+///
+/// Every unfold is planned before any link is touched, and nothing changes when one of them has
+/// conflicts. Each directory is built aside and swapped in for its link, as `--unfold` does, and
+/// files are only moved once their directory is in place.
+pub(crate) fn unfold(
+    config: &Config,
+    folded: &[FoldedDirectory],
+) -> Result<Vec<TargetActionReport>, RustowError> {
+    let plans = folded
+        .iter()
+        .map(|directory| stow::plan_unfold_directory(config, &directory.link))
+        .collect::<Result<Vec<_>, _>>()?;
+    if plans.iter().any(Plan::has_conflicts) {
+        log_event!(Exec, Info, "not unfolding: the plan has conflicts");
+        let mut reports = Vec::new();
+        for plan in plans.iter().filter(|plan| plan.has_conflicts()) {
+            reports.extend(stow::apply_plan(plan)?);
+        }
+        return Ok(reports);
+    }
+
+    let mut reports = Vec::new();
+    for (directory, plan) in folded.iter().zip(&plans) {
+        log_event!(
+            Exec,
+            Info,
+            "unfolding {} to move {} unmanaged file(s) out of package '{}'",
+            directory.link.display(),
+            directory.unmanaged.len(),
+            directory.package
+        );
+        if apply_unfold(plan, &mut reports)? {
+            move_unmanaged_files(config, directory, &mut reports)?;
+        }
+    }
+
+    Ok(reports)
+}
+
+/// Applies an unfold plan, returning whether every action succeeded.
+fn apply_unfold(plan: &Plan, reports: &mut Vec<TargetActionReport>) -> Result<bool, RustowError> {
+    let unfold_reports = stow::apply_plan(plan)?;
+    let succeeded = !plan.has_conflicts()
+        && unfold_reports
+            .iter()
+            .all(|report| !matches!(report.status, TargetActionReportStatus::Failure(_)));
+    reports.extend(unfold_reports);
+    Ok(succeeded)
+}
+
+/// Moves the unmanaged files of an unfolded directory over their links, unfolding the folded
+/// subdirectories on their way first. Files whose directory could not be unfolded stay put.
+fn move_unmanaged_files(
+    config: &Config,
+    directory: &FoldedDirectory,
+    reports: &mut Vec<TargetActionReport>,
+) -> Result<(), RustowError> {
+    'files: for relative in &directory.unmanaged {
+        let from = directory.package_dir.join(relative);
+        let target_relative = if config.dotfiles {
+            PathBuf::from(dotfiles::process_item_name(
+                &relative.to_string_lossy(),
+                true,
+            ))
+        } else {
+            relative.clone()
+        };
+        let to = directory.link.join(&target_relative);
+
+        let mut parent = directory.link.clone();
+        for component in target_relative
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+        {
+            parent.push(component);
+            if fs_utils::is_symlink(&parent)
+                && !apply_unfold(&stow::plan_unfold_directory(config, &parent)?, reports)?
+            {
+                continue 'files;
+            }
+        }
+        fs_utils::create_dir_all(&parent)?;
+
+        // The file takes the place of its link in one rename, like `replace_symlink` does.
+        let io_error = |source| FsError::Io {
+            path: from.clone(),
+            source,
+        };
+        if fs_utils::is_symlink(&to) {
+            let staged = fs_utils::temporary_sibling_path(&to, "unmanaged");
+            fs_utils::rename_or_copy(&from, &staged).map_err(io_error)?;
+            filesystem::current()
+                .rename(&staged, &to)
+                .map_err(io_error)?;
+        } else {
+            fs_utils::rename_or_copy(&from, &to).map_err(io_error)?;
+        }
+        fs_utils::remove_emptied_parents(&from, &directory.package_dir)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,504 @@
+//! `--watch`: keep packages stowed while their contents change.
+//!
+//! Package trees are watched with inotify. Once a burst of events has settled, every package
+//! that changed is restowed on its own, which links new entries and prunes links to entries
+//! that are gone. Conflicts are reported and left in place; the watcher keeps running.
+
+use crate::cli::PathDisplayOverride;
+use crate::config::{Config, PackageOperation, StowMode};
+use crate::diagnostics;
+use crate::dotfiles;
+use crate::error::{ConfigError, RustowError};
+use crate::fs_utils;
+use crate::lock::RunLock;
+use crate::logging::log_event;
+use crate::plan_file::PathState;
+use crate::stow::{self, TargetActionReportStatus};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long the stow directory has to stay quiet before changed packages are restowed.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Packages stowed or restowed by `operations`; these are the ones kept up to date.
+pub(crate) fn watched_packages(operations: &[PackageOperation]) -> Vec<String> {
+    let mut packages = Vec::new();
+    for operation in operations {
+        if operation.mode == StowMode::Delete {
+            continue;
+        }
+        for package in &operation.packages {
+            if !packages.contains(package) {
+                packages.push(package.clone());
+            }
+        }
+    }
+    packages
+}
+
+/// Watches `packages` in the stow directories of `config` and restows them as they change. Only returns on
+/// an error setting up or reading the watch.
+pub(crate) fn watch_packages(
+    config: &Config,
+    packages: &[String],
+    path_displays: &[PathDisplayOverride],
+) -> Result<(), RustowError> {
+    if packages.is_empty() {
+        return Err(RustowError::Config(ConfigError::InvalidOperation(
+            "--watch needs at least one package to stow or restow".to_string(),
+        )));
+    }
+
+    let mut watcher = PackageWatcher::new(config, packages)?;
+    log_event!(
+        Watch,
+        Info,
+        "watching {} package(s) for changes",
+        packages.len()
+    );
+
+    loop {
+        for (package, changed_paths) in watcher.wait_for_changes()? {
+            restow_changed_package(config, &package, &changed_paths, path_displays);
+            watcher.rewatch(&package);
+        }
+    }
+}
+
+/// Watched packages that changed, with the changed paths relative to each package.
+type ChangedPackages = BTreeMap<String, BTreeSet<PathBuf>>;
+
+fn restow_changed_package(
+    config: &Config,
+    package: &str,
+    changed_paths: &BTreeSet<PathBuf>,
+    path_displays: &[PathDisplayOverride],
+) {
+    log_event!(Watch, Debug, "package '{}' changed; restowing", package);
+    if let Err(error) = restow_package(config, package, changed_paths, path_displays) {
+        log_event!(
+            Watch,
+            Error,
+            "restowing package '{}' failed: {}",
+            package,
+            error
+        );
+    }
+}
+
+fn restow_package(
+    config: &Config,
+    package: &str,
+    changed_paths: &BTreeSet<PathBuf>,
+    path_displays: &[PathDisplayOverride],
+) -> Result<(), RustowError> {
+    let lock = RunLock::acquire(config)?;
+    diagnostics::process_stale_locks(lock.stale(), path_displays);
+    stow::validate_package_for_operation(config.package_stow_dir(package), package)?;
+    let mut package_config = config.clone();
+    package_config.mode = StowMode::Restow;
+    package_config.packages = vec![package.to_string()];
+
+    let package_dir = config.package_dir(package);
+    let vanished_targets: Vec<PathBuf> = changed_paths
+        .iter()
+        .filter(|path| {
+            let entry = package_dir.join(path);
+            !fs_utils::path_exists(&entry) && !fs_utils::is_symlink(&entry)
+        })
+        .map(|path| {
+            config.target_dir.join(dotfiles::process_item_name(
+                &path.to_string_lossy(),
+                config.dotfiles,
+            ))
+        })
+        .collect();
+    let plan = stow::plan_restow_packages_pruning(&package_config, &vanished_targets)?;
+
+    let mut states = BTreeMap::new();
+    for action in plan.actions() {
+        for path in [
+            Some(action.target_path.as_path()),
+            action.target_path.parent(),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|path| path.starts_with(&config.target_dir))
+        {
+            states
+                .entry(path.to_path_buf())
+                .or_insert_with(|| PathState::capture(path).ok());
+        }
+    }
+
+    let reports = stow::apply_plan(&plan)?;
+    diagnostics::process_reports(&reports, &package_config, path_displays);
+
+    let conflicts = reports
+        .iter()
+        .filter(|report| matches!(report.status, TargetActionReportStatus::ConflictPrevented))
+        .count();
+    if conflicts > 0 {
+        log_event!(
+            Watch,
+            Warn,
+            "package '{}' left untouched: {} conflict(s)",
+            package,
+            conflicts
+        );
+        return Ok(());
+    }
+
+    // Paths sort before their descendants, so a folded or unfolded directory is reported
+    // once instead of once per entry that appears or disappears below it.
+    let mut changed_paths: Vec<PathBuf> = Vec::new();
+    for (path, before) in states {
+        let after = PathState::capture(&path).ok();
+        if before == after
+            || changed_paths
+                .iter()
+                .any(|changed| path.starts_with(changed))
+        {
+            continue;
+        }
+        log_event!(
+            Watch,
+            Info,
+            "{}: {} -> {}",
+            path.display(),
+            describe_state(before.as_ref()),
+            describe_state(after.as_ref())
+        );
+        changed_paths.push(path);
+    }
+    log_event!(
+        Watch,
+        Debug,
+        "package '{}' restowed with {} change(s)",
+        package,
+        changed_paths.len()
+    );
+
+    Ok(())
+}
+
+fn describe_state(state: Option<&PathState>) -> String {
+    state.map_or_else(|| "unreadable".to_string(), PathState::to_string)
+}
+
+#[cfg(target_os = "linux")]
+struct PackageWatcher {
+    inotify: inotify::Inotify,
+    config: Config,
+    packages: BTreeSet<String>,
+}
+
+#[cfg(target_os = "linux")]
+impl PackageWatcher {
+    fn new(config: &Config, packages: &[String]) -> Result<Self, RustowError> {
+        let mut inotify = inotify::Inotify::new()?;
+        // The stow directories themselves are watched so a package removed and created again
+        // is picked up once more.
+        for stow_dir in config.stow_dirs() {
+            inotify.add_watch(stow_dir, inotify::STOW_DIR_MASK)?;
+        }
+
+        let mut watcher = Self {
+            inotify,
+            config: config.clone(),
+            packages: packages.iter().cloned().collect(),
+        };
+        for package in packages {
+            // So are the namespaces above a nested package, for the same reason.
+            for namespace_dir in Path::new(package)
+                .ancestors()
+                .skip(1)
+                .filter(|namespace| !namespace.as_os_str().is_empty())
+                .flat_map(|namespace| {
+                    config
+                        .stow_dirs()
+                        .into_iter()
+                        .map(move |stow_dir| stow_dir.join(namespace))
+                })
+                .filter(|namespace_dir| namespace_dir.is_dir())
+            {
+                if let Err(error) = watcher
+                    .inotify
+                    .add_watch(&namespace_dir, inotify::STOW_DIR_MASK)
+                {
+                    log_event!(
+                        Watch,
+                        Debug,
+                        "cannot watch {}: {}",
+                        namespace_dir.display(),
+                        error
+                    );
+                }
+            }
+            watcher.rewatch(package);
+        }
+        Ok(watcher)
+    }
+
+    /// Adds watches for every directory in `package`, including ones created since the
+    /// last call.
+    fn rewatch(&mut self, package: &str) {
+        let mut pending = vec![self.config.package_dir(package)];
+        while let Some(dir) = pending.pop() {
+            if let Err(error) = self.inotify.add_watch(&dir, inotify::PACKAGE_MASK) {
+                log_event!(Watch, Debug, "cannot watch {}: {}", dir.display(), error);
+                continue;
+            }
+
+            let Ok(entries) = fs_utils::read_dir(&dir) else {
+                continue;
+            };
+            pending.extend(
+                entries
+                    .into_iter()
+                    .filter(|entry| entry.kind() == crate::filesystem::FileKind::Directory)
+                    .map(|entry| entry.path()),
+            );
+        }
+    }
+
+    /// Blocks until something changes, then waits for the changes to settle and returns the
+    /// watched packages they touched.
+    fn wait_for_changes(&mut self) -> Result<ChangedPackages, RustowError> {
+        let mut changed = ChangedPackages::new();
+        let mut timeout = None;
+        loop {
+            let Some(events) = self.inotify.read_events(timeout)? else {
+                if changed.is_empty() {
+                    timeout = None;
+                    continue;
+                }
+                return Ok(changed);
+            };
+
+            for event in events {
+                match event {
+                    inotify::Event::Overflow => {
+                        log_event!(Watch, Warn, "event queue overflowed; restowing everything");
+                        for package in &self.packages {
+                            changed.entry(package.clone()).or_default();
+                        }
+                    },
+                    inotify::Event::Changed(path) => {
+                        log_event!(Watch, Trace, "change at {}", path.display());
+                        if let Some((package, package_path)) =
+                            fs_utils::owning_package(&self.config.stow_dirs(), &path)
+                            && self.packages.contains(&package)
+                        {
+                            let paths = changed.entry(package).or_default();
+                            if !package_path.as_os_str().is_empty() {
+                                paths.insert(package_path);
+                            }
+                        }
+                    },
+                }
+            }
+            timeout = Some(DEBOUNCE);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct PackageWatcher;
+
+#[cfg(not(target_os = "linux"))]
+impl PackageWatcher {
+    fn new(_config: &Config, _packages: &[String]) -> Result<Self, RustowError> {
+        Err(RustowError::Config(ConfigError::InvalidOperation(
+            "--watch is only supported on Linux".to_string(),
+        )))
+    }
+
+    fn rewatch(&mut self, _package: &str) {}
+
+    fn wait_for_changes(&mut self) -> Result<ChangedPackages, RustowError> {
+        unreachable!("a watcher is never created on this platform")
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use crate::error::{FsError, RustowError};
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    pub(super) const PACKAGE_MASK: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE_SELF
+        | libc::IN_MOVE_SELF
+        | libc::IN_ONLYDIR;
+    pub(super) const STOW_DIR_MASK: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_ONLYDIR;
+
+    const EVENT_HEADER_LEN: usize = std::mem::size_of::<libc::inotify_event>();
+
+    pub(super) enum Event {
+        /// Something was added, removed or moved at this path.
+        Changed(PathBuf),
+        /// The kernel dropped events; anything may have changed.
+        Overflow,
+    }
+
+    pub(super) struct Inotify {
+        fd: OwnedFd,
+        watches: HashMap<i32, PathBuf>,
+    }
+
+    impl Inotify {
+        pub(super) fn new() -> Result<Self, RustowError> {
+            // SAFETY: inotify_init1 has no preconditions; the result is checked below.
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(Self {
+                // SAFETY: `fd` is a freshly created descriptor owned by nobody else.
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+                watches: HashMap::new(),
+            })
+        }
+
+        pub(super) fn add_watch(&mut self, path: &Path, mask: u32) -> Result<(), RustowError> {
+            let io_error = |source| FsError::Io {
+                path: path.to_path_buf(),
+                source,
+            };
+            let c_path =
+                CString::new(path.as_os_str().as_bytes()).map_err(|e| io_error(e.into()))?;
+            // SAFETY: `c_path` is a valid NUL-terminated string for the duration of the call.
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), c_path.as_ptr(), mask) };
+            if wd < 0 {
+                return Err(io_error(io::Error::last_os_error()).into());
+            }
+            self.watches.insert(wd, path.to_path_buf());
+            Ok(())
+        }
+
+        /// Waits up to `timeout` (forever if `None`) and returns the pending events, or
+        /// `None` if the wait timed out.
+        pub(super) fn read_events(
+            &mut self,
+            timeout: Option<Duration>,
+        ) -> Result<Option<Vec<Event>>, RustowError> {
+            let timeout_ms = timeout.map_or(-1, |timeout| {
+                i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+            });
+            let mut poll_fd = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: `poll_fd` is a single valid pollfd.
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    return Ok(Some(Vec::new()));
+                }
+                return Err(error.into());
+            }
+            if ready == 0 {
+                return Ok(None);
+            }
+
+            let mut events = Vec::new();
+            let mut buffer = [0_u8; 16 * 1024];
+            loop {
+                // SAFETY: `buffer` is writable for its full length.
+                let len = unsafe {
+                    libc::read(
+                        self.fd.as_raw_fd(),
+                        buffer.as_mut_ptr().cast(),
+                        buffer.len(),
+                    )
+                };
+                if len < 0 {
+                    let error = io::Error::last_os_error();
+                    match error.kind() {
+                        io::ErrorKind::WouldBlock => break,
+                        io::ErrorKind::Interrupted => continue,
+                        _ => return Err(error.into()),
+                    }
+                }
+                self.parse_events(&buffer[..len as usize], &mut events);
+            }
+
+            Ok(Some(events))
+        }
+
+        fn parse_events(&mut self, mut bytes: &[u8], events: &mut Vec<Event>) {
+            while bytes.len() >= EVENT_HEADER_LEN {
+                // SAFETY: at least a header's worth of bytes remains; the read is unaligned.
+                let header: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast()) };
+                let name_len = header.len as usize;
+                let name_bytes = &bytes[EVENT_HEADER_LEN..EVENT_HEADER_LEN + name_len];
+                bytes = &bytes[EVENT_HEADER_LEN + name_len..];
+
+                if header.mask & libc::IN_Q_OVERFLOW != 0 {
+                    events.push(Event::Overflow);
+                    continue;
+                }
+                if header.mask & libc::IN_IGNORED != 0 {
+                    self.watches.remove(&header.wd);
+                    continue;
+                }
+                let Some(dir) = self.watches.get(&header.wd) else {
+                    continue;
+                };
+
+                let name_end = name_bytes
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .unwrap_or(name_bytes.len());
+                let path = if name_end == 0 {
+                    dir.clone()
+                } else {
+                    dir.join(OsStr::from_bytes(&name_bytes[..name_end]))
+                };
+                events.push(Event::Changed(path));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watched_packages_skip_deleted_and_duplicates() {
+        let operations = [
+            PackageOperation {
+                mode: StowMode::Delete,
+                packages: vec!["gone".to_string()],
+            },
+            PackageOperation {
+                mode: StowMode::Stow,
+                packages: vec!["vim".to_string(), "zsh".to_string()],
+            },
+            PackageOperation {
+                mode: StowMode::Restow,
+                packages: vec!["zsh".to_string(), "git".to_string()],
+            },
+        ];
+
+        assert_eq!(watched_packages(&operations), ["vim", "zsh", "git"]);
+    }
+}
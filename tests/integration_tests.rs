@@ -7,7 +7,9 @@ use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
 
 use rustow::cli::Args;
-use rustow::config::{Config, StowMode};
+use rustow::config::{Config, PackageOperation, StowMode};
+use rustow::error::exit_code;
+use rustow::plan_file::PathState;
 use rustow::stow::{
     ActionType, StowItemType, TargetActionReportStatus, delete_packages, restow_packages,
     stow_packages,
@@ -66,17 +68,26 @@ fn create_test_config(
     Config {
         stow_dir,
         target_dir,
+        stow_path: Vec::new(),
         packages,
         mode: StowMode::Stow, // Default to Stow mode for these tests
         stow: false,
         compat: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
         dotfiles,
+        dir_modes: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: Default::default(),
         overrides: Vec::new(),
         defers: Vec::new(),
         ignore_patterns: Vec::new(),
         simulate: false,
+        incremental: false,
+        force: false,
+        lock_timeout: std::time::Duration::from_secs(10),
         verbosity,                      // Use the passed verbosity
         home_dir: std::env::temp_dir(), // Dummy home dir for tests, not critical for these path tests
     }
@@ -918,6 +929,412 @@ fn test_delete_refolds_single_remaining_package_tree() {
     assert!(!target_dir.join("bin/emacs").exists());
 }
 
+#[test]
+fn test_no_fold_rule_keeps_matching_directory_open_and_unrefolded() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("nvim/dot-config/nvim")).unwrap();
+    fs::create_dir_all(stow_dir.join("git/dot-config/git")).unwrap();
+    fs::write(stow_dir.join("nvim/dot-config/nvim/init.lua"), "nvim").unwrap();
+    fs::write(stow_dir.join("git/dot-config/git/config"), "git").unwrap();
+    let config_for = |packages: &[&str]| {
+        let mut config = create_test_config(
+            stow_dir.clone(),
+            target_dir.clone(),
+            packages.iter().map(|package| package.to_string()).collect(),
+            true,
+            0,
+        );
+        config.no_fold = vec![regex::Regex::new(r"^\.config$").unwrap()];
+        config
+    };
+
+    stow_packages(&config_for(&["nvim"])).unwrap();
+
+    let dot_config = target_dir.join(".config");
+    assert!(!fs::symlink_metadata(&dot_config).unwrap().is_symlink());
+    assert!(
+        fs::symlink_metadata(dot_config.join("nvim"))
+            .unwrap()
+            .is_symlink(),
+        "directories below a --no-fold match still fold"
+    );
+
+    stow_packages(&config_for(&["git"])).unwrap();
+    let mut delete_config = config_for(&["git"]);
+    delete_config.mode = StowMode::Delete;
+    delete_packages(&delete_config).unwrap();
+
+    assert!(
+        !fs::symlink_metadata(&dot_config).unwrap().is_symlink(),
+        ".config should not refold into the remaining package"
+    );
+    assert_eq!(target_entry_names(&dot_config), vec!["nvim"]);
+}
+
+#[test]
+fn test_no_fold_marker_keeps_directory_and_ancestors_open() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    let ssh_dir = stow_dir.join("ssh/dot-ssh");
+    fs::create_dir_all(ssh_dir.join("config.d")).unwrap();
+    fs::write(ssh_dir.join("config"), "Include config.d/*").unwrap();
+    fs::write(ssh_dir.join("config.d/work"), "Host work").unwrap();
+    fs::write(ssh_dir.join(".rustow-no-fold"), "").unwrap();
+
+    stow_packages(&create_test_config(
+        stow_dir.clone(),
+        target_dir.clone(),
+        vec!["ssh".to_string()],
+        true,
+        0,
+    ))
+    .unwrap();
+
+    let dot_ssh = target_dir.join(".ssh");
+    assert!(!fs::symlink_metadata(&dot_ssh).unwrap().is_symlink());
+    assert_eq!(target_entry_names(&dot_ssh), vec!["config", "config.d"]);
+    assert!(
+        fs::symlink_metadata(dot_ssh.join("config.d"))
+            .unwrap()
+            .is_symlink()
+    );
+}
+
+#[test]
+fn test_binary_no_fold_from_stowrc_is_honored() {
+    let temp_dir = tempdir().unwrap();
+    let home_dir = temp_dir.path().join("home");
+    let stow_dir = temp_dir.path().join("stow");
+    let target_dir = temp_dir.path().join("target");
+    fs::create_dir_all(&home_dir).unwrap();
+    fs::create_dir_all(&target_dir).unwrap();
+    fs::create_dir_all(stow_dir.join("data/dot-local/share/app")).unwrap();
+    fs::write(stow_dir.join("data/dot-local/share/app/db"), "db").unwrap();
+    fs::write(
+        home_dir.join(".stowrc"),
+        format!(
+            "--dir={}\n--target={}\n--dotfiles\n--no-fold=^\\.local$\n--no-fold=^\\.local/share$\n",
+            stow_dir.display(),
+            target_dir.display()
+        ),
+    )
+    .unwrap();
+
+    let output = run_rustow_with(["data"], &home_dir, &[("HOME", home_dir.to_str().unwrap())]);
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let share = target_dir.join(".local/share");
+    assert!(
+        !fs::symlink_metadata(target_dir.join(".local"))
+            .unwrap()
+            .is_symlink()
+    );
+    assert!(!fs::symlink_metadata(&share).unwrap().is_symlink());
+    assert!(
+        fs::symlink_metadata(share.join("app"))
+            .unwrap()
+            .is_symlink()
+    );
+}
+
+fn target_entry_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_split_open_and_refold_leave_no_staging_entries() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("perl/bin")).unwrap();
+    fs::create_dir_all(stow_dir.join("emacs/bin")).unwrap();
+    fs::write(stow_dir.join("perl/bin/perl"), "perl").unwrap();
+    fs::write(stow_dir.join("emacs/bin/emacs"), "emacs").unwrap();
+    let config_for = |package: &str| {
+        create_test_config(
+            stow_dir.clone(),
+            target_dir.clone(),
+            vec![package.to_string()],
+            false,
+            0,
+        )
+    };
+
+    stow_packages(&config_for("perl")).unwrap();
+    let reports = stow_packages(&config_for("emacs")).unwrap();
+    assert!(
+        reports
+            .iter()
+            .all(|report| report.status == TargetActionReportStatus::Success)
+    );
+    assert!(
+        !fs::symlink_metadata(target_dir.join("bin"))
+            .unwrap()
+            .file_type()
+            .is_symlink()
+    );
+    assert_eq!(target_entry_names(&target_dir), vec!["bin"]);
+    assert_eq!(
+        target_entry_names(&target_dir.join("bin")),
+        vec!["emacs", "perl"]
+    );
+
+    let mut delete_config = config_for("emacs");
+    delete_config.mode = StowMode::Delete;
+    delete_packages(&delete_config).unwrap();
+    assert!(
+        fs::symlink_metadata(target_dir.join("bin"))
+            .unwrap()
+            .file_type()
+            .is_symlink()
+    );
+    assert_eq!(target_entry_names(&target_dir), vec!["bin"]);
+    assert!(target_dir.join("bin/perl").exists());
+}
+
+#[test]
+fn test_restow_replaces_unchanged_symlinks_in_place() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    let package_dir = create_test_package(&stow_dir, "pkg");
+    fs::write(package_dir.join("dot-vimrc"), "set nocompatible").unwrap();
+    let mut config = create_test_config(
+        stow_dir.clone(),
+        target_dir.clone(),
+        vec!["pkg".to_string()],
+        false,
+        0,
+    );
+    config.no_folding = true;
+    stow_packages(&config).unwrap();
+    let link_path = target_dir.join("dot-vimrc");
+    assert!(
+        fs::symlink_metadata(&link_path)
+            .unwrap()
+            .file_type()
+            .is_symlink()
+    );
+
+    config.mode = StowMode::Restow;
+    let reports = restow_packages(&config).unwrap();
+
+    let delete_report = reports
+        .iter()
+        .find(|report| {
+            report.original_action.target_path == link_path
+                && report.original_action.action_type == ActionType::DeleteSymlink
+        })
+        .expect("restow should report the old symlink");
+    assert_eq!(delete_report.status, TargetActionReportStatus::Success);
+    assert!(
+        delete_report
+            .message
+            .as_deref()
+            .unwrap()
+            .contains("Replaced symlink")
+    );
+    assert!(
+        fs::symlink_metadata(&link_path)
+            .unwrap()
+            .file_type()
+            .is_symlink()
+    );
+    assert_eq!(fs::read_to_string(&link_path).unwrap(), "set nocompatible");
+    assert!(
+        target_entry_names(&target_dir)
+            .iter()
+            .all(|name| !name.contains(".rustow-"))
+    );
+}
+
+#[test]
+fn test_plan_does_not_touch_target_and_apply_executes_filtered_actions() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    create_test_package(&stow_dir, "pkg");
+    let config = create_test_config(stow_dir.clone(), target_dir.clone(), Vec::new(), false, 0);
+    let operations = vec![PackageOperation {
+        mode: StowMode::Stow,
+        packages: vec!["pkg".to_string()],
+    }];
+
+    let mut plan = rustow::plan(&config, &operations).unwrap();
+
+    assert!(target_entry_names(&target_dir).is_empty());
+    assert!(!plan.has_conflicts());
+    assert_eq!(plan.config.packages, vec!["pkg".to_string()]);
+    assert_eq!(plan.operations.len(), 1);
+    assert!(
+        plan.actions()
+            .any(|action| action.target_path == target_dir.join("bin"))
+    );
+
+    plan.stow_actions
+        .retain(|action| action.target_path != target_dir.join("bin"));
+    let reports = rustow::apply(&plan).unwrap();
+
+    assert!(
+        reports
+            .iter()
+            .all(|report| report.status == TargetActionReportStatus::Success)
+    );
+    assert!(target_dir.join("dot-bashrc").exists());
+    assert!(!target_dir.join("bin").exists());
+}
+
+#[test]
+fn test_plan_exposes_conflicts_and_apply_prevents_them() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    create_test_package(&stow_dir, "pkg");
+    fs::write(target_dir.join("dot-bashrc"), "unmanaged").unwrap();
+    let config = create_test_config(stow_dir.clone(), target_dir.clone(), Vec::new(), false, 0);
+    let operations = vec![PackageOperation {
+        mode: StowMode::Stow,
+        packages: vec!["pkg".to_string()],
+    }];
+
+    let plan = rustow::plan(&config, &operations).unwrap();
+
+    let conflicts: Vec<_> = plan.conflicts().collect();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].target_path, target_dir.join("dot-bashrc"));
+
+    let reports = rustow::apply(&plan).unwrap();
+    assert!(
+        reports
+            .iter()
+            .any(|report| { report.status == TargetActionReportStatus::ConflictPrevented })
+    );
+    assert!(!target_dir.join("bin").exists());
+}
+
+#[test]
+fn test_simulate_reports_final_state_without_touching_target() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    create_test_package(&stow_dir, "pkg");
+    let mut config = create_test_config(stow_dir.clone(), target_dir.clone(), Vec::new(), false, 0);
+    config.simulate = true;
+    let operations = vec![PackageOperation {
+        mode: StowMode::Stow,
+        packages: vec!["pkg".to_string()],
+    }];
+
+    let plan = rustow::plan(&config, &operations).unwrap();
+    let simulation = rustow::simulate(&plan).unwrap();
+
+    assert!(target_entry_names(&target_dir).is_empty());
+    assert!(
+        simulation
+            .reports
+            .iter()
+            .all(|report| report.status == TargetActionReportStatus::Skipped)
+    );
+    let bin_state = simulation
+        .final_state
+        .iter()
+        .find(|fingerprint| fingerprint.path == target_dir.join("bin"))
+        .map(|fingerprint| &fingerprint.state)
+        .expect("bin should be a touched path");
+    assert!(matches!(
+        bin_state,
+        PathState::Symlink { destination } if destination.ends_with("pkg/bin")
+    ));
+}
+
+#[test]
+fn test_simulate_unfolds_existing_tree_in_memory() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("pkg1/shared")).unwrap();
+    fs::write(stow_dir.join("pkg1/shared/a"), "a").unwrap();
+    fs::create_dir_all(stow_dir.join("pkg2/shared")).unwrap();
+    fs::write(stow_dir.join("pkg2/shared/b"), "b").unwrap();
+    let config = create_test_config(stow_dir.clone(), target_dir.clone(), Vec::new(), false, 0);
+    let stow = |package: &str| {
+        vec![PackageOperation {
+            mode: StowMode::Stow,
+            packages: vec![package.to_string()],
+        }]
+    };
+    rustow::apply(&rustow::plan(&config, &stow("pkg1")).unwrap()).unwrap();
+    assert!(target_dir.join("shared").is_symlink());
+
+    let simulation = rustow::simulate(&rustow::plan(&config, &stow("pkg2")).unwrap()).unwrap();
+
+    let state_of = |path: PathBuf| {
+        simulation
+            .final_state
+            .iter()
+            .find(|fingerprint| fingerprint.path == path)
+            .map(|fingerprint| fingerprint.state.clone())
+    };
+    assert_eq!(
+        state_of(target_dir.join("shared")),
+        Some(PathState::Directory)
+    );
+    assert!(matches!(
+        state_of(target_dir.join("shared/a")),
+        Some(PathState::Symlink { .. })
+    ));
+    assert!(matches!(
+        state_of(target_dir.join("shared/b")),
+        Some(PathState::Symlink { .. })
+    ));
+    assert!(target_dir.join("shared").is_symlink());
+    assert!(!stow_dir.join("pkg1/shared/b").exists());
+}
+
+#[test]
+fn test_plan_combines_mixed_operations_and_rejects_invalid_packages() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("old")).unwrap();
+    fs::create_dir_all(stow_dir.join("new")).unwrap();
+    fs::write(stow_dir.join("old/old-file"), "old").unwrap();
+    fs::write(stow_dir.join("new/new-file"), "new").unwrap();
+    let config = create_test_config(stow_dir.clone(), target_dir.clone(), Vec::new(), false, 0);
+    rustow::apply(
+        &rustow::plan(
+            &config,
+            &[PackageOperation {
+                mode: StowMode::Stow,
+                packages: vec!["old".to_string()],
+            }],
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let operations = vec![
+        PackageOperation {
+            mode: StowMode::Delete,
+            packages: vec!["old".to_string()],
+        },
+        PackageOperation {
+            mode: StowMode::Stow,
+            packages: vec!["new".to_string()],
+        },
+    ];
+    let plan = rustow::plan(&config, &operations).unwrap();
+    assert_eq!(plan.operations.len(), 2);
+    assert_eq!(plan.delete_actions.len(), 1);
+    assert_eq!(plan.stow_actions.len(), 1);
+
+    rustow::apply(&plan).unwrap();
+    assert_eq!(target_entry_names(&target_dir), vec!["new-file"]);
+
+    let invalid = rustow::plan(
+        &config,
+        &[PackageOperation {
+            mode: StowMode::Stow,
+            packages: vec!["../escape".to_string()],
+        }],
+    );
+    assert!(invalid.is_err());
+}
+
 #[test]
 fn test_empty_package_list() {
     let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
@@ -1342,6 +1759,7 @@ fn test_config_integration_verbosity_and_simulate() {
     let args: Args = Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         packages: vec![package_name.to_string()],
         simulate: true,
@@ -1349,12 +1767,38 @@ fn test_config_integration_verbosity_and_simulate() {
         delete: false,
         restow: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         compat: false,
         override_conflicts: vec![],
         defer_conflicts: vec![],
         ignore_patterns: vec![],
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
     };
 
     let config_result: Result<Config, rustow::error::RustowError> = Config::from_args(args);
@@ -2261,17 +2705,26 @@ fn test_delete_mode_nonexistent_target() {
     let delete_config: Config = Config {
         stow_dir,
         target_dir,
+        stow_path: Vec::new(),
         packages: vec![package_name.to_string()],
         mode: StowMode::Delete,
         stow: false,
         compat: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
         dotfiles: false,
+        dir_modes: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: Default::default(),
         overrides: Vec::new(),
         defers: Vec::new(),
         ignore_patterns: Vec::new(),
         simulate: false,
+        incremental: false,
+        force: false,
+        lock_timeout: std::time::Duration::from_secs(10),
         verbosity: 0,
         home_dir: std::env::temp_dir(),
     };
@@ -2313,17 +2766,26 @@ fn test_delete_mode_non_stow_symlinks() {
     let delete_config: Config = Config {
         stow_dir,
         target_dir: target_dir.clone(),
+        stow_path: Vec::new(),
         packages: vec![package_name.to_string()],
         mode: StowMode::Delete,
         stow: false,
         compat: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
         dotfiles: false,
+        dir_modes: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: Default::default(),
         overrides: Vec::new(),
         defers: Vec::new(),
         ignore_patterns: Vec::new(),
         simulate: false,
+        incremental: false,
+        force: false,
+        lock_timeout: std::time::Duration::from_secs(10),
         verbosity: 0,
         home_dir: std::env::temp_dir(),
     };
@@ -2875,17 +3337,26 @@ fn test_delete_mode_simulate() {
     let delete_config: Config = Config {
         stow_dir,
         target_dir: target_dir.clone(),
+        stow_path: Vec::new(),
         packages: vec![package_name.to_string()],
         mode: StowMode::Delete,
         stow: false,
         compat: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
         dotfiles: false,
+        dir_modes: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: Default::default(),
         overrides: Vec::new(),
         defers: Vec::new(),
         ignore_patterns: Vec::new(),
         simulate: true, // Simulate mode
+        incremental: false,
+        force: false,
+        lock_timeout: std::time::Duration::from_secs(10),
         verbosity: 0,
         home_dir: std::env::temp_dir(),
     };
@@ -2932,15 +3403,42 @@ fn test_cli_integration_modes() {
     let stow_args = Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -2953,15 +3451,42 @@ fn test_cli_integration_modes() {
     let stow_result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -2981,15 +3506,42 @@ fn test_cli_integration_modes() {
     let delete_result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: true, // Delete mode
         restow: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -3009,15 +3561,42 @@ fn test_cli_integration_modes() {
     let restow_result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: true, // Restow mode
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -3299,82 +3878,204 @@ fn test_binary_mixed_same_package_delete_and_stow_prunes_stale_symlink() {
 }
 
 #[test]
-fn test_binary_no_folding_keeps_directory_open() {
-    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+fn test_binary_plan_out_then_apply() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
     let package_dir = stow_dir.join("pkg");
     fs::create_dir_all(package_dir.join("bin")).unwrap();
     fs::write(package_dir.join("bin/tool"), "tool").unwrap();
+    let plan_path = temp_dir.path().join("plan.json");
 
-    let output = run_rustow([
-        "--no-folding",
+    let plan_output = run_rustow([
         "-d",
         stow_dir.to_str().unwrap(),
         "-t",
         target_dir.to_str().unwrap(),
+        "--plan-out",
+        plan_path.to_str().unwrap(),
         "pkg",
     ]);
-
     assert!(
-        output.status.success(),
-        "rustow failed: {}",
-        String::from_utf8_lossy(&output.stderr)
+        plan_output.status.success(),
+        "rustow --plan-out failed: {}",
+        String::from_utf8_lossy(&plan_output.stderr)
     );
+    assert!(plan_path.exists(), "plan file should be written");
     assert!(
-        target_dir.join("bin").is_dir(),
-        "bin should remain a directory"
+        !target_dir.join("bin").exists(),
+        "--plan-out must not modify the target"
     );
+    let plan_json = fs::read_to_string(&plan_path).unwrap();
+    assert!(plan_json.contains("\"fingerprints\""));
+    assert!(plan_json.contains("CreateSymlink"));
+
+    let apply_output = run_rustow(["--apply", plan_path.to_str().unwrap()]);
     assert!(
-        !fs::symlink_metadata(target_dir.join("bin"))
-            .unwrap()
-            .file_type()
-            .is_symlink(),
-        "bin should not be a symlink when --no-folding is enabled"
+        apply_output.status.success(),
+        "rustow --apply failed: {}",
+        String::from_utf8_lossy(&apply_output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(target_dir.join("bin/tool")).unwrap(),
+        "tool"
     );
     assert!(
-        fs::symlink_metadata(target_dir.join("bin/tool"))
+        fs::symlink_metadata(target_dir.join("bin"))
             .unwrap()
             .file_type()
-            .is_symlink(),
-        "bin/tool should be symlinked"
+            .is_symlink()
     );
 }
 
 #[test]
-fn test_binary_no_folding_split_open_with_existing_folded_tree() {
-    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
-
-    let package1_dir = stow_dir.join("perl");
-    let package2_dir = stow_dir.join("emacs");
-    fs::create_dir_all(package1_dir.join("bin")).unwrap();
-    fs::create_dir_all(package2_dir.join("bin")).unwrap();
-    fs::write(package1_dir.join("bin/perl"), "perl").unwrap();
-    fs::write(package2_dir.join("bin/emacs"), "emacs").unwrap();
+fn test_binary_apply_refuses_stale_plan() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let package_dir = stow_dir.join("pkg");
+    fs::create_dir_all(&package_dir).unwrap();
+    fs::write(package_dir.join("config"), "managed").unwrap();
+    let plan_path = temp_dir.path().join("plan.json");
 
-    let first = run_rustow([
+    let plan_output = run_rustow([
         "-d",
         stow_dir.to_str().unwrap(),
         "-t",
         target_dir.to_str().unwrap(),
-        "perl",
+        "--plan-out",
+        plan_path.to_str().unwrap(),
+        "pkg",
     ]);
-    assert!(
-        first.status.success(),
-        "initial rustow failed: {}",
-        String::from_utf8_lossy(&first.stderr)
-    );
-    assert!(
-        fs::symlink_metadata(target_dir.join("bin"))
-            .unwrap()
-            .file_type()
-            .is_symlink(),
-        "first package should fold bin"
+    assert!(plan_output.status.success());
+
+    fs::write(target_dir.join("config"), "written after planning").unwrap();
+    let apply_output = run_rustow(["--apply", plan_path.to_str().unwrap()]);
+
+    assert!(!apply_output.status.success());
+    let stderr = String::from_utf8_lossy(&apply_output.stderr);
+    assert!(stderr.contains("Stale plan"), "stderr: {}", stderr);
+    assert!(stderr.contains("config"), "stderr: {}", stderr);
+    assert_eq!(
+        fs::read_to_string(target_dir.join("config")).unwrap(),
+        "written after planning"
     );
+}
 
-    let second = run_rustow([
-        "--no-folding",
-        "-d",
-        stow_dir.to_str().unwrap(),
-        "-t",
+#[test]
+fn test_binary_plan_out_reports_conflicts_without_writing_plan() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let package_dir = stow_dir.join("pkg");
+    fs::create_dir_all(&package_dir).unwrap();
+    fs::write(package_dir.join("config"), "managed").unwrap();
+    fs::write(target_dir.join("config"), "unmanaged").unwrap();
+    let plan_path = temp_dir.path().join("plan.json");
+
+    let output = run_rustow([
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "--plan-out",
+        plan_path.to_str().unwrap(),
+        "pkg",
+    ]);
+
+    assert_eq!(output.status.code(), Some(exit_code::CONFLICTS));
+    assert!(!plan_path.exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 conflicts"), "stderr: {}", stderr);
+    assert!(
+        stderr.contains(&format!(
+            "plan not written to {}: resolve the conflicts above first",
+            plan_path.display()
+        )),
+        "stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_binary_apply_rejects_packages() {
+    let output = run_rustow(["--apply", "plan.json", "pkg"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+}
+
+#[test]
+fn test_binary_no_folding_keeps_directory_open() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let package_dir = stow_dir.join("pkg");
+    fs::create_dir_all(package_dir.join("bin")).unwrap();
+    fs::write(package_dir.join("bin/tool"), "tool").unwrap();
+
+    let output = run_rustow([
+        "--no-folding",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "pkg",
+    ]);
+
+    assert!(
+        output.status.success(),
+        "rustow failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        target_dir.join("bin").is_dir(),
+        "bin should remain a directory"
+    );
+    assert!(
+        !fs::symlink_metadata(target_dir.join("bin"))
+            .unwrap()
+            .file_type()
+            .is_symlink(),
+        "bin should not be a symlink when --no-folding is enabled"
+    );
+    assert!(
+        fs::symlink_metadata(target_dir.join("bin/tool"))
+            .unwrap()
+            .file_type()
+            .is_symlink(),
+        "bin/tool should be symlinked"
+    );
+}
+
+#[test]
+fn test_binary_no_folding_split_open_with_existing_folded_tree() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+
+    let package1_dir = stow_dir.join("perl");
+    let package2_dir = stow_dir.join("emacs");
+    fs::create_dir_all(package1_dir.join("bin")).unwrap();
+    fs::create_dir_all(package2_dir.join("bin")).unwrap();
+    fs::write(package1_dir.join("bin/perl"), "perl").unwrap();
+    fs::write(package2_dir.join("bin/emacs"), "emacs").unwrap();
+
+    let first = run_rustow([
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "perl",
+    ]);
+    assert!(
+        first.status.success(),
+        "initial rustow failed: {}",
+        String::from_utf8_lossy(&first.stderr)
+    );
+    assert!(
+        fs::symlink_metadata(target_dir.join("bin"))
+            .unwrap()
+            .file_type()
+            .is_symlink(),
+        "first package should fold bin"
+    );
+
+    let second = run_rustow([
+        "--no-folding",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
         target_dir.to_str().unwrap(),
         "emacs",
     ]);
@@ -3432,157 +4133,1630 @@ fn test_binary_mixed_delete_and_stow_operations() {
         stow_dir.to_str().unwrap(),
         "-t",
         target_dir.to_str().unwrap(),
-        "-D",
-        "oldpkg",
-        "-S",
-        "newpkg",
-    ]);
+        "-D",
+        "oldpkg",
+        "-S",
+        "newpkg",
+    ]);
+
+    assert!(
+        output.status.success(),
+        "rustow failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!target_dir.join("bin/old_tool").exists());
+    assert!(target_dir.join("bin/new_tool").exists());
+}
+
+#[test]
+fn test_cli_mixed_stow_then_delete_replaces_overlapping_target() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let old_package_dir = stow_dir.join("oldpkg");
+    let new_package_dir = stow_dir.join("newpkg");
+    fs::create_dir_all(old_package_dir.join("bin")).unwrap();
+    fs::create_dir_all(new_package_dir.join("bin")).unwrap();
+    fs::write(old_package_dir.join("bin/tool"), "old").unwrap();
+    fs::write(new_package_dir.join("bin/tool"), "new").unwrap();
+
+    let old_config = create_test_config(
+        stow_dir.clone(),
+        target_dir.clone(),
+        vec!["oldpkg".to_string()],
+        false,
+        0,
+    );
+    stow_packages(&old_config).unwrap();
+    assert!(target_dir.join("bin/tool").exists());
+
+    let parsed_args = Args::parse_from_with_operation_groups(vec![
+        "rustow".to_string(),
+        "-d".to_string(),
+        stow_dir.to_string_lossy().into_owned(),
+        "-t".to_string(),
+        target_dir.to_string_lossy().into_owned(),
+        "-S".to_string(),
+        "newpkg".to_string(),
+        "-D".to_string(),
+        "oldpkg".to_string(),
+    ]);
+
+    let result = rustow::run_with_operation_groups(parsed_args.args, parsed_args.operation_groups);
+
+    assert!(
+        result.is_ok(),
+        "GNU mixed stow/delete replacement failed: {:?}",
+        result.err()
+    );
+    assert!(target_dir.join("bin/tool").exists());
+    assert_eq!(
+        fs::read_link(target_dir.join("bin")).unwrap(),
+        PathBuf::from("../stow_dir/newpkg/bin")
+    );
+}
+
+#[test]
+fn test_cli_mixed_stow_conflict_prevents_prior_delete() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let old_package_dir = stow_dir.join("oldpkg");
+    let new_package_dir = stow_dir.join("newpkg");
+    fs::create_dir_all(old_package_dir.join("bin")).unwrap();
+    fs::create_dir_all(new_package_dir.join("etc")).unwrap();
+    fs::write(old_package_dir.join("bin/old_tool"), "old").unwrap();
+    fs::write(new_package_dir.join("etc/app.conf"), "new").unwrap();
+
+    let old_config = create_test_config(
+        stow_dir.clone(),
+        target_dir.clone(),
+        vec!["oldpkg".to_string()],
+        false,
+        0,
+    );
+    stow_packages(&old_config).unwrap();
+    fs::create_dir_all(target_dir.join("etc")).unwrap();
+    fs::write(target_dir.join("etc/app.conf"), "unmanaged").unwrap();
+    assert!(target_dir.join("bin/old_tool").exists());
+
+    let parsed_args = Args::parse_from_with_operation_groups(vec![
+        "rustow".to_string(),
+        "-d".to_string(),
+        stow_dir.to_string_lossy().into_owned(),
+        "-t".to_string(),
+        target_dir.to_string_lossy().into_owned(),
+        "-D".to_string(),
+        "oldpkg".to_string(),
+        "-S".to_string(),
+        "newpkg".to_string(),
+    ]);
+
+    let result = rustow::run_with_operation_groups(parsed_args.args, parsed_args.operation_groups);
+
+    assert!(
+        result.is_err(),
+        "mixed operation should fail before mutation when later stow conflicts"
+    );
+    assert!(
+        target_dir.join("bin/old_tool").exists(),
+        "old package should remain stowed when later stow phase conflicts"
+    );
+    assert_eq!(
+        fs::read_to_string(target_dir.join("etc/app.conf")).unwrap(),
+        "unmanaged"
+    );
+}
+
+#[test]
+fn test_binary_mixed_simulate_conflict_reports_failure_without_mutation() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let old_package_dir = stow_dir.join("oldpkg");
+    let new_package_dir = stow_dir.join("newpkg");
+    fs::create_dir_all(old_package_dir.join("bin")).unwrap();
+    fs::create_dir_all(new_package_dir.join("etc")).unwrap();
+    fs::write(old_package_dir.join("bin/old_tool"), "old").unwrap();
+    fs::write(new_package_dir.join("etc/app.conf"), "new").unwrap();
+
+    let old_config = create_test_config(
+        stow_dir.clone(),
+        target_dir.clone(),
+        vec!["oldpkg".to_string()],
+        false,
+        0,
+    );
+    stow_packages(&old_config).unwrap();
+    fs::create_dir_all(target_dir.join("etc")).unwrap();
+    fs::write(target_dir.join("etc/app.conf"), "unmanaged").unwrap();
+
+    let output = run_rustow([
+        "-n",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "-D",
+        "oldpkg",
+        "-S",
+        "newpkg",
+    ]);
+
+    assert_eq!(output.status.code(), Some(exit_code::CONFLICTS));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("CONFLICT"));
+    assert!(target_dir.join("bin/old_tool").exists());
+    assert_eq!(
+        fs::read_to_string(target_dir.join("etc/app.conf")).unwrap(),
+        "unmanaged"
+    );
+}
+
+#[test]
+fn test_binary_check_reports_each_difference_without_mutation() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let package_dir = stow_dir.join("app");
+    fs::create_dir_all(package_dir.join("bin")).unwrap();
+    fs::create_dir_all(package_dir.join("etc")).unwrap();
+    fs::create_dir_all(package_dir.join("share")).unwrap();
+    fs::write(package_dir.join("bin/tool"), "tool").unwrap();
+    fs::write(package_dir.join("bin/helper"), "helper").unwrap();
+    fs::write(package_dir.join("etc/app.conf"), "conf").unwrap();
+    fs::write(package_dir.join("share/old.txt"), "old").unwrap();
+    let check_args = [
+        "--check",
+        "--no-folding",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "app",
+    ];
+
+    let stowed = run_rustow(&check_args[1..]);
+    assert!(stowed.status.success());
+    let clean = run_rustow(check_args);
+    assert_eq!(clean.status.code(), Some(exit_code::SUCCESS));
+    assert!(!String::from_utf8_lossy(&clean.stderr).contains("CHECK:"));
+
+    fs::remove_file(target_dir.join("bin/tool")).unwrap();
+    fs::remove_file(target_dir.join("bin/helper")).unwrap();
+    std::os::unix::fs::symlink("/elsewhere/helper", target_dir.join("bin/helper")).unwrap();
+    fs::remove_file(target_dir.join("etc/app.conf")).unwrap();
+    fs::write(target_dir.join("etc/app.conf"), "local").unwrap();
+    fs::remove_file(package_dir.join("share/old.txt")).unwrap();
+
+    let output = run_rustow(check_args);
+
+    assert_eq!(output.status.code(), Some(exit_code::DRIFT));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let target = target_dir.display();
+    for expected in [
+        format!("CHECK: missing link: {target}/bin/tool -> ../../stow_dir/app/bin/tool"),
+        format!(
+            "CHECK: wrong link: {target}/bin/helper -> /elsewhere/helper \
+             (expected ../../stow_dir/app/bin/helper)"
+        ),
+        format!("CHECK: conflict: {target}/etc/app.conf: "),
+        format!("CHECK: extra link: {target}/share/old.txt -> ../../stow_dir/app/share/old.txt"),
+        "CHECK: 4 difference(s) found".to_string(),
+    ] {
+        assert!(
+            stderr.contains(&expected),
+            "missing {:?} in:\n{}",
+            expected,
+            stderr
+        );
+    }
+    assert!(!target_dir.join("bin/tool").exists());
+    assert_eq!(
+        fs::read_link(target_dir.join("bin/helper")).unwrap(),
+        PathBuf::from("/elsewhere/helper")
+    );
+    assert!(fs::symlink_metadata(target_dir.join("share/old.txt")).is_ok());
+}
+
+#[test]
+fn test_binary_check_reports_links_of_packages_to_unstow() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    let stow_args = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "test_package",
+    ];
+    assert!(run_rustow(stow_args).status.success());
+
+    let output = run_rustow([
+        "--check",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "-D",
+        "test_package",
+    ]);
+
+    assert_eq!(output.status.code(), Some(exit_code::DRIFT));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("CHECK: extra link: "));
+    assert!(fs::symlink_metadata(target_dir.join("bin")).is_ok());
+}
+
+fn set_modified_in_future(path: &Path) {
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+        .unwrap();
+}
+
+#[test]
+fn test_binary_report_unmanaged_lists_files_newer_than_folded_link() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("foo/dot-config/foo")).unwrap();
+    fs::write(stow_dir.join("foo/dot-config/foo/config"), "config").unwrap();
+    let args = |option: &'static str| {
+        [
+            option,
+            "--dotfiles",
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "foo",
+        ]
+    };
+    assert!(run_rustow(args("-S")).status.success());
+
+    let folded = target_dir.join(".config");
+    fs::create_dir_all(folded.join("foo/cache")).unwrap();
+    fs::write(folded.join("foo/cache/blob"), "cache").unwrap();
+    set_modified_in_future(&folded.join("foo/cache/blob"));
+
+    let report = run_rustow(args("--report-unmanaged"));
+
+    assert_eq!(report.status.code(), Some(exit_code::SUCCESS));
+    let stderr = String::from_utf8_lossy(&report.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "UNMANAGED: {} (in package 'foo')",
+            folded.join("foo/cache/blob").display()
+        )),
+        "stderr: {}",
+        stderr
+    );
+    assert!(
+        !stderr.contains("foo/config (in package"),
+        "stderr: {}",
+        stderr
+    );
+    assert!(fs::symlink_metadata(&folded).unwrap().is_symlink());
+
+    let unfold = run_rustow(args("--unfold-unmanaged"));
+
+    assert_eq!(unfold.status.code(), Some(exit_code::SUCCESS));
+    assert!(!fs::symlink_metadata(&folded).unwrap().is_symlink());
+    assert_eq!(
+        fs::read_to_string(folded.join("foo/cache/blob")).unwrap(),
+        "cache"
+    );
+    assert!(!stow_dir.join("foo/dot-config/foo/cache").exists());
+    assert_eq!(
+        fs::read_to_string(folded.join("foo/config")).unwrap(),
+        "config"
+    );
+    assert!(
+        fs::symlink_metadata(folded.join("foo/config"))
+            .unwrap()
+            .is_symlink()
+    );
+    // The directories were built aside and swapped in, leaving nothing staged behind.
+    for dir in [&target_dir, &folded, &folded.join("foo")] {
+        for entry in fs::read_dir(dir).unwrap() {
+            let name = entry.unwrap().file_name();
+            assert!(!name.to_string_lossy().contains(".rustow-"), "{:?}", name);
+        }
+    }
+}
+
+#[test]
+fn test_binary_report_unmanaged_uses_git_tracking_in_stow_dir() {
+    let (_temp_base, home_dir, stow_dir, target_dir) = setup_git_adopt_environment();
+    fs::create_dir_all(stow_dir.join("nvim/.config/nvim")).unwrap();
+    fs::write(stow_dir.join("nvim/.config/nvim/init.lua"), "tracked").unwrap();
+    git_in(&stow_dir, &["add", "nvim"]);
+    git_in(&stow_dir, &["commit", "--quiet", "--message", "nvim"]);
+    fs::remove_dir_all(target_dir.join(".config")).unwrap();
+    let args = |option: &'static str| {
+        [
+            option,
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "nvim",
+        ]
+    };
+    let envs = [("HOME", home_dir.to_str().unwrap())];
+    assert!(
+        run_rustow_with(args("-S"), &home_dir, &envs)
+            .status
+            .success()
+    );
+    fs::write(target_dir.join(".config/nvim/init.lua"), "edited").unwrap();
+    set_modified_in_future(&target_dir.join(".config/nvim/init.lua"));
+    fs::write(target_dir.join(".config/nvim/session.vim"), "state").unwrap();
+
+    let output = run_rustow_with(args("--report-unmanaged"), &home_dir, &envs);
+
+    assert_eq!(output.status.code(), Some(exit_code::SUCCESS));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("/.config/nvim/session.vim (in package 'nvim')"),
+        "stderr: {}",
+        stderr
+    );
+    assert!(!stderr.contains("init.lua"), "stderr: {}", stderr);
+    assert!(stderr.contains("UNMANAGED: 1 file(s) in 1 folded directory(ies)"));
+}
+
+#[test]
+fn test_binary_unfold_and_refold_round_trip_a_managed_directory() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("app/share/app/themes")).unwrap();
+    fs::write(stow_dir.join("app/share/app/app.conf"), "conf").unwrap();
+    fs::write(stow_dir.join("app/share/app/themes/dark"), "dark").unwrap();
+    let dirs = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    assert!(
+        run_rustow(dirs.iter().copied().chain(["app"]))
+            .status
+            .success()
+    );
+    let share = target_dir.join("share");
+    let share_arg = share.to_str().unwrap();
+
+    let unfolded = run_rustow(dirs.iter().copied().chain(["--unfold", share_arg]));
+    let stderr = String::from_utf8_lossy(&unfolded.stderr);
+    assert!(unfolded.status.success(), "stderr: {}", stderr);
+    assert!(stderr.contains(&format!("UNLINK: {}", share.display())));
+    assert!(stderr.contains(&format!("MKDIR: {}", share.display())));
+    assert!(stderr.contains(&format!(
+        "LINK: {} -> ../../stow_dir/app/share/app",
+        share.join("app").display()
+    )));
+    assert!(!fs::symlink_metadata(&share).unwrap().is_symlink());
+    assert!(
+        fs::symlink_metadata(share.join("app"))
+            .unwrap()
+            .is_symlink()
+    );
+
+    let refolded = run_rustow(dirs.iter().copied().chain(["--refold", share_arg]));
+    let stderr = String::from_utf8_lossy(&refolded.stderr);
+    assert!(refolded.status.success(), "stderr: {}", stderr);
+    assert!(stderr.contains(&format!("UNLINK: {}", share.join("app").display())));
+    assert!(stderr.contains(&format!("RMDIR: {}", share.display())));
+    assert_eq!(
+        fs::read_link(&share).unwrap(),
+        PathBuf::from("../stow_dir/app/share")
+    );
+}
+
+#[test]
+fn test_binary_unfold_simulate_leaves_target_unchanged() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    let dirs = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    assert!(
+        run_rustow(dirs.iter().copied().chain(["test_package"]))
+            .status
+            .success()
+    );
+    let bin = target_dir.join("bin");
+
+    let output = run_rustow(
+        dirs.iter()
+            .copied()
+            .chain(["-n", "--unfold", bin.to_str().unwrap()]),
+    );
+
+    assert_eq!(output.status.code(), Some(exit_code::PENDING_CHANGES));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Would perform DeleteSymlink"));
+    assert!(fs::symlink_metadata(&bin).unwrap().is_symlink());
+}
+
+#[test]
+fn test_binary_unfold_and_refold_reject_unmanaged_directories() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    fs::create_dir_all(target_dir.join("notes")).unwrap();
+    fs::write(target_dir.join("notes/todo"), "todo").unwrap();
+    let notes = target_dir.join("notes");
+    let dirs = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+
+    for option in ["--unfold", "--refold"] {
+        let output = run_rustow(
+            dirs.iter()
+                .copied()
+                .chain([option, notes.to_str().unwrap()]),
+        );
+
+        assert_eq!(output.status.code(), Some(exit_code::USAGE), "{}", option);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Not a managed directory"));
+    }
+    assert_eq!(target_entry_names(&notes), vec!["todo"]);
+}
+
+#[test]
+fn test_binary_add_moves_target_paths_into_new_package_and_links_them() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    fs::write(target_dir.join(".tmux.conf"), "set -g mouse on").unwrap();
+    fs::create_dir_all(target_dir.join(".config/tmux/plugins")).unwrap();
+    fs::write(target_dir.join(".config/tmux/plugins/tpm"), "tpm").unwrap();
+    fs::write(target_dir.join(".config/other"), "other").unwrap();
+
+    let output = run_rustow([
+        "--dotfiles",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "--add",
+        "tmux",
+        target_dir.join(".tmux.conf").to_str().unwrap(),
+        target_dir.join(".config/tmux").to_str().unwrap(),
+    ]);
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(stow_dir.join("tmux/dot-tmux.conf")).unwrap(),
+        "set -g mouse on"
+    );
+    assert!(stow_dir.join("tmux/dot-config/tmux/plugins/tpm").is_file());
+    assert_eq!(
+        fs::read_link(target_dir.join(".tmux.conf")).unwrap(),
+        PathBuf::from("../stow_dir/tmux/dot-tmux.conf")
+    );
+    assert_eq!(
+        fs::read_link(target_dir.join(".config/tmux")).unwrap(),
+        PathBuf::from("../../stow_dir/tmux/dot-config/tmux")
+    );
+    assert!(
+        !fs::symlink_metadata(target_dir.join(".config"))
+            .unwrap()
+            .is_symlink()
+    );
+    assert!(!stow_dir.join("tmux/dot-config/other").exists());
+}
+
+#[test]
+fn test_binary_add_simulate_leaves_target_and_stow_dir_unchanged() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    fs::write(target_dir.join(".vimrc"), "set number").unwrap();
+
+    let output = run_rustow([
+        "-n",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "--add",
+        "vim",
+        target_dir.join(".vimrc").to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(exit_code::PENDING_CHANGES));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Would perform AdoptFile"),
+        "stderr: {}",
+        stderr
+    );
+    assert!(!stow_dir.join("vim").exists());
+    assert!(target_dir.join(".vimrc").is_file());
+    assert!(
+        !fs::symlink_metadata(target_dir.join(".vimrc"))
+            .unwrap()
+            .is_symlink()
+    );
+}
+
+#[test]
+fn test_binary_add_refuses_paths_already_in_the_package() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    let dirs = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    assert!(
+        run_rustow(dirs.iter().copied().chain(["test_package"]))
+            .status
+            .success()
+    );
+    fs::write(target_dir.join("notes"), "notes").unwrap();
+    fs::write(stow_dir.join("test_package/notes"), "packaged").unwrap();
+
+    for path in [target_dir.join("notes"), target_dir.join("bin")] {
+        let output = run_rustow(dirs.iter().copied().chain([
+            "--add",
+            "test_package",
+            path.to_str().unwrap(),
+        ]));
+
+        assert_eq!(output.status.code(), Some(exit_code::USAGE), "{:?}", path);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Cannot add path"));
+    }
+    assert_eq!(
+        fs::read_to_string(target_dir.join("notes")).unwrap(),
+        "notes"
+    );
+}
+
+#[test]
+fn test_binary_add_rejects_non_utf8_paths() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    // Arguments must be UTF-8, but one can resolve through a link to a directory that is not.
+    let directory = target_dir.join(OsStr::from_bytes(b"notes-\xff"));
+    fs::create_dir(&directory).unwrap();
+    fs::write(directory.join("todo"), "todo").unwrap();
+    let alias = temp_dir.path().join("alias");
+    std::os::unix::fs::symlink(&directory, &alias).unwrap();
+
+    let output = run_rustow([
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "--add",
+        "new_package",
+        alias.join("todo").to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("not valid UTF-8"),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(fs::read_to_string(directory.join("todo")).unwrap(), "todo");
+    assert!(!stow_dir.join("new_package").exists());
+}
+
+#[test]
+fn test_binary_move_to_relocates_item_and_unfolds_covering_link() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("vim/dot-config/nvim/lua")).unwrap();
+    fs::create_dir_all(stow_dir.join("vim/dot-config/other")).unwrap();
+    fs::write(stow_dir.join("vim/dot-vimrc"), "set nu").unwrap();
+    fs::write(stow_dir.join("vim/dot-config/nvim/init.lua"), "init").unwrap();
+    fs::write(stow_dir.join("vim/dot-config/other/conf"), "other").unwrap();
+    let dirs = [
+        "--dotfiles",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    assert!(
+        run_rustow(dirs.iter().copied().chain(["vim"]))
+            .status
+            .success()
+    );
+
+    let output =
+        run_rustow(
+            dirs.iter()
+                .copied()
+                .chain(["--move-to", "neovim", "vim/dot-config/nvim"]),
+        );
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stow_dir.join("neovim/dot-config/nvim/lua").is_dir());
+    assert!(!stow_dir.join("vim/dot-config/nvim").exists());
+    let dot_config = target_dir.join(".config");
+    assert!(!fs::symlink_metadata(&dot_config).unwrap().is_symlink());
+    assert_eq!(
+        fs::read_link(dot_config.join("nvim")).unwrap(),
+        PathBuf::from("../../stow_dir/neovim/dot-config/nvim")
+    );
+    assert_eq!(
+        fs::read_link(dot_config.join("other")).unwrap(),
+        PathBuf::from("../../stow_dir/vim/dot-config/other")
+    );
+    assert_eq!(
+        fs::read_to_string(dot_config.join("nvim/init.lua")).unwrap(),
+        "init"
+    );
+
+    let check = run_rustow(dirs.iter().copied().chain(["--check", "vim", "neovim"]));
+    assert_eq!(
+        check.status.code(),
+        Some(exit_code::SUCCESS),
+        "stderr: {}",
+        String::from_utf8_lossy(&check.stderr)
+    );
+}
+
+#[test]
+fn test_binary_move_to_renames_package_and_rewrites_links() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    let dirs = [
+        "--no-folding",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    assert!(
+        run_rustow(dirs.iter().copied().chain(["test_package"]))
+            .status
+            .success()
+    );
+
+    let simulated =
+        run_rustow(
+            dirs.iter()
+                .copied()
+                .chain(["-n", "--move-to", "renamed", "test_package"]),
+        );
+    assert_eq!(simulated.status.code(), Some(exit_code::PENDING_CHANGES));
+    assert!(stow_dir.join("test_package").is_dir());
+    assert!(!stow_dir.join("renamed").exists());
+
+    let output = run_rustow(
+        dirs.iter()
+            .copied()
+            .chain(["--move-to", "renamed", "test_package"]),
+    );
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!stow_dir.join("test_package").exists());
+    assert_eq!(
+        fs::read_link(target_dir.join("bin/test_script")).unwrap(),
+        PathBuf::from("../../stow_dir/renamed/bin/test_script")
+    );
+    assert!(target_dir.join("bin/test_script").exists());
+}
+
+#[test]
+fn test_binary_move_to_reports_conflict_with_destination_package() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    fs::create_dir_all(stow_dir.join("other/bin")).unwrap();
+    fs::write(stow_dir.join("other/bin/test_script"), "other").unwrap();
+    let dirs = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    assert!(
+        run_rustow(dirs.iter().copied().chain(["test_package"]))
+            .status
+            .success()
+    );
+    let link_before = fs::read_link(target_dir.join("bin")).unwrap();
+
+    let output = run_rustow(dirs.iter().copied().chain([
+        "--move-to",
+        "other",
+        "test_package/bin/test_script",
+    ]));
+
+    assert_eq!(output.status.code(), Some(exit_code::CONFLICTS));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists in package 'other'"));
+    assert!(stow_dir.join("test_package/bin/test_script").exists());
+    assert_eq!(fs::read_link(target_dir.join("bin")).unwrap(), link_before);
+}
+
+#[test]
+fn test_binary_relink_from_rewrites_links_after_stow_dir_moves() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("vim/dot-config/nvim")).unwrap();
+    fs::create_dir_all(stow_dir.join("bash")).unwrap();
+    fs::write(stow_dir.join("vim/dot-vimrc"), "set nu").unwrap();
+    fs::write(stow_dir.join("vim/dot-config/nvim/init.lua"), "init").unwrap();
+    fs::write(stow_dir.join("bash/dot-bashrc"), "bashrc").unwrap();
+    let stowed = run_rustow([
+        "--dotfiles",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "vim",
+        "bash",
+    ]);
+    assert!(stowed.status.success());
+    let new_stow_dir = temp_dir.path().join("src/dotfiles");
+    fs::create_dir_all(new_stow_dir.parent().unwrap()).unwrap();
+    fs::rename(&stow_dir, &new_stow_dir).unwrap();
+    fs::remove_file(new_stow_dir.join("bash/dot-bashrc")).unwrap();
+
+    let output = run_rustow([
+        "-d",
+        new_stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "--relink-from",
+        stow_dir.to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.status.code(), Some(exit_code::PARTIAL_FAILURE));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "RELINK: cannot relink {}",
+            target_dir.join(".bashrc").display()
+        )),
+        "stderr: {}",
+        stderr
+    );
+    assert_eq!(
+        fs::read_link(target_dir.join(".vimrc")).unwrap(),
+        PathBuf::from("../src/dotfiles/vim/dot-vimrc")
+    );
+    assert_eq!(
+        fs::read_to_string(target_dir.join(".config/nvim/init.lua")).unwrap(),
+        "init"
+    );
+    assert!(fs::symlink_metadata(target_dir.join(".bashrc")).is_ok());
+}
+
+#[test]
+fn test_binary_relink_from_only_touches_given_packages() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    fs::create_dir_all(stow_dir.join("other")).unwrap();
+    fs::write(stow_dir.join("other/notes"), "notes").unwrap();
+    let stowed = run_rustow([
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "test_package",
+        "other",
+    ]);
+    assert!(stowed.status.success());
+    let new_stow_dir = temp_dir.path().join("moved");
+    fs::rename(&stow_dir, &new_stow_dir).unwrap();
+    let args = [
+        "-d",
+        new_stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "--relink-from",
+        stow_dir.to_str().unwrap(),
+        "other",
+    ];
+
+    let simulated = run_rustow(["-n"].into_iter().chain(args));
+    assert_eq!(simulated.status.code(), Some(exit_code::PENDING_CHANGES));
+    assert!(!target_dir.join("notes").exists());
+
+    let output = run_rustow(args);
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(target_dir.join("notes")).unwrap(),
+        "notes"
+    );
+    assert_eq!(
+        fs::read_link(target_dir.join("bin")).unwrap(),
+        PathBuf::from("../stow_dir/test_package/bin")
+    );
+
+    // Packages are selected as for any other operation.
+    let output = run_rustow(
+        args[..6]
+            .iter()
+            .chain(&["--all", "--exclude-package", "oth*"]),
+    );
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_link(target_dir.join("bin")).unwrap(),
+        PathBuf::from("../moved/test_package/bin")
+    );
+
+    // Unfolding and refolding take no packages, so selections are refused rather than ignored.
+    for option in ["--unfold", "--refold"] {
+        let output = run_rustow([
+            "-d",
+            new_stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            option,
+            target_dir.join("bin").to_str().unwrap(),
+            "--all",
+        ]);
+        assert_eq!(output.status.code(), Some(exit_code::USAGE), "{}", option);
+    }
+}
+
+#[test]
+fn test_binary_complete_packages_lists_all_or_stowed_packages() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    fs::create_dir_all(stow_dir.join("other")).unwrap();
+    fs::write(stow_dir.join("other/notes"), "notes").unwrap();
+    fs::create_dir_all(stow_dir.join(".git")).unwrap();
+    let stowed = run_rustow([
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "test_package",
+    ]);
+    assert!(stowed.status.success());
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&home_dir).unwrap();
+    let home = home_dir.to_str().unwrap();
+
+    let from_env = run_rustow_with(
+        ["--complete-packages=all"],
+        temp_dir.path(),
+        &[("HOME", home), ("STOW_DIR", stow_dir.to_str().unwrap())],
+    );
+    assert!(from_env.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&from_env.stdout),
+        "other\ntest_package\n"
+    );
+
+    fs::write(
+        temp_dir.path().join(".stowrc"),
+        format!(
+            "--dir={}\n--target={}\n",
+            stow_dir.display(),
+            target_dir.display()
+        ),
+    )
+    .unwrap();
+    let from_stowrc = run_rustow_with(
+        ["--complete-packages", "stowed"],
+        temp_dir.path(),
+        &[("HOME", home)],
+    );
+    assert!(from_stowrc.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&from_stowrc.stdout),
+        "test_package\n"
+    );
+}
+
+#[test]
+fn test_binary_bash_completion_offers_packages_for_the_operation_mode() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    fs::create_dir_all(stow_dir.join("other")).unwrap();
+    fs::write(stow_dir.join("other/notes"), "notes").unwrap();
+    let stowed = run_rustow([
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "test_package",
+    ]);
+    assert!(stowed.status.success());
+    let output = run_rustow(["--completions=bash"]);
+    assert!(output.status.success());
+    let script_path = temp_dir.path().join("rustow.bash");
+    fs::write(&script_path, &output.stdout).unwrap();
+    let complete = |words: &[&str]| {
+        let script = format!(
+            "source '{}'\nCOMP_WORDS=(\"$@\")\nCOMP_CWORD=$(($# - 1))\n_rustow\n\
+             printf '%s\\n' \"${{COMPREPLY[@]}}\"",
+            script_path.display()
+        );
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(script)
+            .arg("bash")
+            .arg(env!("CARGO_BIN_EXE_rustow"))
+            .args(words)
+            .current_dir(temp_dir.path())
+            .env("HOME", temp_dir.path())
+            .env_remove("STOW_DIR")
+            .output()
+            .expect("Failed to run bash");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert_eq!(
+        complete(&["-d", "stow_dir", "-t", "target_dir", ""]),
+        "other\ntest_package\n"
+    );
+    assert_eq!(
+        complete(&["--di", "stow_dir", "-ttarget_dir", "-nD", ""]),
+        "test_package\n"
+    );
+    assert_eq!(
+        complete(&[
+            "-d",
+            "stow_dir",
+            "--targ",
+            "target_dir",
+            "--del",
+            "other",
+            "-S",
+            "o"
+        ]),
+        "other\n"
+    );
+    assert_eq!(complete(&["--dele"]), "--delete\n");
+    assert_eq!(complete(&["--completions", "=", "f"]), "fish\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_binary_dir_mode_sets_created_directories_and_reports_looser_ones() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let ssh_dir = stow_dir.join("ssh/.ssh");
+    fs::create_dir_all(ssh_dir.join("sockets")).unwrap();
+    fs::write(ssh_dir.join("config"), "Host *").unwrap();
+    fs::write(ssh_dir.join("sockets/.keep"), "").unwrap();
+    fs::set_permissions(&ssh_dir, fs::Permissions::from_mode(0o700)).unwrap();
+    fs::set_permissions(ssh_dir.join("sockets"), fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = run_rustow([
+        "--no-folding",
+        "--dir-mode=package",
+        "--dir-mode=^\\.ssh/sockets$=0711",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "ssh",
+    ]);
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(mode(&target_dir.join(".ssh")), 0o700);
+    assert_eq!(mode(&target_dir.join(".ssh/sockets")), 0o711);
+
+    let other_target = target_dir.join("other");
+    fs::create_dir_all(other_target.join(".ssh")).unwrap();
+    fs::set_permissions(other_target.join(".ssh"), fs::Permissions::from_mode(0o755)).unwrap();
+    let output = run_rustow([
+        "--dir-mode=0700",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        other_target.to_str().unwrap(),
+        "ssh",
+    ]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "PERMISSIONS: {} is 0755, looser than 0700 on {}",
+            other_target.join(".ssh").display(),
+            ssh_dir.display()
+        )),
+        "stderr: {}",
+        stderr
+    );
+    assert_eq!(mode(&other_target.join(".ssh")), 0o755);
+}
+
+#[test]
+fn test_binary_insecure_permissions_on_sensitive_items() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let package_dir = stow_dir.join("secrets");
+    fs::create_dir_all(package_dir.join("shell")).unwrap();
+    fs::write(package_dir.join(".netrc"), "machine example.com").unwrap();
+    fs::write(package_dir.join("shell/aliases"), "alias ll='ls -l'").unwrap();
+    fs::set_permissions(
+        package_dir.join(".netrc"),
+        fs::Permissions::from_mode(0o644),
+    )
+    .unwrap();
+    fs::set_permissions(&package_dir, fs::Permissions::from_mode(0o775)).unwrap();
+    let run = |policy: &str| {
+        run_rustow([
+            &format!("--insecure-permissions={}", policy),
+            "--sensitive=^shell(/|$)",
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "secrets",
+        ])
+    };
+
+    let output = run("refuse");
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Refusing to link a sensitive path with insecure permissions"),
+        "stderr: {}",
+        stderr
+    );
+    assert!(!target_dir.join(".netrc").exists());
+    assert!(!target_dir.join("shell").exists());
+
+    let output = run("warn");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "INSECURE: {} is 0644, linked from a sensitive path ({})",
+            package_dir.join(".netrc").display(),
+            target_dir.join(".netrc").display()
+        )),
+        "stderr: {}",
+        stderr
+    );
+    assert!(stderr.contains(&format!("INSECURE: {} is 0775", package_dir.display())));
+    assert!(target_dir.join(".netrc").is_symlink());
+    assert_eq!(mode(&package_dir.join(".netrc")), 0o644);
+
+    let output = run_rustow([
+        "-D",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "secrets",
+    ]);
+    assert!(output.status.success());
+    // Nothing is fixed while the plan has conflicts, or when only simulating.
+    fs::write(target_dir.join("shell"), "in the way").unwrap();
+    let output = run("fix");
+    assert_eq!(output.status.code(), Some(exit_code::CONFLICTS));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("changing it to"));
+    assert_eq!(mode(&package_dir.join(".netrc")), 0o644);
+    fs::remove_file(target_dir.join("shell")).unwrap();
+    let output = run_rustow([
+        "-n",
+        "--insecure-permissions=fix",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "secrets",
+    ]);
+    assert_eq!(output.status.code(), Some(exit_code::PENDING_CHANGES));
+    assert_eq!(mode(&package_dir.join(".netrc")), 0o644);
+
+    let output = run("fix");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(target_dir.join(".netrc").is_symlink());
+    assert_eq!(mode(&package_dir.join(".netrc")), 0o600);
+    assert_eq!(mode(&package_dir.join("shell")), 0o700);
+    assert_eq!(mode(&package_dir.join("shell/aliases")), 0o600);
+    assert_eq!(mode(&package_dir), 0o755);
+}
+
+#[test]
+fn test_binary_waits_for_locked_target_and_removes_stale_locks() {
+    use std::os::fd::AsRawFd;
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    create_test_package(&stow_dir, "test_package");
+    let lock_path = target_dir.join(".rustow.lock");
+    let run = |timeout: &str| {
+        run_rustow([
+            &format!("--lock-timeout={}", timeout),
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "test_package",
+        ])
+    };
+
+    let mut held = File::create(&lock_path).unwrap();
+    writeln!(held, "{}", std::process::id()).unwrap();
+    assert_eq!(
+        unsafe { libc::flock(held.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) },
+        0
+    );
+    let output = run("0");
+    assert_eq!(output.status.code(), Some(exit_code::RUNTIME_ERROR));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("is held by PID {}", std::process::id())),
+        "stderr: {}",
+        stderr
+    );
+    assert!(!target_dir.join("bin").exists());
+
+    // Release it the way rustow does, while the next run is waiting for it.
+    let held_path = lock_path.clone();
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        fs::remove_file(held_path).unwrap();
+        drop(held);
+    });
+    let output = run("10");
+    release.join().unwrap();
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(target_dir.join("bin").is_symlink());
+    assert!(!lock_path.exists());
+
+    // A lock file nobody holds, with the PID of a process that is gone.
+    let mut child = Command::new("true").spawn().unwrap();
+    let dead_pid = child.id();
+    child.wait().unwrap();
+    fs::write(stow_dir.join(".rustow.lock"), format!("{}\n", dead_pid)).unwrap();
+    let output = run("0");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "STALE LOCK: {} was left by PID {}, which did not finish",
+            stow_dir.join(".rustow.lock").display(),
+            dead_pid
+        )),
+        "stderr: {}",
+        stderr
+    );
+    assert!(!stow_dir.join(".rustow.lock").exists());
+}
+
+#[test]
+fn test_binary_incremental_restow_skips_unchanged_packages() {
+    let (temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    let cache_dir = temp_dir.path().join("cache");
+    for package in ["alpha", "beta"] {
+        fs::create_dir_all(stow_dir.join(package).join(package)).unwrap();
+        fs::write(stow_dir.join(package).join(package).join("rc"), package).unwrap();
+    }
+    let run = |extra: &[&str]| {
+        let mut args = vec![
+            "-v",
+            "--incremental",
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        args.extend(["-R", "alpha", "beta"]);
+        let output = run_rustow_with(
+            args,
+            temp_dir.path(),
+            &[("XDG_CACHE_HOME", cache_dir.to_str().unwrap())],
+        );
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    let skipped = |stderr: &str, package: &str| {
+        stderr.contains(&format!(
+            "package '{}' is unchanged since it was last stowed; skipping it",
+            package
+        ))
+    };
+
+    let stderr = run(&[]);
+    assert!(!skipped(&stderr, "alpha") && !skipped(&stderr, "beta"));
+    assert!(target_dir.join("alpha").is_symlink());
+
+    let stderr = run(&[]);
+    assert!(
+        skipped(&stderr, "alpha") && skipped(&stderr, "beta"),
+        "stderr: {}",
+        stderr
+    );
+
+    // A changed package tree and a link removed from the target are both noticed.
+    fs::remove_file(target_dir.join("alpha")).unwrap();
+    fs::write(stow_dir.join("beta/extra"), "new entry").unwrap();
+    let stderr = run(&[]);
+    assert!(!skipped(&stderr, "alpha") && !skipped(&stderr, "beta"));
+    assert!(target_dir.join("alpha").is_symlink());
+    assert!(target_dir.join("extra").is_symlink());
+
+    let stderr = run(&[]);
+    assert!(skipped(&stderr, "alpha") && skipped(&stderr, "beta"));
+    let stderr = run(&["--dotfiles"]);
+    assert!(!skipped(&stderr, "alpha") && !skipped(&stderr, "beta"));
+    let stderr = run(&["--dotfiles", "--force"]);
+    assert!(!skipped(&stderr, "alpha") && !skipped(&stderr, "beta"));
+
+    // --force only means something with --incremental.
+    let output = run_rustow([
+        "--force",
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "-R",
+        "alpha",
+    ]);
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
+}
+
+#[test]
+fn test_binary_selects_packages_with_all_globs_and_lists() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    for package in ["a-one", "a-two", "b", ".hidden", "CVS"] {
+        fs::create_dir_all(stow_dir.join(package)).unwrap();
+        fs::write(stow_dir.join(package).join(format!("{}.rc", package)), "").unwrap();
+    }
+    let base = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    let run = |extra: &[&str]| {
+        let output = run_rustow(base.iter().chain(extra));
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+    let linked = |package: &str| target_dir.join(format!("{}.rc", package)).is_symlink();
+
+    run(&["--all", "--exclude-package", "a-t*"]);
+    assert!(linked("a-one") && linked("b"));
+    assert!(!linked("a-two") && !linked(".hidden") && !linked("CVS"));
+
+    // Selections belong to the operation they appear in.
+    let list = target_dir.join("packages.txt");
+    fs::write(&list, "# packages to stow\na-two\n\n").unwrap();
+    run(&["-D", "a-*", "-S", "--packages-from", list.to_str().unwrap()]);
+    assert!(!linked("a-one") && linked("a-two") && linked("b"));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rustow"))
+        .args(base)
+        .args(["-D", "--packages-from", "-"])
+        .env_remove("STOW_DIR")
+        .stdin(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"b\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(linked("a-two") && !linked("b"));
+
+    let output = run_rustow(base.iter().chain(&["c-*"]));
+    assert_eq!(output.status.code(), Some(exit_code::PACKAGE_NOT_FOUND));
+    let output = run_rustow(base.iter().chain(&["--all", "--exclude-package=*"]));
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
+
+    // Operations that take no package selection refuse exclusions rather than ignore them.
+    let plan_path = stow_dir.join("plan.json");
+    let target_file = target_dir.join("file");
+    let operations: [&[&str]; 3] = [
+        &["--apply", plan_path.to_str().unwrap()],
+        &["--add", "b", target_file.to_str().unwrap()],
+        &["--move-to", "b", "a-one"],
+    ];
+    for operation in operations {
+        let output = run_rustow(
+            base.iter()
+                .chain(&["--exclude-package", "a-*"])
+                .chain(operation),
+        );
+        assert_eq!(
+            output.status.code(),
+            Some(exit_code::USAGE),
+            "{}",
+            operation[0]
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("cannot be used with"), "stderr: {}", stderr);
+    }
+}
+
+#[test]
+fn test_binary_attributes_links_to_namespaced_packages() {
+    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
+    for package in ["i3", "sway"] {
+        let config_dir = stow_dir
+            .join("desktop")
+            .join(package)
+            .join(".config")
+            .join(package);
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(config_dir.join("config"), package).unwrap();
+    }
+    fs::write(stow_dir.join("desktop/.rustow-namespace"), "").unwrap();
+    let base = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    let run = |extra: &[&str]| run_rustow(base.iter().chain(extra));
 
+    let output = run(&["--all"]);
     assert!(
         output.status.success(),
-        "rustow failed: {}",
+        "stderr: {}",
         String::from_utf8_lossy(&output.stderr)
     );
-    assert!(!target_dir.join("bin/old_tool").exists());
-    assert!(target_dir.join("bin/new_tool").exists());
+    // The packages share `.config`, so it is unfolded rather than claimed by one of them.
+    assert!(!target_dir.join(".config").is_symlink());
+    assert!(target_dir.join(".config/i3").is_symlink());
+    assert!(target_dir.join(".config/sway").is_symlink());
+
+    let output = run(&["-R", "desktop/sway", "-D", "desktop/i3"]);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!target_dir.join(".config/i3").exists());
+    assert_eq!(
+        fs::read_to_string(target_dir.join(".config/sway/config")).unwrap(),
+        "sway"
+    );
+
+    let output = run(&["desktop"]);
+    assert_eq!(output.status.code(), Some(exit_code::RUNTIME_ERROR));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("namespace"));
 }
 
 #[test]
-fn test_cli_mixed_stow_then_delete_replaces_overlapping_target() {
-    let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
-    let old_package_dir = stow_dir.join("oldpkg");
-    let new_package_dir = stow_dir.join("newpkg");
-    fs::create_dir_all(old_package_dir.join("bin")).unwrap();
-    fs::create_dir_all(new_package_dir.join("bin")).unwrap();
-    fs::write(old_package_dir.join("bin/tool"), "old").unwrap();
-    fs::write(new_package_dir.join("bin/tool"), "new").unwrap();
+fn test_binary_resolves_packages_across_stow_path() {
+    let (temp_dir, personal_dir, target_dir): (TempDir, PathBuf, PathBuf) =
+        setup_test_environment();
+    let team_dir = temp_dir.path().join("team");
+    let files = [
+        (&personal_dir, "vim/.vimrc", "personal"),
+        (&personal_dir, "misc/.config/misc/rc", "misc"),
+        (&personal_dir, "shell/.profile", "personal"),
+        (&team_dir, "vim/.vimrc", "team"),
+        (&team_dir, "tools/.config/tools/rc", "tools"),
+        (&team_dir, "team-shell/.profile", "team"),
+    ];
+    for (stow_dir, path, contents) in files {
+        fs::create_dir_all(stow_dir.join(path).parent().unwrap()).unwrap();
+        fs::write(stow_dir.join(path), contents).unwrap();
+    }
+    let base = [
+        "-d",
+        personal_dir.to_str().unwrap(),
+        "--stow-path",
+        team_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
+    let run = |extra: &[&str]| {
+        let output = run_rustow(base.iter().chain(extra));
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
 
-    let old_config = create_test_config(
-        stow_dir.clone(),
-        target_dir.clone(),
-        vec!["oldpkg".to_string()],
-        false,
-        0,
+    // The first stow directory holding a package wins.
+    run(&["vim", "tools"]);
+    assert_eq!(
+        fs::read_to_string(target_dir.join(".vimrc")).unwrap(),
+        "personal"
     );
-    stow_packages(&old_config).unwrap();
-    assert!(target_dir.join("bin/tool").exists());
+    assert!(target_dir.join(".config").is_symlink());
 
-    let parsed_args = Args::parse_from_with_operation_groups(vec![
-        "rustow".to_string(),
-        "-d".to_string(),
-        stow_dir.to_string_lossy().into_owned(),
-        "-t".to_string(),
-        target_dir.to_string_lossy().into_owned(),
-        "-S".to_string(),
-        "newpkg".to_string(),
-        "-D".to_string(),
-        "oldpkg".to_string(),
-    ]);
+    // The folded link into the team directory is recognized and unfolded.
+    run(&["misc"]);
+    assert!(!target_dir.join(".config").is_symlink());
+    assert_eq!(
+        fs::read_to_string(target_dir.join(".config/tools/rc")).unwrap(),
+        "tools"
+    );
+    assert!(target_dir.join(".config/misc").is_symlink());
 
-    let result = rustow::run_with_operation_groups(parsed_args.args, parsed_args.operation_groups);
+    run(&["-D", "tools", "-R", "misc"]);
+    assert!(!target_dir.join(".config/tools").exists());
+    assert!(target_dir.join(".config/misc/rc").exists());
 
-    assert!(
-        result.is_ok(),
-        "GNU mixed stow/delete replacement failed: {:?}",
-        result.err()
+    let output = run_rustow(base.iter().chain(&["shell", "team-shell"]));
+    assert_eq!(output.status.code(), Some(exit_code::CONFLICTS));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("team-shell"), "stderr: {}", stderr);
+    assert!(!target_dir.join(".profile").exists());
+}
+
+#[test]
+fn test_binary_relink_from_and_all_cover_stow_path() {
+    let (temp_dir, personal_dir, target_dir): (TempDir, PathBuf, PathBuf) =
+        setup_test_environment();
+    let team_dir = temp_dir.path().join("team");
+    let files = [
+        (&personal_dir, "vim/.vimrc"),
+        (&team_dir, "tools/.toolsrc"),
+        (&team_dir, "scratch/.scratchrc"),
+    ];
+    for (stow_dir, path) in files {
+        fs::create_dir_all(stow_dir.join(path).parent().unwrap()).unwrap();
+        fs::write(stow_dir.join(path), path).unwrap();
+    }
+    fs::write(team_dir.join(".stow-local-ignore"), "scratch\n").unwrap();
+    let run = |team_dir: &Path, extra: &[&str]| {
+        let output = run_rustow(
+            [
+                "-d",
+                personal_dir.to_str().unwrap(),
+                "--stow-path",
+                team_dir.to_str().unwrap(),
+                "-t",
+                target_dir.to_str().unwrap(),
+            ]
+            .iter()
+            .chain(extra),
+        );
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+
+    // The team directory's own ignore list keeps `scratch` out of `--all`.
+    run(&team_dir, &["--all"]);
+    assert!(target_dir.join(".toolsrc").is_symlink());
+    assert!(!target_dir.join(".scratchrc").exists());
+
+    // Links into a moved `--stow-path` directory are relinked to its new location.
+    let moved_team_dir = temp_dir.path().join("shared/team");
+    fs::create_dir_all(moved_team_dir.parent().unwrap()).unwrap();
+    fs::rename(&team_dir, &moved_team_dir).unwrap();
+    run(
+        &moved_team_dir,
+        &["--relink-from", team_dir.to_str().unwrap()],
     );
-    assert!(target_dir.join("bin/tool").exists());
     assert_eq!(
-        fs::read_link(target_dir.join("bin")).unwrap(),
-        PathBuf::from("../stow_dir/newpkg/bin")
+        fs::read_link(target_dir.join(".toolsrc")).unwrap(),
+        PathBuf::from("../shared/team/tools/.toolsrc")
+    );
+    assert_eq!(
+        fs::read_link(target_dir.join(".vimrc")).unwrap(),
+        PathBuf::from("../stow_dir/vim/.vimrc")
     );
 }
 
 #[test]
-fn test_cli_mixed_stow_conflict_prevents_prior_delete() {
+fn test_binary_honors_stow_and_nonstow_markers() {
     let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
-    let old_package_dir = stow_dir.join("oldpkg");
-    let new_package_dir = stow_dir.join("newpkg");
-    fs::create_dir_all(old_package_dir.join("bin")).unwrap();
-    fs::create_dir_all(new_package_dir.join("etc")).unwrap();
-    fs::write(old_package_dir.join("bin/old_tool"), "old").unwrap();
-    fs::write(new_package_dir.join("etc/app.conf"), "new").unwrap();
+    for path in ["tools/opt/stow/rc", "tools/vault/key", "tools/.toolsrc"] {
+        fs::create_dir_all(stow_dir.join(path).parent().unwrap()).unwrap();
+        fs::write(stow_dir.join(path), "tools").unwrap();
+    }
+    fs::create_dir_all(target_dir.join("opt/stow")).unwrap();
+    fs::write(target_dir.join("opt/stow/.stow"), "").unwrap();
+    fs::create_dir_all(target_dir.join("vault")).unwrap();
+    fs::write(target_dir.join("vault/.nonstow"), "").unwrap();
+    let base = [
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+    ];
 
-    let old_config = create_test_config(
-        stow_dir.clone(),
-        target_dir.clone(),
-        vec!["oldpkg".to_string()],
-        false,
-        0,
+    let output = run_rustow(base.iter().chain(&["tools"]));
+    assert_eq!(output.status.code(), Some(exit_code::CONFLICTS));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("another stow directory"),
+        "stderr: {}",
+        stderr
     );
-    stow_packages(&old_config).unwrap();
-    fs::create_dir_all(target_dir.join("etc")).unwrap();
-    fs::write(target_dir.join("etc/app.conf"), "unmanaged").unwrap();
-    assert!(target_dir.join("bin/old_tool").exists());
-
-    let parsed_args = Args::parse_from_with_operation_groups(vec![
-        "rustow".to_string(),
-        "-d".to_string(),
-        stow_dir.to_string_lossy().into_owned(),
-        "-t".to_string(),
-        target_dir.to_string_lossy().into_owned(),
-        "-D".to_string(),
-        "oldpkg".to_string(),
-        "-S".to_string(),
-        "newpkg".to_string(),
-    ]);
+    assert!(stderr.contains(".nonstow"), "stderr: {}", stderr);
+    assert!(!target_dir.join("opt/stow/rc").exists());
+    assert!(!target_dir.join("vault/key").exists());
+    assert!(!target_dir.join(".toolsrc").exists());
 
-    let result = rustow::run_with_operation_groups(parsed_args.args, parsed_args.operation_groups);
+    // Links inside marked directories are never removed, even when they point into the package.
+    std::os::unix::fs::symlink(
+        stow_dir.join("tools/vault/key"),
+        target_dir.join("vault/key"),
+    )
+    .unwrap();
+    let output = run_rustow(base.iter().chain(&["-D", "tools"]));
+    assert!(output.status.success());
+    assert!(target_dir.join("vault/key").is_symlink());
 
+    fs::remove_dir_all(stow_dir.join("tools/opt")).unwrap();
+    fs::remove_dir_all(stow_dir.join("tools/vault")).unwrap();
+    let output = run_rustow(base.iter().chain(&["--compat", "-R", "tools"]));
     assert!(
-        result.is_err(),
-        "mixed operation should fail before mutation when later stow conflicts"
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
     );
+    assert!(target_dir.join(".toolsrc").is_symlink());
+    assert!(target_dir.join("vault/key").is_symlink());
+
+    // Adopting never moves a directory holding a marked one into the package.
+    fs::create_dir_all(stow_dir.join("local/.local/bin")).unwrap();
+    fs::write(stow_dir.join("local/.local/bin/tool"), "tool").unwrap();
+    fs::create_dir_all(target_dir.join(".local/share")).unwrap();
+    fs::write(target_dir.join(".local/share/.nonstow"), "").unwrap();
+    let output = run_rustow(base.iter().chain(&["--adopt", "local"]));
     assert!(
-        target_dir.join("bin/old_tool").exists(),
-        "old package should remain stowed when later stow phase conflicts"
-    );
-    assert_eq!(
-        fs::read_to_string(target_dir.join("etc/app.conf")).unwrap(),
-        "unmanaged"
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
     );
+    assert!(!target_dir.join(".local").is_symlink());
+    assert!(target_dir.join(".local/share/.nonstow").exists());
+    assert!(target_dir.join(".local/bin").is_symlink());
+    assert!(!stow_dir.join("local/.local/share").exists());
 }
 
 #[test]
-fn test_binary_mixed_simulate_conflict_reports_failure_without_mutation() {
+fn test_binary_rejects_invalid_dir_mode() {
     let (_temp_dir, stow_dir, target_dir): (TempDir, PathBuf, PathBuf) = setup_test_environment();
-    let old_package_dir = stow_dir.join("oldpkg");
-    let new_package_dir = stow_dir.join("newpkg");
-    fs::create_dir_all(old_package_dir.join("bin")).unwrap();
-    fs::create_dir_all(new_package_dir.join("etc")).unwrap();
-    fs::write(old_package_dir.join("bin/old_tool"), "old").unwrap();
-    fs::write(new_package_dir.join("etc/app.conf"), "new").unwrap();
-
-    let old_config = create_test_config(
-        stow_dir.clone(),
-        target_dir.clone(),
-        vec!["oldpkg".to_string()],
-        false,
-        0,
-    );
-    stow_packages(&old_config).unwrap();
-    fs::create_dir_all(target_dir.join("etc")).unwrap();
-    fs::write(target_dir.join("etc/app.conf"), "unmanaged").unwrap();
+    create_test_package(&stow_dir, "test_package");
 
     let output = run_rustow([
-        "-n",
+        "--dir-mode=rwx",
         "-d",
         stow_dir.to_str().unwrap(),
         "-t",
         target_dir.to_str().unwrap(),
-        "-D",
-        "oldpkg",
-        "-S",
-        "newpkg",
+        "test_package",
     ]);
 
-    assert_eq!(output.status.code(), Some(1));
-    assert!(String::from_utf8_lossy(&output.stderr).contains("CONFLICT"));
-    assert!(target_dir.join("bin/old_tool").exists());
-    assert_eq!(
-        fs::read_to_string(target_dir.join("etc/app.conf")).unwrap(),
-        "unmanaged"
-    );
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid --dir-mode 'rwx'"));
+    assert!(!target_dir.join("bin").exists());
 }
 
 #[test]
@@ -3608,15 +5782,42 @@ fn test_cli_restow_conflict_preserves_existing_symlinks() {
     let result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: true,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -3897,7 +6098,7 @@ fn test_binary_long_option_abbreviation_diagnostics() {
     ]);
     assert_eq!(
         accepted.status.code(),
-        Some(0),
+        Some(exit_code::PENDING_CHANGES),
         "rustow failed: {}",
         String::from_utf8_lossy(&accepted.stderr)
     );
@@ -4001,49 +6202,253 @@ fn test_binary_exit_code_policy_and_messages() {
         home_dir.to_str().expect("home dir should be valid utf-8"),
     )];
 
-    let missing_target = run_rustow_with(
-        [
-            "-d",
-            stow_dir.to_str().expect("stow dir should be valid utf-8"),
-            "-t",
-            missing_target_dir
-                .to_str()
-                .expect("missing target should be valid utf-8"),
-            "pkg",
-        ],
+    let missing_target = run_rustow_with(
+        [
+            "-d",
+            stow_dir.to_str().expect("stow dir should be valid utf-8"),
+            "-t",
+            missing_target_dir
+                .to_str()
+                .expect("missing target should be valid utf-8"),
+            "pkg",
+        ],
+        &cwd,
+        &envs,
+    );
+    let missing_target_stderr = String::from_utf8_lossy(&missing_target.stderr);
+    assert_eq!(
+        missing_target.status.code(),
+        Some(exit_code::USAGE),
+        "{}",
+        missing_target_stderr
+    );
+    assert!(missing_target_stderr.contains("Invalid target directory"));
+
+    let missing_package = run_rustow_with(
+        [
+            "-d",
+            stow_dir.to_str().expect("stow dir should be valid utf-8"),
+            "-t",
+            target_dir
+                .to_str()
+                .expect("target dir should be valid utf-8"),
+            "missing-package",
+        ],
+        &cwd,
+        &envs,
+    );
+    let missing_package_stderr = String::from_utf8_lossy(&missing_package.stderr);
+    assert_eq!(
+        missing_package.status.code(),
+        Some(exit_code::PACKAGE_NOT_FOUND),
+        "{}",
+        missing_package_stderr
+    );
+    assert!(missing_package_stderr.contains("Package not found"));
+}
+
+#[test]
+fn test_binary_exit_codes_for_simulate_and_conflicts() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    fs::create_dir_all(stow_dir.join("pkg/bin")).unwrap();
+    fs::write(stow_dir.join("pkg/bin/tool"), "tool").unwrap();
+    let stow_args = |extra: &[&str]| {
+        let mut args = vec![
+            "-d".to_string(),
+            stow_dir.to_str().unwrap().to_string(),
+            "-t".to_string(),
+            target_dir.to_str().unwrap().to_string(),
+        ];
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        args
+    };
+
+    let pending = run_rustow(stow_args(&["-n", "pkg"]));
+    assert_eq!(
+        pending.status.code(),
+        Some(exit_code::PENDING_CHANGES),
+        "stderr: {}",
+        String::from_utf8_lossy(&pending.stderr)
+    );
+
+    let stowed = run_rustow(stow_args(&["pkg"]));
+    assert_eq!(stowed.status.code(), Some(exit_code::SUCCESS));
+
+    let nothing_pending = run_rustow(stow_args(&["-n", "pkg"]));
+    assert_eq!(
+        nothing_pending.status.code(),
+        Some(exit_code::SUCCESS),
+        "stderr: {}",
+        String::from_utf8_lossy(&nothing_pending.stderr)
+    );
+
+    fs::create_dir_all(stow_dir.join("other/bin")).unwrap();
+    fs::write(stow_dir.join("other/bin/tool"), "other").unwrap();
+    let conflicted = run_rustow(stow_args(&["other"]));
+    let stderr = String::from_utf8_lossy(&conflicted.stderr);
+    assert_eq!(
+        conflicted.status.code(),
+        Some(exit_code::CONFLICTS),
+        "stderr: {}",
+        stderr
+    );
+    assert!(stderr.contains("Execution stopped with 1 conflicts and 0 failures"));
+}
+
+#[test]
+fn test_binary_log_file_filters_by_category() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let home_dir = temp_dir.path().join("home");
+    let cwd = temp_dir.path().join("cwd");
+    let stow_dir = temp_dir.path().join("stow");
+    let target_dir = temp_dir.path().join("target");
+    let log_path = temp_dir.path().join("rustow.log");
+    fs::create_dir_all(&home_dir).unwrap();
+    fs::create_dir_all(&cwd).unwrap();
+    fs::create_dir_all(&target_dir).unwrap();
+    fs::create_dir_all(stow_dir.join("pkg/share/doc")).unwrap();
+    fs::write(stow_dir.join("pkg/share/doc/notes"), "notes").unwrap();
+    fs::write(
+        cwd.join(".stowrc"),
+        format!(
+            "--dir={}\n--target={}\n--log-file={}\n",
+            stow_dir.to_string_lossy(),
+            target_dir.to_string_lossy(),
+            log_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+
+    let envs = [("HOME", home_dir.to_str().unwrap())];
+    let output = run_rustow_with(
+        ["--log=cli=info,planner=debug,fs=trace", "pkg"],
         &cwd,
         &envs,
     );
-    let missing_target_stderr = String::from_utf8_lossy(&missing_target.stderr);
     assert_eq!(
-        missing_target.status.code(),
-        Some(1),
-        "{}",
-        missing_target_stderr
+        output.status.code(),
+        Some(exit_code::SUCCESS),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        output.stderr.is_empty(),
+        "log lines must go to the log file: {}",
+        String::from_utf8_lossy(&output.stderr)
     );
-    assert!(missing_target_stderr.contains("Invalid target directory"));
 
-    let missing_package = run_rustow_with(
-        [
-            "-d",
-            stow_dir.to_str().expect("stow dir should be valid utf-8"),
-            "-t",
-            target_dir
-                .to_str()
-                .expect("target dir should be valid utf-8"),
-            "missing-package",
-        ],
-        &cwd,
-        &envs,
+    let log = fs::read_to_string(&log_path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.ends_with("INFO  cli: loaded resource file './.stowrc'")),
+        "{log}"
     );
-    let missing_package_stderr = String::from_utf8_lossy(&missing_package.stderr);
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.contains("DEBUG planner: folding ") && line.contains("/share")),
+        "{log}"
+    );
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.contains("TRACE fs: symlink ") && line.contains("/share")),
+        "{log}"
+    );
+    assert!(!log.contains(" exec: "), "{log}");
+    assert!(!log.contains(" config: "), "{log}");
+    for line in lines {
+        let (timestamp, _) = line.split_once(' ').unwrap();
+        assert_eq!(timestamp.len(), "2026-01-01T00:00:00.000Z".len(), "{line}");
+        assert!(timestamp.ends_with('Z'), "{line}");
+    }
+}
+
+#[test]
+fn test_binary_invalid_log_filter_is_a_usage_error() {
+    let (_temp_dir, stow_dir, target_dir) = setup_test_environment();
+    create_test_package(&stow_dir, "pkg");
+
+    let output = run_rustow([
+        "-d",
+        stow_dir.to_str().unwrap(),
+        "-t",
+        target_dir.to_str().unwrap(),
+        "--log=planner=chatty",
+        "pkg",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(exit_code::USAGE), "{stderr}");
+    assert!(stderr.contains("Invalid log filter: unknown level 'chatty'"));
+    assert!(target_entry_names(&target_dir).is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_binary_watch_restows_changed_package_and_survives_conflicts() {
+    let (temp_dir, stow_dir, target_dir) = setup_test_environment();
+    let package_dir = stow_dir.join("pkg");
+    let log_path = temp_dir.path().join("watch.log");
+    fs::create_dir_all(&package_dir).unwrap();
+    fs::write(package_dir.join("old"), "old").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rustow"))
+        .args(["-d", stow_dir.to_str().unwrap()])
+        .args(["-t", target_dir.to_str().unwrap()])
+        .args(["--log-file", log_path.to_str().unwrap()])
+        .args(["--watch", "pkg"])
+        .env_remove("STOW_DIR")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to start rustow --watch");
+    let wait_until = |description: &str, condition: &dyn Fn() -> bool| {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !condition() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "timed out waiting for {description}; log:\n{}",
+                fs::read_to_string(&log_path).unwrap_or_default()
+            );
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+    };
+    let log_contains =
+        |text: &str| fs::read_to_string(&log_path).is_ok_and(|log| log.contains(text));
+
+    wait_until("the watcher to start", &|| {
+        log_contains("watching 1 package(s)")
+    });
+    assert!(fs::symlink_metadata(target_dir.join("old")).is_ok());
+
+    fs::write(package_dir.join("new"), "new").unwrap();
+    wait_until("the new entry to be linked", &|| {
+        target_dir.join("new").is_symlink()
+    });
+
+    fs::remove_file(package_dir.join("old")).unwrap();
+    wait_until("the obsolete link to be pruned", &|| {
+        fs::symlink_metadata(target_dir.join("old")).is_err()
+    });
+
+    fs::write(target_dir.join("taken"), "mine").unwrap();
+    fs::write(package_dir.join("taken"), "package").unwrap();
+    wait_until("the conflict to be reported", &|| {
+        log_contains("package 'pkg' left untouched: 1 conflict(s)")
+    });
+    assert!(child.try_wait().unwrap().is_none(), "watcher exited");
     assert_eq!(
-        missing_package.status.code(),
-        Some(1),
-        "{}",
-        missing_package_stderr
+        fs::read_to_string(target_dir.join("taken")).unwrap(),
+        "mine"
     );
-    assert!(missing_package_stderr.contains("Package not found"));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    let log = fs::read_to_string(&log_path).unwrap();
+    assert!(log.contains("INFO  watch: "), "{log}");
+    assert!(log.contains("/new: missing -> symlink -> "), "{log}");
 }
 
 #[test]
@@ -4177,7 +6582,7 @@ fn test_binary_mixed_invalid_package_combinations_fail_fast_without_mutation() {
         let delete_then_stow_stderr = String::from_utf8_lossy(&delete_then_stow_output.stderr);
         assert_eq!(
             delete_then_stow_output.status.code(),
-            Some(1),
+            Some(exit_code::USAGE),
             "{}",
             delete_then_stow_stderr
         );
@@ -4203,7 +6608,7 @@ fn test_binary_mixed_invalid_package_combinations_fail_fast_without_mutation() {
         let stow_then_delete_stderr = String::from_utf8_lossy(&stow_then_delete_output.stderr);
         assert_eq!(
             stow_then_delete_output.status.code(),
-            Some(1),
+            Some(exit_code::USAGE),
             "{}",
             stow_then_delete_stderr
         );
@@ -4287,7 +6692,7 @@ fn test_binary_stowrc_long_option_abbreviations_are_accepted() {
 
     assert_eq!(
         output.status.code(),
-        Some(0),
+        Some(exit_code::PENDING_CHANGES),
         "rustow failed: {}",
         String::from_utf8_lossy(&output.stderr)
     );
@@ -4517,7 +6922,12 @@ fn test_binary_stowrc_trailing_backslash_line_is_ignored() {
 
     let output = run_rustow_with(["--simulate", "pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(0), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::PENDING_CHANGES),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains("definitely-not-used"));
     assert!(!stderr.contains("Invalid stow directory"));
 }
@@ -4607,7 +7017,7 @@ fn test_binary_stowrc_env_expanded_path_is_redacted_in_config_error() {
     ];
 
     let output = run_rustow_with(["pkg"], &cwd, &envs);
-    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
     let stderr = String::from_utf8_lossy(&output.stderr);
     assert!(!stderr.contains("secret-value-from-env"));
     assert!(stderr.contains("$RUSTOW_SECRET_PATH/missing"));
@@ -4626,7 +7036,7 @@ fn test_binary_stowrc_env_expanded_path_is_redacted_in_config_error() {
     .unwrap();
 
     let output = run_rustow_with(["pkg"], &cwd, &envs);
-    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
     let stderr = String::from_utf8_lossy(&output.stderr);
     assert!(!stderr.contains("secret-value-from-env"));
     assert!(stderr.contains("$RUSTOW_SECRET_PATH/missing"));
@@ -4643,7 +7053,7 @@ fn test_binary_stowrc_env_expanded_path_is_redacted_in_config_error() {
         ("RUSTOW_TILDE_SECRET", tilde_secret),
     ];
     let output = run_rustow_with(["pkg"], &cwd, &envs);
-    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
     assert!(!String::from_utf8_lossy(&output.stderr).contains("secret-value-from-env"));
     assert!(String::from_utf8_lossy(&output.stderr).contains("$RUSTOW_TILDE_SECRET/missing"));
 
@@ -4656,7 +7066,7 @@ fn test_binary_stowrc_env_expanded_path_is_redacted_in_config_error() {
         ("RUSTOW_SHORT_SECRET", "abc"),
     ];
     let output = run_rustow_with(["pkg"], &cwd, &envs);
-    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
     assert!(!String::from_utf8_lossy(&output.stderr).contains("abc/missing"));
     assert!(String::from_utf8_lossy(&output.stderr).contains("$RUSTOW_SHORT_SECRET/missing"));
 }
@@ -4680,7 +7090,12 @@ fn test_binary_stowrc_relative_env_display_does_not_rewrite_diagnostic_copy() {
 
     let output = run_rustow_with(["pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(1), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::USAGE),
+        "stderr: {}",
+        stderr
+    );
     assert!(stderr.contains("Failed to canonicalize stow directory"));
     assert!(stderr.contains("$RUSTOW_STOW_DIR"));
     assert!(!stderr.contains("Failed to canonicalize $RUSTOW_STOW_DIR directory"));
@@ -4702,7 +7117,12 @@ fn test_binary_stowrc_tilde_expanded_path_is_redacted_in_config_error() {
 
     let output = run_rustow_with(["pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(1), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::USAGE),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains(home_dir.to_str().expect("home dir should be valid utf-8")));
     assert!(stderr.contains("~/missing-stow"));
     assert!(stderr.contains("Invalid stow directory"));
@@ -4823,7 +7243,7 @@ fn test_binary_direct_cli_config_error_keeps_literal_path() {
         &cwd,
         &envs,
     );
-    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
     assert!(
         String::from_utf8_lossy(&output.stderr).contains(missing_stow.to_string_lossy().as_ref())
     );
@@ -4895,7 +7315,12 @@ fn test_binary_stowrc_env_expanded_action_output_is_redacted() {
     ];
     let output = run_rustow_with(["--simulate", "pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(0), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::PENDING_CHANGES),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains("secret-value-from-env"));
     assert!(stderr.contains("$RUSTOW_SECRET_ROOT/stow/pkg/bin"));
     assert!(stderr.contains("$RUSTOW_SECRET_ROOT/target/bin"));
@@ -4934,7 +7359,12 @@ fn test_binary_stowrc_env_expanded_debug_escaped_action_output_is_redacted() {
     ];
     let output = run_rustow_with(["--simulate", "pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(0), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::PENDING_CHANGES),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains("secret\\value-from-env"));
     assert!(!stderr.contains("secret\\\\value-from-env"));
     assert!(stderr.contains("$RUSTOW_SECRET_ROOT/stow/pkg/bin"));
@@ -4975,7 +7405,12 @@ fn test_binary_stowrc_env_expanded_conflict_output_is_redacted() {
     ];
     let output = run_rustow_with(["pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(1), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::CONFLICTS),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains("secret-value-from-env"));
     assert!(stderr.contains("$RUSTOW_SECRET_ROOT/target/bin/tool"));
 }
@@ -5016,7 +7451,12 @@ fn test_binary_stowrc_env_expanded_failure_output_is_redacted() {
     ];
     let output = run_rustow_with(["--no-folding", "pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(1), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::CONFLICTS),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains("secret-value-from-env"));
     assert!(stderr.contains("$RUSTOW_SECRET_ROOT/target/bin"));
 }
@@ -5208,7 +7648,12 @@ fn test_binary_stowrc_env_expanded_default_target_output_is_redacted() {
     ];
     let output = run_rustow_with(["--simulate", "pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(0), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::PENDING_CHANGES),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains("secret-value-from-env"));
     assert!(stderr.contains("$RUSTOW_SECRET_ROOT/bin"));
     assert!(stderr.contains("$RUSTOW_SECRET_ROOT/stow/pkg/bin"));
@@ -5239,7 +7684,12 @@ fn test_binary_stowrc_env_expanded_bare_env_default_target_output_is_redacted()
     ];
     let output = run_rustow_with(["--simulate", "pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(0), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::PENDING_CHANGES),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains("secret-value-from-env"));
     assert!(stderr.contains("$RUSTOW_SECRET_STOW_DIR/pkg/bin"));
     assert!(stderr.contains("$RUSTOW_SECRET_STOW_DIR/../bin"));
@@ -5262,7 +7712,12 @@ fn test_binary_stowrc_tilde_expanded_default_target_output_is_redacted() {
     )];
     let output = run_rustow_with(["--simulate", "pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(0), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::PENDING_CHANGES),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains(home_dir.to_str().expect("home dir should be valid utf-8")));
     assert!(stderr.contains("~/stow/pkg/bin"));
     assert!(stderr.contains("~/bin"));
@@ -5287,7 +7742,12 @@ fn test_binary_stowrc_tilde_expanded_conflict_output_is_redacted() {
     )];
     let output = run_rustow_with(["pkg"], &cwd, &envs);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(output.status.code(), Some(1), "stderr: {}", stderr);
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::CONFLICTS),
+        "stderr: {}",
+        stderr
+    );
     assert!(!stderr.contains(home_dir.to_str().expect("home dir should be valid utf-8")));
     assert!(stderr.contains("~/bin/tool"));
 }
@@ -5356,7 +7816,7 @@ fn test_binary_accepts_hyphen_prefixed_separate_option_values() {
         let output = run_rustow(args);
         let stderr = String::from_utf8_lossy(&output.stderr);
 
-        assert_ne!(output.status.code(), Some(2));
+        assert!(!stderr.starts_with("error:"), "stderr: {}", stderr);
         assert!(!stderr.contains("requires a value"));
     }
 
@@ -5571,15 +8031,42 @@ fn test_cli_package_symlink_alias_can_be_stowed_and_deleted() {
     let stow_result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -5599,15 +8086,42 @@ fn test_cli_package_symlink_alias_can_be_stowed_and_deleted() {
     let repeat_stow_result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -5622,15 +8136,42 @@ fn test_cli_package_symlink_alias_can_be_stowed_and_deleted() {
     let restow_result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: true,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -5649,15 +8190,42 @@ fn test_cli_package_symlink_alias_can_be_stowed_and_deleted() {
     let delete_result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: true,
         restow: false,
         adopt: false,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -6111,15 +8679,42 @@ fn test_stow_rejects_symlinked_target_ancestor_without_external_mutation() {
     let result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: false,
+        git_commit: false,
         no_folding: true,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -6145,15 +8740,42 @@ fn test_adopt_rejects_symlinked_target_ancestor_without_external_mutation() {
     let result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: true,
+        git_commit: false,
         no_folding: true,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -6244,15 +8866,42 @@ fn test_adopt_package_symlink_alias_uses_canonical_destination_and_preserves_ali
     let result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: true,
+        git_commit: false,
         no_folding: true,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -6319,15 +8968,42 @@ fn test_adopt_directory_refuses_symlinked_destination_child_directory() {
     let result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: true,
+        git_commit: false,
         no_folding: true,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -6680,15 +9356,42 @@ fn test_adopt_option_with_existing_file() {
     let result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: true,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -6752,15 +9455,42 @@ fn test_adopt_option_with_existing_directory() {
     let result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: true,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: false,
         verbose: 0,
@@ -6831,15 +9561,42 @@ fn test_adopt_option_simulation_mode() {
     let result = rustow::run(Args {
         target: Some(target_dir.clone()),
         dir: Some(stow_dir.clone()),
+        stow_path: Vec::new(),
         stow: false,
         delete: false,
         restow: false,
         adopt: true,
+        git_commit: false,
         no_folding: false,
+        no_fold: Vec::new(),
+        dir_mode: Vec::new(),
+        sensitive: Vec::new(),
+        insecure_permissions: None,
         dotfiles: false,
         override_conflicts: Vec::new(),
         defer_conflicts: Vec::new(),
         ignore_patterns: Vec::new(),
+        log: None,
+        log_file: None,
+        lock_timeout: None,
+        plan_out: None,
+        apply: None,
+        watch: false,
+        check: false,
+        incremental: false,
+        force: false,
+        report_unmanaged: false,
+        unfold_unmanaged: false,
+        unfold: None,
+        refold: None,
+        add: None,
+        move_to: None,
+        relink_from: None,
+        all: false,
+        packages_from: Vec::new(),
+        exclude_package: Vec::new(),
+        completions: None,
+        complete_packages: None,
         compat: false,
         simulate: true,
         verbose: 1,
@@ -6871,3 +9628,228 @@ fn test_adopt_option_simulation_mode() {
         "Target should remain a regular file in simulation mode"
     );
 }
+
+fn git_in(repo: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .envs(GIT_TEST_IDENTITY.iter().copied())
+        .output()
+        .expect("Failed to run git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+const GIT_TEST_IDENTITY: [(&str, &str); 4] = [
+    ("GIT_AUTHOR_NAME", "rustow tests"),
+    ("GIT_AUTHOR_EMAIL", "tests@example.invalid"),
+    ("GIT_COMMITTER_NAME", "rustow tests"),
+    ("GIT_COMMITTER_EMAIL", "tests@example.invalid"),
+];
+
+fn setup_git_adopt_environment() -> (TempDir, PathBuf, PathBuf, PathBuf) {
+    let temp_base = tempdir().unwrap();
+    let home_dir = temp_base.path().join("home");
+    let stow_dir = temp_base.path().join("stow");
+    let target_dir = temp_base.path().join("target");
+    fs::create_dir_all(&home_dir).unwrap();
+    fs::create_dir_all(stow_dir.join("zsh")).unwrap();
+    fs::create_dir_all(stow_dir.join("vim")).unwrap();
+    fs::create_dir_all(target_dir.join(".config")).unwrap();
+    fs::write(stow_dir.join("zsh/.zshrc"), "package zshrc").unwrap();
+    fs::write(stow_dir.join("vim/.vimrc"), "package vimrc").unwrap();
+    fs::write(stow_dir.join("vim/notes"), "untracked notes").unwrap();
+
+    git_in(&stow_dir, &["init", "--quiet"]);
+    git_in(&stow_dir, &["add", "zsh/.zshrc", "vim/.vimrc"]);
+    git_in(&stow_dir, &["commit", "--quiet", "--message", "initial"]);
+
+    fs::write(target_dir.join(".zshrc"), "local zshrc").unwrap();
+    fs::write(target_dir.join(".vimrc"), "local vimrc").unwrap();
+    (temp_base, home_dir, stow_dir, target_dir)
+}
+
+#[test]
+fn test_git_commit_records_adopted_files() {
+    let (_temp_base, home_dir, stow_dir, target_dir) = setup_git_adopt_environment();
+
+    let mut envs = vec![("HOME", home_dir.to_str().unwrap())];
+    envs.extend(GIT_TEST_IDENTITY);
+    let output = run_rustow_with(
+        [
+            "--adopt",
+            "--git-commit",
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "zsh",
+            "vim",
+        ],
+        &home_dir,
+        &envs,
+    );
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_eq!(
+        git_in(&stow_dir, &["log", "-1", "--format=%B"]).trim_end(),
+        "rustow: adopt 2 path(s) into vim, zsh\n\nzsh: .zshrc\nvim: .vimrc"
+    );
+    assert_eq!(
+        git_in(&stow_dir, &["show", "--name-only", "--format=", "HEAD"]),
+        "vim/.vimrc\nzsh/.zshrc\n"
+    );
+    assert_eq!(
+        git_in(&stow_dir, &["show", "HEAD:zsh/.zshrc"]),
+        "local zshrc"
+    );
+    assert_eq!(
+        git_in(&stow_dir, &["status", "--porcelain"]),
+        "?? vim/notes\n"
+    );
+}
+
+#[test]
+fn test_git_commit_leaves_untracked_package_files_out() {
+    let (_temp_base, home_dir, stow_dir, target_dir) = setup_git_adopt_environment();
+    fs::create_dir_all(stow_dir.join("app/.app")).unwrap();
+    fs::write(stow_dir.join("app/.app/stray.tmp"), "stray").unwrap();
+    fs::create_dir_all(target_dir.join(".app/themes")).unwrap();
+    fs::write(target_dir.join(".app/settings"), "settings").unwrap();
+    fs::write(target_dir.join(".app/themes/dark"), "dark").unwrap();
+    // Ignored files are moved but not committed, as `git add` of the directory would do.
+    fs::write(stow_dir.join(".gitignore"), "*.log\n").unwrap();
+    fs::write(target_dir.join(".app/cache.log"), "log").unwrap();
+
+    let mut envs = vec![("HOME", home_dir.to_str().unwrap())];
+    envs.extend(GIT_TEST_IDENTITY);
+    let output = run_rustow_with(
+        [
+            "--adopt",
+            "--git-commit",
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "app",
+        ],
+        &home_dir,
+        &envs,
+    );
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_eq!(
+        git_in(&stow_dir, &["show", "--name-only", "--format=", "HEAD"]),
+        "app/.app/settings\napp/.app/themes/dark\n"
+    );
+    assert_eq!(
+        git_in(&stow_dir, &["status", "--porcelain"]),
+        "?? .gitignore\n?? app/.app/stray.tmp\n?? vim/notes\n"
+    );
+    assert!(stow_dir.join("app/.app/cache.log").is_file());
+}
+
+#[test]
+fn test_git_commit_names_added_files_as_added() {
+    let (_temp_base, home_dir, stow_dir, target_dir) = setup_git_adopt_environment();
+    fs::write(target_dir.join(".tmux.conf"), "set -g mouse on").unwrap();
+
+    let mut envs = vec![("HOME", home_dir.to_str().unwrap())];
+    envs.extend(GIT_TEST_IDENTITY);
+    let output = run_rustow_with(
+        [
+            "--git-commit",
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "--add",
+            "tmux",
+            target_dir.join(".tmux.conf").to_str().unwrap(),
+        ],
+        &home_dir,
+        &envs,
+    );
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_eq!(
+        git_in(&stow_dir, &["log", "-1", "--format=%B"]).trim_end(),
+        "rustow: add 1 path(s) into tmux\n\ntmux: .tmux.conf"
+    );
+}
+
+#[test]
+fn test_git_commit_refuses_unrelated_staged_changes() {
+    let (_temp_base, home_dir, stow_dir, target_dir) = setup_git_adopt_environment();
+    git_in(&stow_dir, &["add", "vim/notes"]);
+
+    let output = run_rustow_with(
+        [
+            "--adopt",
+            "--git-commit",
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "zsh",
+        ],
+        &home_dir,
+        &[("HOME", home_dir.to_str().unwrap())],
+    );
+
+    assert_eq!(output.status.code(), Some(exit_code::RUNTIME_ERROR));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("staged change(s) (vim/notes)"),
+        "stderr: {}",
+        stderr
+    );
+    assert_eq!(
+        fs::read_to_string(target_dir.join(".zshrc")).unwrap(),
+        "local zshrc"
+    );
+    assert_eq!(
+        fs::read_to_string(stow_dir.join("zsh/.zshrc")).unwrap(),
+        "package zshrc"
+    );
+    assert_eq!(git_in(&stow_dir, &["rev-list", "--count", "HEAD"]), "1\n");
+}
+
+#[test]
+fn test_git_commit_requires_adopt_or_add() {
+    let (_temp_base, home_dir, stow_dir, target_dir) = setup_git_adopt_environment();
+
+    let output = run_rustow_with(
+        [
+            "--git-commit",
+            "-d",
+            stow_dir.to_str().unwrap(),
+            "-t",
+            target_dir.to_str().unwrap(),
+            "zsh",
+        ],
+        &home_dir,
+        &[("HOME", home_dir.to_str().unwrap())],
+    );
+
+    assert_eq!(output.status.code(), Some(exit_code::USAGE));
+    assert!(!target_dir.join(".zshrc").is_symlink());
+}